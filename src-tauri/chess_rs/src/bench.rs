@@ -0,0 +1,93 @@
+use std::sync::atomic::AtomicBool;
+use std::time::{Duration, Instant};
+
+use crate::board::Board;
+use crate::core::{
+    IN_CHECK_FEN, POS_2_KIWIPETE_FEN, POS_3_FEN, POS_4_FEN, POS_4_MIRRORED_FEN, POS_5_FEN,
+    POS_6_FEN, STARTING_POS_FEN,
+};
+use crate::lookup_tables::LookupTables;
+use crate::search::{self, SearchLimits, SearchOptions};
+use crate::tt::TranspositionTable;
+
+pub const DEFAULT_BENCH_DEPTH: u8 = 10;
+
+/// The size of the transposition table the bench searches with, which changes the node count.
+const BENCH_HASH_MB: usize = 16;
+
+/// The positions searched by `bench`: openings, middlegames and endgames with tactics, checks,
+/// promotions and castling of both kinds. Changing them changes the signature.
+pub const BENCH_FENS: [&str; 16] = [
+    STARTING_POS_FEN,
+    POS_2_KIWIPETE_FEN,
+    POS_3_FEN,
+    POS_4_FEN,
+    POS_4_MIRRORED_FEN,
+    POS_5_FEN,
+    POS_6_FEN,
+    IN_CHECK_FEN,
+    "r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 10",
+    "r1bq1rk1/pp2bppp/2n1pn2/2pp4/3P4/2PBPN2/PP1N1PPP/R1BQK2R w KQ - 0 8",
+    "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1",
+    "r3r1k1/pp3pbp/1qp3p1/2B5/2BP2b1/Q1n2N2/P4PPP/3R1K1R b - - 0 17",
+    "1K1k4/1P6/8/8/8/8/r7/2R5 w - - 0 1",
+    "8/8/1p1k4/p1p5/P1P1K3/1P6/8/8 w - - 0 40",
+    "8/5k2/8/8/3Q4/8/8/3K4 w - - 0 1",
+    "8/P7/8/8/8/8/6kp/K7 w - - 0 60",
+];
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BenchResult {
+    /// The total number of nodes searched, which only changes when the search does.
+    pub nodes: u64,
+    pub time: Duration,
+}
+
+impl BenchResult {
+    pub fn nps(&self) -> u64 {
+        (self.nodes as f64 / self.time.as_secs_f64().max(1e-9)) as u64
+    }
+}
+
+/// Searches each of `BENCH_FENS` to `depth` on one thread with the default options, starting
+/// each with an empty transposition table, so the total node count is the same on every run and
+/// every machine. It's used as a signature to show a change doesn't change the search.
+pub fn bench(depth: u8, l: &LookupTables, on_position: &mut dyn FnMut(&str, u64)) -> BenchResult {
+    let tt = TranspositionTable::new(BENCH_HASH_MB);
+    let limits = SearchLimits {
+        depth: Some(depth),
+        ..SearchLimits::default()
+    };
+    let options = SearchOptions::default();
+    let stop = AtomicBool::new(false);
+
+    let start = Instant::now();
+    let mut nodes = 0;
+    for fen in BENCH_FENS {
+        let b = Board::from_fen(fen, l).unwrap();
+        tt.clear();
+        let result = search::search(&b, &tt, &limits, &options, &stop, &mut |_| {});
+        on_position(fen, result.nodes);
+        nodes += result.nodes;
+    }
+
+    BenchResult {
+        nodes,
+        time: start.elapsed(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bench_test() {
+        let l = LookupTables::generate();
+        let mut positions = 0;
+        let first = bench(4, &l, &mut |_, _| positions += 1);
+        assert_eq!(positions, BENCH_FENS.len());
+        assert!(first.nodes > 0);
+        assert_eq!(bench(4, &l, &mut |_, _| {}).nodes, first.nodes);
+    }
+}
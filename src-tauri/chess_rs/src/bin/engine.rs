@@ -0,0 +1,440 @@
+use std::env;
+use std::io::{self, BufRead};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use chess_rs::bench::{self, DEFAULT_BENCH_DEPTH};
+use chess_rs::board::{Board, Legality};
+use chess_rs::book::Book;
+use chess_rs::core::{Move, Player, STARTING_POS_FEN};
+use chess_rs::dtm::DtmTables;
+use chess_rs::lookup_tables::LookupTables;
+use chess_rs::nnue::Network;
+use chess_rs::search::{self, SearchInfo, SearchLimits, SearchOptions, MAX_MULTI_PV, MAX_THREADS};
+use chess_rs::strength::{StrengthLimit, MAX_SKILL_LEVEL};
+use chess_rs::syzygy::Tablebases;
+use chess_rs::time::Clock;
+use chess_rs::tt::{TranspositionTable, DEFAULT_HASH_MB, MAX_HASH_MB};
+
+const DEFAULT_MOVE_OVERHEAD_MS: u64 = 10;
+const MAX_MOVE_OVERHEAD_MS: u64 = 5000;
+
+/// A UCI engine. The search runs on its own thread so `stop` can be handled while it's thinking.
+struct Engine {
+    lookup_tables: &'static LookupTables,
+    board: Board<'static>,
+    tt: Arc<TranspositionTable>,
+    stop: Arc<AtomicBool>,
+    /// Set while the search is pondering and cleared by `ponderhit`.
+    pondering: Arc<AtomicBool>,
+    search_thread: Option<thread::JoinHandle<()>>,
+    chess960: bool,
+    options: SearchOptions,
+    move_overhead: Duration,
+    /// Below `MAX_SKILL_LEVEL` the search is weakened to this level.
+    skill_level: u32,
+    /// The network loaded from `EvalFile`, or `None` to use the hand written evaluation.
+    network: Option<Arc<Network>>,
+    /// The book loaded from `BookFile`, which is played from when `OwnBook` is set.
+    book: Option<Book>,
+    own_book: bool,
+}
+
+fn main() {
+    // The search thread needs the lookup tables for as long as the engine runs.
+    let l: &'static LookupTables = Box::leak(Box::new(LookupTables::generate()));
+
+    // `engine bench [depth]` prints the bench signature and quits, as other engines do.
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().is_some_and(|a| a == "bench") {
+        let args: Vec<&str> = args[1..].iter().map(|a| a.as_str()).collect();
+        if let Err(e) = run_bench(&args, l) {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let mut engine = Engine {
+        lookup_tables: l,
+        board: Board::start_pos(l),
+        tt: Arc::new(TranspositionTable::new(DEFAULT_HASH_MB)),
+        stop: Arc::new(AtomicBool::new(false)),
+        pondering: Arc::new(AtomicBool::new(false)),
+        search_thread: None,
+        chess960: false,
+        options: SearchOptions::default(),
+        move_overhead: Duration::from_millis(DEFAULT_MOVE_OVERHEAD_MS),
+        skill_level: MAX_SKILL_LEVEL,
+        network: None,
+        book: None,
+        own_book: false,
+    };
+
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else { break };
+        match engine.handle_command(&line) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => println!("info string error: {}", e),
+        }
+    }
+
+    engine.stop_search();
+}
+
+impl Engine {
+    /// Handles a line of UCI input. Returns false when the engine should quit.
+    fn handle_command(&mut self, line: &str) -> Result<bool, String> {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let Some((command, args)) = tokens.split_first() else {
+            return Ok(true);
+        };
+
+        match *command {
+            "uci" => {
+                println!("id name chess_rs");
+                println!("id author the chess_rs developers");
+                println!(
+                    "option name Hash type spin default {} min 1 max {}",
+                    DEFAULT_HASH_MB, MAX_HASH_MB
+                );
+                println!(
+                    "option name Threads type spin default 1 min 1 max {}",
+                    MAX_THREADS
+                );
+                println!(
+                    "option name Move Overhead type spin default {} min 0 max {}",
+                    DEFAULT_MOVE_OVERHEAD_MS, MAX_MOVE_OVERHEAD_MS
+                );
+                println!(
+                    "option name MultiPV type spin default 1 min 1 max {}",
+                    MAX_MULTI_PV
+                );
+                println!("option name EvalFile type string default <empty>");
+                println!("option name SyzygyPath type string default <empty>");
+                println!("option name DtmPath type string default <empty>");
+                println!("option name OwnBook type check default false");
+                println!("option name BookFile type string default <empty>");
+                println!("option name Ponder type check default false");
+                println!("option name UCI_Chess960 type check default false");
+                println!(
+                    "option name Skill Level type spin default {} min 0 max {}",
+                    MAX_SKILL_LEVEL, MAX_SKILL_LEVEL
+                );
+                for (name, enabled) in self.options.toggles() {
+                    println!("option name {} type check default {}", name, enabled);
+                }
+                println!("uciok");
+            }
+            "isready" => println!("readyok"),
+            "setoption" => {
+                self.wait_for_search();
+                self.set_option(args)?;
+            }
+            "ucinewgame" => {
+                self.wait_for_search();
+                self.tt.clear();
+            }
+            "position" => {
+                self.wait_for_search();
+                self.set_position(args)?;
+            }
+            "go" => {
+                self.wait_for_search();
+                let limits = self.parse_go(args)?;
+                self.start_search(limits);
+            }
+            "bench" => {
+                self.wait_for_search();
+                run_bench(args, self.lookup_tables)?;
+            }
+            "ponderhit" => self.pondering.store(false, Ordering::Relaxed),
+            "stop" => self.stop_search(),
+            "quit" => return Ok(false),
+            "d" => println!("{}\nfen: {}", self.board, self.board.fen()),
+            c => return Err(format!("unknown command: {}", c)),
+        }
+
+        Ok(true)
+    }
+
+    fn set_option(&mut self, args: &[&str]) -> Result<(), String> {
+        // setoption name <name> [value <value>], where the name can contain spaces.
+        if args.first() != Some(&"name") {
+            return Err("expected name".to_string());
+        }
+        let value_index = args.iter().position(|a| *a == "value");
+        let name = args[1..value_index.unwrap_or(args.len())].join(" ");
+        let value = value_index.map(|i| args[i + 1..].join(" "));
+
+        match (name.as_str(), value) {
+            ("Hash", Some(v)) => {
+                let mb = v
+                    .parse::<usize>()
+                    .map_err(|_| format!("invalid Hash value: {}", v))?;
+                match Arc::get_mut(&mut self.tt) {
+                    Some(tt) => tt.resize(mb),
+                    None => self.tt = Arc::new(TranspositionTable::new(mb)),
+                }
+            }
+            ("Threads", Some(v)) => {
+                let threads = v
+                    .parse::<usize>()
+                    .map_err(|_| format!("invalid Threads value: {}", v))?;
+                self.options.threads = threads.clamp(1, MAX_THREADS);
+            }
+            ("Move Overhead", Some(v)) => {
+                let ms = v
+                    .parse::<u64>()
+                    .map_err(|_| format!("invalid Move Overhead value: {}", v))?;
+                self.move_overhead = Duration::from_millis(ms.min(MAX_MOVE_OVERHEAD_MS));
+            }
+            ("MultiPV", Some(v)) => {
+                let multi_pv = v
+                    .parse::<usize>()
+                    .map_err(|_| format!("invalid MultiPV value: {}", v))?;
+                self.options.multi_pv = multi_pv.clamp(1, MAX_MULTI_PV);
+            }
+            ("EvalFile", v) => {
+                self.network = match v.as_deref() {
+                    None | Some("") | Some("<empty>") => None,
+                    Some(path) => Some(Arc::new(Network::load(path)?)),
+                };
+                self.board.set_network(self.network.clone());
+            }
+            ("SyzygyPath", v) => {
+                self.options.tablebases = match v.as_deref() {
+                    None | Some("") | Some("<empty>") => None,
+                    Some(path) => {
+                        let tablebases = Tablebases::open(path)?;
+                        println!(
+                            "info string found {} tablebases with up to {} pieces",
+                            tablebases.table_count(),
+                            tablebases.max_pieces()
+                        );
+                        Some(Arc::new(tablebases))
+                    }
+                };
+            }
+            ("OwnBook", Some(v)) => self.own_book = v == "true",
+            ("BookFile", v) => {
+                self.book = match v.as_deref() {
+                    None | Some("") | Some("<empty>") => None,
+                    Some(path) => {
+                        let book = Book::load(path)?;
+                        println!("info string loaded {} book moves", book.entries().len());
+                        Some(book)
+                    }
+                };
+            }
+            ("DtmPath", v) => {
+                self.options.dtm_tables = match v.as_deref() {
+                    None | Some("") | Some("<empty>") => None,
+                    Some(path) => {
+                        let tables = DtmTables::load_dir(path)?;
+                        println!(
+                            "info string found {} distance to mate tables with up to {} pieces",
+                            tables.tables().count(),
+                            tables.max_pieces()
+                        );
+                        Some(Arc::new(tables))
+                    }
+                };
+            }
+            // The GUI decides when to ponder so there's nothing to set up.
+            ("Ponder", Some(_)) => {}
+            ("UCI_Chess960", Some(v)) => {
+                self.chess960 = v == "true";
+                self.board.set_chess960(self.chess960);
+            }
+            ("Skill Level", Some(v)) => {
+                let level = v
+                    .parse::<u32>()
+                    .map_err(|_| format!("invalid Skill Level value: {}", v))?;
+                self.skill_level = level.min(MAX_SKILL_LEVEL);
+            }
+            (name, Some(v)) => {
+                let (_, enabled) = self
+                    .options
+                    .toggles()
+                    .into_iter()
+                    .find(|(toggle_name, _)| *toggle_name == name)
+                    .ok_or(format!("unknown option: {}", name))?;
+                *enabled = v == "true";
+            }
+            (name, _) => return Err(format!("unknown option: {}", name)),
+        }
+
+        Ok(())
+    }
+
+    fn set_position(&mut self, args: &[&str]) -> Result<(), String> {
+        let moves_index = args.iter().position(|a| *a == "moves");
+        let position_args = &args[..moves_index.unwrap_or(args.len())];
+
+        let fen = match position_args {
+            ["startpos"] => STARTING_POS_FEN.to_string(),
+            ["fen", fen @ ..] => fen.join(" "),
+            _ => return Err("expected startpos or fen".to_string()),
+        };
+        let mut b = Board::from_fen(&fen, self.lookup_tables).map_err(|e| e.to_string())?;
+        b.set_chess960(self.chess960);
+        b.set_network(self.network.clone());
+
+        for m_str in moves_index.map_or(&[][..], |i| &args[i + 1..]) {
+            let m = Move::try_from(*m_str)?;
+            let legal_moves = b.generate_moves(Legality::Legal);
+            if !legal_moves.contains(&m) {
+                return Err(format!("illegal move: {}", m_str));
+            }
+            b.apply_move(m, &legal_moves);
+        }
+
+        self.board = b;
+        Ok(())
+    }
+
+    fn parse_go(&self, args: &[&str]) -> Result<SearchLimits, String> {
+        let mut limits = SearchLimits {
+            move_overhead: self.move_overhead,
+            ..SearchLimits::default()
+        };
+        let mut time = None;
+        let mut increment = Duration::ZERO;
+        let mut moves_to_go = None;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || -> Result<u64, String> {
+                let v = args.next().ok_or(format!("expected a value for {}", arg))?;
+                v.parse()
+                    .map_err(|_| format!("invalid value for {}: {}", arg, v))
+            };
+
+            match (*arg, self.board.turn()) {
+                ("depth", _) => limits.depth = Some(value()?.min(u8::MAX as u64) as u8),
+                ("nodes", _) => limits.nodes = Some(value()?),
+                ("movetime", _) => limits.movetime = Some(Duration::from_millis(value()?)),
+                ("wtime", Player::White) | ("btime", Player::Black) => {
+                    time = Some(Duration::from_millis(value()?))
+                }
+                ("winc", Player::White) | ("binc", Player::Black) => {
+                    increment = Duration::from_millis(value()?)
+                }
+                ("wtime", _) | ("btime", _) | ("winc", _) | ("binc", _) => {
+                    value()?;
+                }
+                ("movestogo", _) => moves_to_go = Some(value()?.min(u32::MAX as u64) as u32),
+                ("infinite", _) => limits.infinite = true,
+                ("ponder", _) => limits.ponder = Some(Arc::clone(&self.pondering)),
+                (a, _) => return Err(format!("unknown go argument: {}", a)),
+            }
+        }
+
+        limits.clock = time.map(|time| Clock {
+            time,
+            increment,
+            moves_to_go,
+        });
+
+        Ok(limits)
+    }
+
+    fn start_search(&mut self, limits: SearchLimits) {
+        // Book moves are played straight away, except when the GUI wants the engine to think.
+        let book_move = self
+            .book
+            .as_ref()
+            .filter(|_| self.own_book && !limits.infinite && limits.ponder.is_none())
+            .and_then(|book| book.choose(&self.board, rand::random()));
+        if let Some(m) = book_move {
+            println!("bestmove {}", m);
+            return;
+        }
+
+        let board = self.board.clone();
+        let tt = Arc::clone(&self.tt);
+        let stop = Arc::clone(&self.stop);
+        let options = SearchOptions {
+            // A new seed each move so a limited engine doesn't make the same choices every game.
+            strength: (self.skill_level < MAX_SKILL_LEVEL)
+                .then(|| StrengthLimit::new(self.skill_level, rand::random())),
+            ..self.options.clone()
+        };
+        stop.store(false, Ordering::Relaxed);
+        self.pondering
+            .store(limits.ponder.is_some(), Ordering::Relaxed);
+
+        // A limited search looks at extra lines to choose its move from, which the GUI didn't
+        // ask for and mustn't see.
+        let multi_pv = self.options.multi_pv;
+        self.search_thread = Some(thread::spawn(move || {
+            let result = search::search(&board, &tt, &limits, &options, &stop, &mut |info| {
+                if info.multipv <= multi_pv {
+                    println!("{}", format_info(info));
+                }
+            });
+
+            match (result.best_move, result.ponder_move) {
+                (Some(m), Some(ponder_move)) => println!("bestmove {} ponder {}", m, ponder_move),
+                (Some(m), None) => println!("bestmove {}", m),
+                (None, _) => println!("bestmove 0000"),
+            }
+        }));
+    }
+
+    fn stop_search(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.wait_for_search();
+    }
+
+    /// Commands that change the engine's state must not be sent while it's searching, so they
+    /// wait for the search to finish rather than racing with it.
+    fn wait_for_search(&mut self) {
+        if let Some(handle) = self.search_thread.take() {
+            handle.join().unwrap();
+        }
+    }
+}
+
+/// The `info` line reporting a finished iteration of the search.
+fn format_info(info: &SearchInfo) -> String {
+    let pv: Vec<String> = info.pv.iter().map(|m| m.to_string()).collect();
+
+    format!(
+        "info depth {} seldepth {} multipv {} score {} nodes {} nps {} hashfull {} time {} pv {}",
+        info.depth,
+        info.seldepth,
+        info.multipv,
+        info.score,
+        info.nodes,
+        info.nps,
+        info.hashfull,
+        info.time.as_millis(),
+        pv.join(" ")
+    )
+}
+
+/// Searches the bench positions to `[depth]` and prints the total node count, the signature of
+/// the search to quote when a change isn't meant to change it.
+fn run_bench(args: &[&str], l: &LookupTables) -> Result<(), String> {
+    let depth = match args {
+        [] => DEFAULT_BENCH_DEPTH,
+        [depth] => depth
+            .parse()
+            .map_err(|_| format!("invalid depth: {}", depth))?,
+        _ => return Err(format!("unexpected argument: {}", args[1])),
+    };
+
+    let result = bench::bench(depth, l, &mut |fen, nodes| {
+        println!("info string {} nodes {}", fen.trim(), nodes)
+    });
+    println!("===========================");
+    println!("Total time (ms) : {}", result.time.as_millis());
+    println!("Nodes searched  : {}", result.nodes);
+    println!("Nodes/second    : {}", result.nps());
+    Ok(())
+}
@@ -1,51 +1,389 @@
 use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::process;
+use std::time::Duration;
 
-use chess_rs::core::{Move, PieceKind, Square};
+use chess_rs::book::{Book, BuildOptions};
+use chess_rs::core::{Move, STARTING_POS_FEN};
 use chess_rs::lookup_tables;
-use chess_rs::{board, perft};
+use chess_rs::search::{SearchLimits, SearchOptions};
+use chess_rs::tt::{TranspositionTable, DEFAULT_HASH_MB};
+use chess_rs::{board, dtm, perft, pgn, testsuite};
+
+const USAGE: &str = "usage: main <command> [args]
+
+commands:
+  perft <depth> [fen] [moves] [--detailed]  count the leaf nodes at depth
+  divide <depth> [fen] [moves]              perft split by root move (perftree compatible)
+  fen <fen> [moves]                         validate and normalise a FEN
+  moves [fen] [moves]                       list the legal moves in UCI and SAN
+  show [fen] [moves]                        pretty print the board
+  play [fen] [moves]                        play a game in the terminal
+  tablebase <signature> [dir]               generate distance to mate tables, like KRvK
+  makebook <pgn> <book> [options]           build a Polyglot opening book from a PGN file
+  book <book> [fen] [moves]                 list the moves of a Polyglot book for a position
+  testsuite <epd> [options]                 search the positions of a test suite like WAC
+  help                                      print this message
+
+fen defaults to the starting position, which can also be given as \"\" or startpos. moves is a
+space separated list of UCI moves that are applied to the position before running the command.
+tablebase writes the tables to dir, which defaults to the current directory, along with the tables
+the endgame needs that aren't there yet. makebook keeps the moves of the first --plies plies (20)
+of each game that were played in at least --min-games games (3). testsuite searches each position
+to --depth N or for --movetime MS (1000) with --hash MB and --threads N, checking the answer
+against its bm and am opcodes, and fails if fewer than --min-solved N positions are solved.";
 
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
     let l = lookup_tables::LookupTables::generate();
 
-    perftree(&l);
+    if let Err(e) = run(&args, &l) {
+        eprintln!("error: {}\nrun `main help` for usage", e);
+        process::exit(1);
+    }
+}
+
+fn run(args: &[String], l: &lookup_tables::LookupTables) -> Result<(), String> {
+    let (command, rest) = args.split_first().ok_or("expected a command")?;
+
+    match command.as_str() {
+        "perft" => {
+            let detailed = rest.iter().any(|a| a == "--detailed");
+            let rest: Vec<String> = rest
+                .iter()
+                .filter(|a| *a != "--detailed")
+                .cloned()
+                .collect();
+            let (depth, rest) = parse_depth(&rest)?;
+            let b = parse_position(rest, l)?;
+            if detailed {
+                print_perft_detailed(&perft::perft_detailed(&b, depth));
+            } else {
+                println!("{}", perft::perft(&b, depth));
+            }
+        }
+        "divide" => {
+            let (depth, rest) = parse_depth(rest)?;
+            let b = parse_position(rest, l)?;
+            perft::perft_pp(&b, depth);
+        }
+        "fen" => {
+            if rest.is_empty() {
+                return Err("expected a fen".to_string());
+            }
+            let b = parse_position(rest, l)?;
+            println!("{}", b.fen());
+        }
+        "moves" => {
+            let b = parse_position(rest, l)?;
+            print_moves(b);
+        }
+        "show" => {
+            let b = parse_position(rest, l)?;
+            print_board(&b);
+        }
+        "play" => {
+            let b = parse_position(rest, l)?;
+            play(b).map_err(|e| e.to_string())?;
+        }
+        "tablebase" => {
+            let (signature, rest) = rest.split_first().ok_or("expected a signature")?;
+            let signature = dtm::Signature::try_from(signature.as_str())?;
+            let dir = match rest {
+                [] => ".",
+                [dir] => dir.as_str(),
+                _ => return Err(format!("unexpected argument: {}", rest[1])),
+            };
+            generate_tablebase(&signature, dir, l)?;
+        }
+        "makebook" => make_book(rest, l)?,
+        "testsuite" => run_test_suite(rest, l)?,
+        "book" => {
+            let (path, rest) = rest.split_first().ok_or("expected a book")?;
+            let book = Book::load(path)?;
+            let b = parse_position(rest, l)?;
+            for (m, weight) in book.moves(&b) {
+                println!("{} {}", m, weight);
+            }
+        }
+        "help" | "--help" | "-h" => println!("{}", USAGE),
+        c => return Err(format!("unknown command: {}", c)),
+    }
+
+    Ok(())
 }
 
-fn perftree(l: &lookup_tables::LookupTables) {
-    let args: Vec<String> = env::args().collect();
+fn generate_tablebase(
+    signature: &dtm::Signature,
+    dir: &str,
+    l: &lookup_tables::LookupTables,
+) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|e| format!("cannot create {}: {}", dir, e))?;
+    let mut tables = dtm::DtmTables::load_dir(dir)?;
+
+    let mut result = Ok(());
+    tables.generate(signature, l, &mut |table| {
+        let path = Path::new(dir).join(format!("{}.{}", table.signature(), dtm::EXTENSION));
+        if result.is_ok() {
+            result = table.save(&path);
+            if result.is_ok() {
+                println!("wrote {}", path.display());
+            }
+        }
+    })?;
+    result
+}
 
-    let depth = if let Some(depth_str) = args.get(1) {
-        depth_str.parse().unwrap()
-    } else {
-        6
+fn make_book(args: &[String], l: &lookup_tables::LookupTables) -> Result<(), String> {
+    let mut options = BuildOptions::default();
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || -> Result<u32, String> {
+            let v = args.next().ok_or(format!("expected a value for {}", arg))?;
+            v.parse()
+                .map_err(|_| format!("invalid value for {}: {}", arg, v))
+        };
+        match arg.as_str() {
+            "--plies" => options.max_plies = value()? as usize,
+            "--min-games" => options.min_games = value()?,
+            _ => paths.push(arg),
+        }
+    }
+    let [pgn_path, book_path] = paths[..] else {
+        return Err("expected a pgn file and a book file".to_string());
     };
 
-    let fen = if let Some(fen) = args.get(2) {
-        fen.clone()
-    } else {
-        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string()
+    let text =
+        fs::read_to_string(pgn_path).map_err(|e| format!("cannot read {}: {}", pgn_path, e))?;
+    let games = pgn::parse(&text)?;
+    let book = Book::build(&games, l, &options);
+    book.save(book_path)?;
+    println!(
+        "wrote {} moves from {} games to {}",
+        book.entries().len(),
+        games.len(),
+        book_path
+    );
+    Ok(())
+}
+
+fn run_test_suite(args: &[String], l: &lookup_tables::LookupTables) -> Result<(), String> {
+    let mut limits = SearchLimits::default();
+    let mut options = SearchOptions::default();
+    let mut hash_mb = DEFAULT_HASH_MB;
+    let mut min_solved = 0;
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || -> Result<u64, String> {
+            let v = args.next().ok_or(format!("expected a value for {}", arg))?;
+            v.parse()
+                .map_err(|_| format!("invalid value for {}: {}", arg, v))
+        };
+        match arg.as_str() {
+            "--depth" => limits.depth = Some(value()?.min(u8::MAX as u64) as u8),
+            "--movetime" => limits.movetime = Some(Duration::from_millis(value()?)),
+            "--hash" => hash_mb = value()? as usize,
+            "--threads" => options.threads = value()?.max(1) as usize,
+            "--min-solved" => min_solved = value()? as usize,
+            a if a.starts_with("--") => return Err(format!("unknown option: {}", a)),
+            _ => paths.push(arg),
+        }
+    }
+    let [path] = paths[..] else {
+        return Err("expected an epd file".to_string());
     };
+    if limits.depth.is_none() && limits.movetime.is_none() {
+        limits.movetime = Some(Duration::from_millis(1000));
+    }
 
-    let mut b = board::Board::from_fen(&fen, l).unwrap();
-
-    if let Some(moves) = args.get(3) {
-        for m_str in moves.split(' ') {
-            let m = if m_str.len() == 4 {
-                let source = Square::try_from(&m_str[0..2]).unwrap();
-                let dest = Square::try_from(&m_str[2..]).unwrap();
-                Move(source, dest, None)
-            } else if m_str.len() == 5 {
-                let source = Square::try_from(&m_str[0..2]).unwrap();
-                let dest = Square::try_from(&m_str[2..4]).unwrap();
-                let promotion = Some(PieceKind::try_from(&m_str[4..]).expect("cannot parse move"));
-                Move(source, dest, promotion)
-            } else {
-                panic!("invalid move");
+    let positions = testsuite::load(path, l)?;
+    let tt = TranspositionTable::new(hash_mb);
+    let results = testsuite::run(
+        &positions,
+        &limits,
+        &options,
+        &tt,
+        l,
+        &mut |position, result| {
+            // Answers are given in SAN like the bm and am opcodes.
+            let answer = match (result.best_move, board::Board::from_fen(&position.fen, l)) {
+                (Some(m), Ok(b)) => b.san_line(&[m]).remove(0),
+                _ => "none".to_string(),
             };
+            let found = result.found_after.map_or(String::new(), |t| {
+                format!(", found after {:.2}s", t.as_secs_f64())
+            });
+            println!(
+                "{} {} {} (depth {}, {} nodes, {:.2}s{})",
+                position.id,
+                if result.solved { "solved" } else { "failed" },
+                answer,
+                result.depth,
+                result.nodes,
+                result.time.as_secs_f64(),
+                found
+            );
+        },
+    )?;
 
-            let legal_moves = b.generate_moves(board::Legality::Legal);
-            b.apply_move(m, &legal_moves);
+    let solved = results.iter().filter(|r| r.solved).count();
+    let time: Duration = results.iter().map(|r| r.time).sum();
+    let nodes: u64 = results.iter().map(|r| r.nodes).sum();
+    println!(
+        "solved {}/{} in {:.2}s, {} nodes",
+        solved,
+        results.len(),
+        time.as_secs_f64(),
+        nodes
+    );
+    if solved < min_solved {
+        return Err(format!(
+            "solved {} positions, fewer than {}",
+            solved, min_solved
+        ));
+    }
+    Ok(())
+}
+
+fn parse_depth(args: &[String]) -> Result<(u8, &[String]), String> {
+    let (depth_str, rest) = args.split_first().ok_or("expected a depth")?;
+    let depth = depth_str
+        .parse()
+        .map_err(|_| format!("invalid depth: {}", depth_str))?;
+
+    Ok((depth, rest))
+}
+
+/// Builds a board from optional `[fen] [moves]` arguments.
+fn parse_position<'a>(
+    args: &[String],
+    l: &'a lookup_tables::LookupTables,
+) -> Result<board::Board<'a>, String> {
+    if args.len() > 2 {
+        return Err(format!("unexpected argument: {}", args[2]));
+    }
+
+    let fen = match args.first().map(|f| f.as_str()) {
+        None | Some("") | Some("startpos") => STARTING_POS_FEN,
+        Some(f) => f,
+    };
+    let mut b = board::Board::from_fen(fen, l).map_err(|e| format!("invalid fen: {}", e))?;
+
+    if let Some(moves) = args.get(1) {
+        for m_str in moves.split_whitespace() {
+            apply_uci_move(&mut b, m_str)?;
         }
     }
 
-    perft::perft_pp(&b, depth);
+    Ok(b)
+}
+
+fn apply_uci_move(b: &mut board::Board, m_str: &str) -> Result<(), String> {
+    let m = Move::try_from(m_str)?;
+    let legal_moves = b.generate_moves(board::Legality::Legal);
+    if !legal_moves.contains(&m) {
+        return Err(format!("illegal move: {}", m_str));
+    }
+    b.apply_move(m, &legal_moves);
+
+    Ok(())
+}
+
+fn print_perft_detailed(stats: &[perft::PerftStats]) {
+    println!(
+        "{:>5} {:>14} {:>12} {:>10} {:>10} {:>10} {:>10} {:>12} {:>10} {:>10}",
+        "depth",
+        "nodes",
+        "captures",
+        "e.p.",
+        "castles",
+        "promotions",
+        "checks",
+        "disc checks",
+        "dbl checks",
+        "checkmates"
+    );
+    for (i, s) in stats.iter().enumerate() {
+        println!(
+            "{:>5} {:>14} {:>12} {:>10} {:>10} {:>10} {:>10} {:>12} {:>10} {:>10}",
+            i + 1,
+            s.nodes,
+            s.captures,
+            s.en_passants,
+            s.castles,
+            s.promotions,
+            s.checks,
+            s.discovered_checks,
+            s.double_checks,
+            s.checkmates
+        );
+    }
+}
+
+fn print_moves(mut b: board::Board) {
+    let legal_moves = b.generate_moves(board::Legality::Legal);
+    for m in legal_moves.iter() {
+        b.apply_move(*m, &legal_moves);
+        println!("{:<6} {}", m.to_string(), b.san_moves.last().unwrap());
+        b.undo_move();
+    }
+}
+
+fn print_board(b: &board::Board) {
+    println!("{}", b);
+    println!("fen: {}", b.fen());
+    println!("turn: {:?}", b.turn());
+    if b.is_in_check() {
+        println!(
+            "checkers: {}",
+            b.checkers()
+                .map(|s| s.to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+    }
+}
+
+fn play(mut b: board::Board) -> io::Result<()> {
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+
+    loop {
+        print_board(&b);
+
+        if let Some(outcome) = b.outcome() {
+            println!("\n{}", outcome);
+            return Ok(());
+        }
+
+        print!("\n{:?} to move (uci move, moves, undo or quit): ", b.turn());
+        io::stdout().flush()?;
+
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => return Ok(()),
+        };
+
+        match line.trim() {
+            "" => {}
+            "quit" | "exit" => return Ok(()),
+            "moves" => print_moves(b.shallow_clone()),
+            "undo" => {
+                if b.moves.is_empty() {
+                    println!("no moves to undo");
+                } else {
+                    b.undo_move();
+                }
+            }
+            m_str => {
+                if let Err(e) = apply_uci_move(&mut b, m_str) {
+                    println!("{}", e);
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,175 @@
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::process;
+use std::time::Duration;
+
+use chess_rs::lookup_tables::LookupTables;
+use chess_rs::selfplay::{self, EngineConfig, MatchOptions, MoveLimits};
+use chess_rs::sprt::{Sprt, SprtVerdict};
+
+const USAGE: &str = "usage: selfplay --engine1 <engine> --engine2 <engine> [options]
+
+Plays a match between two engines, each opening being played twice with the colours swapped, and
+prints the score of the first engine, the Elo difference and the result of an SPRT as it goes.
+
+engines are given as `internal[:Name=Value,...]` for this crate's search with its UCI options set,
+such as `internal:NullMove=false,Hash=64`, or `uci:<path>[,Name=Value,...]` for a UCI engine.
+The pseudo option `name` sets the name the engine is given in the PGN.
+
+options:
+  --games <n>          the number of games to play (default 100)
+  --openings <file>    a PGN file of openings or an EPD file of positions to start from
+  --depth <n>          search each move to depth n
+  --nodes <n>          search each move for n nodes
+  --movetime <ms>      search each move for ms milliseconds
+  --max-plies <n>      adjudicate games as draws after n plies (default 400)
+  --concurrency <n>    the number of games to play at once (default 1)
+  --pgn <file>         write the games to file
+  --sprt <elo0,elo1[,alpha,beta]>
+                       stop when the first engine is found to be elo0 or elo1 stronger, with
+                       false positive and negative rates alpha and beta (default 0.05)
+
+Without a depth, nodes or movetime limit each move is searched to depth 6.";
+
+const DEFAULT_DEPTH: u8 = 6;
+
+struct Args {
+    engines: [Option<EngineConfig>; 2],
+    openings: Option<String>,
+    pgn: Option<String>,
+    options: MatchOptions,
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty()
+        || args
+            .iter()
+            .any(|a| a == "help" || a == "--help" || a == "-h")
+    {
+        println!("{}", USAGE);
+        return;
+    }
+
+    if let Err(e) = parse_args(&args).and_then(|args| run(&args)) {
+        eprintln!("error: {}\nrun `selfplay help` for usage", e);
+        process::exit(1);
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Args, String> {
+    let mut parsed = Args {
+        engines: [None, None],
+        openings: None,
+        pgn: None,
+        options: MatchOptions::default(),
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("expected a value for {}", arg));
+        let options = &mut parsed.options;
+        match arg.as_str() {
+            "--engine1" => parsed.engines[0] = Some(EngineConfig::try_from(value()?.as_str())?),
+            "--engine2" => parsed.engines[1] = Some(EngineConfig::try_from(value()?.as_str())?),
+            "--games" => options.games = parse_value(arg, value()?)?,
+            "--openings" => parsed.openings = Some(value()?.clone()),
+            "--depth" => options.limits.depth = Some(parse_value(arg, value()?)?),
+            "--nodes" => options.limits.nodes = Some(parse_value(arg, value()?)?),
+            "--movetime" => {
+                options.limits.movetime = Some(Duration::from_millis(parse_value(arg, value()?)?))
+            }
+            "--max-plies" => options.max_plies = parse_value(arg, value()?)?,
+            "--concurrency" => options.concurrency = parse_value(arg, value()?)?,
+            "--pgn" => parsed.pgn = Some(value()?.clone()),
+            "--sprt" => options.sprt = Some(Sprt::try_from(value()?.as_str())?),
+            a => return Err(format!("unknown option: {}", a)),
+        }
+    }
+
+    if parsed.engines.iter().any(|e| e.is_none()) {
+        return Err("expected --engine1 and --engine2".to_string());
+    }
+    if parsed.options.limits == MoveLimits::default() {
+        parsed.options.limits.depth = Some(DEFAULT_DEPTH);
+    }
+    Ok(parsed)
+}
+
+fn parse_value<T: std::str::FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {}: {}", option, value))
+}
+
+fn run(args: &Args) -> Result<(), String> {
+    let l = LookupTables::generate();
+    let engines = [
+        args.engines[0].clone().unwrap(),
+        args.engines[1].clone().unwrap(),
+    ];
+    let openings = match &args.openings {
+        Some(path) => selfplay::load_openings(path, &l)?,
+        None => Vec::new(),
+    };
+    let mut pgn = match &args.pgn {
+        Some(path) => {
+            Some(File::create(path).map_err(|e| format!("cannot create {}: {}", path, e))?)
+        }
+        None => None,
+    };
+    eprintln!(
+        "{} vs {}, {} games",
+        engines[0].name(),
+        engines[1].name(),
+        args.options.games
+    );
+
+    let mut write_error = None;
+    let score = selfplay::run_match(
+        &engines,
+        &openings,
+        &args.options,
+        &l,
+        &mut |game, score| {
+            if let Some(file) = &mut pgn {
+                if let Err(e) = writeln!(file, "{}", game) {
+                    write_error.get_or_insert(e.to_string());
+                }
+            }
+
+            let mut line = format!(
+                "score of {} vs {}: {}",
+                engines[0].name(),
+                engines[1].name(),
+                score
+            );
+            if let Some((elo, margin)) = score.elo() {
+                line.push_str(&format!(", elo {:.1} +/- {:.1}", elo, margin));
+            }
+            if let Some(sprt) = &args.options.sprt {
+                let (lower, upper) = sprt.bounds();
+                line.push_str(&format!(
+                    ", llr {:.2} ({:.2}, {:.2})",
+                    sprt.llr(score),
+                    lower,
+                    upper
+                ));
+            }
+            println!("{}", line);
+        },
+    )?;
+    if let Some(e) = write_error {
+        return Err(format!("cannot write the games: {}", e));
+    }
+
+    if let Some(sprt) = &args.options.sprt {
+        match sprt.verdict(&score) {
+            SprtVerdict::AcceptH0 => println!("sprt: H0 accepted"),
+            SprtVerdict::AcceptH1 => println!("sprt: H1 accepted"),
+            SprtVerdict::Continue => println!("sprt: inconclusive"),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,150 @@
+use std::env;
+use std::fs;
+use std::process;
+use std::thread;
+
+use chess_rs::board::Board;
+use chess_rs::eval_params::DEFAULT_PARAMS;
+use chess_rs::lookup_tables::LookupTables;
+use chess_rs::tune::{self, TuningPosition};
+
+const USAGE: &str = "usage: tune <positions> [options]
+
+Tunes the evaluation parameters by minimising the error between the evaluation of labelled
+positions and the results of their games (Texel tuning).
+
+positions is a file with a FEN and the game's result on each line, such as
+`<fen> [1-0]`, `<fen> \"1/2-1/2\"` or `<fen> c9 \"0-1\";`.
+
+options:
+  --quiescence     resolve captures with a quiescence search before tuning
+  --passes <n>     stop after n passes over the parameters (default 100)
+  --step <n>       how far to move a parameter at a time (default 1)
+  --k <k>          the sigmoid scaling constant, found from the positions if not given
+  --threads <n>    the number of threads to work out the error with (default all)
+  --out <file>     write the tuned eval_params.rs to file after each pass instead of printing
+                   it at the end";
+
+struct Args {
+    positions: String,
+    quiescence: bool,
+    passes: usize,
+    step: i32,
+    k: Option<f64>,
+    threads: usize,
+    out: Option<String>,
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty()
+        || args
+            .iter()
+            .any(|a| a == "help" || a == "--help" || a == "-h")
+    {
+        println!("{}", USAGE);
+        return;
+    }
+
+    if let Err(e) = parse_args(&args).and_then(|args| run(&args)) {
+        eprintln!("error: {}\nrun `tune help` for usage", e);
+        process::exit(1);
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Args, String> {
+    let mut parsed = Args {
+        positions: String::new(),
+        quiescence: false,
+        passes: 100,
+        step: 1,
+        k: None,
+        threads: thread::available_parallelism().map_or(1, |n| n.get()),
+        out: None,
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("expected a value for {}", arg));
+        match arg.as_str() {
+            "--quiescence" => parsed.quiescence = true,
+            "--passes" => parsed.passes = parse_value(arg, value()?)?,
+            "--step" => parsed.step = parse_value(arg, value()?)?,
+            "--k" => parsed.k = Some(parse_value(arg, value()?)?),
+            "--threads" => parsed.threads = parse_value(arg, value()?)?,
+            "--out" => parsed.out = Some(value()?.clone()),
+            a if a.starts_with("--") => return Err(format!("unknown option: {}", a)),
+            a if parsed.positions.is_empty() => parsed.positions = a.to_string(),
+            a => return Err(format!("unexpected argument: {}", a)),
+        }
+    }
+
+    if parsed.positions.is_empty() {
+        return Err("expected a positions file".to_string());
+    }
+    Ok(parsed)
+}
+
+fn parse_value<T: std::str::FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {}: {}", option, value))
+}
+
+fn run(args: &Args) -> Result<(), String> {
+    let l = LookupTables::generate();
+    let contents = fs::read_to_string(&args.positions)
+        .map_err(|e| format!("cannot read {}: {}", args.positions, e))?;
+
+    let mut positions = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (fen, result) = tune::parse_line(line).map_err(|e| format!("line {}: {}", i + 1, e))?;
+        let b = Board::from_fen(&fen, &l).map_err(|e| format!("line {}: {}", i + 1, e))?;
+        let pieces = if args.quiescence {
+            tune::quiet_pieces(&b, &DEFAULT_PARAMS)
+        } else {
+            b.pieces()
+        };
+        positions.push(TuningPosition { pieces, result });
+    }
+    eprintln!("loaded {} positions", positions.len());
+
+    let k = match args.k {
+        Some(k) => k,
+        None => tune::find_k(&positions, &DEFAULT_PARAMS, args.threads),
+    };
+    eprintln!(
+        "k: {:.4}, initial error: {:.6}",
+        k,
+        tune::error(&positions, &DEFAULT_PARAMS, k, args.threads)
+    );
+
+    let mut write_error = None;
+    let tuned = tune::tune(
+        &positions,
+        &DEFAULT_PARAMS,
+        k,
+        args.step,
+        args.passes,
+        args.threads,
+        &mut |pass, error, params| {
+            eprintln!("pass {}: error {:.6}", pass, error);
+            if let Some(out) = &args.out {
+                if let Err(e) = fs::write(out, tune::to_rust_source(params)) {
+                    write_error = Some(format!("cannot write {}: {}", out, e));
+                }
+            }
+        },
+    );
+
+    if let Some(e) = write_error {
+        return Err(e);
+    }
+    if args.out.is_none() {
+        print!("{}", tune::to_rust_source(&tuned));
+    }
+    Ok(())
+}
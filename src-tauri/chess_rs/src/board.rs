@@ -1,14 +1,21 @@
+use std::sync::Arc;
 use std::{cmp, fmt};
 
 use crate::bitboard::BitBoard;
+use crate::chess960;
 use crate::core::{
-    File, Move, Piece, PieceKind, Player, Rank, Square, BLACK_PIECES, FILES, MAX_MOVES, PIECES,
-    RANKS, RANK_1, RANK_8, WHITE_PIECES,
+    File, Move, Piece, PieceKind, Player, Rank, Square, BLACK_PIECES, FILES, LIGHT_SQUARES,
+    MAX_MOVES, PIECES, RANKS, RANK_1, RANK_8, SQUARES, WHITE_PIECES,
 };
+use crate::epd::{Epd, Operand};
+use crate::fen::{Fen, FenError};
 use crate::lookup_tables;
+use crate::nnue::{Network, NnueState};
+use crate::zobrist;
 
 use bitflags::bitflags;
-use regex::Regex;
+use serde::de::{self, DeserializeSeed, Deserializer};
+use serde::{Deserialize, Serialize, Serializer};
 
 bitflags! {
     #[repr(transparent)]
@@ -21,6 +28,17 @@ bitflags! {
     }
 }
 
+impl Castling {
+    /// The position of a single castling right in arrays indexed by right, like the castling
+    /// rooks.
+    pub fn index(self) -> usize {
+        self.bits().trailing_zeros() as usize
+    }
+}
+
+/// The rook squares of the castling rights in standard chess, indexed by `Castling::index`.
+pub const STANDARD_CASTLING_ROOKS: [Square; 4] = [Square::H1, Square::A1, Square::H8, Square::A8];
+
 impl fmt::Display for Castling {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         if self.is_empty() {
@@ -71,6 +89,31 @@ pub enum Legality {
     PseudoLegal,
 }
 
+/// How a game ended. Games played on a `Board` with `apply_move` keep the move counters and the
+/// history the draws are worked out from.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Outcome {
+    /// The given player delivered checkmate.
+    Checkmate(Player),
+    Stalemate,
+    FiftyMoveRule,
+    ThreefoldRepetition,
+    InsufficientMaterial,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Outcome::Checkmate(Player::White) => write!(f, "white wins by checkmate"),
+            Outcome::Checkmate(Player::Black) => write!(f, "black wins by checkmate"),
+            Outcome::Stalemate => write!(f, "draw by stalemate"),
+            Outcome::FiftyMoveRule => write!(f, "draw by the fifty move rule"),
+            Outcome::ThreefoldRepetition => write!(f, "draw by threefold repetition"),
+            Outcome::InsufficientMaterial => write!(f, "draw by insufficient material"),
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 struct BoardState {
     turn: Player,
@@ -80,6 +123,9 @@ struct BoardState {
     en_passant: Option<Square>,
     half_moves: u32,
     full_moves: u32,
+    hash: u64,
+    /// The hash of just the pawns, which keys the pawn hash table.
+    pawn_hash: u64,
 
     // State to help with move generation
     checkers: BitBoard,
@@ -87,13 +133,111 @@ struct BoardState {
     pinned_pieces: BitBoard,
 }
 
-#[derive(Debug)]
+impl BoardState {
+    /// Hashes the position from scratch. `Board` updates the hash incrementally as moves are
+    /// made so this is only needed when setting up a position.
+    fn compute_hash(&self) -> u64 {
+        let mut hash = zobrist::KEYS.castling[self.castling.bits() as usize];
+        for p in PIECES {
+            for s in self.piece_bbs[p as usize] {
+                hash ^= zobrist::KEYS.piece(p, s);
+            }
+        }
+        if let Some(s) = self.en_passant {
+            hash ^= zobrist::KEYS.en_passant[File::from(s) as usize];
+        }
+        if self.turn == Player::Black {
+            hash ^= zobrist::KEYS.black_to_move;
+        }
+
+        hash
+    }
+
+    /// The key of the position in Polyglot opening books. Unlike the hash, the en passant file is
+    /// only part of the key when a pawn of the player to move is next to the pawn that can be
+    /// taken.
+    fn compute_polyglot_key(&self) -> u64 {
+        let keys = &zobrist::POLYGLOT_KEYS;
+        let mut key = 0;
+        for p in PIECES {
+            let kind = match PieceKind::from(p) {
+                PieceKind::Pawn => 0,
+                PieceKind::Knight => 1,
+                PieceKind::Bishop => 2,
+                PieceKind::Rook => 3,
+                PieceKind::Queen => 4,
+                PieceKind::King => 5,
+            };
+            let index = 2 * kind + (Player::from(p) == Player::White) as usize;
+            for s in self.piece_bbs[p as usize] {
+                key ^= keys[64 * index + s as usize];
+            }
+        }
+
+        for (i, right) in [
+            Castling::WHITE_K,
+            Castling::WHITE_Q,
+            Castling::BLACK_K,
+            Castling::BLACK_Q,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            if self.castling.contains(right) {
+                key ^= keys[768 + i];
+            }
+        }
+
+        if let Some(s) = self.en_passant {
+            let (pawn, rank) = match self.turn {
+                Player::White => (Piece::WhitePawn, Rank::R5),
+                Player::Black => (Piece::BlackPawn, Rank::R4),
+            };
+            let file = File::from(s) as usize;
+            let can_capture = [file.wrapping_sub(1), file + 1]
+                .into_iter()
+                .filter(|f| *f < 8)
+                .any(|f| {
+                    let s = Square::from((FILES[f], rank));
+                    self.piece_bbs[pawn as usize].get_bit(s)
+                });
+            if can_capture {
+                key ^= keys[772 + file];
+            }
+        }
+
+        if self.turn == Player::White {
+            key ^= keys[780];
+        }
+
+        key
+    }
+
+    /// Hashes the pawns from scratch.
+    fn compute_pawn_hash(&self) -> u64 {
+        let mut hash = 0;
+        for p in [Piece::WhitePawn, Piece::BlackPawn] {
+            for s in self.piece_bbs[p as usize] {
+                hash ^= zobrist::KEYS.piece(p, s);
+            }
+        }
+
+        hash
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct Board<'a> {
     state: BoardState,
     previous_states: Vec<BoardState>,
     pub moves: Vec<Move>,
     pub san_moves: Vec<String>,
     lookup_tables: &'a lookup_tables::LookupTables,
+    // The castling rook squares don't change during a game so they aren't part of the state.
+    castling_rooks: [Square; 4],
+    chess960: bool,
+    /// The accumulators of the network used to evaluate the position, if there is one.
+    nnue: Option<NnueState>,
 }
 
 impl<'a> Board<'a> {
@@ -107,6 +251,8 @@ impl<'a> Board<'a> {
                 en_passant: None,
                 half_moves: 0,
                 full_moves: 0,
+                hash: 0,
+                pawn_hash: 0,
                 checkers: BitBoard::new(),
                 attacked_squares: BitBoard::new(),
                 pinned_pieces: BitBoard::new(),
@@ -115,97 +261,154 @@ impl<'a> Board<'a> {
             moves: Vec::new(),
             san_moves: Vec::new(),
             lookup_tables: l,
+            castling_rooks: STANDARD_CASTLING_ROOKS,
+            chess960: false,
+            nnue: None,
         }
     }
 
-    pub fn from_fen(fen: &str, l: &'a lookup_tables::LookupTables) -> Result<Self, String> {
-        let mut b = Board::new(l);
-
-        let re = Regex::new(
-            r"^([rnbqkpRNBQKP/1-8]+)\s+(w|b)\s+([KQkq]+|-)\s+(-|[a-h][36])\s*(\d*)\s*(\d*)\s*$",
-        )
-        .unwrap();
+    pub fn from_fen(fen: &str, l: &'a lookup_tables::LookupTables) -> Result<Self, FenError> {
+        Board::from_fen_fields(&Fen::parse(fen)?, l)
+    }
 
-        let caps = re.captures(fen).ok_or("invalid fen")?;
+    /// The position described by the fields of a FEN, which is checked the same way as by
+    /// `from_fen`.
+    pub fn from_fen_fields(
+        fen: &Fen,
+        l: &'a lookup_tables::LookupTables,
+    ) -> Result<Self, FenError> {
+        let mut b = Board::new(l);
 
-        let mut current_index: u8 = 0;
-        for (i, row) in caps
-            .get(1)
-            .ok_or("expected piece placement")?
-            .as_str()
-            .split('/')
-            .rev()
-            .enumerate()
-        {
-            if i > 7 {
-                return Err("too many ranks".to_string());
-            }
-            for c in row.chars() {
-                match c {
-                    'R' | 'N' | 'B' | 'Q' | 'K' | 'P' | 'r' | 'n' | 'b' | 'q' | 'k' | 'p' => {
-                        let piece = Piece::try_from(c).unwrap();
-                        let bb_index = piece as usize;
-                        let player = Player::from(piece);
-                        let occ_index = player as usize;
-                        let current_square = Square::try_from(current_index).unwrap();
-                        b.state.piece_bbs[bb_index].set_bit(current_square);
-                        b.state.occ_bbs[occ_index].set_bit(current_square);
-                        b.state.occ_bbs[2].set_bit(current_square);
-                        current_index += 1;
-                    }
-                    '1' | '2' | '3' | '4' | '5' | '6' | '7' | '8' => {
-                        current_index += c.to_digit(10).unwrap() as u8;
-                    }
-                    x => panic!("unexpected piece placement character: {}", x),
-                };
+        for (square, piece) in SQUARES.iter().zip(fen.pieces) {
+            if let Some(p) = piece {
+                b.state.piece_bbs[p as usize].set_bit(*square);
+                b.state.occ_bbs[Player::from(p) as usize].set_bit(*square);
+                b.state.occ_bbs[2].set_bit(*square);
             }
         }
 
-        b.state.turn =
-            Player::try_from(caps.get(2).ok_or("expected active color")?.as_str()).unwrap();
+        b.state.turn = fen.turn;
+        b.state.castling = fen.castling;
+        b.castling_rooks = fen.castling_rooks;
+        b.chess960 = fen.chess960;
+        b.state.en_passant = fen.en_passant;
+        b.state.half_moves = fen.half_moves;
+        b.state.full_moves = fen.full_moves;
+        b.finish_set_up()
+    }
 
-        b.state.castling = caps
-            .get(3)
-            .ok_or("expected castling rights")?
-            .as_str()
-            .chars()
-            .fold(Castling::empty(), |rights, c| {
-                rights | Castling::try_from(c).unwrap()
-            });
+    /// The fields of the FEN of the position, without going through a string.
+    pub fn fen_fields(&self) -> Fen {
+        let mut pieces = [None; 64];
+        for (square, piece) in SQUARES.iter().zip(pieces.iter_mut()) {
+            *piece = self.get_piece(*square);
+        }
+        Fen {
+            pieces,
+            turn: self.state.turn,
+            castling: self.state.castling,
+            castling_rooks: self.castling_rooks,
+            chess960: self.chess960,
+            en_passant: self.state.en_passant,
+            half_moves: self.state.half_moves,
+            full_moves: self.state.full_moves,
+        }
+    }
+
+    /// The position of an EPD record, with the move counters of its `hmvc` and `fmvn` operations.
+    pub fn from_epd(epd: &Epd, l: &'a lookup_tables::LookupTables) -> Result<Self, FenError> {
+        Board::from_fen(&epd.fen(), l)
+    }
 
-        b.state.en_passant = match caps.get(4).ok_or("expected en passant sqaure")?.as_str() {
-            "-" => None,
-            s => Some(Square::try_from(s)?),
+    /// The position as an EPD record, with the move counters as `hmvc` and `fmvn` operations.
+    pub fn epd(&self) -> Epd {
+        let fen = self.fen();
+        let fields: Vec<&str> = fen.split(' ').collect();
+        let mut epd = Epd {
+            position: fields[..4].join(" "),
+            operations: Vec::new(),
         };
+        let counter = |n: u32| vec![Operand::Token(n.to_string())];
+        epd.set_operation("hmvc", counter(self.state.half_moves));
+        epd.set_operation("fmvn", counter(self.state.full_moves));
+        epd
+    }
 
-        b.state.half_moves = caps
-            .get(5)
-            .ok_or("expected half move count")?
-            .as_str()
-            .parse()
-            .unwrap_or(0);
+    /// The position with `pieces` and `turn` to move, without castling rights or an en passant
+    /// square.
+    pub fn from_pieces(
+        pieces: &[(Piece, Square)],
+        turn: Player,
+        l: &'a lookup_tables::LookupTables,
+    ) -> Result<Self, FenError> {
+        let mut b = Board::new(l);
+        for (p, s) in pieces {
+            b.state.piece_bbs[*p as usize].set_bit(*s);
+            b.state.occ_bbs[Player::from(*p) as usize].set_bit(*s);
+            b.state.occ_bbs[2].set_bit(*s);
+        }
 
-        b.state.full_moves = caps
-            .get(6)
-            .ok_or("expected full move count")?
-            .as_str()
-            .parse()
-            .unwrap_or(0);
+        b.state.turn = turn;
+        b.state.castling = Castling::empty();
+        b.state.full_moves = 1;
+        b.finish_set_up()
+    }
 
-        let (checkers, attacked_squares, pinned_pieces) = b.get_check_info();
-        b.state.checkers = checkers;
-        b.state.attacked_squares = attacked_squares;
-        b.state.pinned_pieces = pinned_pieces;
+    /// Works out the state that follows from the pieces and checks the position is valid.
+    fn finish_set_up(mut self) -> Result<Self, FenError> {
+        self.state.hash = self.state.compute_hash();
+        self.state.pawn_hash = self.state.compute_pawn_hash();
 
-        if let Some(errors) = b.is_valid() {
-            return Err(errors.join("\n"));
+        let (checkers, attacked_squares, pinned_pieces) = self.get_check_info();
+        self.state.checkers = checkers;
+        self.state.attacked_squares = attacked_squares;
+        self.state.pinned_pieces = pinned_pieces;
+
+        if let Some(errors) = self.is_valid() {
+            return Err(FenError::InvalidPosition(errors));
         }
 
-        Ok(b)
+        Ok(self)
     }
 
-    // FIXME: proper error handling
+    /// The FEN of the position. Chess960 positions use X-FEN castling rights.
     pub fn fen(&self) -> String {
+        self.format_fen(false)
+    }
+
+    /// The FEN of the position before the moves recorded in `moves` were played.
+    pub fn start_fen(&self) -> String {
+        if self.moves.is_empty() {
+            return self.fen();
+        }
+        let mut start = self.shallow_clone();
+        start.nnue = None;
+        start.state = self.start_state();
+        start.fen()
+    }
+
+    /// The position before the moves recorded in `moves` were played, without any history.
+    pub fn start_position(&self) -> Board<'a> {
+        let mut start = self.shallow_clone();
+        if !self.moves.is_empty() {
+            start.state = self.start_state();
+            start.set_network(self.network().cloned());
+        }
+        start
+    }
+
+    fn start_state(&self) -> BoardState {
+        self.previous_states[self.previous_states.len() - self.moves.len()]
+    }
+
+    /// The FEN of the position with Shredder-FEN castling rights, which name the file of the
+    /// castling rook.
+    pub fn shredder_fen(&self) -> String {
+        self.format_fen(true)
+    }
+
+    // FIXME: proper error handling
+    fn format_fen(&self, shredder: bool) -> String {
         let mut fen_string = String::new();
 
         for rank in RANKS.iter().rev() {
@@ -238,7 +441,7 @@ impl<'a> Board<'a> {
         fen_string.push_str(&self.state.turn.to_string());
 
         fen_string.push(' ');
-        fen_string.push_str(&self.state.castling.to_string());
+        fen_string.push_str(&self.castling_fen(shredder));
 
         match self.state.en_passant {
             Some(s) => {
@@ -257,6 +460,49 @@ impl<'a> Board<'a> {
         fen_string
     }
 
+    fn castling_fen(&self, shredder: bool) -> String {
+        if self.state.castling.is_empty() || (!shredder && !self.chess960) {
+            return self.state.castling.to_string();
+        }
+
+        self.state
+            .castling
+            .iter()
+            .map(|right| {
+                let rook_square = self.castling_rooks[right.index()];
+                let rook_file = File::from(rook_square) as u8;
+                let is_white = right.intersects(Castling::WHITE_K | Castling::WHITE_Q);
+                let is_kingside = right.intersects(Castling::WHITE_K | Castling::BLACK_K);
+
+                // X-FEN only names the file when there's another rook further out on the same
+                // side of the king.
+                let rook_piece = if is_white {
+                    Piece::WhiteRook
+                } else {
+                    Piece::BlackRook
+                };
+                let is_outermost = self.state.piece_bbs[rook_piece as usize]
+                    .filter(|s| Rank::from(*s) == Rank::from(rook_square))
+                    .all(|s| {
+                        let file = File::from(s) as u8;
+                        if is_kingside {
+                            file <= rook_file
+                        } else {
+                            file >= rook_file
+                        }
+                    });
+
+                if !shredder && is_outermost {
+                    right.to_string()
+                } else if is_white {
+                    File::from(rook_square).to_string().to_uppercase()
+                } else {
+                    File::from(rook_square).to_string()
+                }
+            })
+            .collect()
+    }
+
     pub fn start_pos(l: &'a lookup_tables::LookupTables) -> Self {
         Board::from_fen(
             "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
@@ -265,13 +511,16 @@ impl<'a> Board<'a> {
         .unwrap()
     }
 
-    pub fn shallow_clone(&self) -> Board {
+    pub fn shallow_clone(&self) -> Board<'a> {
         Board {
             state: self.state,
             previous_states: Vec::new(),
             moves: Vec::new(),
             san_moves: Vec::new(),
             lookup_tables: self.lookup_tables,
+            castling_rooks: self.castling_rooks,
+            chess960: self.chess960,
+            nnue: self.nnue.as_ref().map(NnueState::shallow_clone),
         }
     }
 
@@ -283,6 +532,114 @@ impl<'a> Board<'a> {
         }
     }
 
+    /// The Chess960 start position with Scharnagl number `id`, where 518 is the standard start
+    /// position. Castling moves are always encoded as king captures rook.
+    pub fn start_pos_960(id: u16, l: &'a lookup_tables::LookupTables) -> Option<Self> {
+        let fen = chess960::start_pos_fen(id)?;
+        let mut b = Board::from_fen(&fen, l).unwrap();
+        b.chess960 = true;
+        Some(b)
+    }
+
+    pub fn is_chess960(&self) -> bool {
+        self.chess960
+    }
+
+    /// Switches between standard and Chess960 castling move encoding, for example when a GUI
+    /// sets `UCI_Chess960`. Positions with castling rights that only exist in Chess960 always use
+    /// Chess960 encoding.
+    pub fn set_chess960(&mut self, chess960: bool) {
+        let needs_chess960 = self.state.castling.iter().any(|right| {
+            let (king, king_square) = if right.intersects(Castling::WHITE_K | Castling::WHITE_Q) {
+                (Piece::WhiteKing, Square::E1)
+            } else {
+                (Piece::BlackKing, Square::E8)
+            };
+            self.get_piece(king_square) != Some(king)
+                || self.castling_rooks[right.index()] != STANDARD_CASTLING_ROOKS[right.index()]
+        });
+        self.chess960 = chess960 || needs_chess960;
+    }
+
+    pub fn turn(&self) -> Player {
+        self.state.turn
+    }
+
+    pub fn en_passant(&self) -> Option<Square> {
+        self.state.en_passant
+    }
+
+    /// Whether either player still has a castling right.
+    pub fn can_castle(&self) -> bool {
+        !self.state.castling.is_empty()
+    }
+
+    pub fn half_moves(&self) -> u32 {
+        self.state.half_moves
+    }
+
+    pub fn full_moves(&self) -> u32 {
+        self.state.full_moves
+    }
+
+    pub fn piece_bb(&self, p: Piece) -> BitBoard {
+        self.state.piece_bbs[p as usize]
+    }
+
+    pub fn occupancy(&self, player: Player) -> BitBoard {
+        self.state.occ_bbs[player as usize]
+    }
+
+    /// The kind of piece `m` captures, including en passant captures.
+    pub fn captured_piece_kind(&self, m: &Move) -> Option<PieceKind> {
+        let moved_piece = self.get_piece(m.0)?;
+        if PieceKind::from(moved_piece) == PieceKind::Pawn && Some(m.1) == self.state.en_passant {
+            return Some(PieceKind::Pawn);
+        }
+
+        match self.get_piece(m.1) {
+            Some(p) if Player::from(p) != self.state.turn => Some(PieceKind::from(p)),
+            _ => None,
+        }
+    }
+
+    pub fn is_capture(&self, m: &Move) -> bool {
+        self.captured_piece_kind(m).is_some()
+    }
+
+    /// The pieces of both players that attack `s` when the board is occupied by `occ`. Sliding
+    /// pieces are found through squares missing from `occ`, which makes x-rays easy to find.
+    pub fn attackers_to(&self, s: Square, occ: BitBoard) -> BitBoard {
+        let bbs = &self.state.piece_bbs;
+        let rooks = bbs[Piece::WhiteRook as usize]
+            | bbs[Piece::BlackRook as usize]
+            | bbs[Piece::WhiteQueen as usize]
+            | bbs[Piece::BlackQueen as usize];
+        let bishops = bbs[Piece::WhiteBishop as usize]
+            | bbs[Piece::BlackBishop as usize]
+            | bbs[Piece::WhiteQueen as usize]
+            | bbs[Piece::BlackQueen as usize];
+        let knights = bbs[Piece::WhiteKnight as usize] | bbs[Piece::BlackKnight as usize];
+        let kings = bbs[Piece::WhiteKing as usize] | bbs[Piece::BlackKing as usize];
+
+        let l = self.lookup_tables;
+        (l.lookup_moves(Piece::WhiteRook, s, occ) & rooks)
+            | (l.lookup_moves(Piece::WhiteBishop, s, occ) & bishops)
+            | (l.lookup_moves(Piece::WhiteKnight, s, occ) & knights)
+            | (l.lookup_moves(Piece::WhiteKing, s, occ) & kings)
+            // A white pawn attacks s from the squares a black pawn on s would attack.
+            | (l.lookup_capture_moves(Piece::BlackPawn, s) & bbs[Piece::WhitePawn as usize])
+            | (l.lookup_capture_moves(Piece::WhitePawn, s) & bbs[Piece::BlackPawn as usize])
+    }
+
+    pub fn checkers(&self) -> BitBoard {
+        self.state.checkers
+    }
+
+    pub fn is_in_check(&self) -> bool {
+        !self.state.checkers.is_empty()
+    }
+
     pub fn pieces(&self) -> Vec<(Piece, Square)> {
         PIECES.iter().fold(Vec::new(), |mut acc, p| {
             for s in self.state.piece_bbs[*p as usize] {
@@ -501,88 +858,97 @@ impl<'a> Board<'a> {
             .lookup_moves(p, from, self.state.occ_bbs[2]);
         moves_bb &= move_mask;
 
-        // FIXME: Can I avoid this match or clean up the code?
-        let castling_infos = match self.state.turn {
-            Player::White => [
-                (
-                    Castling::WHITE_Q,
-                    (Square::A1, Square::E1),
-                    (Square::B1, Square::F1),
-                    Square::C1,
-                ),
-                (
-                    Castling::WHITE_K,
-                    (Square::H1, Square::E1),
-                    (Square::H1, Square::D1),
-                    Square::G1,
-                ),
-            ],
-            Player::Black => [
-                (
-                    Castling::BLACK_Q,
-                    (Square::A8, Square::E8),
-                    (Square::B8, Square::F8),
-                    Square::C8,
-                ),
-                (
-                    Castling::BLACK_K,
-                    (Square::H8, Square::E8),
-                    (Square::H8, Square::D8),
-                    Square::G8,
-                ),
-            ],
+        for to in moves_bb {
+            moves.push(Move(from, to, None));
+        }
+
+        let (rights, rook_piece, enemy_rooks) = match self.state.turn {
+            Player::White => (
+                [Castling::WHITE_K, Castling::WHITE_Q],
+                Piece::WhiteRook,
+                self.state.piece_bbs[Piece::BlackRook as usize]
+                    | self.state.piece_bbs[Piece::BlackQueen as usize],
+            ),
+            Player::Black => (
+                [Castling::BLACK_K, Castling::BLACK_Q],
+                Piece::BlackRook,
+                self.state.piece_bbs[Piece::WhiteRook as usize]
+                    | self.state.piece_bbs[Piece::WhiteQueen as usize],
+            ),
         };
 
-        for info in castling_infos {
-            if self.state.castling.contains(info.0) {
-                let between_bb = self
-                    .lookup_tables
-                    .lookup_between_squares(info.1 .0, info.1 .1);
-                let check_between_bb = self
-                    .lookup_tables
-                    .lookup_between_squares(info.2 .0, info.2 .1);
-                if (between_bb.0 & self.state.occ_bbs[2].0) == 0
-                    && (check_between_bb.0 & self.state.attacked_squares.0) == 0
-                {
-                    moves_bb.set_bit(info.3);
-                }
+        for right in rights {
+            if !self.state.castling.contains(right) {
+                continue;
             }
-        }
 
-        for to in moves_bb {
-            moves.push(Move(from, to, None));
+            let rook_from = self.castling_rooks[right.index()];
+            let (king_to, rook_to) = castling_destinations(right);
+
+            // The king and rook can jump over each other in Chess960 so they're ignored when
+            // checking that their paths are clear.
+            let mut occ = self.state.occ_bbs[2];
+            occ.unset_bit(from);
+            occ.unset_bit(rook_from);
+
+            let mut king_path = self.lookup_tables.lookup_between_squares(from, king_to);
+            king_path.set_bit(from);
+            king_path.set_bit(king_to);
+            let mut rook_path = self
+                .lookup_tables
+                .lookup_between_squares(rook_from, rook_to);
+            rook_path.set_bit(rook_to);
+
+            if !((king_path | rook_path) & occ).is_empty()
+                || !(king_path & self.state.attacked_squares).is_empty()
+            {
+                continue;
+            }
+
+            // The castling rook can block an attack along the back rank on the king's destination
+            // that opens up once it moves.
+            if !(self.lookup_tables.lookup_moves(rook_piece, king_to, occ) & enemy_rooks).is_empty()
+            {
+                continue;
+            }
+
+            if self.chess960 {
+                moves.push(Move(from, rook_from, None));
+            } else {
+                moves.push(Move(from, king_to, None));
+            }
         }
     }
 
     pub fn apply_move(&mut self, m: Move, legal_moves: &[Move]) {
+        self.make_move(m);
+        self.moves.push(m);
+        self.san_moves.push(self.uci_to_san_move(&m, legal_moves));
+    }
+
+    pub fn undo_move(&mut self) {
+        self.unmake_move();
+        self.moves.pop();
+        self.san_moves.pop();
+    }
+
+    /// Applies `m` without recording it in `moves` and `san_moves`, which is much faster than
+    /// `apply_move` because the SAN isn't worked out. It must be undone with `unmake_move`.
+    pub fn make_move(&mut self, m: Move) {
         self.previous_states.push(self.state);
+        if let Some(nnue) = &mut self.nnue {
+            nnue.push();
+        }
 
         let moved_piece = self.get_piece(m.0).unwrap();
-        let captured_piece = self.get_piece(m.1);
+        let castling_right = self.castling_right_for_move(&self.state, &m);
+        // In Chess960 castling is encoded as the king capturing its own rook.
+        let captured_piece = match castling_right {
+            Some(_) => None,
+            None => self.get_piece(m.1),
+        };
         if let Some(p) = captured_piece {
-            self.state.piece_bbs[p as usize].unset_bit(m.1);
-            let captured_color = match self.state.turn {
-                Player::White => Player::Black,
-                Player::Black => Player::White,
-            };
-            self.state.occ_bbs[captured_color as usize].unset_bit(m.1);
-            self.state.occ_bbs[2].unset_bit(m.1);
-
-            if !self.state.castling.is_empty() {
-                if p == Piece::WhiteRook {
-                    match m.1 {
-                        Square::A1 => self.state.castling.remove(Castling::WHITE_Q),
-                        Square::H1 => self.state.castling.remove(Castling::WHITE_K),
-                        _ => (),
-                    };
-                } else if p == Piece::BlackRook {
-                    match m.1 {
-                        Square::A8 => self.state.castling.remove(Castling::BLACK_Q),
-                        Square::H8 => self.state.castling.remove(Castling::BLACK_K),
-                        _ => (),
-                    };
-                }
-            }
+            self.toggle_piece(p, m.1);
         }
 
         if Some(m.1) == self.state.en_passant && PieceKind::from(moved_piece) == PieceKind::Pawn {
@@ -590,98 +956,74 @@ impl<'a> Board<'a> {
             let en_passant_rank = Rank::from(en_passant_square);
             if en_passant_rank == Rank::R3 {
                 let capture_square = Square::from((File::from(en_passant_square), Rank::R4));
-                self.state.piece_bbs[Piece::WhitePawn as usize].unset_bit(capture_square);
-                self.state.occ_bbs[Player::White as usize].unset_bit(capture_square);
-                self.state.occ_bbs[2].unset_bit(capture_square);
+                self.toggle_piece(Piece::WhitePawn, capture_square);
             } else if en_passant_rank == Rank::R6 {
                 let capture_square = Square::from((File::from(en_passant_square), Rank::R5));
-                self.state.piece_bbs[Piece::BlackPawn as usize].unset_bit(capture_square);
-                self.state.occ_bbs[Player::Black as usize].unset_bit(capture_square);
-                self.state.occ_bbs[2].unset_bit(capture_square);
+                self.toggle_piece(Piece::BlackPawn, capture_square);
             }
         }
 
-        self.state.piece_bbs[moved_piece as usize].unset_bit(m.0);
-        if let Some(promotion_piece_kind) = m.2 {
-            let promotion_piece = match self.state.turn {
-                Player::White => match promotion_piece_kind {
-                    PieceKind::Rook => Piece::WhiteRook,
-                    PieceKind::Knight => Piece::WhiteKnight,
-                    PieceKind::Bishop => Piece::WhiteBishop,
-                    PieceKind::Queen => Piece::WhiteQueen,
-                    PieceKind::King => Piece::WhiteKing,
-                    PieceKind::Pawn => Piece::WhitePawn,
-                },
-                Player::Black => match promotion_piece_kind {
-                    PieceKind::Rook => Piece::BlackRook,
-                    PieceKind::Knight => Piece::BlackKnight,
-                    PieceKind::Bishop => Piece::BlackBishop,
-                    PieceKind::Queen => Piece::BlackQueen,
-                    PieceKind::King => Piece::BlackKing,
-                    PieceKind::Pawn => Piece::BlackPawn,
-                },
+        if let Some(right) = castling_right {
+            let rook_piece = match self.state.turn {
+                Player::White => Piece::WhiteRook,
+                Player::Black => Piece::BlackRook,
             };
+            let rook_from = self.castling_rooks[right.index()];
+            let (king_to, rook_to) = castling_destinations(right);
+
+            // Both pieces are removed before either is placed because the king and rook can
+            // swap squares in Chess960.
+            self.toggle_piece(moved_piece, m.0);
+            self.toggle_piece(rook_piece, rook_from);
+            self.toggle_piece(moved_piece, king_to);
+            self.toggle_piece(rook_piece, rook_to);
+        } else {
+            self.toggle_piece(moved_piece, m.0);
+            if let Some(promotion_piece_kind) = m.2 {
+                let promotion_piece = match self.state.turn {
+                    Player::White => match promotion_piece_kind {
+                        PieceKind::Rook => Piece::WhiteRook,
+                        PieceKind::Knight => Piece::WhiteKnight,
+                        PieceKind::Bishop => Piece::WhiteBishop,
+                        PieceKind::Queen => Piece::WhiteQueen,
+                        PieceKind::King => Piece::WhiteKing,
+                        PieceKind::Pawn => Piece::WhitePawn,
+                    },
+                    Player::Black => match promotion_piece_kind {
+                        PieceKind::Rook => Piece::BlackRook,
+                        PieceKind::Knight => Piece::BlackKnight,
+                        PieceKind::Bishop => Piece::BlackBishop,
+                        PieceKind::Queen => Piece::BlackQueen,
+                        PieceKind::King => Piece::BlackKing,
+                        PieceKind::Pawn => Piece::BlackPawn,
+                    },
+                };
 
-            self.state.piece_bbs[promotion_piece as usize].set_bit(m.1);
+                self.toggle_piece(promotion_piece, m.1);
+            } else {
+                self.toggle_piece(moved_piece, m.1);
+            }
+        }
+
+        if PieceKind::from(moved_piece) == PieceKind::Pawn || captured_piece.is_some() {
+            self.state.half_moves = 0;
         } else {
-            self.state.piece_bbs[moved_piece as usize].set_bit(m.1);
-        }
-        self.state.occ_bbs[self.state.turn as usize].unset_bit(m.0);
-        self.state.occ_bbs[self.state.turn as usize].set_bit(m.1);
-        self.state.occ_bbs[2].unset_bit(m.0);
-        self.state.occ_bbs[2].set_bit(m.1);
-
-        if m.0 == Square::E1 && m.1 == Square::G1 && moved_piece == Piece::WhiteKing {
-            self.state.piece_bbs[Piece::WhiteRook as usize].unset_bit(Square::H1);
-            self.state.piece_bbs[Piece::WhiteRook as usize].set_bit(Square::F1);
-
-            self.state.occ_bbs[self.state.turn as usize].unset_bit(Square::H1);
-            self.state.occ_bbs[self.state.turn as usize].set_bit(Square::F1);
-
-            self.state.occ_bbs[2].unset_bit(Square::H1);
-            self.state.occ_bbs[2].set_bit(Square::F1);
-            self.state.castling.remove(Castling::WHITE_K);
-            self.state.castling.remove(Castling::WHITE_Q);
-        } else if m.0 == Square::E1 && m.1 == Square::C1 && moved_piece == Piece::WhiteKing {
-            self.state.piece_bbs[Piece::WhiteRook as usize].unset_bit(Square::A1);
-            self.state.piece_bbs[Piece::WhiteRook as usize].set_bit(Square::D1);
-
-            self.state.occ_bbs[self.state.turn as usize].unset_bit(Square::A1);
-            self.state.occ_bbs[self.state.turn as usize].set_bit(Square::D1);
-
-            self.state.occ_bbs[2].unset_bit(Square::A1);
-            self.state.occ_bbs[2].set_bit(Square::D1);
-            self.state.castling.remove(Castling::WHITE_K);
-            self.state.castling.remove(Castling::WHITE_Q);
-        } else if m.0 == Square::E8 && m.1 == Square::G8 && moved_piece == Piece::BlackKing {
-            self.state.piece_bbs[Piece::BlackRook as usize].unset_bit(Square::H8);
-            self.state.piece_bbs[Piece::BlackRook as usize].set_bit(Square::F8);
-
-            self.state.occ_bbs[self.state.turn as usize].unset_bit(Square::H8);
-            self.state.occ_bbs[self.state.turn as usize].set_bit(Square::F8);
-
-            self.state.occ_bbs[2].unset_bit(Square::H8);
-            self.state.occ_bbs[2].set_bit(Square::F8);
-            self.state.castling.remove(Castling::BLACK_K);
-            self.state.castling.remove(Castling::BLACK_Q);
-        } else if m.0 == Square::E8 && m.1 == Square::C8 && moved_piece == Piece::BlackKing {
-            self.state.piece_bbs[Piece::BlackRook as usize].unset_bit(Square::A8);
-            self.state.piece_bbs[Piece::BlackRook as usize].set_bit(Square::D8);
-
-            self.state.occ_bbs[self.state.turn as usize].unset_bit(Square::A8);
-            self.state.occ_bbs[self.state.turn as usize].set_bit(Square::D8);
-
-            self.state.occ_bbs[2].unset_bit(Square::A8);
-            self.state.occ_bbs[2].set_bit(Square::D8);
-            self.state.castling.remove(Castling::BLACK_K);
-            self.state.castling.remove(Castling::BLACK_Q);
+            self.state.half_moves += 1;
+        }
+
+        if self.state.turn == Player::Black {
+            self.state.full_moves += 1;
         }
 
         self.state.turn = match self.state.turn {
             Player::White => Player::Black,
             Player::Black => Player::White,
         };
+        self.state.hash ^= zobrist::KEYS.black_to_move;
 
+        if let Some(s) = self.state.en_passant {
+            self.state.hash ^= zobrist::KEYS.en_passant[File::from(s) as usize];
+        }
         let from_file = File::from(m.0);
         let from_rank = Rank::from(m.0);
         let to_file = File::from(m.1);
@@ -697,118 +1039,344 @@ impl<'a> Board<'a> {
         } else {
             self.state.en_passant = None;
         }
+        if let Some(s) = self.state.en_passant {
+            self.state.hash ^= zobrist::KEYS.en_passant[File::from(s) as usize];
+        }
 
         if !self.state.castling.is_empty() {
-            if moved_piece == Piece::WhiteKing {
-                self.state.castling.remove(Castling::WHITE_K);
-                self.state.castling.remove(Castling::WHITE_Q);
-            } else if moved_piece == Piece::WhiteRook {
-                if m.0 == Square::H1 {
-                    self.state.castling.remove(Castling::WHITE_K);
-                } else if m.0 == Square::A1 {
-                    self.state.castling.remove(Castling::WHITE_Q);
-                }
-            } else if moved_piece == Piece::BlackKing {
-                self.state.castling.remove(Castling::BLACK_K);
-                self.state.castling.remove(Castling::BLACK_Q);
-            } else if moved_piece == Piece::BlackRook {
-                if m.0 == Square::H8 {
-                    self.state.castling.remove(Castling::BLACK_K);
-                } else if m.0 == Square::A8 {
-                    self.state.castling.remove(Castling::BLACK_Q);
+            self.state.hash ^= zobrist::KEYS.castling[self.state.castling.bits() as usize];
+
+            match moved_piece {
+                Piece::WhiteKing => self
+                    .state
+                    .castling
+                    .remove(Castling::WHITE_K | Castling::WHITE_Q),
+                Piece::BlackKing => self
+                    .state
+                    .castling
+                    .remove(Castling::BLACK_K | Castling::BLACK_Q),
+                _ => (),
+            }
+
+            // A move from or to a castling rook's square either moves or captures the rook.
+            let castling = self.state.castling;
+            for right in castling.iter() {
+                let rook_square = self.castling_rooks[right.index()];
+                if m.0 == rook_square || m.1 == rook_square {
+                    self.state.castling.remove(right);
                 }
             }
+
+            self.state.hash ^= zobrist::KEYS.castling[self.state.castling.bits() as usize];
         }
 
         let (checkers, attacked_squares, pinned_pieces) = self.get_check_info();
         self.state.checkers = checkers;
         self.state.attacked_squares = attacked_squares;
         self.state.pinned_pieces = pinned_pieces;
-
-        self.moves.push(m);
-        self.san_moves.push(self.uci_to_san_move(&m, legal_moves));
     }
 
-    pub fn undo_move(&mut self) {
+    pub fn unmake_move(&mut self) {
         self.state = self.previous_states.pop().unwrap();
-        self.moves.pop();
-        self.san_moves.pop();
+        if let Some(nnue) = &mut self.nnue {
+            nnue.pop();
+        }
     }
 
-    // FIXME: Things I still need to handle:
-    // * If the move is checkmate
-    pub fn uci_to_san_move(&self, m: &Move, legal_moves: &[Move]) -> String {
-        if *m == Move(Square::E1, Square::G1, None) || *m == Move(Square::E8, Square::G8, None) {
-            return "O-O".to_string();
-        } else if *m == Move(Square::E1, Square::C1, None)
-            || *m == Move(Square::E8, Square::C8, None)
-        {
-            return "O-O-O".to_string();
+    /// Passes the turn to the other player, which the search uses to prove a position is so good
+    /// that it can be pruned. It must not be made when in check and must be undone with
+    /// `unmake_move`.
+    pub fn make_null_move(&mut self) {
+        self.previous_states.push(self.state);
+        if let Some(nnue) = &mut self.nnue {
+            nnue.push();
         }
 
-        let moved_piece = PIECES
-            .into_iter()
-            .find(|p| self.previous_states.last().unwrap().piece_bbs[*p as usize].get_bit(m.0))
-            .unwrap();
-        let moved_piece_kind = PieceKind::from(moved_piece);
-        let possible_moves = legal_moves
+        if let Some(s) = self.state.en_passant.take() {
+            self.state.hash ^= zobrist::KEYS.en_passant[File::from(s) as usize];
+        }
+        self.state.half_moves += 1;
+        if self.state.turn == Player::Black {
+            self.state.full_moves += 1;
+        }
+        self.state.turn = match self.state.turn {
+            Player::White => Player::Black,
+            Player::Black => Player::White,
+        };
+        self.state.hash ^= zobrist::KEYS.black_to_move;
+
+        let (checkers, attacked_squares, pinned_pieces) = self.get_check_info();
+        self.state.checkers = checkers;
+        self.state.attacked_squares = attacked_squares;
+        self.state.pinned_pieces = pinned_pieces;
+    }
+
+    /// Adds `p` to `s` if it's empty or removes it if `p` is there, keeping the occupancy and
+    /// hash in sync.
+    #[inline(always)]
+    fn toggle_piece(&mut self, p: Piece, s: Square) {
+        let bb = BitBoard(1 << s as u8);
+        self.state.piece_bbs[p as usize] ^= bb;
+        self.state.occ_bbs[Player::from(p) as usize] ^= bb;
+        self.state.occ_bbs[2] ^= bb;
+        self.state.hash ^= zobrist::KEYS.piece(p, s);
+        if matches!(p, Piece::WhitePawn | Piece::BlackPawn) {
+            self.state.pawn_hash ^= zobrist::KEYS.piece(p, s);
+        }
+        if let Some(nnue) = &mut self.nnue {
+            if self.state.piece_bbs[p as usize].get_bit(s) {
+                nnue.add_piece(p, s);
+            } else {
+                nnue.remove_piece(p, s);
+            }
+        }
+    }
+
+    /// Evaluates positions with `network` from now on, or with the hand written evaluation if
+    /// it's `None`.
+    pub fn set_network(&mut self, network: Option<Arc<Network>>) {
+        self.nnue = network.map(|network| NnueState::new(network, &self.pieces()));
+    }
+
+    pub fn network(&self) -> Option<&Arc<Network>> {
+        self.nnue.as_ref().map(NnueState::network)
+    }
+
+    /// The network's evaluation in centipawns from the point of view of the player to move, if
+    /// the board has one.
+    pub fn nnue_evaluate(&self) -> Option<i32> {
+        self.nnue
+            .as_ref()
+            .map(|nnue| nnue.evaluate(self.state.turn))
+    }
+
+    /// Whether the incrementally updated accumulator matches one computed from scratch.
+    pub fn is_nnue_consistent(&self) -> bool {
+        self.nnue
+            .as_ref()
+            .is_none_or(|nnue| nnue.is_consistent(&self.pieces()))
+    }
+
+    /// The Zobrist hash of the position. Positions that only differ in their move counters have
+    /// the same hash.
+    pub fn hash(&self) -> u64 {
+        self.state.hash
+    }
+
+    /// The Zobrist hash of the pawns, which is the same for positions with the same pawn
+    /// structure.
+    pub fn pawn_hash(&self) -> u64 {
+        self.state.pawn_hash
+    }
+
+    /// The key of the position in Polyglot opening books.
+    pub fn polyglot_key(&self) -> u64 {
+        self.state.compute_polyglot_key()
+    }
+
+    /// Whether `m` castles in the current position.
+    pub fn is_castling(&self, m: &Move) -> bool {
+        self.castling_right_for_move(&self.state, m).is_some()
+    }
+
+    /// The castling right `m` uses if it's a castling move in `state`. Castling moves are encoded
+    /// as the king moving to its destination in standard chess and as the king capturing its own
+    /// rook in Chess960.
+    fn castling_right_for_move(&self, state: &BoardState, m: &Move) -> Option<Castling> {
+        let (king, rights) = match state.turn {
+            Player::White => (Piece::WhiteKing, [Castling::WHITE_K, Castling::WHITE_Q]),
+            Player::Black => (Piece::BlackKing, [Castling::BLACK_K, Castling::BLACK_Q]),
+        };
+        if !state.piece_bbs[king as usize].get_bit(m.0) {
+            return None;
+        }
+
+        rights.into_iter().find(|right| {
+            state.castling.contains(*right)
+                && if self.chess960 {
+                    m.1 == self.castling_rooks[right.index()]
+                } else {
+                    m.1 == castling_destinations(*right).0
+                }
+        })
+    }
+
+    /// The SAN of a line of moves played from this position. It stops at the first illegal move.
+    pub fn san_line(&self, line: &[Move]) -> Vec<String> {
+        let mut b = self.shallow_clone();
+        for m in line {
+            let legal_moves = b.generate_moves(Legality::Legal);
+            if !legal_moves.contains(m) {
+                break;
+            }
+            b.apply_move(*m, &legal_moves);
+        }
+
+        b.san_moves
+    }
+
+    /// The legal move written as `san`. Check marks, annotations like `!?`, the `=` of
+    /// promotions and zeros instead of `O` when castling are all accepted.
+    pub fn parse_san(&self, san: &str) -> Result<Move, String> {
+        let normalise = |s: &str| {
+            s.trim_end_matches(['+', '#', '!', '?'])
+                .replace('0', "O")
+                .replace('=', "")
+        };
+        let wanted = normalise(san);
+        let legal_moves = self.generate_moves(Legality::Legal);
+        legal_moves
             .iter()
-            .filter(|lm| {
-                lm.1 == m.1
-                    && moved_piece_kind
-                        == PieceKind::from(
-                            PIECES
-                                .into_iter()
-                                .find(|p| {
-                                    self.previous_states.last().unwrap().piece_bbs[*p as usize]
-                                        .get_bit(lm.0)
-                                })
-                                .unwrap(),
-                        )
+            .find(|m| {
+                let mut b = self.shallow_clone();
+                b.apply_move(**m, &legal_moves);
+                normalise(&b.san_moves[0]) == wanted
             })
-            .collect::<Vec<&Move>>();
-        let is_ambigious = possible_moves.len() > 1;
-        let is_capture = PIECES
-            .into_iter()
-            .any(|p| self.previous_states.last().unwrap().piece_bbs[p as usize].get_bit(m.1));
+            .copied()
+            .ok_or_else(|| format!("illegal move: {}", san))
+    }
+
+    /// Converts a move that was just applied to the board into SAN. `legal_moves` are the legal
+    /// moves of the position before the move.
+    pub fn uci_to_san_move(&self, m: &Move, legal_moves: &[Move]) -> String {
+        let previous_state = self.previous_states.last().unwrap();
+        let piece_at = |s: Square| {
+            PIECES
+                .into_iter()
+                .find(|p| previous_state.piece_bbs[*p as usize].get_bit(s))
+        };
+
+        let moved_piece = piece_at(m.0).unwrap();
+        let moved_piece_kind = PieceKind::from(moved_piece);
 
         let mut san_move = String::new();
 
-        if moved_piece_kind != PieceKind::Pawn {
-            san_move.push_str(&moved_piece_kind.to_string().to_uppercase());
+        if let Some(right) = self.castling_right_for_move(previous_state, m) {
+            if right.intersects(Castling::WHITE_K | Castling::BLACK_K) {
+                san_move.push_str("O-O");
+            } else {
+                san_move.push_str("O-O-O");
+            }
+        } else {
+            let other_moves = legal_moves
+                .iter()
+                .filter(|lm| {
+                    lm.0 != m.0
+                        && lm.1 == m.1
+                        && lm.2 == m.2
+                        && PieceKind::from(piece_at(lm.0).unwrap()) == moved_piece_kind
+                })
+                .collect::<Vec<&Move>>();
+            let is_capture = piece_at(m.1).is_some()
+                || (moved_piece_kind == PieceKind::Pawn && Some(m.1) == previous_state.en_passant);
+
+            if moved_piece_kind != PieceKind::Pawn {
+                san_move.push_str(&moved_piece_kind.to_string().to_uppercase());
+            }
+
+            let from_file = File::from(m.0);
+            let from_rank = Rank::from(m.0);
+            if (moved_piece_kind == PieceKind::Pawn && is_capture) || !other_moves.is_empty() {
+                if moved_piece_kind == PieceKind::Pawn
+                    || other_moves.iter().all(|om| File::from(om.0) != from_file)
+                {
+                    san_move.push_str(&from_file.to_string());
+                } else if other_moves.iter().all(|om| Rank::from(om.0) != from_rank) {
+                    san_move.push_str(&from_rank.to_string());
+                } else {
+                    san_move.push_str(&m.0.to_string());
+                }
+            }
+
+            if is_capture {
+                san_move.push('x');
+            }
+
+            san_move.push_str(&m.1.to_string());
+
+            if let Some(p) = m.2 {
+                san_move.push('=');
+                san_move.push_str(&p.to_string().to_uppercase());
+            }
         }
 
-        let mut has_source_file = false;
-        if (is_ambigious
-            && !possible_moves
-                .iter()
-                .all(|pm| File::from(pm.0) == File::from(possible_moves[0].0)))
-            || (moved_piece_kind == PieceKind::Pawn && is_capture)
-        {
-            has_source_file = true;
-            san_move.push_str(&File::from(m.0).to_string());
+        if !self.state.checkers.is_empty() {
+            if self.generate_moves(Legality::Legal).is_empty() {
+                san_move.push('#');
+            } else {
+                san_move.push('+');
+            }
         }
 
-        if is_ambigious && !has_source_file {
-            san_move.push_str(&Rank::from(m.0).to_string());
+        san_move
+    }
+
+    /// Returns how the game ended if the current position is terminal. Draws that need to be
+    /// claimed, like the fifty move rule and threefold repetition, are reported as soon as they
+    /// can be claimed.
+    pub fn outcome(&self) -> Option<Outcome> {
+        if self.generate_moves(Legality::Legal).is_empty() {
+            return if self.is_in_check() {
+                Some(Outcome::Checkmate(match self.state.turn {
+                    Player::White => Player::Black,
+                    Player::Black => Player::White,
+                }))
+            } else {
+                Some(Outcome::Stalemate)
+            };
         }
 
-        if is_capture {
-            san_move.push('x');
+        if self.state.half_moves >= 100 {
+            return Some(Outcome::FiftyMoveRule);
         }
 
-        san_move.push_str(&m.1.to_string());
+        if self.repetitions() >= 2 {
+            return Some(Outcome::ThreefoldRepetition);
+        }
 
-        if let Some(p) = m.2 {
-            san_move.push('=');
-            san_move.push_str(&p.to_string().to_uppercase());
+        if self.is_insufficient_material() {
+            return Some(Outcome::InsufficientMaterial);
         }
 
-        if !self.state.checkers.is_empty() {
-            san_move.push('+');
+        None
+    }
+
+    /// The number of times the current position occurred earlier in the game.
+    pub fn repetitions(&self) -> usize {
+        self.previous_states
+            .iter()
+            .rev()
+            .take(self.state.half_moves as usize)
+            .filter(|s| s.hash == self.state.hash)
+            .count()
+    }
+
+    /// Whether neither player has enough material left to deliver checkmate.
+    pub fn is_insufficient_material(&self) -> bool {
+        let heavy_pieces = [
+            Piece::WhitePawn,
+            Piece::WhiteRook,
+            Piece::WhiteQueen,
+            Piece::BlackPawn,
+            Piece::BlackRook,
+            Piece::BlackQueen,
+        ];
+        if heavy_pieces.iter().any(|p| self.count_piece(*p) > 0) {
+            return false;
         }
 
-        san_move
+        let knights = self.state.piece_bbs[Piece::WhiteKnight as usize]
+            | self.state.piece_bbs[Piece::BlackKnight as usize];
+        let bishops = self.state.piece_bbs[Piece::WhiteBishop as usize]
+            | self.state.piece_bbs[Piece::BlackBishop as usize];
+
+        if knights.pop_count() + bishops.pop_count() <= 1 {
+            return true;
+        }
+
+        // Any number of bishops can't mate if they are all on the same colour.
+        knights.is_empty()
+            && ((bishops & LIGHT_SQUARES).is_empty() || (bishops & !LIGHT_SQUARES).is_empty())
     }
 
     fn get_check_info(&self) -> (BitBoard, BitBoard, BitBoard) {
@@ -902,13 +1470,13 @@ impl<'a> Board<'a> {
         (checkers, attacked_squares, pinned_pieces)
     }
 
-    fn get_piece(&self, s: Square) -> Option<Piece> {
+    pub fn get_piece(&self, s: Square) -> Option<Piece> {
         PIECES
             .into_iter()
             .find(|p| self.state.piece_bbs[*p as usize].get_bit(s))
     }
 
-    fn count_piece(&self, p: Piece) -> u32 {
+    pub fn count_piece(&self, p: Piece) -> u32 {
         self.state.piece_bbs[p as usize].pop_count()
     }
 
@@ -1150,45 +1718,60 @@ impl<'a> Board<'a> {
             errors.push("too many promoted black pieces".to_string())
         }
 
-        if self.get_piece(Square::E1) != Some(Piece::WhiteKing) {
-            if self.state.castling.contains(Castling::WHITE_Q) {
-                errors.push("white shouldn't have queenside castling rights".to_string())
-            }
-            if self.state.castling.contains(Castling::WHITE_K) {
-                errors.push("white shouldn't have kingside castling rights".to_string())
-            }
-        } else {
-            if self.get_piece(Square::A1) != Some(Piece::WhiteRook)
-                && self.state.castling.contains(Castling::WHITE_Q)
-            {
-                errors.push("white shouldn't have queenside castling rights".to_string())
+        for (right, king, rook, back_rank, message) in [
+            (
+                Castling::WHITE_Q,
+                Piece::WhiteKing,
+                Piece::WhiteRook,
+                Rank::R1,
+                "white shouldn't have queenside castling rights",
+            ),
+            (
+                Castling::WHITE_K,
+                Piece::WhiteKing,
+                Piece::WhiteRook,
+                Rank::R1,
+                "white shouldn't have kingside castling rights",
+            ),
+            (
+                Castling::BLACK_Q,
+                Piece::BlackKing,
+                Piece::BlackRook,
+                Rank::R8,
+                "black shouldn't have queenside castling rights",
+            ),
+            (
+                Castling::BLACK_K,
+                Piece::BlackKing,
+                Piece::BlackRook,
+                Rank::R8,
+                "black shouldn't have kingside castling rights",
+            ),
+        ] {
+            if !self.state.castling.contains(right) {
+                continue;
             }
 
-            if self.get_piece(Square::H1) != Some(Piece::WhiteRook)
-                && self.state.castling.contains(Castling::WHITE_K)
-            {
-                errors.push("white shouldn't have kingside castling rights".to_string())
-            }
-        }
-
-        if self.get_piece(Square::E8) != Some(Piece::BlackKing) {
-            if self.state.castling.contains(Castling::BLACK_Q) {
-                errors.push("black shouldn't have queenside castling rights".to_string())
-            }
-            if self.state.castling.contains(Castling::BLACK_K) {
-                errors.push("black shouldn't have kingside castling rights".to_string())
-            }
-        } else {
-            if self.get_piece(Square::A8) != Some(Piece::BlackRook)
-                && self.state.castling.contains(Castling::BLACK_Q)
-            {
-                errors.push("black shouldn't have queenside castling rights".to_string())
-            }
+            let king_square = self.state.piece_bbs[king as usize].get_lsb();
+            let rook_square = self.castling_rooks[right.index()];
+            let rook_on_correct_side = match king_square {
+                Some(k) if right.intersects(Castling::WHITE_K | Castling::BLACK_K) => {
+                    File::from(rook_square) as u8 > File::from(k) as u8
+                }
+                Some(k) => (File::from(rook_square) as u8) < File::from(k) as u8,
+                None => false,
+            };
+            let king_on_start_square = if self.chess960 {
+                king_square.map(Rank::from) == Some(back_rank)
+            } else {
+                king_square == Some(Square::from((File::E, back_rank)))
+            };
 
-            if self.get_piece(Square::H8) != Some(Piece::BlackRook)
-                && self.state.castling.contains(Castling::BLACK_K)
+            if !king_on_start_square
+                || self.get_piece(rook_square) != Some(rook)
+                || !rook_on_correct_side
             {
-                errors.push("black shouldn't have kingside castling rights".to_string())
+                errors.push(message.to_string())
             }
         }
 
@@ -1200,6 +1783,20 @@ impl<'a> Board<'a> {
     }
 }
 
+/// The squares the king and rook end up on after castling with `right`. These are the same in
+/// standard chess and Chess960.
+fn castling_destinations(right: Castling) -> (Square, Square) {
+    if right == Castling::WHITE_K {
+        (Square::G1, Square::F1)
+    } else if right == Castling::WHITE_Q {
+        (Square::C1, Square::D1)
+    } else if right == Castling::BLACK_K {
+        (Square::G8, Square::F8)
+    } else {
+        (Square::C8, Square::D8)
+    }
+}
+
 impl<'a> fmt::Display for Board<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut s = String::new();
@@ -1225,11 +1822,63 @@ impl<'a> fmt::Display for Board<'a> {
     }
 }
 
+/// The shape `Board` is serialised as: the position, the position the recorded moves were played
+/// from and the moves, so the history needed to detect repetitions isn't lost.
+#[derive(Serialize, Deserialize)]
+struct SerializedBoard {
+    fen: String,
+    start_fen: String,
+    chess960: bool,
+    moves: Vec<Move>,
+}
+
+impl Serialize for Board<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerializedBoard {
+            fen: self.fen(),
+            start_fen: self.start_fen(),
+            chess960: self.chess960,
+            moves: self.moves.clone(),
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Deserialises a `Board`, which needs the lookup tables to set up, by replaying its moves.
+pub struct BoardSeed<'a>(pub &'a lookup_tables::LookupTables);
+
+impl<'de, 'a> DeserializeSeed<'de> for BoardSeed<'a> {
+    type Value = Board<'a>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        let serialized = SerializedBoard::deserialize(deserializer)?;
+        let mut b = Board::from_fen(&serialized.start_fen, self.0)
+            .map_err(|e| de::Error::custom(format!("invalid start_fen: {}", e)))?;
+        b.set_chess960(serialized.chess960);
+        for m in serialized.moves {
+            let legal_moves = b.generate_moves(Legality::Legal);
+            if !legal_moves.contains(&m) {
+                return Err(de::Error::custom(format!("illegal move: {}", m)));
+            }
+            b.apply_move(m, &legal_moves);
+        }
+
+        if b.fen() != serialized.fen {
+            return Err(de::Error::custom(format!(
+                "the moves lead to {} rather than {}",
+                b.fen(),
+                serialized.fen
+            )));
+        }
+        Ok(b)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
         bitboard,
-        board::{Board, BoardState, Castling},
+        board::{Board, BoardState, Castling, Outcome},
         core::{
             Move, PieceKind, Player, Square, EN_PASSANT_FEN, IN_CHECK_FEN, POS_2_KIWIPETE_FEN,
             POS_3_FEN, POS_5_FEN, POS_6_FEN, STARTING_POS_FEN,
@@ -1264,6 +1913,8 @@ mod tests {
         en_passant: None,
         half_moves: 0,
         full_moves: 1,
+        hash: 0,
+        pawn_hash: 0,
         checkers: bitboard::BitBoard(0),
         attacked_squares: bitboard::BitBoard(9151313343305220096),
         pinned_pieces: bitboard::BitBoard(0),
@@ -1294,6 +1945,8 @@ mod tests {
         en_passant: Some(Square::D6),
         half_moves: 0,
         full_moves: 3,
+        hash: 0,
+        pawn_hash: 0,
         checkers: bitboard::BitBoard(0),
         attacked_squares: bitboard::BitBoard(9151313525111521280),
         pinned_pieces: bitboard::BitBoard(0),
@@ -1324,6 +1977,8 @@ mod tests {
         en_passant: None,
         half_moves: 0,
         full_moves: 0,
+        hash: 0,
+        pawn_hash: 0,
         checkers: bitboard::BitBoard(0),
         attacked_squares: bitboard::BitBoard(18427602327210643456),
         pinned_pieces: bitboard::BitBoard(0),
@@ -1354,6 +2009,8 @@ mod tests {
         en_passant: None,
         half_moves: 0,
         full_moves: 0,
+        hash: 0,
+        pawn_hash: 0,
         checkers: bitboard::BitBoard(0),
         attacked_squares: bitboard::BitBoard(9259553660634923008),
         pinned_pieces: bitboard::BitBoard(8589934592),
@@ -1384,6 +2041,8 @@ mod tests {
         en_passant: None,
         half_moves: 1,
         full_moves: 8,
+        hash: 0,
+        pawn_hash: 0,
         checkers: bitboard::BitBoard(0),
         attacked_squares: bitboard::BitBoard(9151313686139830408),
         pinned_pieces: bitboard::BitBoard(0),
@@ -1414,6 +2073,8 @@ mod tests {
         en_passant: None,
         half_moves: 0,
         full_moves: 10,
+        hash: 0,
+        pawn_hash: 0,
         checkers: bitboard::BitBoard(0),
         attacked_squares: bitboard::BitBoard(18446180846641684480),
         pinned_pieces: bitboard::BitBoard(8192),
@@ -1444,6 +2105,8 @@ mod tests {
         en_passant: None,
         half_moves: 2,
         full_moves: 3,
+        hash: 0,
+        pawn_hash: 0,
         checkers: bitboard::BitBoard(33554432),
         attacked_squares: bitboard::BitBoard(9133299415094986768),
         pinned_pieces: bitboard::BitBoard(0),
@@ -1503,11 +2166,12 @@ mod tests {
                 println!("{}\n{}", b, b.state.pinned_pieces);
             }
 
-            assert_eq!(
-                b.state, test_case.expected_state,
-                "{} failed",
-                test_case.name
-            );
+            let expected_state = BoardState {
+                hash: test_case.expected_state.compute_hash(),
+                pawn_hash: test_case.expected_state.compute_pawn_hash(),
+                ..test_case.expected_state
+            };
+            assert_eq!(b.state, expected_state, "{} failed", test_case.name);
         }
     }
 
@@ -1607,4 +2271,405 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn san_line_test() {
+        let l = LookupTables::generate();
+        let b = Board::start_pos(&l);
+        let line: Vec<Move> = ["e2e4", "e7e5", "g1f3", "b8c6", "f1b5", "a7a6", "e1g1"]
+            .into_iter()
+            .map(|m| Move::try_from(m).unwrap())
+            .collect();
+        assert_eq!(
+            b.san_line(&line),
+            vec!["e4", "e5", "Nf3", "Nc6", "Bb5", "a6", "O-O"]
+        );
+
+        // The line is cut at an illegal move.
+        assert_eq!(b.san_line(&line[1..]), Vec::<String>::new());
+        assert!(b.moves.is_empty());
+    }
+
+    #[test]
+    fn parse_san_test() {
+        let l = LookupTables::generate();
+        let b = Board::from_fen("r3k2r/1P6/8/3pP3/8/1N3N2/8/R3K2R w KQkq d6 0 1", &l).unwrap();
+        let parse = |san| b.parse_san(san).map(|m| m.to_string());
+        assert_eq!(parse("exd6"), Ok("e5d6".to_string()));
+        assert_eq!(parse("Nbd4"), Ok("b3d4".to_string()));
+        assert_eq!(parse("bxa8=Q+"), Ok("b7a8q".to_string()));
+        assert_eq!(parse("bxa8Q"), Ok("b7a8q".to_string()));
+        assert_eq!(parse("0-0-0!?"), Ok("e1c1".to_string()));
+        assert_eq!(parse("O-O"), Ok("e1g1".to_string()));
+        // Ambiguous and illegal moves.
+        assert!(parse("Nd4").is_err());
+        assert!(parse("e4").is_err());
+    }
+
+    #[test]
+    fn polyglot_key_test() {
+        let l = LookupTables::generate();
+        // The examples from the Polyglot book format's description.
+        let test_cases = [
+            ("", 0x463b96181691fc9c),
+            ("e2e4", 0x823c9b50fd114196),
+            ("e2e4 d7d5", 0x0756b94461c50fb0),
+            ("e2e4 d7d5 e4e5", 0x662fafb965db29d4),
+            ("e2e4 d7d5 e4e5 f7f5", 0x22a48b5a8e47ff78),
+            ("e2e4 d7d5 e4e5 f7f5 e1e2", 0x652a607ca3f242c1),
+            ("e2e4 d7d5 e4e5 f7f5 e1e2 e8f7", 0x00fdd303c946bdd9),
+            ("a2a4 b7b5 h2h4 b5b4 c2c4", 0x3c8123ea7b067637),
+            ("a2a4 b7b5 h2h4 b5b4 c2c4 b4c3 a1a3", 0x5c3f9b829b279560),
+        ];
+        for (moves, key) in test_cases {
+            let mut b = Board::start_pos(&l);
+            for m in moves.split_whitespace() {
+                b.make_move(Move::try_from(m).unwrap());
+            }
+            assert_eq!(b.polyglot_key(), key, "{}", moves);
+        }
+    }
+
+    #[test]
+    fn uci_to_san_move_edge_cases_test() {
+        struct TestCase {
+            name: &'static str,
+            fen: &'static str,
+            m: Move,
+            expected_san: &'static str,
+        }
+        let l = LookupTables::generate();
+
+        let test_cases = vec![
+            TestCase {
+                name: "en passant",
+                fen: "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+                m: Move(Square::E5, Square::F6, None),
+                expected_san: "exf6",
+            },
+            TestCase {
+                name: "castling",
+                fen: "4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1",
+                m: Move(Square::E1, Square::C1, None),
+                expected_san: "O-O-O",
+            },
+            TestCase {
+                name: "rook on the castling squares",
+                fen: "3k4/8/8/8/8/8/8/4R1K1 w - - 0 1",
+                m: Move(Square::E1, Square::C1, None),
+                expected_san: "Rc1",
+            },
+            TestCase {
+                name: "promotion",
+                fen: "4k3/1P6/8/8/8/8/8/4K3 w - - 0 1",
+                m: Move(Square::B7, Square::B8, Some(PieceKind::Knight)),
+                expected_san: "b8=N",
+            },
+            TestCase {
+                name: "rank disambiguation",
+                fen: "7k/8/8/1Q1Q4/8/1Q6/8/4K3 w - - 0 1",
+                m: Move(Square::B3, Square::C4, None),
+                expected_san: "Q3c4",
+            },
+            TestCase {
+                name: "square disambiguation",
+                fen: "7k/8/8/1Q1Q4/8/1Q6/8/4K3 w - - 0 1",
+                m: Move(Square::B5, Square::C4, None),
+                expected_san: "Qb5c4",
+            },
+            TestCase {
+                name: "checkmate",
+                fen: "k7/8/1K6/8/8/8/8/7R w - - 0 1",
+                m: Move(Square::H1, Square::H8, None),
+                expected_san: "Rh8#",
+            },
+            TestCase {
+                name: "chess960 castling with the king staying put",
+                fen: "1k6/8/8/8/8/8/8/5RKR w K - 0 1",
+                m: Move(Square::G1, Square::H1, None),
+                expected_san: "O-O",
+            },
+            TestCase {
+                name: "chess960 queenside castling",
+                fen: "rk5r/8/8/8/8/8/8/RK5R w KQkq - 0 1",
+                m: Move(Square::B1, Square::A1, None),
+                expected_san: "O-O-O",
+            },
+        ];
+
+        for test_case in test_cases {
+            let mut b = Board::from_fen(test_case.fen, &l).unwrap();
+            let legal_moves = b.generate_moves(Legality::Legal);
+            b.apply_move(test_case.m, &legal_moves);
+
+            assert_eq!(
+                b.san_moves.last().unwrap(),
+                test_case.expected_san,
+                "{} failed",
+                test_case.name
+            );
+        }
+    }
+
+    #[test]
+    fn chess960_test() {
+        struct TestCase {
+            name: &'static str,
+            fen: &'static str,
+            m: Move,
+            expected_fen: &'static str,
+            expected_shredder_fen: &'static str,
+        }
+        let l = LookupTables::generate();
+
+        let test_cases = vec![
+            TestCase {
+                name: "king and rook swap squares",
+                fen: "rk5r/8/8/8/8/8/8/RK5R w KQkq - 0 1",
+                m: Move(Square::B1, Square::A1, None),
+                expected_fen: "rk5r/8/8/8/8/8/8/2KR3R b kq - 1 1",
+                expected_shredder_fen: "rk5r/8/8/8/8/8/8/2KR3R b ha - 1 1",
+            },
+            TestCase {
+                name: "capturing a castling rook",
+                fen: "rk5r/8/8/8/8/8/8/RK5R w KQkq - 0 1",
+                m: Move(Square::H1, Square::H8, None),
+                expected_fen: "rk5R/8/8/8/8/8/8/RK6 b Qq - 0 1",
+                expected_shredder_fen: "rk5R/8/8/8/8/8/8/RK6 b Aa - 0 1",
+            },
+            TestCase {
+                name: "inner rook needs the file",
+                fen: "1r2k1rr/8/8/8/8/8/8/1R2K1RR w Gg - 0 1",
+                m: Move(Square::B1, Square::B2, None),
+                expected_fen: "1r2k1rr/8/8/8/8/8/1R6/4K1RR b Gg - 1 1",
+                expected_shredder_fen: "1r2k1rr/8/8/8/8/8/1R6/4K1RR b Gg - 1 1",
+            },
+        ];
+
+        for test_case in test_cases {
+            let mut b = Board::from_fen(test_case.fen, &l).unwrap();
+            assert!(b.is_chess960(), "{} failed", test_case.name);
+
+            let legal_moves = b.generate_moves(Legality::Legal);
+            assert!(
+                legal_moves.contains(&test_case.m),
+                "{} failed",
+                test_case.name
+            );
+            b.apply_move(test_case.m, &legal_moves);
+
+            assert_eq!(b.fen(), test_case.expected_fen, "{} failed", test_case.name);
+            assert_eq!(
+                b.shredder_fen(),
+                test_case.expected_shredder_fen,
+                "{} failed",
+                test_case.name
+            );
+            assert_eq!(
+                Board::from_fen(&b.fen(), &l).unwrap().shredder_fen(),
+                test_case.expected_shredder_fen,
+                "{} failed",
+                test_case.name
+            );
+        }
+
+        // The standard start position only uses king captures rook castling once it's enabled.
+        let mut b = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", &l).unwrap();
+        assert!(b
+            .generate_moves(Legality::Legal)
+            .contains(&Move(Square::E1, Square::G1, None)));
+        b.set_chess960(true);
+        let legal_moves = b.generate_moves(Legality::Legal);
+        assert!(!legal_moves.contains(&Move(Square::E1, Square::G1, None)));
+        assert!(legal_moves.contains(&Move(Square::E1, Square::H1, None)));
+        assert_eq!(b.fen(), "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        assert_eq!(b.shredder_fen(), "r3k2r/8/8/8/8/8/8/R3K2R w HAha - 0 1");
+    }
+
+    #[test]
+    fn hash_test() {
+        fn check_hashes(b: &mut Board, depth: u8) {
+            assert_eq!(b.state.hash, b.state.compute_hash(), "{}", b.fen());
+            assert_eq!(
+                b.state.pawn_hash,
+                b.state.compute_pawn_hash(),
+                "{}",
+                b.fen()
+            );
+            if depth == 0 {
+                return;
+            }
+            for m in b.generate_moves(Legality::Legal) {
+                b.make_move(m);
+                check_hashes(b, depth - 1);
+                b.unmake_move();
+            }
+        }
+
+        let l = LookupTables::generate();
+        for fen in [
+            POS_2_KIWIPETE_FEN,
+            POS_3_FEN,
+            POS_5_FEN,
+            "rk5r/8/8/8/8/8/8/RK5R w KQkq - 0 1",
+        ] {
+            let mut b = Board::from_fen(fen, &l).unwrap();
+            check_hashes(&mut b, 3);
+        }
+
+        // The same position reached by different move orders has the same hash.
+        let mut b1 = Board::start_pos(&l);
+        let mut b2 = Board::start_pos(&l);
+        for (b, moves) in [
+            (&mut b1, ["g1f3", "g8f6", "b1c3", "b8c6"]),
+            (&mut b2, ["b1c3", "b8c6", "g1f3", "g8f6"]),
+        ] {
+            for m in moves {
+                b.make_move(Move::try_from(m).unwrap());
+            }
+        }
+        assert_eq!(b1.hash(), b2.hash());
+        assert_ne!(b1.hash(), Board::start_pos(&l).hash());
+        // Only the pieces moved so the pawns hash the same.
+        assert_eq!(b1.pawn_hash(), Board::start_pos(&l).pawn_hash());
+    }
+
+    #[test]
+    fn null_move_test() {
+        let l = LookupTables::generate();
+        let mut b = Board::from_fen(EN_PASSANT_FEN, &l).unwrap();
+        let fen = b.fen();
+
+        b.make_null_move();
+        assert_eq!(b.turn(), Player::Black);
+        assert_eq!(b.en_passant(), None);
+        assert_eq!(b.state.hash, b.state.compute_hash());
+        assert_eq!(
+            b.generate_moves(Legality::Legal).len(),
+            Board::from_fen(
+                "rnbqkbnr/1pp1pppp/p7/3pP3/8/8/PPPP1PPP/RNBQKBNR b KQkq - 1 3",
+                &l
+            )
+            .unwrap()
+            .generate_moves(Legality::Legal)
+            .len()
+        );
+
+        b.unmake_move();
+        assert_eq!(b.fen(), fen);
+    }
+
+    #[test]
+    fn outcome_test() {
+        let l = LookupTables::generate();
+
+        let b = Board::from_fen("k7/7R/1K6/8/8/8/8/8 b - - 0 1", &l).unwrap();
+        assert_eq!(b.outcome(), None);
+
+        let b = Board::from_fen("R6k/8/6K1/8/8/8/8/8 b - - 0 1", &l).unwrap();
+        assert_eq!(b.outcome(), Some(Outcome::Checkmate(Player::White)));
+
+        let b = Board::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", &l).unwrap();
+        assert_eq!(b.outcome(), Some(Outcome::Stalemate));
+
+        let b = Board::from_fen("7k/8/6K1/8/8/8/8/R7 b - - 100 80", &l).unwrap();
+        assert_eq!(b.outcome(), Some(Outcome::FiftyMoveRule));
+
+        let b = Board::from_fen("7k/8/6K1/8/2b5/8/4B3/8 b - - 0 1", &l).unwrap();
+        assert_eq!(b.outcome(), Some(Outcome::InsufficientMaterial));
+
+        let b = Board::from_fen("7k/8/6K1/8/2b5/8/3B4/8 b - - 0 1", &l).unwrap();
+        assert_eq!(b.outcome(), None);
+
+        let mut b = Board::start_pos(&l);
+        for m in [
+            Move(Square::G1, Square::F3, None),
+            Move(Square::G8, Square::F6, None),
+            Move(Square::F3, Square::G1, None),
+            Move(Square::F6, Square::G8, None),
+            Move(Square::G1, Square::F3, None),
+            Move(Square::G8, Square::F6, None),
+            Move(Square::F3, Square::G1, None),
+        ] {
+            assert_eq!(b.outcome(), None);
+            let legal_moves = b.generate_moves(Legality::Legal);
+            b.apply_move(m, &legal_moves);
+        }
+        let legal_moves = b.generate_moves(Legality::Legal);
+        b.apply_move(Move(Square::F6, Square::G8, None), &legal_moves);
+        assert_eq!(b.outcome(), Some(Outcome::ThreefoldRepetition));
+    }
+
+    #[test]
+    fn move_counters_test() {
+        let l = LookupTables::generate();
+        let mut b = Board::start_pos(&l);
+        for (m, half_moves, full_moves) in [
+            (Move(Square::G1, Square::F3, None), 1, 1),
+            (Move(Square::G8, Square::F6, None), 2, 2),
+            (Move(Square::E2, Square::E4, None), 0, 2),
+            (Move(Square::F6, Square::E4, None), 0, 3),
+            (Move(Square::F3, Square::G1, None), 1, 3),
+        ] {
+            let legal_moves = b.generate_moves(Legality::Legal);
+            b.apply_move(m, &legal_moves);
+            assert_eq!(b.half_moves(), half_moves, "{}", m);
+            assert_eq!(b.full_moves(), full_moves, "{}", m);
+        }
+        assert_eq!(
+            b.fen(),
+            "rnbqkb1r/pppppppp/8/8/4n3/8/PPPP1PPP/RNBQKBNR b KQkq - 1 3"
+        );
+
+        for _ in 0..5 {
+            b.undo_move();
+        }
+        assert_eq!(b.fen(), STARTING_POS_FEN);
+    }
+
+    #[test]
+    fn repetitions_test() {
+        let l = LookupTables::generate();
+        let mut b = Board::start_pos(&l);
+        let play = |b: &mut Board, m: Move| {
+            let legal_moves = b.generate_moves(Legality::Legal);
+            b.apply_move(m, &legal_moves);
+        };
+
+        assert_eq!(b.repetitions(), 0);
+        for repetitions in [1, 2] {
+            play(&mut b, Move(Square::G1, Square::F3, None));
+            play(&mut b, Move(Square::G8, Square::F6, None));
+            play(&mut b, Move(Square::F3, Square::G1, None));
+            play(&mut b, Move(Square::F6, Square::G8, None));
+            assert_eq!(b.repetitions(), repetitions);
+        }
+
+        // A pawn move can't be undone, so the positions before it can't come up again.
+        play(&mut b, Move(Square::E2, Square::E3, None));
+        assert_eq!(b.repetitions(), 0);
+        play(&mut b, Move(Square::B8, Square::C6, None));
+        play(&mut b, Move(Square::G1, Square::F3, None));
+        play(&mut b, Move(Square::C6, Square::B8, None));
+        play(&mut b, Move(Square::F3, Square::G1, None));
+        assert_eq!(b.repetitions(), 1);
+    }
+
+    #[test]
+    fn insufficient_material_test() {
+        let l = LookupTables::generate();
+        let insufficient = |fen: &str| Board::from_fen(fen, &l).unwrap().is_insufficient_material();
+
+        assert!(insufficient("7k/8/6K1/8/8/8/8/8 w - - 0 1"));
+        assert!(insufficient("7k/8/6K1/8/8/8/8/6N1 w - - 0 1"));
+        assert!(insufficient("7k/8/6K1/8/8/8/8/5B2 w - - 0 1"));
+        // Bishops on a1 and h8, which are dark, and on h1 and a8, which are light.
+        assert!(insufficient("7b/8/6K1/8/8/8/8/B6k w - - 0 1"));
+        assert!(insufficient("b7/8/6K1/8/8/8/8/k6B w - - 0 1"));
+        assert!(!insufficient("b7/8/6K1/8/8/8/8/B6k w - - 0 1"));
+        assert!(!insufficient("7k/8/6K1/8/8/8/8/5BN1 w - - 0 1"));
+        assert!(!insufficient("7k/8/6K1/8/8/8/8/6NN w - - 0 1"));
+        assert!(!insufficient("7k/8/6K1/8/8/8/P7/8 w - - 0 1"));
+        assert!(!insufficient("7k/8/6K1/8/8/8/8/R7 w - - 0 1"));
+    }
 }
@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::board::{Board, Legality};
+use crate::core::{File, Move, PieceKind, Rank, Square};
+use crate::lookup_tables::LookupTables;
+use crate::pgn::Game;
+
+/// The size of an entry in a book file.
+const ENTRY_SIZE: usize = 16;
+
+/// A move for a position in a Polyglot book.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BookEntry {
+    /// The Polyglot key of the position.
+    pub key: u64,
+    /// The move with its destination square in bits 0 to 5, its origin in bits 6 to 11 and its
+    /// promotion in bits 12 to 14, from 1 for a knight to 4 for a queen. Castling moves are the
+    /// king moving onto its rook.
+    pub mv: u16,
+    /// How often the move is played compared to the other moves of the position.
+    pub weight: u16,
+    /// Unused, but kept so books are written back the way they were read.
+    pub learn: u32,
+}
+
+/// How `Book::build` picks the positions and moves to keep.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BuildOptions {
+    /// Moves after this many plies of a game are left out.
+    pub max_plies: usize,
+    /// Moves played in fewer games than this are left out.
+    pub min_games: u32,
+}
+
+impl Default for BuildOptions {
+    fn default() -> Self {
+        BuildOptions {
+            max_plies: 20,
+            min_games: 3,
+        }
+    }
+}
+
+/// An opening book in the Polyglot `.bin` format: entries of 16 big endian bytes sorted by
+/// position key.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Book {
+    entries: Vec<BookEntry>,
+}
+
+impl Book {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if !bytes.len().is_multiple_of(ENTRY_SIZE) {
+            return Err(format!(
+                "the length of a book must be a multiple of {} bytes",
+                ENTRY_SIZE
+            ));
+        }
+
+        let mut entries: Vec<BookEntry> = bytes
+            .chunks_exact(ENTRY_SIZE)
+            .map(|e| BookEntry {
+                key: u64::from_be_bytes(e[0..8].try_into().unwrap()),
+                mv: u16::from_be_bytes(e[8..10].try_into().unwrap()),
+                weight: u16::from_be_bytes(e[10..12].try_into().unwrap()),
+                learn: u32::from_be_bytes(e[12..16].try_into().unwrap()),
+            })
+            .collect();
+        // Books are meant to be sorted already, but looking moves up relies on it.
+        entries.sort_by_key(|e| e.key);
+        Ok(Book { entries })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.entries.len() * ENTRY_SIZE);
+        for e in &self.entries {
+            bytes.extend_from_slice(&e.key.to_be_bytes());
+            bytes.extend_from_slice(&e.mv.to_be_bytes());
+            bytes.extend_from_slice(&e.weight.to_be_bytes());
+            bytes.extend_from_slice(&e.learn.to_be_bytes());
+        }
+        bytes
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        Book::from_bytes(&bytes).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        fs::write(path, self.to_bytes())
+            .map_err(|e| format!("cannot write {}: {}", path.display(), e))
+    }
+
+    pub fn entries(&self) -> &[BookEntry] {
+        &self.entries
+    }
+
+    /// The legal moves the book has for `b` with their weights, heaviest first.
+    pub fn moves(&self, b: &Board) -> Vec<(Move, u16)> {
+        let key = b.polyglot_key();
+        let start = self.entries.partition_point(|e| e.key < key);
+        let legal_moves = b.generate_moves(Legality::Legal);
+
+        let mut moves: Vec<(Move, u16)> = self.entries[start..]
+            .iter()
+            .take_while(|e| e.key == key)
+            .filter_map(|e| {
+                legal_moves
+                    .iter()
+                    .find(|m| encode_move(b, m) == e.mv)
+                    .map(|m| (*m, e.weight))
+            })
+            .collect();
+        moves.sort_by_key(|(_, weight)| std::cmp::Reverse(*weight));
+        moves
+    }
+
+    /// A move for `b` picked at random in proportion to the weights of the book's moves, or
+    /// `None` if the book has no move with any weight.
+    pub fn choose(&self, b: &Board, seed: u64) -> Option<Move> {
+        let moves = self.moves(b);
+        let total: u32 = moves.iter().map(|(_, weight)| *weight as u32).sum();
+        if total == 0 {
+            return None;
+        }
+
+        let mut pick = StdRng::seed_from_u64(seed).gen_range(0..total);
+        for (m, weight) in moves {
+            if pick < weight as u32 {
+                return Some(m);
+            }
+            pick -= weight as u32;
+        }
+        unreachable!()
+    }
+
+    /// Builds a book from the moves played in `games`, weighted by how many games played them.
+    /// A game that can't be replayed is used up to the move that can't be read.
+    pub fn build(games: &[Game], l: &LookupTables, options: &BuildOptions) -> Self {
+        let mut counts: HashMap<(u64, u16), u32> = HashMap::new();
+        for game in games {
+            let Ok(mut b) = game.start_position(l) else {
+                continue;
+            };
+            for san in game.moves.iter().take(options.max_plies) {
+                let Ok(m) = b.parse_san(san) else {
+                    break;
+                };
+                *counts
+                    .entry((b.polyglot_key(), encode_move(&b, &m)))
+                    .or_default() += 1;
+                b.make_move(m);
+            }
+        }
+
+        let mut counts: Vec<((u64, u16), u32)> = counts
+            .into_iter()
+            .filter(|(_, count)| *count >= options.min_games)
+            .collect();
+        counts.sort_by_key(|((key, mv), count)| (*key, std::cmp::Reverse(*count), *mv));
+
+        // Weights only go up to u16::MAX, so positions played more often than that are scaled down
+        // keeping the moves in proportion.
+        let mut entries = Vec::with_capacity(counts.len());
+        for position in counts.chunk_by(|a, b| a.0 .0 == b.0 .0) {
+            let most = position[0].1;
+            for ((key, mv), count) in position {
+                let weight = if most > u16::MAX as u32 {
+                    (*count as u64 * u16::MAX as u64 / most as u64).max(1) as u16
+                } else {
+                    *count as u16
+                };
+                entries.push(BookEntry {
+                    key: *key,
+                    mv: *mv,
+                    weight,
+                    learn: 0,
+                });
+            }
+        }
+        Book { entries }
+    }
+}
+
+/// `m` packed the way Polyglot books store moves.
+fn encode_move(b: &Board, m: &Move) -> u16 {
+    let mut to = m.1;
+    // Chess960 castling moves are already encoded as the king taking its own rook.
+    if !b.is_chess960() && b.is_castling(m) {
+        let rook_file = if File::from(m.1) == File::G {
+            File::H
+        } else {
+            File::A
+        };
+        to = Square::from((rook_file, Rank::from(m.1)));
+    }
+    let promotion = match m.2 {
+        Some(PieceKind::Knight) => 1,
+        Some(PieceKind::Bishop) => 2,
+        Some(PieceKind::Rook) => 3,
+        Some(PieceKind::Queen) => 4,
+        _ => 0,
+    };
+
+    to as u16 | (m.0 as u16) << 6 | promotion << 12
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pgn;
+
+    #[test]
+    fn book_test() {
+        let l = LookupTables::generate();
+        let games = pgn::parse(
+            "1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 1-0
+            1. e4 e5 2. Nf3 Nc6 3. Bc4 Bc5 4. O-O 1/2-1/2
+            1. e4 c5 2. Nf3 d6 0-1
+            1. d4 d5 *",
+        )
+        .unwrap();
+        let options = BuildOptions {
+            max_plies: 6,
+            min_games: 1,
+        };
+        let book = Book::build(&games, &l, &options);
+
+        let mut b = Board::start_pos(&l);
+        let uci = |moves: Vec<(Move, u16)>| {
+            moves
+                .into_iter()
+                .map(|(m, weight)| (m.to_string(), weight))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            uci(book.moves(&b)),
+            [("e2e4".to_string(), 3), ("d2d4".to_string(), 1)]
+        );
+        b.make_move(Move::try_from("e2e4").unwrap());
+        assert_eq!(
+            uci(book.moves(&b)),
+            [("e7e5".to_string(), 2), ("c7c5".to_string(), 1)]
+        );
+
+        // Castling is the king moving onto its rook and is left out past the plies.
+        let castling = Book::build(
+            &games[1..2],
+            &l,
+            &BuildOptions {
+                max_plies: 7,
+                min_games: 1,
+            },
+        );
+        let e1h1 = (Square::E1 as u16) << 6 | Square::H1 as u16;
+        assert!(castling.entries().iter().any(|e| e.mv == e1h1));
+        assert!(!book.entries().iter().any(|e| e.mv == e1h1));
+
+        // Moves played in too few games are left out.
+        let frequent = Book::build(
+            &games,
+            &l,
+            &BuildOptions {
+                max_plies: 6,
+                min_games: 2,
+            },
+        );
+        assert_eq!(frequent.moves(&Board::start_pos(&l)).len(), 1);
+
+        // The choice follows the weights and a book reads back the same.
+        let start = Board::start_pos(&l);
+        let e4 = (0..100)
+            .filter(|seed| book.choose(&start, *seed) == Some(Move::try_from("e2e4").unwrap()))
+            .count();
+        assert!((60..=90).contains(&e4));
+        assert_eq!(Book::from_bytes(&book.to_bytes()).unwrap(), book);
+        assert!(Book::from_bytes(&[0; 15]).is_err());
+        assert_eq!(Book::default().choose(&start, 0), None);
+    }
+}
@@ -0,0 +1,124 @@
+use crate::core::PieceKind;
+
+/// The number of Chess960 start positions.
+pub const NUM_START_POSITIONS: u16 = 960;
+
+/// The Scharnagl number of the standard chess start position.
+pub const STANDARD_START_POSITION: u16 = 518;
+
+// The squares of the two knights among the five squares left after placing the bishops and queen.
+const KNIGHT_PLACEMENTS: [(usize, usize); 10] = [
+    (0, 1),
+    (0, 2),
+    (0, 3),
+    (0, 4),
+    (1, 2),
+    (1, 3),
+    (1, 4),
+    (2, 3),
+    (2, 4),
+    (3, 4),
+];
+
+/// The white back rank of the Chess960 start position with Scharnagl number `id`, from the A file
+/// to the H file.
+pub fn back_rank(id: u16) -> Option<[PieceKind; 8]> {
+    if id >= NUM_START_POSITIONS {
+        return None;
+    }
+
+    let mut pieces: [Option<PieceKind>; 8] = [None; 8];
+    let mut n = id as usize;
+
+    // Light squared bishop on b, d, f or h then dark squared bishop on a, c, e or g.
+    pieces[2 * (n % 4) + 1] = Some(PieceKind::Bishop);
+    n /= 4;
+    pieces[2 * (n % 4)] = Some(PieceKind::Bishop);
+    n /= 4;
+
+    let place_on_empty = |pieces: &mut [Option<PieceKind>; 8], index: usize, p| {
+        let file = (0..8).filter(|f| pieces[*f].is_none()).nth(index).unwrap();
+        pieces[file] = Some(p);
+    };
+
+    place_on_empty(&mut pieces, n % 6, PieceKind::Queen);
+    n /= 6;
+
+    // The second knight is placed after the first so its index among the empty squares shifts.
+    let (first_knight, second_knight) = KNIGHT_PLACEMENTS[n];
+    place_on_empty(&mut pieces, first_knight, PieceKind::Knight);
+    place_on_empty(&mut pieces, second_knight - 1, PieceKind::Knight);
+
+    // The king always goes between the rooks.
+    for p in [PieceKind::Rook, PieceKind::King, PieceKind::Rook] {
+        place_on_empty(&mut pieces, 0, p);
+    }
+
+    Some(pieces.map(|p| p.unwrap()))
+}
+
+/// The FEN of the Chess960 start position with Scharnagl number `id`. The castling rights use
+/// X-FEN, which is the same as standard FEN for start positions.
+pub fn start_pos_fen(id: u16) -> Option<String> {
+    let back_rank = back_rank(id)?;
+    let black: String = back_rank.iter().map(|p| p.to_string()).collect();
+
+    Some(format!(
+        "{}/pppppppp/8/8/8/8/PPPPPPPP/{} w KQkq - 0 1",
+        black,
+        black.to_uppercase()
+    ))
+}
+
+/// The Scharnagl number of a Chess960 back rank, the inverse of `back_rank`.
+pub fn start_pos_id(back_rank: &[PieceKind; 8]) -> Option<u16> {
+    (0..NUM_START_POSITIONS).find(|id| self::back_rank(*id).as_ref() == Some(back_rank))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::STARTING_POS_FEN;
+    use std::collections::HashSet;
+
+    #[test]
+    fn start_pos_fen_test() {
+        assert_eq!(
+            start_pos_fen(STANDARD_START_POSITION).unwrap(),
+            STARTING_POS_FEN
+        );
+        assert_eq!(
+            start_pos_fen(0).unwrap(),
+            "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1"
+        );
+        assert_eq!(
+            start_pos_fen(959).unwrap(),
+            "rkrnnqbb/pppppppp/8/8/8/8/PPPPPPPP/RKRNNQBB w KQkq - 0 1"
+        );
+        assert_eq!(start_pos_fen(960), None);
+    }
+
+    #[test]
+    fn back_rank_test() {
+        let mut seen = HashSet::new();
+        for id in 0..NUM_START_POSITIONS {
+            let back_rank = back_rank(id).unwrap();
+            assert!(seen.insert(back_rank.map(|p| p as u8)));
+            assert_eq!(start_pos_id(&back_rank), Some(id));
+
+            let files_of = |kind| {
+                back_rank
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, p)| **p == kind)
+                    .map(|(f, _)| f)
+                    .collect::<Vec<usize>>()
+            };
+            let bishops = files_of(PieceKind::Bishop);
+            let rooks = files_of(PieceKind::Rook);
+            let king = files_of(PieceKind::King)[0];
+            assert_eq!(bishops[0] % 2 + bishops[1] % 2, 1);
+            assert!(rooks[0] < king && king < rooks[1]);
+        }
+    }
+}
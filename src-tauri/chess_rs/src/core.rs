@@ -2,7 +2,7 @@
 
 use std::fmt;
 
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
 pub const MAX_MOVES: usize = 250;
 
@@ -28,6 +28,9 @@ pub const FILE_G: u64 = 0b01000000_01000000_01000000_01000000_01000000_01000000_
 /// Bit representation of file G.
 pub const FILE_H: u64 = 0b10000000_10000000_10000000_10000000_10000000_10000000_10000000_10000000;
 
+/// Bit representation of the light squares.
+pub const LIGHT_SQUARES: u64 = 0x55AA_55AA_55AA_55AA;
+
 /// Bit representation of rank 1.
 pub const RANK_1: u64 = 0x0000_0000_0000_00FF;
 /// Bit representation of rank 2.
@@ -211,7 +214,7 @@ impl TryFrom<&str> for PieceKind {
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum Player {
     White,
@@ -276,9 +279,23 @@ impl From<Piece> for Player {
 
 pub const PLAYERS: [Player; 2] = [Player::White, Player::Black];
 
-#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Move(pub Square, pub Square, pub Option<PieceKind>);
 
+/// Moves are serialised as UCI strings like `e2e4` and `e7e8q`.
+impl Serialize for Move {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Move {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Move::try_from(s.as_str()).map_err(de::Error::custom)
+    }
+}
+
 impl fmt::Display for Move {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let promotion = match self.2 {
@@ -289,6 +306,31 @@ impl fmt::Display for Move {
     }
 }
 
+impl TryFrom<&str> for Move {
+    type Error = String;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        if s.len() != 4 && s.len() != 5 || !s.is_ascii() {
+            return Err(format!("invalid move: {}", s));
+        }
+
+        let source = Square::try_from(&s[0..2])?;
+        let dest = Square::try_from(&s[2..4])?;
+        let promotion = if s.len() == 5 {
+            match PieceKind::try_from(&s[4..])? {
+                PieceKind::King | PieceKind::Pawn => {
+                    return Err(format!("invalid promotion piece: {}", &s[4..]))
+                }
+                pk => Some(pk),
+            }
+        } else {
+            None
+        };
+
+        Ok(Move(source, dest, promotion))
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 #[repr(u8)]
 pub enum File {
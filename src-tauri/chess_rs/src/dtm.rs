@@ -0,0 +1,1016 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::bitboard::BitBoard;
+use crate::board::{Board, Legality};
+use crate::core::{Piece, PieceKind, Player, PIECES, SQUARES};
+use crate::endgame::{self, MaterialKey};
+use crate::lookup_tables::LookupTables;
+
+/// The extension of table files.
+pub const EXTENSION: &str = "crtb";
+
+/// Identifies a table file.
+const MAGIC: &[u8; 4] = b"CRTB";
+const VERSION: u32 = 1;
+
+/// The most pieces in a table, including the kings.
+pub const MAX_PIECES: usize = 4;
+
+/// The longest distance to mate a table can store.
+const MAX_PLIES: u16 = 253;
+
+// The values of positions while a table is generated, besides their distances to mate.
+const UNKNOWN: u16 = u16::MAX;
+const DRAW: u16 = u16::MAX - 1;
+const INVALID: u16 = u16::MAX - 2;
+/// A position that can't be lost because a capture or promotion draws.
+const NEVER_LOST: u16 = u16::MAX;
+
+/// The kinds of piece in the order they're listed in a signature.
+const KIND_ORDER: [PieceKind; 6] = [
+    PieceKind::King,
+    PieceKind::Queen,
+    PieceKind::Rook,
+    PieceKind::Bishop,
+    PieceKind::Knight,
+    PieceKind::Pawn,
+];
+
+/// The a1-d1-d4 triangle, which one of the symmetries of the board puts any square in.
+const TRIANGLE: [usize; 10] = [0, 1, 2, 3, 9, 10, 11, 18, 19, 27];
+
+/// The distance to mate of a position with perfect play, in plies from the point of view of the
+/// player to move. The 50 move rule isn't taken into account.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Dtm {
+    /// The player to move mates in this many plies, which is odd.
+    Win(u32),
+    /// The player to move is mated in this many plies, which is even, or 0 if they're mated.
+    Loss(u32),
+    Draw,
+}
+
+impl Dtm {
+    fn from_plies(plies: u16) -> Dtm {
+        if plies % 2 == 1 {
+            Dtm::Win(plies as u32)
+        } else {
+            Dtm::Loss(plies as u32)
+        }
+    }
+}
+
+fn piece(player: Player, kind: PieceKind) -> Piece {
+    PIECES[player as usize * 6 + kind as usize]
+}
+
+fn swap_colour(p: Piece) -> Piece {
+    let player = match Player::from(p) {
+        Player::White => Player::Black,
+        Player::Black => Player::White,
+    };
+    piece(player, PieceKind::from(p))
+}
+
+/// The pieces of an endgame, like `KQvKR` for king and queen against king and rook, with white's
+/// pieces first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Signature(Vec<Piece>);
+
+impl Signature {
+    pub fn of(b: &Board) -> Self {
+        Signature::from_key(&endgame::material_key(b))
+    }
+
+    fn from_key(key: &MaterialKey) -> Self {
+        let mut pieces = Vec::new();
+        for player in [Player::White, Player::Black] {
+            for kind in KIND_ORDER {
+                let p = piece(player, kind);
+                pieces.extend((0..key[p as usize]).map(|_| p));
+            }
+        }
+        Signature(pieces)
+    }
+
+    pub fn key(&self) -> MaterialKey {
+        let mut key = [0; 12];
+        for p in &self.0 {
+            key[*p as usize] += 1;
+        }
+        key
+    }
+
+    /// The same endgame with the colours swapped.
+    pub fn flipped(&self) -> Self {
+        let mut key = [0; 12];
+        for p in &self.0 {
+            key[swap_colour(*p) as usize] += 1;
+        }
+        Signature::from_key(&key)
+    }
+
+    pub fn pieces(&self) -> &[Piece] {
+        &self.0
+    }
+
+    fn has_pawns(&self) -> bool {
+        self.0
+            .iter()
+            .any(|p| PieceKind::from(*p) == PieceKind::Pawn)
+    }
+
+    /// The endgames a capture or promotion leads to.
+    fn successors(&self) -> Vec<Signature> {
+        let mut successors = Vec::new();
+        for (i, p) in self.0.iter().enumerate() {
+            let mut rest = self.0.clone();
+            match PieceKind::from(*p) {
+                PieceKind::King => continue,
+                PieceKind::Pawn => {
+                    for kind in [
+                        PieceKind::Queen,
+                        PieceKind::Rook,
+                        PieceKind::Bishop,
+                        PieceKind::Knight,
+                    ] {
+                        rest[i] = piece(Player::from(*p), kind);
+                        successors.push(Signature::from_key(&Signature(rest.clone()).key()));
+                    }
+                    rest[i] = *p;
+                }
+                _ => {}
+            }
+            rest.remove(i);
+            successors.push(Signature::from_key(&Signature(rest).key()));
+        }
+        successors
+    }
+}
+
+impl TryFrom<&str> for Signature {
+    type Error = String;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        let error = || format!("invalid signature: {}", s);
+        let (white, black) = s.split_once('v').ok_or_else(error)?;
+        let mut key = [0; 12];
+        for (pieces, white) in [(white, true), (black, false)] {
+            for c in pieces.chars() {
+                if !c.is_ascii_uppercase() {
+                    return Err(error());
+                }
+                let c = if white { c } else { c.to_ascii_lowercase() };
+                let p = Piece::try_from(c).map_err(|_| error())?;
+                key[p as usize] += 1;
+            }
+        }
+
+        if key[Piece::WhiteKing as usize] != 1 || key[Piece::BlackKing as usize] != 1 {
+            return Err(format!("{} doesn't have one king each", s));
+        }
+        let signature = Signature::from_key(&key);
+        if signature.0.len() > MAX_PIECES {
+            return Err(format!("{} has more than {} pieces", signature, MAX_PIECES));
+        }
+        Ok(signature)
+    }
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for player in [Player::White, Player::Black] {
+            if player == Player::Black {
+                write!(f, "v")?;
+            }
+            for p in self.0.iter().filter(|p| Player::from(**p) == player) {
+                write!(f, "{}", piece(Player::White, PieceKind::from(*p)))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Symmetry `g` of the board applied to a square: bit 2 mirrors it in the a1-h8 diagonal, bit 0
+/// flips its file and bit 1 its rank.
+fn transform(s: usize, g: usize) -> usize {
+    let mut s = s;
+    if g & 4 != 0 {
+        s = ((s >> 3) | (s << 3)) & 63;
+    }
+    if g & 1 != 0 {
+        s ^= 7;
+    }
+    if g & 2 != 0 {
+        s ^= 56;
+    }
+    s
+}
+
+/// The squares of the pieces of a signature, in its order.
+type Squares = [usize; MAX_PIECES];
+
+/// Maps the positions of a signature to indexes. Positions that are the same as each other after
+/// a symmetry of the board share an index: the first piece, the white king, is on the a to d
+/// files, and without pawns, in the a1-d1-d4 triangle. The one used is the lowest of the
+/// positions that put it there.
+struct Indexer {
+    pieces: Vec<Piece>,
+    /// The ranges of identical pieces in `pieces`, whose squares are kept in order.
+    groups: Vec<Range<usize>>,
+    has_pawns: bool,
+    size: usize,
+}
+
+impl Indexer {
+    fn new(signature: &Signature) -> Self {
+        let pieces = signature.0.clone();
+        let mut groups: Vec<Range<usize>> = Vec::new();
+        for (i, p) in pieces.iter().enumerate() {
+            match groups.last_mut() {
+                Some(group) if pieces[group.start] == *p => group.end = i + 1,
+                _ => groups.push(i..i + 1),
+            }
+        }
+
+        let has_pawns = signature.has_pawns();
+        let regions = if has_pawns { 32 } else { TRIANGLE.len() };
+        Indexer {
+            size: regions * 64usize.pow(pieces.len() as u32 - 1) * 2,
+            pieces,
+            groups,
+            has_pawns,
+        }
+    }
+
+    fn symmetries(&self) -> usize {
+        if self.has_pawns {
+            2
+        } else {
+            8
+        }
+    }
+
+    /// The index of the first piece's square among the squares it can be on.
+    fn region(&self, s: usize) -> Option<usize> {
+        if self.has_pawns {
+            (s % 8 < 4).then_some(s / 8 * 4 + s % 8)
+        } else {
+            TRIANGLE.iter().position(|&t| t == s)
+        }
+    }
+
+    fn region_square(&self, region: usize) -> usize {
+        if self.has_pawns {
+            region / 4 * 8 + region % 4
+        } else {
+            TRIANGLE[region]
+        }
+    }
+
+    /// `squares` after symmetry `g`, with the squares of identical pieces in order.
+    fn normalised(&self, squares: &Squares, g: usize) -> Squares {
+        let mut result = [0; MAX_PIECES];
+        for i in 0..self.pieces.len() {
+            result[i] = transform(squares[i], g);
+        }
+        for group in &self.groups {
+            result[group.clone()].sort_unstable();
+        }
+        result
+    }
+
+    /// The squares of the position that represents `squares`.
+    fn canonical(&self, squares: &Squares) -> Squares {
+        (0..self.symmetries())
+            .map(|g| self.normalised(squares, g))
+            .filter(|s| self.region(s[0]).is_some())
+            .min()
+            .unwrap()
+    }
+
+    fn index(&self, canonical: &Squares, turn: Player) -> usize {
+        let mut idx = self.region(canonical[0]).unwrap();
+        for s in &canonical[1..self.pieces.len()] {
+            idx = idx * 64 + s;
+        }
+        turn as usize * self.size / 2 + idx
+    }
+
+    fn decode(&self, idx: usize) -> (Squares, Player) {
+        let turn = if idx < self.size / 2 {
+            Player::White
+        } else {
+            Player::Black
+        };
+        let mut idx = idx % (self.size / 2);
+        let mut squares = [0; MAX_PIECES];
+        for i in (1..self.pieces.len()).rev() {
+            squares[i] = idx % 64;
+            idx /= 64;
+        }
+        squares[0] = self.region_square(idx);
+        (squares, turn)
+    }
+
+    /// The squares of the pieces of `b` in the order of the signature, with the colours swapped
+    /// and the board flipped if `flip` is set.
+    fn squares_of(&self, b: &Board, flip: bool) -> Squares {
+        let mut squares = [0; MAX_PIECES];
+        for group in &self.groups {
+            let p = self.pieces[group.start];
+            let (p, flip_squares) = if flip { (swap_colour(p), 56) } else { (p, 0) };
+            for (i, s) in group.clone().zip(b.piece_bb(p)) {
+                squares[i] = s as usize ^ flip_squares;
+            }
+        }
+        squares
+    }
+
+    /// Whether pieces can be on `squares`: no two on the same square and no pawns on the first or
+    /// last rank.
+    fn is_placeable(&self, squares: &Squares) -> bool {
+        let mut occupied = 0u64;
+        for (p, &s) in self.pieces.iter().zip(squares) {
+            if occupied & (1 << s) != 0
+                || (PieceKind::from(*p) == PieceKind::Pawn && !(8..56).contains(&s))
+            {
+                return false;
+            }
+            occupied |= 1 << s;
+        }
+        true
+    }
+
+    /// The indexes of the positions the last move could have been made from, where it wasn't a
+    /// capture or promotion. Some may be illegal positions.
+    fn predecessors(&self, squares: &Squares, turn: Player, l: &LookupTables) -> Vec<usize> {
+        let mover = match turn {
+            Player::White => Player::Black,
+            Player::Black => Player::White,
+        };
+        let occupied = self.pieces[..]
+            .iter()
+            .zip(squares)
+            .fold(0u64, |occ, (_, &s)| occ | 1 << s);
+
+        let mut predecessors = Vec::new();
+        for (i, p) in self.pieces.iter().enumerate() {
+            if Player::from(*p) != mover {
+                continue;
+            }
+
+            let s = squares[i];
+            let targets = if PieceKind::from(*p) == PieceKind::Pawn {
+                // Pawns move back towards their own side, and can have come from their starting
+                // square two ranks back.
+                let (back, start_rank, rank) = match mover {
+                    Player::White => (-8i32, 1, s / 8),
+                    Player::Black => (8, 1, 7 - s / 8),
+                };
+                let mut targets = 0u64;
+                let one_back = (s as i32 + back) as usize;
+                if rank >= 2 && occupied & (1 << one_back) == 0 {
+                    targets |= 1 << one_back;
+                    let two_back = (s as i32 + 2 * back) as usize;
+                    if rank == start_rank + 2 && occupied & (1 << two_back) == 0 {
+                        targets |= 1 << two_back;
+                    }
+                }
+                targets
+            } else {
+                l.lookup_moves(*p, SQUARES[s], BitBoard(occupied)).0 & !occupied
+            };
+
+            for t in BitBoard(targets) {
+                let mut predecessor = *squares;
+                predecessor[i] = t as usize;
+                predecessors.push(self.index(&self.canonical(&predecessor), mover));
+            }
+        }
+        predecessors
+    }
+}
+
+/// The distance to mate of every position of an endgame.
+#[derive(Clone, PartialEq)]
+pub struct DtmTable {
+    signature: Signature,
+    /// The distance to mate in plies plus one of each index, or 0 for draws and positions that
+    /// can't happen.
+    entries: Vec<u8>,
+}
+
+impl fmt::Debug for DtmTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "DtmTable({})", self.signature)
+    }
+}
+
+impl DtmTable {
+    pub fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    /// The distance to mate of `b`, which can have the material of the table with either
+    /// colours, or `None` if it doesn't. En passant captures are ignored.
+    pub fn probe(&self, b: &Board) -> Option<Dtm> {
+        let key = endgame::material_key(b);
+        let flip = if key == self.signature.key() {
+            false
+        } else if key == self.signature.flipped().key() {
+            true
+        } else {
+            return None;
+        };
+
+        let indexer = Indexer::new(&self.signature);
+        let squares = indexer.squares_of(b, flip);
+        let turn = match (b.turn(), flip) {
+            (Player::White, false) | (Player::Black, true) => Player::White,
+            _ => Player::Black,
+        };
+        let value = self.entries[indexer.index(&indexer.canonical(&squares), turn)];
+        Some(match value {
+            0 => Dtm::Draw,
+            v => Dtm::from_plies(v as u16 - 1),
+        })
+    }
+
+    /// Works out the table of `signature` by retrograde analysis. Starting from the mates, the
+    /// positions a move before the positions already known are found with the un-move
+    /// generator: a position with a move to a lost position is won, and one with only moves to
+    /// won positions is lost. `tables` has to have the tables of the endgames captures and
+    /// promotions lead to.
+    pub fn generate(
+        signature: &Signature,
+        tables: &DtmTables,
+        l: &LookupTables,
+    ) -> Result<Self, String> {
+        let indexer = Indexer::new(signature);
+        let key = signature.key();
+
+        let mut values = vec![UNKNOWN; indexer.size];
+        // The number of moves to positions of this endgame that aren't known to lose.
+        let mut remaining = vec![0u8; indexer.size];
+        // The longest a position can be lost in, going by its captures and promotions.
+        let mut conversion_loss = vec![0u16; indexer.size];
+        // The positions with each distance to mate, in the order they're found.
+        let mut found: Vec<Vec<u32>> = Vec::new();
+        let add = |found: &mut Vec<Vec<u32>>, plies: u16, idx: usize| {
+            if found.len() <= plies as usize {
+                found.resize(plies as usize + 1, Vec::new());
+            }
+            found[plies as usize].push(idx as u32);
+        };
+
+        for idx in 0..indexer.size {
+            let (squares, turn) = indexer.decode(idx);
+            if !indexer.is_placeable(&squares) || indexer.canonical(&squares) != squares {
+                values[idx] = INVALID;
+                continue;
+            }
+            let pieces: Vec<_> = indexer
+                .pieces
+                .iter()
+                .zip(squares)
+                .map(|(p, s)| (*p, SQUARES[s]))
+                .collect();
+            let Ok(mut b) = Board::from_pieces(&pieces, turn, l) else {
+                values[idx] = INVALID;
+                continue;
+            };
+
+            let moves = b.generate_moves(Legality::Legal);
+            if moves.is_empty() {
+                if b.is_in_check() {
+                    values[idx] = 0;
+                    add(&mut found, 0, idx);
+                } else {
+                    values[idx] = DRAW;
+                }
+                continue;
+            }
+
+            let mut best_win = None;
+            let mut longest_loss = 0;
+            let mut can_draw = false;
+            // The moves to the positions of this endgame, as un-moves of the positions that
+            // represent them. There's one for each un-move that finds this position from them.
+            let mut unmoves = Vec::new();
+            for m in moves {
+                b.make_move(m);
+                if endgame::material_key(&b) != key {
+                    match tables.probe(&b) {
+                        Some(Dtm::Loss(plies)) => {
+                            let win = plies as u16 + 1;
+                            best_win = Some(best_win.map_or(win, |w: u16| w.min(win)));
+                        }
+                        Some(Dtm::Win(plies)) => longest_loss = longest_loss.max(plies as u16 + 1),
+                        Some(Dtm::Draw) => can_draw = true,
+                        None => {
+                            return Err(format!(
+                                "{} needs the {} table",
+                                signature,
+                                Signature::of(&b)
+                            ))
+                        }
+                    }
+                } else {
+                    let child = indexer.squares_of(&b, false);
+                    let canonical = indexer.canonical(&child);
+                    let child_idx = indexer.index(&canonical, b.turn());
+                    for g in 0..indexer.symmetries() {
+                        if indexer.normalised(&child, g) == canonical {
+                            let (from, to) = (m.0 as usize, m.1 as usize);
+                            unmoves.push((child_idx, transform(to, g), transform(from, g)));
+                        }
+                    }
+                }
+                b.unmake_move();
+            }
+            unmoves.sort_unstable();
+            unmoves.dedup();
+            remaining[idx] = unmoves.len() as u8;
+
+            if let Some(win) = best_win {
+                values[idx] = win;
+                add(&mut found, win, idx);
+            } else if unmoves.is_empty() {
+                if can_draw {
+                    values[idx] = DRAW;
+                } else {
+                    values[idx] = longest_loss;
+                    add(&mut found, longest_loss, idx);
+                }
+            } else {
+                conversion_loss[idx] = if can_draw { NEVER_LOST } else { longest_loss };
+            }
+        }
+
+        let mut plies = 0;
+        while plies < found.len() {
+            let positions = std::mem::take(&mut found[plies]);
+            let plies_u16 = plies as u16;
+            for idx in positions {
+                let idx = idx as usize;
+                // The position has been found to be won sooner.
+                if values[idx] != plies_u16 {
+                    continue;
+                }
+                if plies_u16 >= MAX_PLIES {
+                    return Err(format!(
+                        "{} has mates longer than a table can store",
+                        signature
+                    ));
+                }
+
+                let (squares, turn) = indexer.decode(idx);
+                for predecessor in indexer.predecessors(&squares, turn, l) {
+                    let value = values[predecessor];
+                    if plies % 2 == 0 {
+                        // A move to a lost position wins.
+                        let is_longer_win = value % 2 == 1 && value < INVALID;
+                        if value == UNKNOWN || (is_longer_win && value > plies_u16 + 1) {
+                            values[predecessor] = plies_u16 + 1;
+                            add(&mut found, plies_u16 + 1, predecessor);
+                        }
+                    } else if value == UNKNOWN {
+                        // A position is lost when all its moves are to won positions.
+                        remaining[predecessor] -= 1;
+                        if remaining[predecessor] == 0 && conversion_loss[predecessor] != NEVER_LOST
+                        {
+                            let loss = (plies_u16 + 1).max(conversion_loss[predecessor]);
+                            values[predecessor] = loss;
+                            add(&mut found, loss, predecessor);
+                        }
+                    }
+                }
+            }
+            plies += 1;
+        }
+
+        let entries = values
+            .into_iter()
+            .map(|v| if v >= INVALID { 0 } else { v as u8 + 1 })
+            .collect();
+        Ok(DtmTable {
+            signature: signature.clone(),
+            entries,
+        })
+    }
+
+    /// The table as a file: `CRTB`, the version as a little endian `u32`, the length of the
+    /// signature as a byte and the signature, the number of entries as a little endian `u64`,
+    /// then the entries with runs of the same entry compressed.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        let name = self.signature.to_string();
+        bytes.push(name.len() as u8);
+        bytes.extend_from_slice(name.as_bytes());
+        bytes.extend_from_slice(&(self.entries.len() as u64).to_le_bytes());
+
+        // Runs of 3 to 130 of the same entry are a byte of 125 plus the length and the entry, and
+        // other entries go in groups of up to 128 after a byte of the count less one.
+        let entries = &self.entries;
+        let run_at = |i: usize| {
+            entries[i..]
+                .iter()
+                .take(130)
+                .take_while(|v| **v == entries[i])
+                .count()
+        };
+        let mut i = 0;
+        while i < entries.len() {
+            let run = run_at(i);
+            if run >= 3 {
+                bytes.push((run + 125) as u8);
+                bytes.push(entries[i]);
+                i += run;
+                continue;
+            }
+
+            let start = i;
+            while i < entries.len() && i - start < 128 && run_at(i) < 3 {
+                i += 1;
+            }
+            bytes.push((i - start - 1) as u8);
+            bytes.extend_from_slice(&entries[start..i]);
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < 9 || &bytes[..4] != MAGIC {
+            return Err("not a table file".to_string());
+        }
+        let version = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+        if version != VERSION {
+            return Err(format!("unsupported table version: {}", version));
+        }
+        let name_end = 9 + bytes[8] as usize;
+        let name = bytes
+            .get(9..name_end)
+            .and_then(|name| std::str::from_utf8(name).ok())
+            .ok_or("the signature is cut short")?;
+        let signature = Signature::try_from(name)?;
+        let count = bytes
+            .get(name_end..name_end + 8)
+            .map(|b| u64::from_le_bytes(b.try_into().unwrap()) as usize)
+            .ok_or("the entry count is cut short")?;
+        if count != Indexer::new(&signature).size {
+            return Err(format!(
+                "wrong number of entries for {}: {}",
+                signature, count
+            ));
+        }
+
+        let mut entries = Vec::with_capacity(count);
+        let mut data = bytes[name_end + 8..].iter();
+        while let Some(&control) = data.next() {
+            if control >= 128 {
+                let value = *data.next().ok_or("a run is cut short")?;
+                entries.resize(entries.len() + control as usize - 125, value);
+            } else {
+                for _ in 0..=control {
+                    entries.push(*data.next().ok_or("a group of entries is cut short")?);
+                }
+            }
+            if entries.len() > count {
+                return Err("too many entries".to_string());
+            }
+        }
+        if entries.len() != count {
+            return Err(format!(
+                "expected {} entries but found {}",
+                count,
+                entries.len()
+            ));
+        }
+
+        Ok(DtmTable { signature, entries })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        DtmTable::from_bytes(&bytes).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        fs::write(path, self.to_bytes())
+            .map_err(|e| format!("cannot write {}: {}", path.display(), e))
+    }
+}
+
+/// The distance to mate tables of a set of endgames.
+#[derive(Default, PartialEq)]
+pub struct DtmTables {
+    /// The tables keyed by their material with both colours.
+    tables: HashMap<MaterialKey, Arc<DtmTable>>,
+    max_pieces: usize,
+}
+
+impl fmt::Debug for DtmTables {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut names: Vec<String> = self.tables().map(|t| t.signature.to_string()).collect();
+        names.sort();
+        write!(f, "DtmTables({})", names.join(", "))
+    }
+}
+
+impl DtmTables {
+    /// Loads the tables in `dir`.
+    pub fn load_dir(dir: impl AsRef<Path>) -> Result<Self, String> {
+        let dir = dir.as_ref();
+        let entries =
+            fs::read_dir(dir).map_err(|e| format!("cannot read {}: {}", dir.display(), e))?;
+        let mut tables = DtmTables::default();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|e| e == EXTENSION) {
+                tables.insert(DtmTable::load(&path)?);
+            }
+        }
+        Ok(tables)
+    }
+
+    pub fn insert(&mut self, table: DtmTable) {
+        self.max_pieces = self.max_pieces.max(table.signature.0.len());
+        let table = Arc::new(table);
+        self.tables
+            .insert(table.signature.flipped().key(), table.clone());
+        self.tables.insert(table.signature.key(), table);
+    }
+
+    pub fn get(&self, signature: &Signature) -> Option<&DtmTable> {
+        self.tables.get(&signature.key()).map(|t| t.as_ref())
+    }
+
+    /// Each of the tables once.
+    pub fn tables(&self) -> impl Iterator<Item = &DtmTable> {
+        self.tables
+            .iter()
+            .filter(|(key, table)| **key == table.signature.key())
+            .map(|(_, table)| table.as_ref())
+    }
+
+    /// The most pieces in any of the tables.
+    pub fn max_pieces(&self) -> usize {
+        self.max_pieces
+    }
+
+    /// The distance to mate of `b`, or `None` if there isn't a table for it. Two kings are
+    /// always drawn.
+    pub fn probe(&self, b: &Board) -> Option<Dtm> {
+        let key = endgame::material_key(b);
+        if key.iter().map(|&n| n as usize).sum::<usize>() == 2 {
+            return Some(Dtm::Draw);
+        }
+        if b.can_castle() {
+            return None;
+        }
+        self.tables.get(&key)?.probe(b)
+    }
+
+    /// Generates the table of `signature` and the tables it needs that aren't in the set yet,
+    /// calling `on_generated` with each one after it's added.
+    pub fn generate(
+        &mut self,
+        signature: &Signature,
+        l: &LookupTables,
+        on_generated: &mut dyn FnMut(&DtmTable),
+    ) -> Result<(), String> {
+        if self.get(signature).is_some() || signature.0.len() <= 2 {
+            return Ok(());
+        }
+        for successor in signature.successors() {
+            self.generate(&successor, l, on_generated)?;
+        }
+
+        let table = DtmTable::generate(signature, self, l)?;
+        on_generated(&table);
+        self.insert(table);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn probe(tables: &DtmTables, fen: &str, l: &LookupTables) -> Option<Dtm> {
+        tables.probe(&Board::from_fen(fen, l).unwrap())
+    }
+
+    #[test]
+    fn signature_test() {
+        let signature = Signature::try_from("KRvKQ").unwrap();
+        assert_eq!(signature.to_string(), "KRvKQ");
+        assert_eq!(signature.flipped().to_string(), "KQvKR");
+        assert_eq!(Signature::try_from("KNBvK").unwrap().to_string(), "KBNvK");
+
+        let mut successors: Vec<String> = Signature::try_from("KPvKR")
+            .unwrap()
+            .successors()
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        successors.sort();
+        assert_eq!(
+            successors,
+            ["KBvKR", "KNvKR", "KPvK", "KQvKR", "KRvKR", "KvKR"]
+        );
+
+        assert!(Signature::try_from("KRK").is_err());
+        assert!(Signature::try_from("KRvR").is_err());
+        assert!(Signature::try_from("KQRBvK").is_err());
+        assert!(Signature::try_from("KXvK").is_err());
+    }
+
+    #[test]
+    fn indexer_test() {
+        let indexer = Indexer::new(&Signature::try_from("KNNvK").unwrap());
+        // Swapping the knights or mirroring the board gives the same index.
+        let squares = [5, 40, 20, 63];
+        let swapped = [5, 20, 40, 63];
+        let mirrored = squares.map(|s| s ^ 7);
+        let index = indexer.index(&indexer.canonical(&squares), Player::Black);
+        assert_eq!(
+            indexer.index(&indexer.canonical(&swapped), Player::Black),
+            index
+        );
+        assert_eq!(
+            indexer.index(&indexer.canonical(&mirrored), Player::Black),
+            index
+        );
+        assert_eq!(
+            indexer.decode(index),
+            (indexer.canonical(&squares), Player::Black)
+        );
+    }
+
+    #[test]
+    fn generate_test() {
+        let l = LookupTables::generate();
+        let mut tables = DtmTables::default();
+        let mut generated = Vec::new();
+        tables
+            .generate(&Signature::try_from("KRvK").unwrap(), &l, &mut |t| {
+                generated.push(t.signature().to_string())
+            })
+            .unwrap();
+        assert_eq!(generated, ["KRvK"]);
+        assert_eq!(tables.max_pieces(), 3);
+
+        // Mate in one and mated.
+        assert_eq!(
+            probe(&tables, "k7/8/1K6/8/8/8/8/7R w - - 0 1", &l),
+            Some(Dtm::Win(1))
+        );
+        assert_eq!(
+            probe(&tables, "R1k5/8/2K5/8/8/8/8/8 b - - 0 1", &l),
+            Some(Dtm::Loss(0))
+        );
+        // Black to move takes the rook, and the same with the colours swapped.
+        assert_eq!(
+            probe(&tables, "8/8/8/8/8/8/1k6/1R5K b - - 0 1", &l),
+            Some(Dtm::Draw)
+        );
+        assert_eq!(
+            probe(&tables, "1r5k/1K6/8/8/8/8/8/8 w - - 0 1", &l),
+            Some(Dtm::Draw)
+        );
+        // The longest mates take 16 moves.
+        let longest = tables
+            .get(&Signature::try_from("KRvK").unwrap())
+            .unwrap()
+            .entries
+            .iter()
+            .max()
+            .copied();
+        assert_eq!(longest, Some(32 + 1));
+
+        // Every move from a won position leads to a position that's won no sooner, and the best
+        // one keeps the distance.
+        let mut b = Board::from_fen("8/8/8/4k3/8/8/8/R3K3 w - - 0 1", &l).unwrap();
+        let Some(Dtm::Win(plies)) = tables.probe(&b) else {
+            panic!("expected a win");
+        };
+        let mut best = u32::MAX;
+        for m in b.generate_moves(Legality::Legal) {
+            b.make_move(m);
+            if let Some(Dtm::Loss(child)) = tables.probe(&b) {
+                best = best.min(child + 1);
+            }
+            b.unmake_move();
+        }
+        assert_eq!(best, plies);
+
+        let bytes = tables
+            .get(&Signature::try_from("KvKR").unwrap())
+            .unwrap()
+            .to_bytes();
+        let table = DtmTable::from_bytes(&bytes).unwrap();
+        assert_eq!(
+            &table,
+            tables.get(&Signature::try_from("KRvK").unwrap()).unwrap()
+        );
+        assert!(bytes.len() < table.entries.len());
+        assert!(DtmTable::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(DtmTable::from_bytes(b"CRTB").is_err());
+    }
+
+    #[test]
+    fn kpk_test() {
+        let l = LookupTables::generate();
+        let mut tables = DtmTables::default();
+        let mut generated = Vec::new();
+        tables
+            .generate(&Signature::try_from("KPvK").unwrap(), &l, &mut |t| {
+                generated.push(t.signature().to_string())
+            })
+            .unwrap();
+        generated.sort();
+        assert_eq!(generated, ["KBvK", "KNvK", "KPvK", "KQvK", "KRvK"]);
+        // Queen endings take up to 10 moves to mate and minor pieces can't mate, so their tables
+        // are all draws.
+        let longest = |name| {
+            let table = tables.get(&Signature::try_from(name).unwrap()).unwrap();
+            table.entries.iter().max().copied().unwrap()
+        };
+        assert_eq!(longest("KQvK"), 20 + 1);
+        assert_eq!(longest("KBvK"), 0);
+        assert_eq!(longest("KNvK"), 0);
+
+        // The table agrees with the KPK bitbase the evaluation uses on every position.
+        for turn in [Player::White, Player::Black] {
+            for wk in SQUARES {
+                for bk in SQUARES {
+                    for p in &SQUARES[8..56] {
+                        let pieces = [
+                            (Piece::WhiteKing, wk),
+                            (Piece::BlackKing, bk),
+                            (Piece::WhitePawn, *p),
+                        ];
+                        let Ok(b) = Board::from_pieces(&pieces, turn, &l) else {
+                            continue;
+                        };
+                        let white_wins = match tables.probe(&b).unwrap() {
+                            Dtm::Win(_) => turn == Player::White,
+                            Dtm::Loss(_) => turn == Player::Black,
+                            Dtm::Draw => false,
+                        };
+                        let score = endgame::evaluate(&b).unwrap();
+                        assert_eq!(score != 0, white_wins, "{}", b.fen());
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn black_pawn_test() {
+        let l = LookupTables::generate();
+        let mut white = DtmTables::default();
+        white
+            .generate(&Signature::try_from("KPvK").unwrap(), &l, &mut |_| {})
+            .unwrap();
+        let mut black = DtmTables::default();
+        black
+            .generate(&Signature::try_from("KvKP").unwrap(), &l, &mut |_| {})
+            .unwrap();
+        assert_eq!(
+            probe(&black, "8/1p6/8/8/8/8/8/K1k5 w - - 0 1", &l),
+            probe(&white, "k1K5/8/8/8/8/8/1P6/8 b - - 0 1", &l)
+        );
+
+        // The table generated with a black pawn agrees with the white one probed with the colours
+        // swapped on every position.
+        for turn in [Player::White, Player::Black] {
+            for wk in SQUARES {
+                for bk in SQUARES {
+                    for p in &SQUARES[8..56] {
+                        let pieces = [
+                            (Piece::WhiteKing, wk),
+                            (Piece::BlackKing, bk),
+                            (Piece::BlackPawn, *p),
+                        ];
+                        let Ok(b) = Board::from_pieces(&pieces, turn, &l) else {
+                            continue;
+                        };
+                        assert_eq!(black.probe(&b), white.probe(&b), "{}", b.fen());
+                    }
+                }
+            }
+        }
+    }
+}
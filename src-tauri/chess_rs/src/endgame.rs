@@ -0,0 +1,502 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use crate::board::Board;
+use crate::core::{Piece, PieceKind, Player, Square, LIGHT_SQUARES, PIECES};
+use crate::eval_params::DEFAULT_PARAMS;
+
+/// The score of a position that's known to be won but isn't a mate yet. It's well below the mate
+/// scores so the search still prefers a mate it has found.
+pub const KNOWN_WIN: i32 = 10_000;
+
+/// The scale factor that leaves an evaluation as it is.
+pub const SCALE_NORMAL: i32 = 64;
+
+/// The number of each piece on the board, indexed by `Piece`.
+pub type MaterialKey = [u8; 12];
+
+pub fn material_key(b: &Board) -> MaterialKey {
+    PIECES.map(|p| b.count_piece(p) as u8)
+}
+
+/// The endgames with specialised knowledge. Evaluators replace the evaluation of positions with
+/// exactly their material and scalers shrink the evaluation of drawish positions.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Endgame {
+    /// King and pawn against king, looked up in the bitbase.
+    Kpk,
+    /// King, bishop and knight against king, which is mated in a corner of the bishop's colour.
+    Kbnk,
+    /// King and a heavy piece against king, which is mated on an edge.
+    Kxk,
+    /// Material that can't force mate.
+    Draw,
+    /// Pawns on a rook file, with or without a bishop that doesn't control the promotion square.
+    RookPawns,
+    /// Bishops of opposite colours.
+    OppositeBishops,
+}
+
+/// The endgames keyed by their material with the side that has the advantage.
+fn evaluators() -> &'static HashMap<MaterialKey, (Endgame, Player)> {
+    static EVALUATORS: OnceLock<HashMap<MaterialKey, (Endgame, Player)>> = OnceLock::new();
+    EVALUATORS.get_or_init(|| {
+        endgame_table(&[
+            ("KPvK", Endgame::Kpk),
+            ("KBNvK", Endgame::Kbnk),
+            ("KRvK", Endgame::Kxk),
+            ("KQvK", Endgame::Kxk),
+            ("KNNvK", Endgame::Draw),
+        ])
+    })
+}
+
+/// Like `evaluators` but keyed with at most one pawn for each side, so `P` in the codes stands
+/// for any number of pawns.
+fn scalers() -> &'static HashMap<MaterialKey, (Endgame, Player)> {
+    static SCALERS: OnceLock<HashMap<MaterialKey, (Endgame, Player)>> = OnceLock::new();
+    SCALERS.get_or_init(|| {
+        endgame_table(&[
+            ("KPvK", Endgame::RookPawns),
+            ("KBPvK", Endgame::RookPawns),
+            ("KBPvKB", Endgame::OppositeBishops),
+            ("KBPvKBP", Endgame::OppositeBishops),
+        ])
+    })
+}
+
+fn endgame_table(codes: &[(&str, Endgame)]) -> HashMap<MaterialKey, (Endgame, Player)> {
+    let mut table = HashMap::new();
+    for (code, endgame) in codes {
+        for strong in [Player::White, Player::Black] {
+            table.insert(key_from_code(code, strong), (*endgame, strong));
+        }
+    }
+    table
+}
+
+/// The material key of a code like `KBNvK`, where the pieces before the `v` are `strong`'s.
+fn key_from_code(code: &str, strong: Player) -> MaterialKey {
+    let (strong_pieces, weak_pieces) = code.split_once('v').unwrap();
+    let (white, black) = match strong {
+        Player::White => (strong_pieces, weak_pieces),
+        Player::Black => (weak_pieces, strong_pieces),
+    };
+
+    let mut key = [0; 12];
+    for c in white.chars() {
+        key[Piece::try_from(c).unwrap() as usize] += 1;
+    }
+    for c in black.chars() {
+        key[Piece::try_from(c.to_ascii_lowercase()).unwrap() as usize] += 1;
+    }
+    key
+}
+
+/// The key with at most one pawn of each colour, which is how scalers are looked up.
+fn scaler_key(mut key: MaterialKey) -> MaterialKey {
+    for p in [Piece::WhitePawn, Piece::BlackPawn] {
+        key[p as usize] = key[p as usize].min(1);
+    }
+    key
+}
+
+/// The specialised evaluation of the position in centipawns from the point of view of the
+/// player to move, if there is one for its material.
+pub fn evaluate(b: &Board) -> Option<i32> {
+    if b.is_insufficient_material() {
+        return Some(0);
+    }
+
+    let (endgame, strong) = *evaluators().get(&material_key(b))?;
+    let score = match endgame {
+        Endgame::Kpk => evaluate_kpk(b, strong),
+        Endgame::Kbnk => evaluate_kbnk(b, strong),
+        Endgame::Kxk => evaluate_kxk(b, strong),
+        Endgame::Draw => 0,
+        _ => return None,
+    };
+
+    Some(if b.turn() == strong { score } else { -score })
+}
+
+/// How much of the evaluation to keep for a drawish position, out of `SCALE_NORMAL`.
+pub fn scale_factor(b: &Board) -> i32 {
+    let Some((endgame, strong)) = scalers().get(&scaler_key(material_key(b))) else {
+        return SCALE_NORMAL;
+    };
+
+    match endgame {
+        Endgame::RookPawns => scale_rook_pawns(b, *strong),
+        Endgame::OppositeBishops => scale_opposite_bishops(b),
+        _ => SCALE_NORMAL,
+    }
+}
+
+fn piece(player: Player, kind: PieceKind) -> Piece {
+    PIECES
+        .into_iter()
+        .find(|p| Player::from(*p) == player && PieceKind::from(*p) == kind)
+        .unwrap()
+}
+
+fn other(player: Player) -> Player {
+    match player {
+        Player::White => Player::Black,
+        Player::Black => Player::White,
+    }
+}
+
+fn king_square(b: &Board, player: Player) -> usize {
+    b.piece_bb(piece(player, PieceKind::King))
+        .get_lsb()
+        .unwrap() as usize
+}
+
+fn distance(a: usize, b: usize) -> i32 {
+    let files = (a % 8).abs_diff(b % 8);
+    let ranks = (a / 8).abs_diff(b / 8);
+    files.max(ranks) as i32
+}
+
+/// Higher the closer `s` is to an edge, and highest in a corner.
+fn push_to_edge(s: usize) -> i32 {
+    let file = (s % 8) as i32;
+    let rank = (s / 8) as i32;
+    let from_centre = (3 - file).max(file - 4) + (3 - rank).max(rank - 4);
+    20 * from_centre
+}
+
+/// Higher the closer the kings are.
+fn push_close(a: usize, b: usize) -> i32 {
+    20 * (7 - distance(a, b))
+}
+
+fn evaluate_kxk(b: &Board, strong: Player) -> i32 {
+    let strong_king = king_square(b, strong);
+    let weak_king = king_square(b, other(strong));
+    let material: i32 = PIECES
+        .into_iter()
+        .filter(|p| Player::from(*p) == strong)
+        .map(|p| b.count_piece(p) as i32 * DEFAULT_PARAMS.material_eg[PieceKind::from(p) as usize])
+        .sum();
+
+    KNOWN_WIN + material + push_to_edge(weak_king) + push_close(strong_king, weak_king)
+}
+
+fn evaluate_kbnk(b: &Board, strong: Player) -> i32 {
+    let strong_king = king_square(b, strong);
+    let weak_king = king_square(b, other(strong));
+    let bishop = b.piece_bb(piece(strong, PieceKind::Bishop));
+
+    // The bishop can only cover the corners of its own colour, which are a8 and h1 for a light
+    // squared bishop.
+    let corners = if (bishop & LIGHT_SQUARES).is_empty() {
+        [Square::A1 as usize, Square::H8 as usize]
+    } else {
+        [Square::A8 as usize, Square::H1 as usize]
+    };
+    let corner_distance = corners
+        .iter()
+        .map(|c| distance(*c, weak_king))
+        .min()
+        .unwrap();
+
+    KNOWN_WIN + 40 * (7 - corner_distance) + push_close(strong_king, weak_king)
+}
+
+fn evaluate_kpk(b: &Board, strong: Player) -> i32 {
+    let pawn = b
+        .piece_bb(piece(strong, PieceKind::Pawn))
+        .get_lsb()
+        .unwrap() as usize;
+    let strong_king = king_square(b, strong);
+    let weak_king = king_square(b, other(strong));
+
+    // The bitbase is from white's point of view so black's pieces are flipped.
+    let flip = |s: usize| match strong {
+        Player::White => s,
+        Player::Black => s ^ 56,
+    };
+    if !kpk::probe(
+        flip(strong_king),
+        flip(weak_king),
+        flip(pawn),
+        b.turn() == strong,
+    ) {
+        return 0;
+    }
+
+    let relative_rank = (flip(pawn) / 8) as i32;
+    KNOWN_WIN + DEFAULT_PARAMS.material_eg[PieceKind::Pawn as usize] + 20 * relative_rank
+}
+
+/// Pawns all on one rook file can't be promoted when the defending king gets to the corner in
+/// front of them, unless a bishop can drive it away by controlling the promotion square.
+fn scale_rook_pawns(b: &Board, strong: Player) -> i32 {
+    let pawns = b.piece_bb(piece(strong, PieceKind::Pawn));
+    let weak_king = king_square(b, other(strong));
+
+    let files: Vec<usize> = pawns.map(|s| s as usize % 8).collect();
+    let file = files[0];
+    if (file != 0 && file != 7) || files.iter().any(|f| *f != file) {
+        return SCALE_NORMAL;
+    }
+
+    let promotion_square = match strong {
+        Player::White => 56 + file,
+        Player::Black => file,
+    };
+    let bishop = b.piece_bb(piece(strong, PieceKind::Bishop));
+    let promotion_is_light = LIGHT_SQUARES & (1 << promotion_square) != 0;
+    let bishop_controls_promotion =
+        !bishop.is_empty() && (bishop & LIGHT_SQUARES).is_empty() != promotion_is_light;
+
+    if !bishop_controls_promotion && distance(weak_king, promotion_square) <= 1 {
+        0
+    } else {
+        SCALE_NORMAL
+    }
+}
+
+/// Bishops of opposite colours can each blockade the other side's pawns so extra pawns are often
+/// not enough to win.
+fn scale_opposite_bishops(b: &Board) -> i32 {
+    let white_light = !(b.piece_bb(Piece::WhiteBishop) & LIGHT_SQUARES).is_empty();
+    let black_light = !(b.piece_bb(Piece::BlackBishop) & LIGHT_SQUARES).is_empty();
+
+    if white_light != black_light {
+        SCALE_NORMAL / 2
+    } else {
+        SCALE_NORMAL
+    }
+}
+
+/// A bitbase of king and pawn against king positions, which are either wins for the side with
+/// the pawn or draws. It's generated by retrograde analysis the first time it's used.
+mod kpk {
+    use std::sync::OnceLock;
+
+    use super::distance;
+
+    /// The pawn is on files a to d, as the other files are mirrored, and ranks 2 to 7.
+    const PAWN_SQUARES: usize = 24;
+    const SIZE: usize = 2 * 64 * 64 * PAWN_SQUARES;
+
+    // The results while generating, which are bits so the results of the moves from a position
+    // can be combined.
+    const INVALID: u8 = 0;
+    const UNKNOWN: u8 = 1;
+    const DRAW: u8 = 2;
+    const WIN: u8 = 4;
+
+    /// The index of a position with white's king on `wk`, black's on `bk`, the white pawn on `p`
+    /// (on files a to d) and white to move if `white_to_move`.
+    fn index(wk: usize, bk: usize, p: usize, white_to_move: bool) -> usize {
+        let pawn_index = (p / 8 - 1) * 4 + p % 8;
+        ((white_to_move as usize * 64 + wk) * 64 + bk) * PAWN_SQUARES + pawn_index
+    }
+
+    /// Whether white wins with its king on `wk`, black's on `bk` and the pawn on `p`.
+    pub fn probe(wk: usize, bk: usize, p: usize, white_to_move: bool) -> bool {
+        // Positions with the pawn on the e to h files are mirrored to the a to d files.
+        let mirror = if p % 8 > 3 { 7 } else { 0 };
+        let bitbase = BITBASE.get_or_init(generate);
+        let i = index(wk ^ mirror, bk ^ mirror, p ^ mirror, white_to_move);
+        bitbase[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    static BITBASE: OnceLock<Vec<u64>> = OnceLock::new();
+
+    fn pawn_attacks(p: usize, s: usize) -> bool {
+        s / 8 == p / 8 + 1 && (s % 8).abs_diff(p % 8) == 1
+    }
+
+    fn king_moves(k: usize) -> impl Iterator<Item = usize> {
+        (0..64).filter(move |s| distance(k, *s) == 1)
+    }
+
+    /// The result of a position before any moves are looked at.
+    fn initial_result(wk: usize, bk: usize, p: usize, white_to_move: bool) -> u8 {
+        if distance(wk, bk) <= 1 || wk == p || bk == p || (white_to_move && pawn_attacks(p, bk)) {
+            return INVALID;
+        }
+
+        let push = p + 8;
+        if white_to_move {
+            // The pawn promotes and the queen can't be taken.
+            if p / 8 == 6
+                && wk != push
+                && bk != push
+                && (distance(bk, push) > 1 || distance(wk, push) == 1)
+            {
+                return WIN;
+            }
+        } else {
+            let stalemate = king_moves(bk).all(|s| distance(s, wk) <= 1 || pawn_attacks(p, s));
+            let takes_pawn = distance(bk, p) == 1 && distance(wk, p) > 1;
+            if stalemate || takes_pawn {
+                return DRAW;
+            }
+        }
+
+        UNKNOWN
+    }
+
+    /// The result of a position from the results of its moves, or `UNKNOWN` if it depends on
+    /// moves that aren't known yet.
+    fn classify(results: &[u8], wk: usize, bk: usize, p: usize, white_to_move: bool) -> u8 {
+        let mut r = INVALID;
+        if white_to_move {
+            for s in king_moves(wk) {
+                r |= results[index(s, bk, p, false)];
+            }
+            let push = p + 8;
+            if p / 8 < 6 && push != bk && push != wk {
+                r |= results[index(wk, bk, push, false)];
+                let double_push = push + 8;
+                if p / 8 == 1 && double_push != bk && double_push != wk {
+                    r |= results[index(wk, bk, double_push, false)];
+                }
+            }
+
+            if r & WIN != 0 {
+                WIN
+            } else if r & UNKNOWN != 0 {
+                UNKNOWN
+            } else {
+                DRAW
+            }
+        } else {
+            for s in king_moves(bk) {
+                r |= results[index(wk, s, p, true)];
+            }
+
+            if r & DRAW != 0 {
+                DRAW
+            } else if r & UNKNOWN != 0 {
+                UNKNOWN
+            } else {
+                WIN
+            }
+        }
+    }
+
+    fn squares() -> impl Iterator<Item = (usize, usize, usize, bool)> {
+        (0..2).flat_map(|stm| {
+            (0..64).flat_map(move |wk| {
+                (0..64).flat_map(move |bk| {
+                    (8..56)
+                        .filter(|p| p % 8 < 4)
+                        .map(move |p| (wk, bk, p, stm == 1))
+                })
+            })
+        })
+    }
+
+    fn generate() -> Vec<u64> {
+        let mut results = vec![INVALID; SIZE];
+        for (wk, bk, p, white_to_move) in squares() {
+            results[index(wk, bk, p, white_to_move)] = initial_result(wk, bk, p, white_to_move);
+        }
+
+        // Keep resolving positions from their moves until nothing changes.
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (wk, bk, p, white_to_move) in squares() {
+                let i = index(wk, bk, p, white_to_move);
+                if results[i] == UNKNOWN {
+                    let r = classify(&results, wk, bk, p, white_to_move);
+                    if r != UNKNOWN {
+                        results[i] = r;
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        // The positions that are still unknown can't be forced to a win.
+        let mut bitbase = vec![0; SIZE.div_ceil(64)];
+        for (i, r) in results.iter().enumerate() {
+            if *r == WIN {
+                bitbase[i / 64] |= 1 << (i % 64);
+            }
+        }
+        bitbase
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lookup_tables::LookupTables;
+
+    fn evaluate_fen(fen: &str, l: &LookupTables) -> Option<i32> {
+        evaluate(&Board::from_fen(fen, l).unwrap())
+    }
+
+    #[test]
+    fn kpk_test() {
+        let l = LookupTables::generate();
+
+        // The pawn outruns the king.
+        assert!(evaluate_fen("k7/8/8/8/8/7P/8/K7 w - - 0 1", &l).unwrap() > KNOWN_WIN);
+        // A rook pawn can't be promoted with the king in the corner.
+        assert_eq!(evaluate_fen("k7/8/8/8/P7/K7/8/8 w - - 0 1", &l), Some(0));
+        // The king in front of the pawn wins with the opposition...
+        assert!(evaluate_fen("8/4k3/8/4K3/4P3/8/8/8 b - - 0 1", &l).unwrap() < -KNOWN_WIN);
+        // ...and draws without it.
+        assert_eq!(evaluate_fen("8/4k3/8/4K3/4P3/8/8/8 w - - 0 1", &l), Some(0));
+        // The king two squares in front of the pawn always wins.
+        assert!(evaluate_fen("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1", &l).unwrap() > KNOWN_WIN);
+        assert!(evaluate_fen("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1", &l).unwrap() < -KNOWN_WIN);
+        // The same for black with the board flipped and mirrored.
+        assert!(evaluate_fen("8/8/8/8/3p4/3k4/8/3K4 w - - 0 1", &l).unwrap() < -KNOWN_WIN);
+        assert_eq!(evaluate_fen("8/8/8/8/8/k7/p7/K7 b - - 0 1", &l), Some(0));
+    }
+
+    #[test]
+    fn mating_test() {
+        let l = LookupTables::generate();
+
+        // The weak king is pushed to the edge and the strong king comes closer.
+        let centre = evaluate_fen("8/8/8/3k4/8/8/8/R2K4 w - - 0 1", &l).unwrap();
+        let edge = evaluate_fen("3k4/8/8/8/3K4/8/8/R7 w - - 0 1", &l).unwrap();
+        let close = evaluate_fen("3k4/8/3K4/8/8/8/8/R7 w - - 0 1", &l).unwrap();
+        assert!(KNOWN_WIN < centre && centre < edge && edge < close);
+        assert!(evaluate_fen("8/8/8/3k4/8/8/8/q3K3 w - - 0 1", &l).unwrap() < -KNOWN_WIN);
+
+        // With a dark squared bishop the king is mated in a1 or h8 rather than a8 or h1.
+        let right_corner = evaluate_fen("7k/8/5K2/8/8/8/8/2B1N3 w - - 0 1", &l).unwrap();
+        let wrong_corner = evaluate_fen("k7/8/2K5/8/8/8/8/2B1N3 w - - 0 1", &l).unwrap();
+        assert!(KNOWN_WIN < wrong_corner && wrong_corner < right_corner);
+
+        assert_eq!(
+            evaluate_fen("8/8/3k4/8/8/8/8/1NN1K3 w - - 0 1", &l),
+            Some(0)
+        );
+        assert_eq!(evaluate_fen("8/8/3k4/8/8/8/8/2B1K3 w - - 0 1", &l), Some(0));
+        assert_eq!(evaluate_fen("8/8/3k4/8/8/8/2PP4/4K3 w - - 0 1", &l), None);
+    }
+
+    #[test]
+    fn scale_factor_test() {
+        let l = LookupTables::generate();
+        let scale = |fen: &str| scale_factor(&Board::from_fen(fen, &l).unwrap());
+
+        // The dark squared bishop can't drive the king out of a8.
+        assert_eq!(scale("k7/8/8/P7/P7/8/8/2B1K3 w - - 0 1"), 0);
+        assert_eq!(scale("k7/8/8/P7/P7/8/8/3BK3 w - - 0 1"), SCALE_NORMAL);
+        assert_eq!(scale("8/8/8/P3k3/8/8/8/2B1K3 w - - 0 1"), SCALE_NORMAL);
+        assert_eq!(scale("k7/8/8/P7/P7/8/8/4K3 w - - 0 1"), 0);
+        assert_eq!(scale("8/8/8/8/7p/8/6K1/2b1k3 w - - 0 1"), 0);
+
+        assert_eq!(
+            scale("4k3/2b5/8/3P4/2P5/8/4B3/4K3 w - - 0 1"),
+            SCALE_NORMAL / 2
+        );
+        assert_eq!(scale("4k3/3b4/8/3P4/2P5/8/4B3/4K3 w - - 0 1"), SCALE_NORMAL);
+        assert_eq!(scale(crate::core::STARTING_POS_FEN), SCALE_NORMAL);
+    }
+}
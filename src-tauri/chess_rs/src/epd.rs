@@ -0,0 +1,350 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::board::Board;
+use crate::core::Move;
+use crate::fen::Fen;
+
+/// An operand of an EPD operation.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Operand {
+    /// An unquoted operand, such as a move, a number or a name.
+    Token(String),
+    /// An operand written in double quotes, which can contain spaces and semicolons.
+    Quoted(String),
+}
+
+impl Operand {
+    /// The operand without its quotes.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Operand::Token(s) | Operand::Quoted(s) => s,
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Token(s) => write!(f, "{}", s),
+            Operand::Quoted(s) => {
+                // A backslash is only escaped where it would otherwise be read as an escape, so
+                // operands like "C:\dir" are written back the way they were read.
+                write!(f, "\"")?;
+                let mut chars = s.chars().peekable();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => write!(f, "\\\"")?,
+                        '\\' if matches!(chars.peek(), None | Some('"') | Some('\\')) => {
+                            write!(f, "\\\\")?
+                        }
+                        c => write!(f, "{}", c)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+        }
+    }
+}
+
+/// An opcode like `bm`, `id` or `c0` with its operands, written as `bm Qg6 Rxh7;`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Operation {
+    pub opcode: String,
+    pub operands: Vec<Operand>,
+}
+
+/// A record of an EPD file: a position without the move counters, which can be given by the
+/// `hmvc` and `fmvn` operations instead, followed by any number of operations. Operations are kept
+/// in the order they're given, with the operands quoted the way they were, so records are written
+/// back the way they were read.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Epd {
+    /// The piece placement, side to move, castling rights and en passant square of a FEN.
+    pub position: String,
+    pub operations: Vec<Operation>,
+}
+
+impl Epd {
+    /// The operands of the first operation with `opcode`.
+    pub fn operation(&self, opcode: &str) -> Option<&[Operand]> {
+        self.operations
+            .iter()
+            .find(|o| o.opcode == opcode)
+            .map(|o| o.operands.as_slice())
+    }
+
+    /// The first operand of `opcode`, such as the name given by `id`.
+    pub fn operand(&self, opcode: &str) -> Option<&str> {
+        self.operation(opcode)
+            .and_then(|operands| operands.first())
+            .map(|o| o.as_str())
+    }
+
+    /// Replaces the operands of `opcode`, or adds it at the end if there isn't one.
+    pub fn set_operation(&mut self, opcode: &str, operands: Vec<Operand>) {
+        match self.operations.iter_mut().find(|o| o.opcode == opcode) {
+            Some(operation) => operation.operands = operands,
+            None => self.operations.push(Operation {
+                opcode: opcode.to_string(),
+                operands,
+            }),
+        }
+    }
+
+    pub fn remove_operation(&mut self, opcode: &str) {
+        self.operations.retain(|o| o.opcode != opcode);
+    }
+
+    /// The FEN of the position, with the move counters of `hmvc` and `fmvn` if there are any.
+    pub fn fen(&self) -> String {
+        format!(
+            "{} {} {}",
+            self.position,
+            self.operand("hmvc").unwrap_or("0"),
+            self.operand("fmvn").unwrap_or("1")
+        )
+    }
+
+    /// The moves of an operation like `bm`, `am` or `pv`, whose operands are moves in SAN played
+    /// one after the other from `b` for `pv` and alternatives for the others.
+    pub fn moves(&self, opcode: &str, b: &Board) -> Result<Vec<Move>, String> {
+        let Some(operands) = self.operation(opcode) else {
+            return Ok(Vec::new());
+        };
+
+        let mut b = b.shallow_clone();
+        let mut moves = Vec::with_capacity(operands.len());
+        for operand in operands {
+            let m = b
+                .parse_san(operand.as_str())
+                .map_err(|e| format!("{}: {}", opcode, e))?;
+            if opcode == "pv" {
+                b.make_move(m);
+            }
+            moves.push(m);
+        }
+        Ok(moves)
+    }
+}
+
+impl TryFrom<&str> for Epd {
+    type Error = String;
+
+    fn try_from(line: &str) -> Result<Self, Self::Error> {
+        let mut rest = line.trim();
+        let mut fields = Vec::with_capacity(4);
+        for _ in 0..4 {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            if end == 0 {
+                return Err(format!("expected a position: {}", line));
+            }
+            fields.push(&rest[..end]);
+            rest = rest[end..].trim_start();
+        }
+        let position = fields.join(" ");
+        Fen::parse(&position).map_err(|e| format!("invalid position: {}", e))?;
+
+        Ok(Epd {
+            position,
+            operations: parse_operations(rest)?,
+        })
+    }
+}
+
+fn parse_operations(s: &str) -> Result<Vec<Operation>, String> {
+    let mut operations = Vec::new();
+    let mut operands = Vec::new();
+    let mut chars = s.chars().peekable();
+    loop {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+        match chars.peek() {
+            None => break,
+            Some(';') => {
+                chars.next();
+                if operands.is_empty() {
+                    return Err("expected an opcode before ;".to_string());
+                }
+                let opcode = match operands.remove(0) {
+                    Operand::Token(opcode) => opcode,
+                    quoted => return Err(format!("invalid opcode: {}", quoted)),
+                };
+                let is_opcode = opcode.starts_with(|c: char| c.is_ascii_alphabetic())
+                    && opcode
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_');
+                if !is_opcode {
+                    return Err(format!("invalid opcode: {}", opcode));
+                }
+                operations.push(Operation {
+                    opcode,
+                    operands: std::mem::take(&mut operands),
+                });
+            }
+            Some('"') => {
+                chars.next();
+                let mut operand = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') if matches!(chars.peek(), Some('"') | Some('\\')) => {
+                            operand.push(chars.next().unwrap())
+                        }
+                        Some(c) => operand.push(c),
+                        None => return Err(format!("unterminated string: \"{}", operand)),
+                    }
+                }
+                operands.push(Operand::Quoted(operand));
+            }
+            Some(_) => {
+                let mut operand = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == ';' || c == '"' {
+                        break;
+                    }
+                    operand.push(c);
+                    chars.next();
+                }
+                operands.push(Operand::Token(operand));
+            }
+        }
+    }
+
+    if let Some(operand) = operands.first() {
+        return Err(format!("expected ; after {}", operand));
+    }
+    Ok(operations)
+}
+
+impl fmt::Display for Epd {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.position)?;
+        for operation in &self.operations {
+            write!(f, " {}", operation.opcode)?;
+            for operand in &operation.operands {
+                write!(f, " {}", operand)?;
+            }
+            write!(f, ";")?;
+        }
+        Ok(())
+    }
+}
+
+/// The lines of an EPD file that aren't blank or comments starting with `#`, trimmed and numbered
+/// from 1.
+pub fn lines(epd: &str) -> impl Iterator<Item = (usize, &str)> {
+    epd.lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+}
+
+/// Reads the records of an EPD file along with their line numbers.
+pub fn parse(epd: &str) -> Result<Vec<(usize, Epd)>, String> {
+    lines(epd)
+        .map(|(n, line)| {
+            Epd::try_from(line)
+                .map(|record| (n, record))
+                .map_err(|e| format!("line {}: {}", n, e))
+        })
+        .collect()
+}
+
+pub fn load(path: impl AsRef<Path>) -> Result<Vec<(usize, Epd)>, String> {
+    let path = path.as_ref();
+    let contents =
+        fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    parse(&contents).map_err(|e| format!("{} {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lookup_tables::LookupTables;
+
+    #[test]
+    fn parse_test() {
+        let line =
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id \"WAC.001\"; \
+            c0 \"mate; in \\\"3\\\"\"; ce +32767; pv Qg6 fxg6 Nxg6+; hmvc 4; fmvn 25; noop;";
+        let epd = Epd::try_from(line).unwrap();
+        assert_eq!(
+            epd.position,
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - -"
+        );
+        assert_eq!(epd.operations.len(), 8);
+        assert_eq!(epd.operand("id"), Some("WAC.001"));
+        assert_eq!(epd.operand("c0"), Some("mate; in \"3\""));
+        assert_eq!(epd.operand("ce"), Some("+32767"));
+        assert_eq!(epd.operation("noop"), Some(&[][..]));
+        assert_eq!(epd.operation("am"), None);
+        assert_eq!(
+            epd.fen(),
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 4 25"
+        );
+
+        // Records are written back the way they were read.
+        assert_eq!(epd.to_string(), line.replace("            ", ""));
+        assert_eq!(Epd::try_from(epd.to_string().as_str()).unwrap(), epd);
+        let spaced = Epd::try_from("8/8/8/4k3/8/8/8/4K2R  w K -   id  x ;c1 \"\";").unwrap();
+        assert_eq!(
+            spaced.to_string(),
+            "8/8/8/4k3/8/8/8/4K2R w K - id x; c1 \"\";"
+        );
+
+        let l = LookupTables::generate();
+        let b = Board::from_fen(&epd.fen(), &l).unwrap();
+        let uci = |moves: Vec<Move>| moves.iter().map(|m| m.to_string()).collect::<Vec<_>>();
+        assert_eq!(uci(epd.moves("bm", &b).unwrap()), ["g3g6"]);
+        assert_eq!(uci(epd.moves("pv", &b).unwrap()), ["g3g6", "f7g6", "e5g6"]);
+        assert!(epd.moves("id", &b).is_err());
+
+        let mut epd = epd;
+        epd.set_operation("id", vec![Operand::Quoted("renamed".to_string())]);
+        epd.remove_operation("c0");
+        epd.set_operation("am", vec![Operand::Token("Qg4".to_string())]);
+        assert!(epd
+            .to_string()
+            .contains("bm Qg6; id \"renamed\"; ce +32767;"));
+        assert!(epd.to_string().ends_with(" noop; am Qg4;"));
+
+        assert!(Epd::try_from("8/8/8 w").is_err());
+        assert!(Epd::try_from("8/8/8/4k3/8/8/8/4K2R x K -").is_err());
+        assert!(Epd::try_from("8/8/8/4k3/8/8/8/4K2R w K - bm Rh5").is_err());
+        assert!(Epd::try_from("8/8/8/4k3/8/8/8/4K2R w K - c0 \"open;").is_err());
+        assert!(Epd::try_from("8/8/8/4k3/8/8/8/4K2R w K - 1x y;").is_err());
+        assert!(Epd::try_from("8/8/8/4k3/8/8/8/4K2R w K - ;").is_err());
+
+        // Backslashes are only escapes before a quote or another backslash.
+        let paths = "8/8/8/4k3/8/8/8/4K2R w K - c0 \"C:\\dir\"; c1 \"a\\\\\\\"b\\\\\";";
+        let epd = Epd::try_from(paths).unwrap();
+        assert_eq!(epd.operand("c0"), Some("C:\\dir"));
+        assert_eq!(epd.operand("c1"), Some("a\\\"b\\"));
+        assert_eq!(epd.to_string(), paths);
+        // An escaped backslash that didn't need to be is written without the escape.
+        let redundant = Epd::try_from("8/8/8/4k3/8/8/8/4K2R w K - c0 \"C:\\\\dir\";").unwrap();
+        assert_eq!(redundant.operand("c0"), Some("C:\\dir"));
+        assert_eq!(
+            redundant.to_string(),
+            "8/8/8/4k3/8/8/8/4K2R w K - c0 \"C:\\dir\";"
+        );
+
+        let records = parse("# comment\n\n8/8/8/4k3/8/8/8/4K2R w K -\nbad\n");
+        assert_eq!(records.unwrap_err(), "line 4: expected a position: bad");
+    }
+
+    #[test]
+    fn board_test() {
+        let l = LookupTables::generate();
+        let b = Board::from_fen("8/8/8/4k3/8/8/8/4K2R w K - 7 30", &l).unwrap();
+        let epd = b.epd();
+        assert_eq!(
+            epd.to_string(),
+            "8/8/8/4k3/8/8/8/4K2R w K - hmvc 7; fmvn 30;"
+        );
+        assert_eq!(Board::from_epd(&epd, &l).unwrap().fen(), b.fen());
+    }
+}
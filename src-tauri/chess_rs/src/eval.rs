@@ -0,0 +1,193 @@
+use crate::bitboard::BitBoard;
+use crate::board::Board;
+use crate::core::{Piece, PieceKind, Player, Square, PIECES};
+use crate::endgame;
+use crate::eval_params::DEFAULT_PARAMS;
+use crate::pawns::{evaluate_pawns, PawnEntry, PawnTable};
+
+/// The tunable parameters of the evaluation. Arrays of piece values are indexed by `PieceKind`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EvalParams {
+    pub material_mg: [i32; 6],
+    pub material_eg: [i32; 6],
+    pub pst_mg: [[i32; 64]; 6],
+    pub pst_eg: [[i32; 64]; 6],
+    /// Indexed by the rank of the pawn from its side of the board, from 0.
+    pub passed_pawn_mg: [i32; 8],
+    pub passed_pawn_eg: [i32; 8],
+    /// For each pawn behind another on the same file.
+    pub doubled_pawn_mg: i32,
+    pub doubled_pawn_eg: i32,
+    pub isolated_pawn_mg: i32,
+    pub isolated_pawn_eg: i32,
+    pub rook_open_file_mg: i32,
+    pub rook_open_file_eg: i32,
+    pub rook_semi_open_file_mg: i32,
+    pub rook_semi_open_file_eg: i32,
+    /// For each pawn in front of a king that's on one of its first two ranks.
+    pub pawn_shield_mg: i32,
+    pub pawn_shield_eg: i32,
+}
+
+/// How much each piece kind counts towards the game phase. The phase is `MAX_PHASE` with all the
+/// pieces on the board and 0 with only kings and pawns.
+pub const PHASE_WEIGHTS: [i32; 6] = [2, 1, 1, 4, 0, 0];
+pub const MAX_PHASE: i32 = 24;
+
+/// Evaluates the position in centipawns from the point of view of the player to move, with the
+/// specialised knowledge of its endgame or the board's network if it has them.
+pub fn evaluate(b: &Board) -> i32 {
+    if let Some(score) = endgame::evaluate(b).or_else(|| b.nnue_evaluate()) {
+        return score;
+    }
+    let pawns = evaluate_pawns(b.pawn_hash(), board_pawns(b), &DEFAULT_PARAMS);
+    evaluate_hand_written(b, &DEFAULT_PARAMS, &pawns)
+}
+
+/// Like `evaluate` but looks the pawn structure up in `pawn_table` instead of working it out.
+pub fn evaluate_cached(b: &Board, pawn_table: &mut PawnTable) -> i32 {
+    if let Some(score) = endgame::evaluate(b).or_else(|| b.nnue_evaluate()) {
+        return score;
+    }
+    let pawns = pawn_table.probe(b.pawn_hash(), board_pawns(b), &DEFAULT_PARAMS);
+    evaluate_hand_written(b, &DEFAULT_PARAMS, pawns)
+}
+
+pub fn evaluate_with(b: &Board, params: &EvalParams) -> i32 {
+    if let Some(score) = endgame::evaluate(b) {
+        return score;
+    }
+    let pawns = evaluate_pawns(b.pawn_hash(), board_pawns(b), params);
+    evaluate_hand_written(b, params, &pawns)
+}
+
+fn evaluate_hand_written(b: &Board, params: &EvalParams, pawns: &PawnEntry) -> i32 {
+    let piece_bbs = PIECES.map(|p| b.piece_bb(p));
+    let score =
+        evaluate_bbs(&piece_bbs, params, pawns) * endgame::scale_factor(b) / endgame::SCALE_NORMAL;
+
+    match b.turn() {
+        Player::White => score,
+        Player::Black => -score,
+    }
+}
+
+fn board_pawns(b: &Board) -> [BitBoard; 2] {
+    [b.piece_bb(Piece::WhitePawn), b.piece_bb(Piece::BlackPawn)]
+}
+
+/// Evaluates a position with `pieces` on the board in centipawns from white's point of view with
+/// just the parameters, which is what the tuner needs.
+pub fn evaluate_pieces(
+    pieces: impl IntoIterator<Item = (Piece, Square)>,
+    params: &EvalParams,
+) -> i32 {
+    let mut piece_bbs = [BitBoard::new(); 12];
+    for (p, s) in pieces {
+        piece_bbs[p as usize].set_bit(s);
+    }
+    let pawns = evaluate_pawns(
+        0,
+        [
+            piece_bbs[Piece::WhitePawn as usize],
+            piece_bbs[Piece::BlackPawn as usize],
+        ],
+        params,
+    );
+    evaluate_bbs(&piece_bbs, params, &pawns)
+}
+
+/// Evaluates the position with the pieces in `piece_bbs` and the pawn structure in `pawns` from
+/// white's point of view.
+fn evaluate_bbs(piece_bbs: &[BitBoard; 12], params: &EvalParams, pawns: &PawnEntry) -> i32 {
+    let mut mg = pawns.mg;
+    let mut eg = pawns.eg;
+    let mut phase = 0;
+
+    for p in PIECES {
+        let kind = PieceKind::from(p);
+        let player = Player::from(p);
+        let sign = match player {
+            Player::White => 1,
+            Player::Black => -1,
+        };
+
+        for s in piece_bbs[p as usize] {
+            let i = pst_index(p, s as usize);
+            mg += sign * (params.material_mg[kind as usize] + params.pst_mg[kind as usize][i]);
+            eg += sign * (params.material_eg[kind as usize] + params.pst_eg[kind as usize][i]);
+            phase += PHASE_WEIGHTS[kind as usize];
+
+            let file = s as usize % 8;
+            match kind {
+                PieceKind::Rook => {
+                    let semi_open = pawns.semi_open_files[player as usize] & (1 << file) != 0;
+                    let open =
+                        semi_open && pawns.semi_open_files[1 - player as usize] & (1 << file) != 0;
+                    if open {
+                        mg += sign * params.rook_open_file_mg;
+                        eg += sign * params.rook_open_file_eg;
+                    } else if semi_open {
+                        mg += sign * params.rook_semi_open_file_mg;
+                        eg += sign * params.rook_semi_open_file_eg;
+                    }
+                }
+                PieceKind::King => {
+                    let relative_rank = match player {
+                        Player::White => s as usize / 8,
+                        Player::Black => 7 - s as usize / 8,
+                    };
+                    if relative_rank < 2 {
+                        let shield = pawns.shield[player as usize][file] as i32;
+                        mg += sign * shield * params.pawn_shield_mg;
+                        eg += sign * shield * params.pawn_shield_eg;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Promotions can push the phase past the maximum.
+    let phase = phase.min(MAX_PHASE);
+    (mg * phase + eg * (MAX_PHASE - phase)) / MAX_PHASE
+}
+
+/// The index into a piece square table of a piece on `square`. The tables are written with the
+/// eighth rank first so white's squares are flipped vertically while black's are already mirrored.
+pub fn pst_index(p: Piece, square: usize) -> usize {
+    match Player::from(p) {
+        Player::White => square ^ 56,
+        Player::Black => square,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{POS_2_KIWIPETE_FEN, STARTING_POS_FEN};
+    use crate::lookup_tables::LookupTables;
+
+    #[test]
+    fn evaluate_test() {
+        let l = LookupTables::generate();
+
+        let b = Board::from_fen(STARTING_POS_FEN, &l).unwrap();
+        assert_eq!(evaluate(&b), 0);
+
+        // Mirrored positions evaluate the same for the player to move.
+        let b = Board::from_fen(POS_2_KIWIPETE_FEN, &l).unwrap();
+        let mirrored = Board::from_fen(
+            "r3k2r/pppbbppp/2n2q1P/1P2p3/3pn3/BN2PNP1/P1PPQPB1/R3K2R b KQkq - 0 1",
+            &l,
+        )
+        .unwrap();
+        assert_eq!(evaluate(&b), evaluate(&mirrored));
+
+        // An extra queen is winning for whoever has it.
+        let b = Board::from_fen("4k3/8/8/8/8/8/8/3QK3 w - - 0 1", &l).unwrap();
+        assert!(evaluate(&b) > 800);
+        let b = Board::from_fen("4k3/8/8/8/8/8/8/3QK3 b - - 0 1", &l).unwrap();
+        assert!(evaluate(&b) < -800);
+    }
+}
@@ -0,0 +1,596 @@
+use std::fmt;
+
+use crate::board::{Castling, STANDARD_CASTLING_ROOKS};
+use crate::core::{File, Piece, Player, Rank, Square, FILES, RANKS};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FenField {
+    PiecePlacement,
+    ActiveColor,
+    Castling,
+    EnPassant,
+    HalfMoveClock,
+    FullMoveNumber,
+}
+
+impl fmt::Display for FenField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                FenField::PiecePlacement => "piece placement",
+                FenField::ActiveColor => "active color",
+                FenField::Castling => "castling rights",
+                FenField::EnPassant => "en passant square",
+                FenField::HalfMoveClock => "half move clock",
+                FenField::FullMoveNumber => "full move number",
+            }
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FenErrorKind {
+    MissingField,
+    UnexpectedCharacter(char),
+    WrongNumberOfRanks(usize),
+    WrongRankWidth(Rank, u32),
+    WrongNumberOfKings(Player, u32),
+    DuplicateCastlingRight(char),
+    /// The castling right doesn't have a king and rook on their starting squares.
+    UnsupportedCastlingRight(char),
+    ImplausibleEnPassant(Square),
+    InvalidNumber(String),
+}
+
+impl fmt::Display for FenErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FenErrorKind::MissingField => write!(f, "missing field"),
+            FenErrorKind::UnexpectedCharacter(c) => write!(f, "unexpected character '{}'", c),
+            FenErrorKind::WrongNumberOfRanks(n) => write!(f, "expected 8 ranks but found {}", n),
+            FenErrorKind::WrongRankWidth(r, n) => {
+                write!(f, "expected rank {} to have 8 squares but found {}", r, n)
+            }
+            FenErrorKind::WrongNumberOfKings(p, n) => {
+                write!(f, "expected one {:?} king but found {}", p, n)
+            }
+            FenErrorKind::DuplicateCastlingRight(c) => {
+                write!(f, "castling right '{}' is given more than once", c)
+            }
+            FenErrorKind::UnsupportedCastlingRight(c) => write!(
+                f,
+                "castling right '{}' doesn't match the king and rook placement",
+                c
+            ),
+            FenErrorKind::ImplausibleEnPassant(s) => {
+                write!(f, "no pawn can have just double pushed past {}", s)
+            }
+            FenErrorKind::InvalidNumber(s) => write!(f, "invalid number '{}'", s),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FenError {
+    /// A field of the FEN is malformed. `position` is the byte offset of the problem in the FEN.
+    Field {
+        field: FenField,
+        position: usize,
+        kind: FenErrorKind,
+    },
+    TooManyFields {
+        position: usize,
+    },
+    /// The FEN is well formed but describes a position that can't be reached.
+    InvalidPosition(Vec<String>),
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FenError::Field {
+                field,
+                position,
+                kind,
+            } => write!(f, "{}: {} at position {}", field, kind, position),
+            FenError::TooManyFields { position } => {
+                write!(f, "unexpected extra field at position {}", position)
+            }
+            FenError::InvalidPosition(errors) => write!(f, "{}", errors.join(", ")),
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
+/// The fields of a syntactically valid FEN. Both standard FEN and X-FEN/Shredder-FEN castling
+/// rights are accepted.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fen {
+    pub pieces: [Option<Piece>; 64],
+    pub turn: Player,
+    pub castling: Castling,
+    /// The square of the rook for each castling right, indexed by `Castling::index`.
+    pub castling_rooks: [Square; 4],
+    /// Whether the castling rights can only be used with Chess960 rules, i.e. the king or a
+    /// castling rook isn't on its standard square.
+    pub chess960: bool,
+    pub en_passant: Option<Square>,
+    pub half_moves: u32,
+    pub full_moves: u32,
+}
+
+impl Fen {
+    pub fn parse(fen: &str) -> Result<Self, FenError> {
+        let mut fields = split_fields(fen).into_iter();
+        let mut next_field = |field: FenField| {
+            fields.next().ok_or(FenError::Field {
+                field,
+                position: fen.len(),
+                kind: FenErrorKind::MissingField,
+            })
+        };
+
+        let (placement_pos, placement) = next_field(FenField::PiecePlacement)?;
+        let pieces = parse_placement(placement, placement_pos)?;
+
+        let (turn_pos, turn_str) = next_field(FenField::ActiveColor)?;
+        let turn = match turn_str {
+            "w" => Player::White,
+            "b" => Player::Black,
+            _ => {
+                return Err(FenError::Field {
+                    field: FenField::ActiveColor,
+                    position: turn_pos,
+                    kind: FenErrorKind::UnexpectedCharacter(turn_str.chars().next().unwrap()),
+                })
+            }
+        };
+
+        let (castling_pos, castling_str) = next_field(FenField::Castling)?;
+        let (castling_rooks, castling) = parse_castling(castling_str, castling_pos, &pieces)?;
+        let chess960 = castling.iter().any(|right| {
+            let (king, king_square) = if right.intersects(Castling::WHITE_K | Castling::WHITE_Q) {
+                (Piece::WhiteKing, Square::E1)
+            } else {
+                (Piece::BlackKing, Square::E8)
+            };
+            pieces[king_square as usize] != Some(king)
+                || castling_rooks[right.index()] != STANDARD_CASTLING_ROOKS[right.index()]
+        });
+
+        let (en_passant_pos, en_passant_str) = next_field(FenField::EnPassant)?;
+        let en_passant = parse_en_passant(en_passant_str, en_passant_pos, turn, &pieces)?;
+
+        // The move counters are often left off so they are optional.
+        let half_moves = match fields.next() {
+            Some((pos, s)) => parse_number(s, pos, FenField::HalfMoveClock)?,
+            None => 0,
+        };
+        let full_moves = match fields.next() {
+            Some((pos, s)) => parse_number(s, pos, FenField::FullMoveNumber)?,
+            None => 0,
+        };
+
+        if let Some((position, _)) = fields.next() {
+            return Err(FenError::TooManyFields { position });
+        }
+
+        Ok(Fen {
+            pieces,
+            turn,
+            castling,
+            castling_rooks,
+            chess960,
+            en_passant,
+            half_moves,
+            full_moves,
+        })
+    }
+}
+
+/// Splits `fen` on whitespace keeping the byte offset of every field.
+fn split_fields(fen: &str) -> Vec<(usize, &str)> {
+    let mut fields = Vec::new();
+    let mut start = None;
+    for (i, c) in fen.char_indices() {
+        match (c.is_whitespace(), start) {
+            (true, Some(s)) => {
+                fields.push((s, &fen[s..i]));
+                start = None;
+            }
+            (false, None) => start = Some(i),
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        fields.push((s, &fen[s..]));
+    }
+
+    fields
+}
+
+fn parse_placement(placement: &str, offset: usize) -> Result<[Option<Piece>; 64], FenError> {
+    let mut pieces = [None; 64];
+
+    let ranks: Vec<&str> = placement.split('/').collect();
+    if ranks.len() != 8 {
+        return Err(FenError::Field {
+            field: FenField::PiecePlacement,
+            position: offset,
+            kind: FenErrorKind::WrongNumberOfRanks(ranks.len()),
+        });
+    }
+
+    let mut position = offset;
+    for (rank_str, rank) in ranks.iter().zip(RANKS.iter().rev()) {
+        let mut width = 0;
+        for (i, c) in rank_str.char_indices() {
+            let square_count = match c {
+                '1'..='8' => c.to_digit(10).unwrap(),
+                _ => {
+                    let piece = Piece::try_from(c).map_err(|_| FenError::Field {
+                        field: FenField::PiecePlacement,
+                        position: position + i,
+                        kind: FenErrorKind::UnexpectedCharacter(c),
+                    })?;
+                    if width < 8 {
+                        pieces[Square::from((FILES[width as usize], *rank)) as usize] = Some(piece);
+                    }
+                    1
+                }
+            };
+            width += square_count;
+        }
+
+        if width != 8 {
+            return Err(FenError::Field {
+                field: FenField::PiecePlacement,
+                position,
+                kind: FenErrorKind::WrongRankWidth(*rank, width),
+            });
+        }
+
+        position += rank_str.len() + 1;
+    }
+
+    for (player, king) in [
+        (Player::White, Piece::WhiteKing),
+        (Player::Black, Piece::BlackKing),
+    ] {
+        let count = pieces.iter().filter(|p| **p == Some(king)).count() as u32;
+        if count != 1 {
+            return Err(FenError::Field {
+                field: FenField::PiecePlacement,
+                position: offset,
+                kind: FenErrorKind::WrongNumberOfKings(player, count),
+            });
+        }
+    }
+
+    Ok(pieces)
+}
+
+fn parse_castling(
+    castling_str: &str,
+    offset: usize,
+    pieces: &[Option<Piece>; 64],
+) -> Result<([Square; 4], Castling), FenError> {
+    let mut castling_rooks = STANDARD_CASTLING_ROOKS;
+    if castling_str == "-" {
+        return Ok((castling_rooks, Castling::empty()));
+    }
+
+    let mut castling = Castling::empty();
+    for (i, c) in castling_str.char_indices() {
+        let error = |kind| FenError::Field {
+            field: FenField::Castling,
+            position: offset + i,
+            kind,
+        };
+
+        if !matches!(c, 'K' | 'Q' | 'k' | 'q' | 'A'..='H' | 'a'..='h') {
+            return Err(error(FenErrorKind::UnexpectedCharacter(c)));
+        }
+
+        let (player, back_rank, king, rook) = if c.is_ascii_uppercase() {
+            (Player::White, Rank::R1, Piece::WhiteKing, Piece::WhiteRook)
+        } else {
+            (Player::Black, Rank::R8, Piece::BlackKing, Piece::BlackRook)
+        };
+        let piece_at = |f: &File| pieces[Square::from((*f, back_rank)) as usize];
+
+        let king_file = FILES
+            .iter()
+            .find(|f| piece_at(f) == Some(king))
+            .ok_or(error(FenErrorKind::UnsupportedCastlingRight(c)))?;
+
+        // X-FEN uses KQkq for the outermost rook on each side of the king, while Shredder-FEN and
+        // X-FEN name the file of the castling rook when there's any ambiguity.
+        let rook_file = match c {
+            'K' | 'k' => FILES
+                .iter()
+                .rev()
+                .take_while(|f| **f as u8 > *king_file as u8)
+                .find(|f| piece_at(f) == Some(rook)),
+            'Q' | 'q' => FILES
+                .iter()
+                .take_while(|f| (**f as u8) < *king_file as u8)
+                .find(|f| piece_at(f) == Some(rook)),
+            _ => Some(&FILES[c.to_ascii_lowercase() as usize - 'a' as usize]),
+        }
+        .filter(|f| piece_at(f) == Some(rook) && *f != king_file)
+        .ok_or(error(FenErrorKind::UnsupportedCastlingRight(c)))?;
+
+        let right = match (player, *rook_file as u8 > *king_file as u8) {
+            (Player::White, true) => Castling::WHITE_K,
+            (Player::White, false) => Castling::WHITE_Q,
+            (Player::Black, true) => Castling::BLACK_K,
+            (Player::Black, false) => Castling::BLACK_Q,
+        };
+
+        if castling.contains(right) {
+            return Err(error(FenErrorKind::DuplicateCastlingRight(c)));
+        }
+        castling |= right;
+        castling_rooks[right.index()] = Square::from((*rook_file, back_rank));
+    }
+
+    Ok((castling_rooks, castling))
+}
+
+fn parse_en_passant(
+    en_passant_str: &str,
+    offset: usize,
+    turn: Player,
+    pieces: &[Option<Piece>; 64],
+) -> Result<Option<Square>, FenError> {
+    if en_passant_str == "-" {
+        return Ok(None);
+    }
+
+    let square = Square::try_from(en_passant_str).map_err(|_| FenError::Field {
+        field: FenField::EnPassant,
+        position: offset,
+        kind: FenErrorKind::UnexpectedCharacter(en_passant_str.chars().next().unwrap()),
+    })?;
+
+    // The square must be the one a pawn of the player who just moved skipped over, so it must be
+    // empty, the pawn must be in front of it and its starting square must be empty.
+    let file = File::from(square);
+    let (ep_rank, pawn_rank, start_rank, pawn) = match turn {
+        Player::White => (Rank::R6, Rank::R5, Rank::R7, Piece::BlackPawn),
+        Player::Black => (Rank::R3, Rank::R4, Rank::R2, Piece::WhitePawn),
+    };
+    if Rank::from(square) != ep_rank
+        || pieces[square as usize].is_some()
+        || pieces[Square::from((file, start_rank)) as usize].is_some()
+        || pieces[Square::from((file, pawn_rank)) as usize] != Some(pawn)
+    {
+        return Err(FenError::Field {
+            field: FenField::EnPassant,
+            position: offset,
+            kind: FenErrorKind::ImplausibleEnPassant(square),
+        });
+    }
+
+    Ok(Some(square))
+}
+
+fn parse_number(s: &str, offset: usize, field: FenField) -> Result<u32, FenError> {
+    s.parse().map_err(|_| FenError::Field {
+        field,
+        position: offset,
+        kind: FenErrorKind::InvalidNumber(s.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{POS_2_KIWIPETE_FEN, STARTING_POS_FEN};
+
+    #[test]
+    fn parse_test() {
+        let fen = Fen::parse(STARTING_POS_FEN).unwrap();
+        assert_eq!(fen.pieces[Square::E1 as usize], Some(Piece::WhiteKing));
+        assert_eq!(fen.pieces[Square::D8 as usize], Some(Piece::BlackQueen));
+        assert_eq!(fen.pieces[Square::E4 as usize], None);
+        assert_eq!(fen.turn, Player::White);
+        assert_eq!(fen.castling, Castling::all());
+        assert_eq!(fen.en_passant, None);
+        assert_eq!(fen.half_moves, 0);
+        assert_eq!(fen.full_moves, 1);
+
+        let fen = Fen::parse(POS_2_KIWIPETE_FEN).unwrap();
+        assert_eq!(fen.full_moves, 0);
+
+        let fen = Fen::parse("rnbqkbnr/pppp1ppp/8/8/3Pp3/8/PPP1PPPP/RNBQKBNR  b  KQkq  d3  0  3  ")
+            .unwrap();
+        assert_eq!(fen.en_passant, Some(Square::D3));
+        assert_eq!(fen.full_moves, 3);
+    }
+
+    #[test]
+    fn parse_shredder_and_x_fen_castling_test() {
+        let fen = Fen::parse("r3k2r/8/8/8/8/8/8/R3K2R w HAha - 0 1").unwrap();
+        assert_eq!(fen.castling, Castling::all());
+
+        let fen = Fen::parse("r3k2r/8/8/8/8/8/8/R3K2R w Hq - 0 1").unwrap();
+        assert_eq!(fen.castling, Castling::WHITE_K | Castling::BLACK_Q);
+        assert!(!fen.chess960);
+
+        let fen = Fen::parse("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9")
+            .unwrap();
+        assert_eq!(fen.castling, Castling::all());
+        assert_eq!(
+            fen.castling_rooks,
+            [Square::H1, Square::F1, Square::H8, Square::F8]
+        );
+        assert!(fen.chess960);
+
+        // X-FEN uses KQkq for the outermost rooks.
+        let fen = Fen::parse("1r2k1rr/8/8/8/8/8/8/1R2K1RR w KQgq - 0 1").unwrap();
+        assert_eq!(
+            fen.castling_rooks,
+            [Square::H1, Square::B1, Square::G8, Square::B8]
+        );
+        assert!(fen.chess960);
+    }
+
+    #[test]
+    fn parse_error_test() {
+        struct TestCase {
+            fen: &'static str,
+            expected_error: FenError,
+        }
+
+        let field_error = |field, position, kind| FenError::Field {
+            field,
+            position,
+            kind,
+        };
+
+        let test_cases = vec![
+            TestCase {
+                fen: "",
+                expected_error: field_error(
+                    FenField::PiecePlacement,
+                    0,
+                    FenErrorKind::MissingField,
+                ),
+            },
+            TestCase {
+                fen: "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR",
+                expected_error: field_error(FenField::ActiveColor, 43, FenErrorKind::MissingField),
+            },
+            TestCase {
+                fen: "rnbqkbnr/pppppppp/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                expected_error: field_error(
+                    FenField::PiecePlacement,
+                    0,
+                    FenErrorKind::WrongNumberOfRanks(7),
+                ),
+            },
+            TestCase {
+                fen: "rnbqkbnr/pppppppp/8/8/44/8/PPPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                expected_error: field_error(
+                    FenField::PiecePlacement,
+                    27,
+                    FenErrorKind::WrongRankWidth(Rank::R2, 9),
+                ),
+            },
+            TestCase {
+                fen: "rnbqkbnr/pppppppp/7/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                expected_error: field_error(
+                    FenField::PiecePlacement,
+                    18,
+                    FenErrorKind::WrongRankWidth(Rank::R6, 7),
+                ),
+            },
+            TestCase {
+                fen: "rnbqkbnr/pppppppp/8/8/3X4/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                expected_error: field_error(
+                    FenField::PiecePlacement,
+                    23,
+                    FenErrorKind::UnexpectedCharacter('X'),
+                ),
+            },
+            TestCase {
+                fen: "rnbqqbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQ - 0 1",
+                expected_error: field_error(
+                    FenField::PiecePlacement,
+                    0,
+                    FenErrorKind::WrongNumberOfKings(Player::Black, 0),
+                ),
+            },
+            TestCase {
+                fen: "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1",
+                expected_error: field_error(
+                    FenField::ActiveColor,
+                    44,
+                    FenErrorKind::UnexpectedCharacter('x'),
+                ),
+            },
+            TestCase {
+                fen: "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBN1 w KQkq - 0 1",
+                expected_error: field_error(
+                    FenField::Castling,
+                    46,
+                    FenErrorKind::UnsupportedCastlingRight('K'),
+                ),
+            },
+            TestCase {
+                fen: "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KKq - 0 1",
+                expected_error: field_error(
+                    FenField::Castling,
+                    47,
+                    FenErrorKind::DuplicateCastlingRight('K'),
+                ),
+            },
+            TestCase {
+                fen: "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkx - 0 1",
+                expected_error: field_error(
+                    FenField::Castling,
+                    49,
+                    FenErrorKind::UnexpectedCharacter('x'),
+                ),
+            },
+            TestCase {
+                fen: "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w Bk - 0 1",
+                expected_error: field_error(
+                    FenField::Castling,
+                    46,
+                    FenErrorKind::UnsupportedCastlingRight('B'),
+                ),
+            },
+            TestCase {
+                fen: "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e3 0 1",
+                expected_error: field_error(
+                    FenField::EnPassant,
+                    51,
+                    FenErrorKind::ImplausibleEnPassant(Square::E3),
+                ),
+            },
+            TestCase {
+                fen: "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e9 0 1",
+                expected_error: field_error(
+                    FenField::EnPassant,
+                    51,
+                    FenErrorKind::UnexpectedCharacter('e'),
+                ),
+            },
+            TestCase {
+                fen: "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - x 1",
+                expected_error: field_error(
+                    FenField::HalfMoveClock,
+                    53,
+                    FenErrorKind::InvalidNumber("x".to_string()),
+                ),
+            },
+            TestCase {
+                fen: "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 -1",
+                expected_error: field_error(
+                    FenField::FullMoveNumber,
+                    55,
+                    FenErrorKind::InvalidNumber("-1".to_string()),
+                ),
+            },
+            TestCase {
+                fen: "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 x",
+                expected_error: FenError::TooManyFields { position: 57 },
+            },
+        ];
+
+        for test_case in test_cases {
+            assert_eq!(
+                Fen::parse(test_case.fen),
+                Err(test_case.expected_error),
+                "{} failed",
+                test_case.fen
+            );
+        }
+    }
+}
@@ -1,6 +1,31 @@
+pub mod bench;
 pub mod bitboard;
 pub mod board;
+pub mod book;
+pub mod chess960;
 pub mod core;
+pub mod dtm;
+pub mod endgame;
+pub mod epd;
+pub mod eval;
+pub mod eval_params;
+pub mod fen;
 pub mod lookup_tables;
 pub mod magics;
+pub mod movepick;
+pub mod nnue;
+pub mod packed;
+pub mod pawns;
 pub mod perft;
+pub mod pgn;
+pub mod record;
+pub mod search;
+pub mod selfplay;
+pub mod sprt;
+pub mod strength;
+pub mod syzygy;
+pub mod testsuite;
+pub mod time;
+pub mod tt;
+pub mod tune;
+pub mod zobrist;
@@ -591,8 +591,8 @@ fn gen_between_squares_inner(from: Square, to: Square) -> bitboard::BitBoard {
 }
 
 fn gen_lines(
-    rook_moves_table: &Vec<Vec<bitboard::BitBoard>>,
-    bishop_moves_table: &Vec<Vec<bitboard::BitBoard>>,
+    rook_moves_table: &[Vec<bitboard::BitBoard>],
+    bishop_moves_table: &[Vec<bitboard::BitBoard>],
 ) -> [[bitboard::BitBoard; 64]; 64] {
     let mut lines = [[bitboard::BitBoard::new(); 64]; 64];
     for from in SQUARES {
@@ -0,0 +1,549 @@
+use crate::board::{Board, Legality};
+use crate::core::{Move, Piece, PieceKind, Player, PIECES};
+use crate::search::MAX_PLY;
+
+/// Piece values used to order and exchange captures, indexed by `PieceKind`.
+pub const SEE_VALUES: [i32; 6] = [500, 320, 330, 900, 20_000, 100];
+
+/// History scores are kept within this bound.
+pub const MAX_HISTORY: i32 = 16_384;
+
+/// The stages of the move picker in the order they are returned.
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+pub enum Stage {
+    TtMove,
+    GoodCaptures,
+    Promotions,
+    Killers,
+    Countermove,
+    Quiets,
+    BadCaptures,
+    Done,
+}
+
+/// How well quiet moves did in earlier searches, indexed by player, from square and to square.
+#[derive(Clone)]
+pub struct ButterflyHistory(Box<[[[i32; 64]; 64]; 2]>);
+
+impl Default for ButterflyHistory {
+    fn default() -> Self {
+        Self(Box::new([[[0; 64]; 64]; 2]))
+    }
+}
+
+impl ButterflyHistory {
+    pub fn get(&self, player: Player, m: &Move) -> i32 {
+        self.0[player as usize][m.0 as usize][m.1 as usize]
+    }
+
+    /// Adds `bonus`, which is negative for moves that didn't work, scaled down as the score
+    /// approaches `MAX_HISTORY` so it stays in bounds.
+    pub fn update(&mut self, player: Player, m: &Move, bonus: i32) {
+        let bonus = bonus.clamp(-MAX_HISTORY, MAX_HISTORY);
+        let entry = &mut self.0[player as usize][m.0 as usize][m.1 as usize];
+        *entry += bonus - *entry * bonus.abs() / MAX_HISTORY;
+    }
+
+    pub fn clear(&mut self) {
+        *self = ButterflyHistory::default();
+    }
+}
+
+/// Two quiet moves per ply that caused beta cutoffs in sibling positions.
+#[derive(Clone)]
+pub struct Killers(Vec<[Option<Move>; 2]>);
+
+impl Default for Killers {
+    fn default() -> Self {
+        Self(vec![[None; 2]; MAX_PLY + 1])
+    }
+}
+
+impl Killers {
+    pub fn get(&self, ply: usize) -> [Option<Move>; 2] {
+        self.0[ply]
+    }
+
+    pub fn update(&mut self, ply: usize, m: Move) {
+        let killers = &mut self.0[ply];
+        if killers[0] != Some(m) {
+            killers[1] = killers[0];
+            killers[0] = Some(m);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Killers::default();
+    }
+}
+
+/// The quiet move that refuted each previous move, indexed by the piece that moved and where it
+/// moved to.
+#[derive(Clone)]
+pub struct CounterMoves([[Option<Move>; 64]; 12]);
+
+impl Default for CounterMoves {
+    fn default() -> Self {
+        Self([[None; 64]; 12])
+    }
+}
+
+impl CounterMoves {
+    pub fn get(&self, previous: Option<(Piece, Move)>) -> Option<Move> {
+        previous.and_then(|(p, m)| self.0[p as usize][m.1 as usize])
+    }
+
+    pub fn update(&mut self, previous: Option<(Piece, Move)>, m: Move) {
+        if let Some((p, previous_move)) = previous {
+            self.0[p as usize][previous_move.1 as usize] = Some(m);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = CounterMoves::default();
+    }
+}
+
+/// Returns the legal moves of a position in stages so the moves most likely to cause a cutoff
+/// are searched first: the hash move, captures that don't lose material by MVV-LVA, quiet
+/// promotions, killers, the countermove, the other quiets by history and finally the captures
+/// that lose material.
+pub struct MovePicker {
+    stage: Stage,
+    tt_move: Option<Move>,
+    killers: [Option<Move>; 2],
+    killer_index: usize,
+    countermove: Option<Move>,
+    captures: Vec<(Move, i32)>,
+    promotions: Vec<(Move, i32)>,
+    quiets: Vec<(Move, i32)>,
+    bad_captures: Vec<Move>,
+    quiets_scored: bool,
+    skip_quiets: bool,
+}
+
+impl MovePicker {
+    pub fn new(
+        b: &Board,
+        tt_move: Option<Move>,
+        killers: [Option<Move>; 2],
+        countermove: Option<Move>,
+    ) -> Self {
+        let mut picker = MovePicker {
+            stage: Stage::TtMove,
+            tt_move: None,
+            killers,
+            killer_index: 0,
+            countermove,
+            captures: Vec::new(),
+            promotions: Vec::new(),
+            quiets: Vec::new(),
+            bad_captures: Vec::new(),
+            quiets_scored: false,
+            skip_quiets: false,
+        };
+
+        for m in b.generate_moves(Legality::Legal) {
+            if Some(m) == tt_move {
+                picker.tt_move = tt_move;
+            } else if let Some(victim) = b.captured_piece_kind(&m) {
+                let attacker = PieceKind::from(b.get_piece(m.0).unwrap());
+                let promotion = m.2.map_or(0, |pk| SEE_VALUES[pk as usize]);
+                let score = SEE_VALUES[victim as usize] * 16 + promotion
+                    - SEE_VALUES[attacker as usize] / 100;
+                picker.captures.push((m, score));
+            } else if let Some(pk) = m.2 {
+                picker.promotions.push((m, SEE_VALUES[pk as usize]));
+            } else {
+                picker.quiets.push((m, 0));
+            }
+        }
+
+        picker
+    }
+
+    /// A picker for the quiescence search, which only returns captures and queen promotions
+    /// unless the player to move is in check.
+    pub fn new_quiescence(b: &Board, tt_move: Option<Move>) -> Self {
+        let mut picker = MovePicker::new(b, tt_move, [None; 2], None);
+        if !b.is_in_check() {
+            picker.skip_quiets = true;
+            picker
+                .promotions
+                .retain(|(m, _)| m.2 == Some(PieceKind::Queen));
+            if picker
+                .tt_move
+                .is_some_and(|m| !b.is_capture(&m) && m.2 != Some(PieceKind::Queen))
+            {
+                picker.tt_move = None;
+            }
+        }
+
+        picker
+    }
+
+    pub fn stage(&self) -> Stage {
+        self.stage
+    }
+
+    /// The next move to search. `history` orders the quiet moves.
+    pub fn next(&mut self, b: &Board, history: &ButterflyHistory) -> Option<Move> {
+        loop {
+            match self.stage {
+                Stage::TtMove => {
+                    self.stage = Stage::GoodCaptures;
+                    if self.tt_move.is_some() {
+                        return self.tt_move;
+                    }
+                }
+                Stage::GoodCaptures => match pop_best(&mut self.captures) {
+                    Some(m) if see(b, &m) >= 0 => return Some(m),
+                    Some(m) => self.bad_captures.push(m),
+                    None => self.stage = Stage::Promotions,
+                },
+                Stage::Promotions => match pop_best(&mut self.promotions) {
+                    Some(m) => return Some(m),
+                    None if self.skip_quiets => self.stage = Stage::BadCaptures,
+                    None => self.stage = Stage::Killers,
+                },
+                Stage::Killers => {
+                    // Killers come from sibling positions so they might not be legal quiet
+                    // moves here, in which case they are skipped.
+                    let Some(&killer) = self.killers.get(self.killer_index) else {
+                        self.stage = Stage::Countermove;
+                        continue;
+                    };
+                    self.killer_index += 1;
+                    if let Some(m) = killer.and_then(|k| self.take_quiet(k)) {
+                        return Some(m);
+                    }
+                }
+                Stage::Countermove => {
+                    self.stage = Stage::Quiets;
+                    if let Some(m) = self.countermove.and_then(|c| self.take_quiet(c)) {
+                        return Some(m);
+                    }
+                }
+                Stage::Quiets => {
+                    if !self.quiets_scored {
+                        for (m, score) in self.quiets.iter_mut() {
+                            *score = history.get(b.turn(), m);
+                        }
+                        self.quiets_scored = true;
+                    }
+                    match pop_best(&mut self.quiets) {
+                        Some(m) => return Some(m),
+                        None => self.stage = Stage::BadCaptures,
+                    }
+                }
+                Stage::BadCaptures => {
+                    if self.bad_captures.is_empty() {
+                        self.stage = Stage::Done;
+                    } else {
+                        return Some(self.bad_captures.remove(0));
+                    }
+                }
+                Stage::Done => return None,
+            }
+        }
+    }
+
+    /// Removes `m` from the quiet moves, returning it if it was there.
+    fn take_quiet(&mut self, m: Move) -> Option<Move> {
+        let i = self.quiets.iter().position(|(q, _)| *q == m)?;
+        Some(self.quiets.swap_remove(i).0)
+    }
+}
+
+/// Removes the highest scoring move. Moves are picked one at a time because a cutoff usually
+/// comes before the list is exhausted, so sorting everything would be wasted work.
+fn pop_best(moves: &mut Vec<(Move, i32)>) -> Option<Move> {
+    let (i, _) = moves
+        .iter()
+        .enumerate()
+        .max_by_key(|(i, (_, score))| (*score, -(*i as i32)))?;
+    Some(moves.remove(i).0)
+}
+
+/// Static exchange evaluation: the material `m` wins or loses if both players keep recapturing
+/// on its destination with their least valuable piece, stopping whenever recapturing loses.
+pub fn see(b: &Board, m: &Move) -> i32 {
+    let mut gains = [0; 32];
+    let mut occ = b.occupancy(Player::White) | b.occupancy(Player::Black);
+
+    let moved_piece = b.get_piece(m.0).unwrap();
+    let mut attacker_value = SEE_VALUES[PieceKind::from(moved_piece) as usize];
+    if let Some(victim) = b.captured_piece_kind(m) {
+        gains[0] = SEE_VALUES[victim as usize];
+    }
+    if let Some(pk) = m.2 {
+        gains[0] += SEE_VALUES[pk as usize] - SEE_VALUES[PieceKind::Pawn as usize];
+        attacker_value = SEE_VALUES[pk as usize];
+    }
+    if PieceKind::from(moved_piece) == PieceKind::Pawn && Some(m.1) == b.en_passant() {
+        // The captured pawn is beside the moving pawn, not on the destination.
+        let captured_square = crate::core::SQUARES[(m.0 as usize & !7) | (m.1 as usize & 7)];
+        occ.unset_bit(captured_square);
+    }
+    occ.unset_bit(m.0);
+
+    let mut player = Player::from(moved_piece);
+    let mut depth = 0;
+    loop {
+        player = match player {
+            Player::White => Player::Black,
+            Player::Black => Player::White,
+        };
+
+        let attackers = b.attackers_to(m.1, occ) & occ & b.occupancy(player);
+        let Some((piece, from)) = least_valuable_attacker(b, attackers, player) else {
+            break;
+        };
+
+        depth += 1;
+        // The gain for the player recapturing is the piece it takes minus what was gained so far.
+        gains[depth] = attacker_value - gains[depth - 1];
+        if depth == gains.len() - 1 {
+            break;
+        }
+
+        attacker_value = SEE_VALUES[PieceKind::from(piece) as usize];
+        occ.unset_bit(from);
+    }
+
+    while depth > 0 {
+        gains[depth - 1] = -(-gains[depth - 1]).max(gains[depth]);
+        depth -= 1;
+    }
+
+    gains[0]
+}
+
+fn least_valuable_attacker(
+    b: &Board,
+    attackers: crate::bitboard::BitBoard,
+    player: Player,
+) -> Option<(Piece, crate::core::Square)> {
+    let mut pieces: Vec<Piece> = PIECES
+        .into_iter()
+        .filter(|p| Player::from(*p) == player)
+        .collect();
+    pieces.sort_by_key(|p| SEE_VALUES[PieceKind::from(*p) as usize]);
+
+    pieces
+        .into_iter()
+        .find_map(|p| (b.piece_bb(p) & attackers).get_lsb().map(|s| (p, s)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{
+        Square, POS_2_KIWIPETE_FEN, POS_3_FEN, POS_4_FEN, POS_5_FEN, POS_6_FEN, STARTING_POS_FEN,
+    };
+    use crate::lookup_tables::LookupTables;
+
+    #[test]
+    fn see_test() {
+        struct TestCase {
+            name: &'static str,
+            fen: &'static str,
+            m: Move,
+            expected: i32,
+        }
+
+        let test_cases = vec![
+            TestCase {
+                name: "undefended pawn",
+                fen: "1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1",
+                m: Move(Square::E1, Square::E5, None),
+                expected: 100,
+            },
+            TestCase {
+                name: "defended pawn with x-ray",
+                fen: "1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1",
+                m: Move(Square::D3, Square::E5, None),
+                expected: -220,
+            },
+            TestCase {
+                name: "quiet move to a safe square",
+                fen: "4k3/8/3p4/8/3N4/8/8/4K3 w - - 0 1",
+                m: Move(Square::D4, Square::E6, None),
+                expected: 0,
+            },
+            TestCase {
+                name: "quiet move to a pawn attacked square",
+                fen: "4k3/8/3p4/8/5N2/8/8/4K3 w - - 0 1",
+                m: Move(Square::F4, Square::E5, None),
+                expected: -320,
+            },
+            TestCase {
+                name: "rook takes defended knight",
+                fen: "4k3/4p3/3n4/8/8/8/8/3RK3 w - - 0 1",
+                m: Move(Square::D1, Square::D6, None),
+                expected: 320 - 500,
+            },
+            TestCase {
+                name: "en passant",
+                fen: "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1",
+                m: Move(Square::E5, Square::D6, None),
+                expected: 100,
+            },
+            TestCase {
+                name: "promotion",
+                fen: "4k3/P7/8/8/8/8/8/4K3 w - - 0 1",
+                m: Move(Square::A7, Square::A8, Some(PieceKind::Queen)),
+                expected: 800,
+            },
+        ];
+
+        let l = LookupTables::generate();
+        for test_case in test_cases {
+            let b = Board::from_fen(test_case.fen, &l).unwrap();
+            assert_eq!(
+                see(&b, &test_case.m),
+                test_case.expected,
+                "{} failed",
+                test_case.name
+            );
+        }
+    }
+
+    fn picked_moves(
+        b: &Board,
+        tt_move: Option<Move>,
+        killers: [Option<Move>; 2],
+        countermove: Option<Move>,
+        history: &ButterflyHistory,
+    ) -> Vec<(Move, Stage)> {
+        let mut picker = MovePicker::new(b, tt_move, killers, countermove);
+        let mut moves = Vec::new();
+        while let Some(m) = picker.next(b, history) {
+            moves.push((m, picker.stage()));
+        }
+        moves
+    }
+
+    #[test]
+    fn move_picker_test() {
+        let l = LookupTables::generate();
+        let history = ButterflyHistory::default();
+
+        for fen in [
+            STARTING_POS_FEN,
+            POS_2_KIWIPETE_FEN,
+            POS_3_FEN,
+            POS_4_FEN,
+            POS_5_FEN,
+            POS_6_FEN,
+        ] {
+            let b = Board::from_fen(fen, &l).unwrap();
+            let legal_moves = b.generate_moves(Legality::Legal);
+
+            let quiets: Vec<Move> = legal_moves
+                .iter()
+                .filter(|m| !b.is_capture(m) && m.2.is_none())
+                .copied()
+                .collect();
+            let tt_move = legal_moves.last().copied();
+            let killers = [quiets.first().copied(), quiets.get(1).copied()];
+            let countermove = quiets.get(2).copied();
+
+            let picked = picked_moves(&b, tt_move, killers, countermove, &history);
+
+            // Every legal move is returned exactly once.
+            let mut sorted_picked: Vec<String> =
+                picked.iter().map(|(m, _)| m.to_string()).collect();
+            let mut sorted_legal: Vec<String> = legal_moves.iter().map(|m| m.to_string()).collect();
+            sorted_picked.sort();
+            sorted_legal.sort();
+            assert_eq!(sorted_picked, sorted_legal, "{}", fen);
+
+            assert_eq!(picked[0].0, tt_move.unwrap(), "{}", fen);
+
+            // Stages only move forwards.
+            assert!(picked.windows(2).all(|w| w[0].1 <= w[1].1), "{}", fen);
+
+            // Good captures come in MVV-LVA order and don't lose material, bad captures do.
+            let value = |m: &Move| SEE_VALUES[b.captured_piece_kind(m).unwrap() as usize];
+            let good_captures: Vec<Move> = picked
+                .iter()
+                .filter(|(m, s)| *s == Stage::GoodCaptures && Some(*m) != tt_move)
+                .map(|(m, _)| *m)
+                .collect();
+            assert!(good_captures
+                .windows(2)
+                .all(|w| value(&w[0]) >= value(&w[1])));
+            assert!(good_captures.iter().all(|m| see(&b, m) >= 0));
+            assert!(picked
+                .iter()
+                .filter(|(_, s)| *s == Stage::BadCaptures)
+                .all(|(m, _)| see(&b, m) < 0));
+
+            // Killers and the countermove come straight after the captures and promotions.
+            let first_quiet = picked
+                .iter()
+                .position(|(m, _)| Some(*m) != tt_move && quiets.contains(m));
+            if let Some(i) = first_quiet {
+                let expected: Vec<Move> = killers
+                    .iter()
+                    .chain([countermove].iter())
+                    .flatten()
+                    .filter(|m| Some(**m) != tt_move)
+                    .copied()
+                    .collect();
+                let actual: Vec<Move> = picked[i..i + expected.len()]
+                    .iter()
+                    .map(|(m, _)| *m)
+                    .collect();
+                assert_eq!(actual, expected, "{}", fen);
+            }
+        }
+    }
+
+    #[test]
+    fn move_picker_history_test() {
+        let l = LookupTables::generate();
+        let b = Board::from_fen(STARTING_POS_FEN, &l).unwrap();
+
+        let mut history = ButterflyHistory::default();
+        let good = Move(Square::G1, Square::F3, None);
+        let bad = Move(Square::B1, Square::A3, None);
+        history.update(Player::White, &good, 400);
+        history.update(Player::White, &bad, -400);
+
+        let picked = picked_moves(&b, None, [None; 2], None, &history);
+        assert_eq!(picked[0].0, good);
+        assert_eq!(picked.last().unwrap().0, bad);
+
+        // Illegal killers and countermoves are skipped.
+        let illegal = Move(Square::E2, Square::E5, None);
+        let picked = picked_moves(&b, None, [Some(illegal), None], Some(illegal), &history);
+        assert_eq!(picked.len(), 20);
+    }
+
+    #[test]
+    fn quiescence_picker_test() {
+        let l = LookupTables::generate();
+        let b = Board::from_fen(POS_2_KIWIPETE_FEN, &l).unwrap();
+
+        let mut picker = MovePicker::new_quiescence(&b, None);
+        let mut count = 0;
+        while let Some(m) = picker.next(&b, &ButterflyHistory::default()) {
+            assert!(b.is_capture(&m));
+            count += 1;
+        }
+        assert_eq!(count, 8);
+    }
+
+    #[test]
+    fn history_update_test() {
+        let mut history = ButterflyHistory::default();
+        let m = Move(Square::E2, Square::E4, None);
+        for _ in 0..1000 {
+            history.update(Player::Black, &m, 2000);
+        }
+        assert!(history.get(Player::Black, &m) <= MAX_HISTORY);
+        assert_eq!(history.get(Player::White, &m), 0);
+    }
+}
@@ -0,0 +1,423 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::core::{Piece, Player, Square};
+
+/// Identifies a weights file.
+const MAGIC: &[u8; 4] = b"CRNN";
+const VERSION: u32 = 1;
+
+/// One input for each piece on each square.
+pub const INPUTS: usize = 768;
+
+/// The activations of the hidden layer are clipped to between 0 and this.
+const QA: i32 = 255;
+/// The output weights are scaled up by this.
+const QB: i32 = 64;
+/// Converts the output of the network to centipawns.
+const SCALE: i32 = 400;
+
+/// A network with a hidden layer of N neurons for each side, (768→N)x2→1. The inputs are seen
+/// from the point of view of each player so the same weights are used for both, and the output
+/// layer takes the side to move's half of the hidden layer first.
+///
+/// The weights file is a header of `CRNN`, the version and N as little endian `u32`s followed by
+/// the feature weights (768 rows of N), feature biases (N) and output weights (2N) as little
+/// endian `i16`s and the output bias as a little endian `i32`.
+#[derive(Clone, PartialEq)]
+pub struct Network {
+    hidden_size: usize,
+    feature_weights: Vec<i16>,
+    feature_biases: Vec<i16>,
+    output_weights: Vec<i16>,
+    output_bias: i32,
+}
+
+impl fmt::Debug for Network {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Network({}→{}x2→1)", INPUTS, self.hidden_size)
+    }
+}
+
+impl Network {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        Network::from_bytes(&bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < 12 || &bytes[..4] != MAGIC {
+            return Err("not a network file".to_string());
+        }
+        let version = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+        if version != VERSION {
+            return Err(format!("unsupported network version: {}", version));
+        }
+        let hidden_size = u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize;
+        if hidden_size == 0 {
+            return Err("the hidden layer is empty".to_string());
+        }
+
+        let weight_count = INPUTS * hidden_size + hidden_size + 2 * hidden_size;
+        let expected_len = 12 + weight_count * 2 + 4;
+        if bytes.len() != expected_len {
+            return Err(format!(
+                "expected {} bytes for a hidden layer of {} but found {}",
+                expected_len,
+                hidden_size,
+                bytes.len()
+            ));
+        }
+
+        let mut weights = bytes[12..12 + weight_count * 2]
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]));
+        let mut take = |n: usize| weights.by_ref().take(n).collect::<Vec<i16>>();
+
+        Ok(Network {
+            hidden_size,
+            feature_weights: take(INPUTS * hidden_size),
+            feature_biases: take(hidden_size),
+            output_weights: take(2 * hidden_size),
+            output_bias: i32::from_le_bytes(bytes[expected_len - 4..].try_into().unwrap()),
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(self.hidden_size as u32).to_le_bytes());
+        for w in self
+            .feature_weights
+            .iter()
+            .chain(&self.feature_biases)
+            .chain(&self.output_weights)
+        {
+            bytes.extend_from_slice(&w.to_le_bytes());
+        }
+        bytes.extend_from_slice(&self.output_bias.to_le_bytes());
+        bytes
+    }
+
+    pub fn hidden_size(&self) -> usize {
+        self.hidden_size
+    }
+
+    fn feature_weights(&self, feature: usize) -> &[i16] {
+        &self.feature_weights[feature * self.hidden_size..(feature + 1) * self.hidden_size]
+    }
+
+    /// The evaluation in centipawns from the side to move's point of view.
+    fn output(&self, us: &[i16], them: &[i16]) -> i32 {
+        let (our_weights, their_weights) = self.output_weights.split_at(self.hidden_size);
+        let sum = simd::crelu_dot(us, our_weights) + simd::crelu_dot(them, their_weights);
+        (sum + self.output_bias) * SCALE / (QA * QB)
+    }
+}
+
+/// The inputs of the point of view of `perspective` for `p` on `s`. Black sees the board flipped
+/// with the colours swapped.
+fn feature(perspective: Player, p: Piece, s: Square) -> usize {
+    match perspective {
+        Player::White => p as usize * 64 + s as usize,
+        Player::Black => (p as usize + 6) % 12 * 64 + (s as usize ^ 56),
+    }
+}
+
+/// The hidden layer before it's activated, for each point of view.
+#[derive(Clone, Debug, PartialEq)]
+struct Accumulator {
+    white: Vec<i16>,
+    black: Vec<i16>,
+}
+
+/// The accumulators of the positions from the root to the current one, so moves can be undone by
+/// going back to the previous accumulator instead of recomputing it.
+#[derive(Clone)]
+pub struct NnueState {
+    network: Arc<Network>,
+    accumulators: Vec<Accumulator>,
+    // Accumulators past this are kept to be reused without allocating.
+    len: usize,
+}
+
+impl fmt::Debug for NnueState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "NnueState({:?}, {} accumulators)",
+            self.network, self.len
+        )
+    }
+}
+
+impl NnueState {
+    pub fn new(network: Arc<Network>, pieces: &[(Piece, Square)]) -> Self {
+        let mut state = NnueState {
+            accumulators: Vec::new(),
+            len: 1,
+            network,
+        };
+        state.accumulators.push(state.compute(pieces));
+        state
+    }
+
+    pub fn network(&self) -> &Arc<Network> {
+        &self.network
+    }
+
+    /// Computes an accumulator from scratch.
+    fn compute(&self, pieces: &[(Piece, Square)]) -> Accumulator {
+        let mut accumulator = Accumulator {
+            white: self.network.feature_biases.clone(),
+            black: self.network.feature_biases.clone(),
+        };
+        for (p, s) in pieces {
+            simd::add(
+                &mut accumulator.white,
+                self.network.feature_weights(feature(Player::White, *p, *s)),
+            );
+            simd::add(
+                &mut accumulator.black,
+                self.network.feature_weights(feature(Player::Black, *p, *s)),
+            );
+        }
+        accumulator
+    }
+
+    /// Whether the current accumulator matches one computed from scratch for `pieces`.
+    pub fn is_consistent(&self, pieces: &[(Piece, Square)]) -> bool {
+        self.accumulators[self.len - 1] == self.compute(pieces)
+    }
+
+    /// Copies the current accumulator so the next move can change it.
+    pub fn push(&mut self) {
+        if self.len == self.accumulators.len() {
+            self.accumulators
+                .push(self.accumulators[self.len - 1].clone());
+        } else {
+            let (done, rest) = self.accumulators.split_at_mut(self.len);
+            rest[0].white.copy_from_slice(&done[self.len - 1].white);
+            rest[0].black.copy_from_slice(&done[self.len - 1].black);
+        }
+        self.len += 1;
+    }
+
+    pub fn pop(&mut self) {
+        self.len -= 1;
+    }
+
+    pub fn add_piece(&mut self, p: Piece, s: Square) {
+        let accumulator = &mut self.accumulators[self.len - 1];
+        simd::add(
+            &mut accumulator.white,
+            self.network.feature_weights(feature(Player::White, p, s)),
+        );
+        simd::add(
+            &mut accumulator.black,
+            self.network.feature_weights(feature(Player::Black, p, s)),
+        );
+    }
+
+    pub fn remove_piece(&mut self, p: Piece, s: Square) {
+        let accumulator = &mut self.accumulators[self.len - 1];
+        simd::sub(
+            &mut accumulator.white,
+            self.network.feature_weights(feature(Player::White, p, s)),
+        );
+        simd::sub(
+            &mut accumulator.black,
+            self.network.feature_weights(feature(Player::Black, p, s)),
+        );
+    }
+
+    /// Only the current accumulator, for a board that can't undo moves.
+    pub fn shallow_clone(&self) -> Self {
+        NnueState {
+            network: Arc::clone(&self.network),
+            accumulators: vec![self.accumulators[self.len - 1].clone()],
+            len: 1,
+        }
+    }
+
+    /// The evaluation in centipawns from `turn`'s point of view.
+    pub fn evaluate(&self, turn: Player) -> i32 {
+        let accumulator = &self.accumulators[self.len - 1];
+        match turn {
+            Player::White => self.network.output(&accumulator.white, &accumulator.black),
+            Player::Black => self.network.output(&accumulator.black, &accumulator.white),
+        }
+    }
+}
+
+/// The vector operations of the network. The additions are simple enough for the compiler to
+/// vectorise on its own but the activation and dot product are written with AVX2 intrinsics when
+/// the CPU has them.
+mod simd {
+    use super::QA;
+
+    pub fn add(accumulator: &mut [i16], weights: &[i16]) {
+        for (a, w) in accumulator.iter_mut().zip(weights) {
+            *a = a.wrapping_add(*w);
+        }
+    }
+
+    pub fn sub(accumulator: &mut [i16], weights: &[i16]) {
+        for (a, w) in accumulator.iter_mut().zip(weights) {
+            *a = a.wrapping_sub(*w);
+        }
+    }
+
+    /// The dot product of the clipped ReLU of `values` with `weights`.
+    pub fn crelu_dot(values: &[i16], weights: &[i16]) -> i32 {
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("avx2") {
+                // SAFETY: the CPU supports AVX2.
+                return unsafe { crelu_dot_avx2(values, weights) };
+            }
+        }
+
+        crelu_dot_scalar(values, weights)
+    }
+
+    pub fn crelu_dot_scalar(values: &[i16], weights: &[i16]) -> i32 {
+        values
+            .iter()
+            .zip(weights)
+            .map(|(v, w)| (*v as i32).clamp(0, QA) * *w as i32)
+            .sum()
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[target_feature(enable = "avx2")]
+    unsafe fn crelu_dot_avx2(values: &[i16], weights: &[i16]) -> i32 {
+        use std::arch::x86_64::*;
+
+        const LANES: usize = 16;
+        let chunks = values.len() / LANES;
+        let zero = _mm256_setzero_si256();
+        let qa = _mm256_set1_epi16(QA as i16);
+        let mut sum = _mm256_setzero_si256();
+        for i in 0..chunks {
+            let v = _mm256_loadu_si256(values.as_ptr().add(i * LANES) as *const __m256i);
+            let w = _mm256_loadu_si256(weights.as_ptr().add(i * LANES) as *const __m256i);
+            let clipped = _mm256_min_epi16(_mm256_max_epi16(v, zero), qa);
+            sum = _mm256_add_epi32(sum, _mm256_madd_epi16(clipped, w));
+        }
+
+        let mut lanes = [0i32; 8];
+        _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, sum);
+        lanes.iter().sum::<i32>()
+            + crelu_dot_scalar(&values[chunks * LANES..], &weights[chunks * LANES..])
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::board::{Board, Legality};
+    use crate::core::{POS_2_KIWIPETE_FEN, POS_4_FEN, POS_5_FEN, STARTING_POS_FEN};
+    use crate::lookup_tables::LookupTables;
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::{Rng, SeedableRng};
+
+    /// A network with random weights, which is enough to check the evaluation is computed
+    /// consistently.
+    pub fn random_network(hidden_size: usize, seed: u64) -> Network {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut weights = |n: usize, range: i16| -> Vec<i16> {
+            (0..n).map(|_| rng.gen_range(-range..=range)).collect()
+        };
+        Network {
+            hidden_size,
+            feature_weights: weights(INPUTS * hidden_size, 64),
+            feature_biases: weights(hidden_size, 64),
+            output_weights: weights(2 * hidden_size, 64),
+            output_bias: 1000,
+        }
+    }
+
+    #[test]
+    fn load_test() {
+        let network = random_network(40, 1);
+        let bytes = network.to_bytes();
+        assert_eq!(Network::from_bytes(&bytes), Ok(network));
+
+        assert!(Network::from_bytes(b"CRNN").is_err());
+        assert!(Network::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        let mut wrong_version = bytes.clone();
+        wrong_version[4] = 2;
+        assert!(Network::from_bytes(&wrong_version).is_err());
+        assert!(Network::load("does/not/exist.nnue").is_err());
+    }
+
+    #[test]
+    fn crelu_dot_test() {
+        let mut rng = StdRng::seed_from_u64(2);
+        // Sizes that do and don't fill whole SIMD registers.
+        for n in [16, 37, 256] {
+            let values: Vec<i16> = (0..n).map(|_| rng.gen_range(-400..400)).collect();
+            let weights: Vec<i16> = (0..n).map(|_| rng.gen_range(-100..100)).collect();
+            assert_eq!(
+                simd::crelu_dot(&values, &weights),
+                simd::crelu_dot_scalar(&values, &weights)
+            );
+        }
+    }
+
+    #[test]
+    fn incremental_test() {
+        let l = LookupTables::generate();
+        let network = Arc::new(random_network(64, 3));
+        let mut rng = StdRng::seed_from_u64(4);
+
+        for fen in [STARTING_POS_FEN, POS_2_KIWIPETE_FEN, POS_4_FEN, POS_5_FEN] {
+            for _ in 0..10 {
+                let mut b = Board::from_fen(fen, &l).unwrap();
+                b.set_network(Some(Arc::clone(&network)));
+
+                let mut evaluations = vec![b.nnue_evaluate().unwrap()];
+                for _ in 0..60 {
+                    let legal_moves = b.generate_moves(Legality::Legal);
+                    let Some(m) = legal_moves.choose(&mut rng).copied() else {
+                        break;
+                    };
+                    b.apply_move(m, &legal_moves);
+                    assert!(b.is_nnue_consistent(), "{} after {}", fen, m);
+
+                    let mut from_scratch = b.clone();
+                    from_scratch.set_network(Some(Arc::clone(&network)));
+                    assert_eq!(b.nnue_evaluate(), from_scratch.nnue_evaluate());
+                    evaluations.push(b.nnue_evaluate().unwrap());
+                }
+
+                // Undoing the moves goes back through the same evaluations.
+                while !b.moves.is_empty() {
+                    evaluations.pop();
+                    b.undo_move();
+                    assert!(b.is_nnue_consistent());
+                    assert_eq!(b.nnue_evaluate(), evaluations.last().copied());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn symmetry_test() {
+        let l = LookupTables::generate();
+        let network = Arc::new(random_network(32, 5));
+
+        // A position and its mirror with the colours swapped are the same for the side to move.
+        let mut b = Board::from_fen(POS_4_FEN, &l).unwrap();
+        let mut mirrored = Board::from_fen(crate::core::POS_4_MIRRORED_FEN, &l).unwrap();
+        b.set_network(Some(Arc::clone(&network)));
+        mirrored.set_network(Some(network));
+        assert_eq!(b.nnue_evaluate(), mirrored.nnue_evaluate());
+    }
+}
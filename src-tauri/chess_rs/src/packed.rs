@@ -0,0 +1,327 @@
+use crate::board::{Board, Castling, Legality, STANDARD_CASTLING_ROOKS};
+use crate::core::{Player, Square, PIECES, SQUARES};
+use crate::fen::Fen;
+use crate::lookup_tables::LookupTables;
+
+/// The size of a packed position.
+pub const POSITION_SIZE: usize = 32;
+
+/// No en passant square.
+const NO_EN_PASSANT: u8 = 0xff;
+
+/// A position in 32 bytes, for storing the millions of positions of training data or game
+/// databases:
+///
+/// - bytes 0 to 7: the occupied squares as a little endian bitboard
+/// - bytes 8 to 23: the piece on each occupied square from A1 to H8 in a nibble, low nibble
+///   first, numbered in the order of `PIECES`
+/// - byte 24: black to move in bit 0, the castling rights in bits 1 to 4 and Chess960 castling
+///   in bit 5
+/// - byte 25: the en passant square, or 255 if there isn't one
+/// - bytes 26 and 27: the file of the rook of each castling right in 3 bits, in the order of
+///   `Castling::index`, as a little endian number
+/// - bytes 28 to 31: the half move clock and the full move number as little endian numbers
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PackedPosition(pub [u8; POSITION_SIZE]);
+
+impl PackedPosition {
+    /// Fails if the position has more than 32 pieces or its move counters don't fit in 16 bits.
+    pub fn from_fen_fields(fen: &Fen) -> Result<Self, String> {
+        let mut bytes = [0; POSITION_SIZE];
+
+        let mut occupancy = 0u64;
+        let mut n = 0;
+        for (s, piece) in fen.pieces.iter().enumerate() {
+            let Some(p) = piece else {
+                continue;
+            };
+            if n == 32 {
+                return Err("more than 32 pieces".to_string());
+            }
+            occupancy |= 1 << s;
+            bytes[8 + n / 2] |= (*p as u8) << (4 * (n % 2));
+            n += 1;
+        }
+        bytes[0..8].copy_from_slice(&occupancy.to_le_bytes());
+
+        bytes[24] = (fen.turn == Player::Black) as u8
+            | fen.castling.bits() << 1
+            | (fen.chess960 as u8) << 5;
+        bytes[25] = fen.en_passant.map_or(NO_EN_PASSANT, |s| s as u8);
+        let mut rook_files = 0u16;
+        for (i, rook) in fen.castling_rooks.iter().enumerate() {
+            // The rooks of rights that are gone are left out so a position always packs the same.
+            let rook = match fen.castling.bits() & (1 << i) {
+                0 => STANDARD_CASTLING_ROOKS[i],
+                _ => *rook,
+            };
+            rook_files |= (rook as u16 % 8) << (3 * i);
+        }
+        bytes[26..28].copy_from_slice(&rook_files.to_le_bytes());
+
+        let half_moves = u16::try_from(fen.half_moves)
+            .map_err(|_| format!("half move clock too large: {}", fen.half_moves))?;
+        let full_moves = u16::try_from(fen.full_moves)
+            .map_err(|_| format!("full move number too large: {}", fen.full_moves))?;
+        bytes[28..30].copy_from_slice(&half_moves.to_le_bytes());
+        bytes[30..32].copy_from_slice(&full_moves.to_le_bytes());
+
+        Ok(PackedPosition(bytes))
+    }
+
+    /// Fails if the bytes aren't a packed position, but not if they're a position that can't be
+    /// reached, which `board` checks.
+    pub fn fen_fields(&self) -> Result<Fen, String> {
+        let bytes = &self.0;
+
+        let occupancy = u64::from_le_bytes(bytes[0..8].try_into().unwrap());
+        if occupancy.count_ones() > 32 {
+            return Err("more than 32 pieces".to_string());
+        }
+        let mut pieces = [None; 64];
+        for (n, s) in (0..64).filter(|s| occupancy & (1 << s) != 0).enumerate() {
+            let code = (bytes[8 + n / 2] >> (4 * (n % 2))) & 0xf;
+            let p = PIECES
+                .get(code as usize)
+                .ok_or_else(|| format!("invalid piece: {}", code))?;
+            pieces[s] = Some(*p);
+        }
+
+        let flags = bytes[24];
+        if flags >> 6 != 0 {
+            return Err(format!("invalid flags: {:#04x}", flags));
+        }
+        let en_passant = match bytes[25] {
+            NO_EN_PASSANT => None,
+            s => {
+                Some(Square::try_from(s).map_err(|_| format!("invalid en passant square: {}", s))?)
+            }
+        };
+        let rook_files = u16::from_le_bytes(bytes[26..28].try_into().unwrap());
+        let mut castling_rooks = [Square::A1; 4];
+        for (i, rook) in castling_rooks.iter_mut().enumerate() {
+            let file = (rook_files >> (3 * i)) as usize & 7;
+            // The rooks of the first two rights are White's.
+            *rook = SQUARES[if i < 2 { file } else { 56 + file }];
+        }
+
+        Ok(Fen {
+            pieces,
+            turn: if flags & 1 == 0 {
+                Player::White
+            } else {
+                Player::Black
+            },
+            castling: Castling::from_bits_truncate(flags >> 1),
+            castling_rooks,
+            chess960: flags & (1 << 5) != 0,
+            en_passant,
+            half_moves: u16::from_le_bytes(bytes[28..30].try_into().unwrap()) as u32,
+            full_moves: u16::from_le_bytes(bytes[30..32].try_into().unwrap()) as u32,
+        })
+    }
+
+    pub fn from_board(b: &Board) -> Result<Self, String> {
+        PackedPosition::from_fen_fields(&b.fen_fields())
+    }
+
+    pub fn board<'a>(&self, l: &'a LookupTables) -> Result<Board<'a>, String> {
+        Board::from_fen_fields(&self.fen_fields()?, l)
+            .map_err(|e| format!("invalid position: {}", e))
+    }
+}
+
+/// A game stored as its start position followed by the number of moves as a little endian 16 bit
+/// number and a byte for each move, which is its index in the legal moves of its position in the
+/// order they're generated. Changing the move generation order makes stored games unreadable.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PackedGame {
+    pub start: PackedPosition,
+    pub moves: Vec<u8>,
+}
+
+impl PackedGame {
+    /// The game played on `b`, made of the moves it recorded with `apply_move`.
+    pub fn from_board(b: &Board) -> Result<Self, String> {
+        if b.moves.len() > u16::MAX as usize {
+            return Err(format!("too many moves: {}", b.moves.len()));
+        }
+
+        let mut board = b.start_position();
+        let start = PackedPosition::from_board(&board)?;
+        let mut moves = Vec::with_capacity(b.moves.len());
+        for m in &b.moves {
+            let legal_moves = board.generate_moves(Legality::Legal);
+            let index = legal_moves
+                .iter()
+                .position(|legal| legal == m)
+                .ok_or_else(|| format!("illegal move: {}", m))?;
+            moves.push(index as u8);
+            board.make_move(*m);
+        }
+
+        Ok(PackedGame { start, moves })
+    }
+
+    /// Replays the game, recording its moves in the board's `moves` and `san_moves`.
+    pub fn board<'a>(&self, l: &'a LookupTables) -> Result<Board<'a>, String> {
+        let mut b = self.start.board(l)?;
+        for (ply, index) in self.moves.iter().enumerate() {
+            let legal_moves = b.generate_moves(Legality::Legal);
+            let m = *legal_moves.get(*index as usize).ok_or_else(|| {
+                format!(
+                    "move {} of ply {} is out of the {} legal moves",
+                    index,
+                    ply + 1,
+                    legal_moves.len()
+                )
+            })?;
+            b.apply_move(m, &legal_moves);
+        }
+        Ok(b)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(POSITION_SIZE + 2 + self.moves.len());
+        bytes.extend_from_slice(&self.start.0);
+        bytes.extend_from_slice(&(self.moves.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(&self.moves);
+        bytes
+    }
+
+    /// Reads the game at the start of `bytes`, returning it with the number of bytes it took up
+    /// so games can be stored one after the other.
+    pub fn read(bytes: &[u8]) -> Result<(Self, usize), String> {
+        if bytes.len() < POSITION_SIZE + 2 {
+            return Err(format!("expected a game but found {} bytes", bytes.len()));
+        }
+        let count = u16::from_le_bytes([bytes[POSITION_SIZE], bytes[POSITION_SIZE + 1]]) as usize;
+        let size = POSITION_SIZE + 2 + count;
+        if bytes.len() < size {
+            return Err(format!(
+                "expected {} moves but found {}",
+                count,
+                bytes.len() - POSITION_SIZE - 2
+            ));
+        }
+
+        let game = PackedGame {
+            start: PackedPosition(bytes[..POSITION_SIZE].try_into().unwrap()),
+            moves: bytes[POSITION_SIZE + 2..size].to_vec(),
+        };
+        Ok((game, size))
+    }
+}
+
+/// Reads games stored one after the other with `PackedGame::to_bytes`.
+pub fn read_games(mut bytes: &[u8]) -> Result<Vec<PackedGame>, String> {
+    let mut games = Vec::new();
+    while !bytes.is_empty() {
+        let (game, size) =
+            PackedGame::read(bytes).map_err(|e| format!("game {}: {}", games.len() + 1, e))?;
+        games.push(game);
+        bytes = &bytes[size..];
+    }
+    Ok(games)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn random_game<'a>(mut b: Board<'a>, rng: &mut StdRng) -> Board<'a> {
+        for _ in 0..300 {
+            let legal_moves = b.generate_moves(Legality::Legal);
+            if legal_moves.is_empty() {
+                break;
+            }
+            let m = legal_moves[rng.gen_range(0..legal_moves.len())];
+            b.apply_move(m, &legal_moves);
+        }
+        b
+    }
+
+    #[test]
+    fn position_test() {
+        let l = LookupTables::generate();
+        let b = Board::from_fen(
+            "rnbqkbnr/1pp1pppp/p7/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 3",
+            &l,
+        )
+        .unwrap();
+        let packed = PackedPosition::from_board(&b).unwrap();
+        assert_eq!(packed.0[24], 0b11110);
+        assert_eq!(packed.0[25], Square::D6 as u8);
+        assert_eq!(packed.board(&l).unwrap().fen(), b.fen());
+
+        let mut rng = StdRng::seed_from_u64(0);
+        for id in [518, 0, 959, 123] {
+            let start = Board::start_pos_960(id, &l).unwrap();
+            let mut b = random_game(start, &mut rng);
+            while !b.moves.is_empty() {
+                let packed = PackedPosition::from_board(&b).unwrap();
+                let read = packed.board(&l).unwrap();
+                assert_eq!(read.fen(), b.fen());
+                assert_eq!(read.is_chess960(), b.is_chess960());
+                assert_eq!(
+                    read.generate_moves(Legality::Legal),
+                    b.generate_moves(Legality::Legal)
+                );
+                assert_eq!(PackedPosition::from_board(&read).unwrap(), packed);
+                b.undo_move();
+            }
+        }
+
+        let mut invalid = packed;
+        invalid.0[8] = 0xff;
+        assert_eq!(invalid.board(&l).unwrap_err(), "invalid piece: 15");
+        let mut invalid = packed;
+        invalid.0[25] = 64;
+        assert!(invalid.board(&l).is_err());
+        let mut invalid = packed;
+        invalid.0[8] = 0;
+        assert!(invalid
+            .board(&l)
+            .unwrap_err()
+            .starts_with("invalid position"));
+        let full = Board::from_fen("8/8/8/4k3/8/8/8/4K2R w K - 0 70000", &l).unwrap();
+        assert!(PackedPosition::from_board(&full).is_err());
+    }
+
+    #[test]
+    fn game_test() {
+        let l = LookupTables::generate();
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut boards = Vec::new();
+        let mut bytes = Vec::new();
+        for id in [518, 518, 518, 42, 700] {
+            let b = random_game(Board::start_pos_960(id, &l).unwrap(), &mut rng);
+            let game = PackedGame::from_board(&b).unwrap();
+            assert_eq!(game.moves.len(), b.moves.len());
+            assert_eq!(game.board(&l).unwrap().san_moves, b.san_moves);
+            bytes.extend(game.to_bytes());
+            boards.push(b);
+        }
+
+        let games = read_games(&bytes).unwrap();
+        assert_eq!(games.len(), boards.len());
+        for (game, b) in games.iter().zip(&boards) {
+            let read = game.board(&l).unwrap();
+            assert_eq!(read.fen(), b.fen());
+            assert_eq!(read.start_fen(), b.start_fen());
+            assert_eq!(read.moves, b.moves);
+        }
+
+        assert!(read_games(&bytes[..bytes.len() - 1])
+            .unwrap_err()
+            .starts_with(&format!("game {}: expected", boards.len())));
+        let mut invalid = games[0].clone();
+        invalid.moves[0] = 20;
+        assert_eq!(
+            invalid.board(&l).unwrap_err(),
+            "move 20 of ply 1 is out of the 20 legal moves"
+        );
+    }
+}
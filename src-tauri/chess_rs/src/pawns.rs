@@ -0,0 +1,214 @@
+use crate::bitboard::BitBoard;
+use crate::core::{Player, FILE_A};
+use crate::eval::EvalParams;
+
+pub const DEFAULT_PAWN_TABLE_ENTRIES: usize = 1 << 14;
+
+/// What's known about a pawn structure. The scores are from white's point of view and the rest
+/// is indexed by `Player`.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct PawnEntry {
+    pub key: u64,
+    pub mg: i32,
+    pub eg: i32,
+    pub passed: [BitBoard; 2],
+    /// The files without any of the player's pawns, with a bit for each file from a.
+    pub semi_open_files: [u8; 2],
+    /// The number of the player's pawns shielding a king on each file, which are the pawns on
+    /// the king's and the neighbouring files on the second and third ranks.
+    pub shield: [[u8; 8]; 2],
+}
+
+/// A cache of pawn structure evaluations keyed by `Board::pawn_hash`. The pawns change far less
+/// often than the rest of the position so most evaluations in a search find their entry here.
+///
+/// The scores in the entries depend on the parameters they were worked out with, so a table
+/// should only be used with one set of parameters.
+pub struct PawnTable {
+    entries: Vec<PawnEntry>,
+}
+
+impl Default for PawnTable {
+    fn default() -> Self {
+        PawnTable::new(DEFAULT_PAWN_TABLE_ENTRIES)
+    }
+}
+
+impl PawnTable {
+    /// A table with `entries` rounded up to a power of two entries.
+    pub fn new(entries: usize) -> Self {
+        PawnTable {
+            entries: vec![PawnEntry::default(); entries.next_power_of_two()],
+        }
+    }
+
+    /// The entry for `pawns`, indexed by `Player`, which is evaluated and stored if it isn't in
+    /// the table.
+    pub fn probe(&mut self, key: u64, pawns: [BitBoard; 2], params: &EvalParams) -> &PawnEntry {
+        let index = key as usize & (self.entries.len() - 1);
+        let entry = &mut self.entries[index];
+        // The empty entry has a key of 0, which is also the key of a board without pawns.
+        if entry.key != key || key == 0 {
+            *entry = evaluate_pawns(key, pawns, params);
+        }
+        entry
+    }
+}
+
+fn file_mask(file: usize) -> u64 {
+    FILE_A << file
+}
+
+fn adjacent_files_mask(file: usize) -> u64 {
+    let left = if file > 0 { file_mask(file - 1) } else { 0 };
+    let right = if file < 7 { file_mask(file + 1) } else { 0 };
+    left | right
+}
+
+/// The squares on the ranks in front of `rank` from `player`'s point of view.
+fn ranks_ahead_mask(player: Player, rank: usize) -> u64 {
+    match player {
+        Player::White if rank < 7 => u64::MAX << (8 * (rank + 1)),
+        Player::Black => (1 << (8 * rank)) - 1,
+        _ => 0,
+    }
+}
+
+/// Evaluates the pawn structure of `pawns`, indexed by `Player`: passed, doubled and isolated
+/// pawns, and the open files and pawn shields used by the rest of the evaluation.
+pub fn evaluate_pawns(key: u64, pawns: [BitBoard; 2], params: &EvalParams) -> PawnEntry {
+    let mut entry = PawnEntry {
+        key,
+        ..PawnEntry::default()
+    };
+
+    for player in [Player::White, Player::Black] {
+        let ours = pawns[player as usize].0;
+        let theirs = pawns[1 - player as usize].0;
+        let sign = match player {
+            Player::White => 1,
+            Player::Black => -1,
+        };
+
+        for s in BitBoard(ours) {
+            let file = s as usize % 8;
+            let rank = s as usize / 8;
+            let relative_rank = match player {
+                Player::White => rank,
+                Player::Black => 7 - rank,
+            };
+            let ahead = ranks_ahead_mask(player, rank);
+
+            // Only the front pawn of doubled pawns can be passed.
+            let front_span = ahead & (file_mask(file) | adjacent_files_mask(file));
+            if theirs & front_span == 0 && ours & ahead & file_mask(file) == 0 {
+                entry.passed[player as usize].set_bit(s);
+                entry.mg += sign * params.passed_pawn_mg[relative_rank];
+                entry.eg += sign * params.passed_pawn_eg[relative_rank];
+            }
+
+            if ours & adjacent_files_mask(file) == 0 {
+                entry.mg += sign * params.isolated_pawn_mg;
+                entry.eg += sign * params.isolated_pawn_eg;
+            }
+        }
+
+        for file in 0..8 {
+            let count = (ours & file_mask(file)).count_ones() as i32;
+            if count == 0 {
+                entry.semi_open_files[player as usize] |= 1 << file;
+            } else if count > 1 {
+                entry.mg += sign * params.doubled_pawn_mg * (count - 1);
+                entry.eg += sign * params.doubled_pawn_eg * (count - 1);
+            }
+
+            let shield_ranks = match player {
+                Player::White => 0x0000_0000_00FF_FF00,
+                Player::Black => 0x00FF_FF00_0000_0000,
+            };
+            let shield_files = file_mask(file) | adjacent_files_mask(file);
+            entry.shield[player as usize][file] =
+                (ours & shield_ranks & shield_files).count_ones() as u8;
+        }
+    }
+
+    entry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{Board, Legality};
+    use crate::core::{Piece, Square, POS_4_FEN};
+    use crate::eval_params::DEFAULT_PARAMS;
+    use crate::lookup_tables::LookupTables;
+
+    fn pawns(b: &Board) -> [BitBoard; 2] {
+        [b.piece_bb(Piece::WhitePawn), b.piece_bb(Piece::BlackPawn)]
+    }
+
+    #[test]
+    fn evaluate_pawns_test() {
+        let l = LookupTables::generate();
+        // White's a and b pawns are passed, the doubled e pawns are isolated and only the front
+        // one could be passed but the d pawn stops it. Black's d pawn is isolated.
+        let b = Board::from_fen("4k3/8/8/1P1p4/4P3/P3P3/8/4K3 w - - 0 1", &l).unwrap();
+        let entry = evaluate_pawns(b.pawn_hash(), pawns(&b), &DEFAULT_PARAMS);
+
+        let mut white_passed = BitBoard::new();
+        white_passed.set_bit(Square::A3);
+        white_passed.set_bit(Square::B5);
+        assert_eq!(entry.passed[Player::White as usize], white_passed);
+        assert!(entry.passed[Player::Black as usize].is_empty());
+        assert_eq!(entry.semi_open_files[Player::White as usize], 0b1110_1100);
+        assert_eq!(entry.semi_open_files[Player::Black as usize], 0b1111_0111);
+        // The pawns on a3 and e3 shield a king on the a, b and d to f files.
+        assert_eq!(
+            entry.shield[Player::White as usize],
+            [1, 1, 0, 1, 1, 1, 0, 0]
+        );
+
+        let p = &DEFAULT_PARAMS;
+        assert_eq!(
+            entry.eg,
+            p.passed_pawn_eg[2] + p.passed_pawn_eg[4] + 2 * p.isolated_pawn_eg + p.doubled_pawn_eg
+                - p.isolated_pawn_eg
+        );
+    }
+
+    #[test]
+    fn pawn_table_test() {
+        let l = LookupTables::generate();
+        let mut table = PawnTable::new(1000);
+        assert_eq!(table.entries.len(), 1024);
+
+        let b = Board::from_fen("4k3/8/8/1P1p4/4P3/P3P3/8/4K1N1 w - - 0 1", &l).unwrap();
+        let expected = evaluate_pawns(b.pawn_hash(), pawns(&b), &DEFAULT_PARAMS);
+        assert_eq!(
+            *table.probe(b.pawn_hash(), pawns(&b), &DEFAULT_PARAMS),
+            expected
+        );
+        // The entry is found again after moves that don't touch the pawns rather than being
+        // worked out with the new parameters.
+        let mut other_params = DEFAULT_PARAMS.clone();
+        other_params.isolated_pawn_eg -= 50;
+        let mut b = b;
+        for m in b.generate_moves(Legality::Legal) {
+            if b.get_piece(m.0) == Some(Piece::WhiteKnight) && !b.is_capture(&m) {
+                b.make_move(m);
+                assert_eq!(
+                    *table.probe(b.pawn_hash(), pawns(&b), &other_params),
+                    expected
+                );
+                b.unmake_move();
+            }
+        }
+
+        // A different pawn structure replaces it.
+        let b = Board::from_fen(POS_4_FEN, &l).unwrap();
+        assert_eq!(
+            *table.probe(b.pawn_hash(), pawns(&b), &other_params),
+            evaluate_pawns(b.pawn_hash(), pawns(&b), &other_params)
+        );
+    }
+}
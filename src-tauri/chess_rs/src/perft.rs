@@ -1,4 +1,20 @@
 use crate::board;
+use crate::core::PieceKind;
+
+/// Move counts for a single depth of a detailed perft run. The columns match the ones used on the
+/// Chess Programming Wiki perft results pages.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct PerftStats {
+    pub nodes: u64,
+    pub captures: u64,
+    pub en_passants: u64,
+    pub castles: u64,
+    pub promotions: u64,
+    pub checks: u64,
+    pub discovered_checks: u64,
+    pub double_checks: u64,
+    pub checkmates: u64,
+}
 
 // TODO: version with nice printing like stockfish does
 //
@@ -47,6 +63,66 @@ fn inner_perft(b: &mut board::Board, depth: u8, print_results: bool) -> u64 {
     count
 }
 
+/// Runs perft to `depth` and returns the detailed counts for every depth from 1 to `depth`. The
+/// stats for depth `n` are at index `n - 1`.
+pub fn perft_detailed(b: &board::Board, depth: u8) -> Vec<PerftStats> {
+    let mut stats = vec![PerftStats::default(); depth as usize];
+    if depth > 0 {
+        let mut b2 = b.shallow_clone();
+        inner_perft_detailed(&mut b2, 0, &mut stats);
+    }
+    stats
+}
+
+fn inner_perft_detailed(b: &mut board::Board, ply: usize, stats: &mut [PerftStats]) {
+    let moves = b.generate_moves(board::Legality::Legal);
+
+    for m in moves.iter() {
+        let moved_piece_kind = PieceKind::from(b.get_piece(m.0).unwrap());
+        let is_en_passant = moved_piece_kind == PieceKind::Pawn && Some(m.1) == b.en_passant();
+        let is_castle = b.is_castling(m);
+        let is_capture = is_en_passant || (!is_castle && b.get_piece(m.1).is_some());
+
+        b.apply_move(*m, &moves);
+
+        let s = &mut stats[ply];
+        s.nodes += 1;
+        if is_capture {
+            s.captures += 1;
+        }
+        if is_en_passant {
+            s.en_passants += 1;
+        }
+        if is_castle {
+            s.castles += 1;
+        }
+        if m.2.is_some() {
+            s.promotions += 1;
+        }
+
+        let checkers = b.checkers();
+        if !checkers.is_empty() {
+            s.checks += 1;
+            // Like the wiki, a double check is only counted as a double check and not also as a
+            // discovered check.
+            if checkers.pop_count() > 1 {
+                s.double_checks += 1;
+            } else if checkers.get_lsb() != Some(m.1) {
+                s.discovered_checks += 1;
+            }
+            if b.generate_moves(board::Legality::Legal).is_empty() {
+                s.checkmates += 1;
+            }
+        }
+
+        if ply + 1 < stats.len() {
+            inner_perft_detailed(b, ply + 1, stats);
+        }
+
+        b.undo_move();
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -167,4 +243,181 @@ mod tests {
         let b: Board = board::Board::from_fen(core::POS_6_FEN, &l).unwrap();
         assert_eq!(6_923_051_137, perft(&b, 6));
     }
+
+    #[test]
+    fn chess960_perft() {
+        struct TestCase {
+            fen: &'static str,
+            expected: Vec<u64>,
+        }
+
+        // From the published Chess960 perft suite.
+        let test_cases = vec![
+            TestCase {
+                fen: "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+                expected: vec![21, 528, 12_189, 326_672],
+            },
+            TestCase {
+                fen: "b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9",
+                expected: vec![20, 479, 10_471, 273_318],
+            },
+            TestCase {
+                fen: "qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9",
+                expected: vec![22, 593, 13_440, 382_958],
+            },
+            TestCase {
+                fen: "1nbbnrkr/p1p1ppp1/3p4/1p3P1p/3Pq2P/8/PPP1P1P1/QNBBNRKR w HFhf - 0 9",
+                expected: vec![28, 1120, 31_058, 1_171_749],
+            },
+            TestCase {
+                fen: "qnbnr1kr/ppp1b1pp/4p3/3p1p2/8/2NPP3/PPP1BPPP/QNB1R1KR w HEhe - 1 9",
+                expected: vec![29, 899, 26_578, 824_055],
+            },
+            TestCase {
+                fen: "q1bnrkr1/ppppp2p/2n2p2/4b1p1/2NP4/8/PPP1PPPP/QNB1RRKB w ge - 1 9",
+                expected: vec![30, 860, 24_566, 732_757],
+            },
+            TestCase {
+                fen: "qbn1brkr/ppp1p1p1/2n4p/3p1p2/P7/6PP/QPPPPP2/1BNNBRKR w HFhf - 0 9",
+                expected: vec![25, 635, 17_054, 465_806],
+            },
+            TestCase {
+                fen: "qnnbbrkr/1p2ppp1/2pp3p/p7/1P5P/2NP4/P1P1PPP1/Q1NBBRKR w HFhf - 0 9",
+                expected: vec![24, 572, 15_243, 384_260],
+            },
+            TestCase {
+                fen: "qn1rbbkr/ppp2p1p/1n1pp1p1/8/3P4/P6P/1PP1PPPK/QNNRBB1R w hd - 2 9",
+                expected: vec![28, 811, 23_175, 679_699],
+            },
+        ];
+
+        // Regression values for castling edge cases the suite doesn't cover, counted by this
+        // engine. They catch changes to move generation but not a bug it already had.
+        let regression_cases = vec![
+            // The king and rook swap squares and the rook shields the king's destination.
+            TestCase {
+                fen: "rk5r/8/8/8/8/8/8/RK5R w KQkq - 0 1",
+                expected: vec![24, 479, 11_099, 242_723],
+            },
+            TestCase {
+                fen: "4k3/8/8/8/8/8/8/qRK4R w KQ - 0 1",
+                expected: vec![16, 265, 5087],
+            },
+            TestCase {
+                fen: "1k6/8/8/8/8/8/8/5RKR w K - 0 1",
+                expected: vec![22, 95, 2480],
+            },
+        ];
+
+        let l = LookupTables::generate();
+        for test_case in test_cases.into_iter().chain(regression_cases) {
+            let b = Board::from_fen(test_case.fen, &l).unwrap();
+            for (depth, expected) in test_case.expected.iter().enumerate() {
+                assert_eq!(
+                    *expected,
+                    perft(&b, depth as u8 + 1),
+                    "{} at depth {}",
+                    test_case.fen,
+                    depth + 1
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn chess960_start_pos_perft() {
+        let l = LookupTables::generate();
+
+        // Position 518 is the standard start position, with the published counts. The counts for
+        // 0 and 959 were counted by this engine, and as the positions are mirror images they only
+        // check the two agree.
+        for (id, expected) in [
+            (518, [20, 400, 8902, 197_281]),
+            (0, [20, 400, 9006, 201_143]),
+            (959, [20, 400, 9006, 201_143]),
+        ] {
+            let b = Board::start_pos_960(id, &l).unwrap();
+            for (depth, expected) in expected.iter().enumerate() {
+                assert_eq!(*expected, perft(&b, depth as u8 + 1));
+            }
+        }
+    }
+
+    fn stats(counts: [u64; 9]) -> PerftStats {
+        PerftStats {
+            nodes: counts[0],
+            captures: counts[1],
+            en_passants: counts[2],
+            castles: counts[3],
+            promotions: counts[4],
+            checks: counts[5],
+            discovered_checks: counts[6],
+            double_checks: counts[7],
+            checkmates: counts[8],
+        }
+    }
+
+    #[test]
+    fn start_pos_perft_detailed() {
+        let l = LookupTables::generate();
+
+        let b: Board = board::Board::start_pos(&l);
+        assert_eq!(Vec::<PerftStats>::new(), perft_detailed(&b, 0));
+        assert_eq!(
+            vec![
+                stats([20, 0, 0, 0, 0, 0, 0, 0, 0]),
+                stats([400, 0, 0, 0, 0, 0, 0, 0, 0]),
+                stats([8902, 34, 0, 0, 0, 12, 0, 0, 0]),
+                stats([197_281, 1576, 0, 0, 0, 469, 0, 0, 8]),
+            ],
+            perft_detailed(&b, 4)
+        );
+    }
+
+    #[test]
+    fn pos_2_kiwipete_perft_detailed() {
+        let l = LookupTables::generate();
+
+        let b: Board = board::Board::from_fen(core::POS_2_KIWIPETE_FEN, &l).unwrap();
+        assert_eq!(
+            vec![
+                stats([48, 8, 0, 2, 0, 0, 0, 0, 0]),
+                stats([2039, 351, 1, 91, 0, 3, 0, 0, 0]),
+                stats([97862, 17102, 45, 3162, 0, 993, 0, 0, 1]),
+            ],
+            perft_detailed(&b, 3)
+        );
+    }
+
+    #[test]
+    fn pos_3_perft_detailed() {
+        let l = LookupTables::generate();
+
+        let b: Board = board::Board::from_fen(core::POS_3_FEN, &l).unwrap();
+        assert_eq!(
+            vec![
+                stats([14, 1, 0, 0, 0, 2, 0, 0, 0]),
+                stats([191, 14, 0, 0, 0, 10, 0, 0, 0]),
+                stats([2812, 209, 2, 0, 0, 267, 3, 0, 0]),
+                stats([43_238, 3348, 123, 0, 0, 1680, 106, 0, 17]),
+                stats([674_624, 52_051, 1165, 0, 0, 52_950, 1292, 3, 0]),
+            ],
+            perft_detailed(&b, 5)
+        );
+    }
+
+    #[test]
+    fn pos_4_perft_detailed() {
+        let l = LookupTables::generate();
+
+        let b: Board = board::Board::from_fen(core::POS_4_FEN, &l).unwrap();
+        assert_eq!(
+            vec![
+                stats([6, 0, 0, 0, 0, 0, 0, 0, 0]),
+                stats([264, 87, 0, 6, 48, 10, 0, 0, 0]),
+                stats([9467, 1021, 4, 0, 120, 38, 2, 0, 22]),
+            ],
+            perft_detailed(&b, 3)
+        );
+    }
 }
@@ -0,0 +1,266 @@
+use std::fmt;
+
+use crate::board::Board;
+use crate::core::{Move, Player, STARTING_POS_FEN};
+use crate::lookup_tables::LookupTables;
+
+/// A game read from a PGN file. Only the main line is kept: comments, variations and numeric
+/// annotation glyphs are skipped.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Game {
+    /// The tag pairs in the order they're given.
+    pub tags: Vec<(String, String)>,
+    /// The moves in SAN.
+    pub moves: Vec<String>,
+    /// `1-0`, `0-1`, `1/2-1/2` or `*` for an unfinished game.
+    pub result: String,
+}
+
+impl Game {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The position the game starts from, which is given by the `FEN` tag if there is one.
+    pub fn start_position<'a>(&self, l: &'a LookupTables) -> Result<Board<'a>, String> {
+        let fen = self.tag("FEN").unwrap_or(STARTING_POS_FEN);
+        let mut b = Board::from_fen(fen, l).map_err(|e| format!("invalid fen: {}", e))?;
+        if self
+            .tag("Variant")
+            .is_some_and(|v| v.eq_ignore_ascii_case("chess960"))
+        {
+            b.set_chess960(true);
+        }
+        Ok(b)
+    }
+
+    /// The moves of the game, which are checked to be legal.
+    pub fn parse_moves(&self, l: &LookupTables) -> Result<Vec<Move>, String> {
+        let mut b = self.start_position(l)?;
+        let mut moves = Vec::with_capacity(self.moves.len());
+        for san in &self.moves {
+            let m = b.parse_san(san)?;
+            b.make_move(m);
+            moves.push(m);
+        }
+        Ok(moves)
+    }
+}
+
+/// Reads the games of a PGN file.
+pub fn parse(pgn: &str) -> Result<Vec<Game>, String> {
+    let mut games = Vec::new();
+    let mut game = Game::default();
+    let mut in_movetext = false;
+    let mut chars = pgn.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            '[' if !in_movetext || game.moves.is_empty() => {
+                chars.next();
+                let tag: String = chars.by_ref().take_while(|c| *c != ']').collect();
+                let (name, value) = tag
+                    .trim()
+                    .split_once(char::is_whitespace)
+                    .ok_or_else(|| format!("invalid tag: [{}]", tag))?;
+                let value = value.trim();
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|v| v.strip_suffix('"'))
+                    .ok_or_else(|| format!("invalid tag value: {}", value))?;
+                let value = value.replace("\\\"", "\"").replace("\\\\", "\\");
+                game.tags.push((name.to_string(), value));
+            }
+            '[' => {
+                // A game without a result ends at the tags of the next.
+                games.push(std::mem::take(&mut game));
+                in_movetext = false;
+            }
+            '{' => {
+                chars.by_ref().take_while(|c| *c != '}').for_each(drop);
+            }
+            ';' | '%' => {
+                chars.by_ref().take_while(|c| *c != '\n').for_each(drop);
+            }
+            '(' => {
+                let mut depth = 0;
+                for c in chars.by_ref() {
+                    match c {
+                        '(' => depth += 1,
+                        ')' => depth -= 1,
+                        _ => {}
+                    }
+                    if depth == 0 {
+                        break;
+                    }
+                }
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            _ => {
+                let mut token = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "{}();[".contains(c) {
+                        break;
+                    }
+                    token.push(c);
+                    chars.next();
+                }
+                in_movetext = true;
+
+                match token.as_str() {
+                    "1-0" | "0-1" | "1/2-1/2" | "*" => {
+                        game.result = token;
+                        games.push(std::mem::take(&mut game));
+                        in_movetext = false;
+                    }
+                    t if t.starts_with('$') => {}
+                    t => {
+                        // Move numbers can be written against the move, like `1.e4` or `3...Nf6`.
+                        let san = t.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+                        if !san.is_empty() {
+                            game.moves.push(san.to_string());
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    if in_movetext || !game.tags.is_empty() {
+        games.push(game);
+    }
+    Ok(games)
+}
+
+/// `moves` played from `b` written in SAN with move numbers, wrapped at 80 columns and followed by
+/// the result, as they appear in a PGN file.
+pub fn movetext(b: &Board, moves: &[Move], result: &str) -> String {
+    let sans = b.san_line(moves);
+    format_movetext(&sans, b.turn(), b.full_moves(), result)
+}
+
+fn format_movetext(sans: &[String], turn: Player, full_moves: u32, result: &str) -> String {
+    let mut tokens = Vec::new();
+    let mut turn = turn;
+    let mut full_moves = full_moves;
+    for (i, san) in sans.iter().enumerate() {
+        match turn {
+            Player::White => tokens.push(format!("{}.", full_moves)),
+            Player::Black if i == 0 => tokens.push(format!("{}...", full_moves)),
+            Player::Black => {}
+        }
+        tokens.push(san.clone());
+        if turn == Player::Black {
+            full_moves += 1;
+        }
+        turn = match turn {
+            Player::White => Player::Black,
+            Player::Black => Player::White,
+        };
+    }
+    tokens.push(result.to_string());
+
+    let mut text = String::new();
+    let mut line_len = 0;
+    for token in tokens {
+        if line_len > 0 && line_len + 1 + token.len() > 80 {
+            text.push('\n');
+            line_len = 0;
+        } else if line_len > 0 {
+            text.push(' ');
+            line_len += 1;
+        }
+        line_len += token.len();
+        text.push_str(&token);
+    }
+    text
+}
+
+impl fmt::Display for Game {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, value) in &self.tags {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            writeln!(f, "[{} \"{}\"]", name, value)?;
+        }
+        writeln!(f)?;
+
+        // The move numbers carry on from the position the game starts from.
+        let fen: Vec<&str> = self
+            .tag("FEN")
+            .unwrap_or(STARTING_POS_FEN)
+            .split(' ')
+            .collect();
+        let turn = if fen.get(1) == Some(&"b") {
+            Player::Black
+        } else {
+            Player::White
+        };
+        let full_moves = fen.get(5).and_then(|n| n.parse().ok()).unwrap_or(1);
+        let result = if self.result.is_empty() {
+            "*"
+        } else {
+            &self.result
+        };
+        writeln!(
+            f,
+            "{}",
+            format_movetext(&self.moves, turn, full_moves, result)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_test() {
+        let pgn = r#"[Event "Test"]
+[White "A \"quoted\" name"]
+[Result "1-0"]
+
+1. e4 {best by test} e5 2.Nf3 (2. f4 exf4 (2... d5) 3. Nf3) 2... Nc6 $1 3. Bb5 a6?!
+; a comment to the end of the line
+4. Ba4 1-0
+
+[Event "Second"]
+[FEN "8/8/8/8/8/5k2/8/R3K3 w Q - 0 1"]
+
+1. O-O-O Ke2 *
+"#;
+        let games = parse(pgn).unwrap();
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].tag("White"), Some("A \"quoted\" name"));
+        assert_eq!(
+            games[0].moves,
+            ["e4", "e5", "Nf3", "Nc6", "Bb5", "a6?!", "Ba4"]
+        );
+        assert_eq!(games[0].result, "1-0");
+        assert_eq!(games[1].tag("Event"), Some("Second"));
+        assert_eq!(games[1].result, "*");
+
+        let l = LookupTables::generate();
+        let moves = games[1].parse_moves(&l).unwrap();
+        assert_eq!(moves[0], Move::try_from("e1c1").unwrap());
+        let text = movetext(&games[1].start_position(&l).unwrap(), &moves, "*");
+        assert_eq!(text, "1. O-O-O Ke2 *");
+        assert_eq!(parse(&text).unwrap()[0].moves, games[1].moves);
+
+        // Games are written back the way they were read.
+        let written = games[0].to_string();
+        assert!(written.starts_with("[Event \"Test\"]\n[White \"A \\\"quoted\\\" name\"]\n"));
+        assert!(written.ends_with("\n\n1. e4 e5 2. Nf3 Nc6 3. Bb5 a6?! 4. Ba4 1-0\n"));
+        assert_eq!(parse(&written).unwrap(), games[..1]);
+
+        // Games can leave out the result.
+        let games = parse("[Event \"A\"]\n1. d4\n[Event \"B\"]\n1. c4").unwrap();
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[1].moves, ["c4"]);
+        assert!(parse("[Event]").is_err());
+    }
+}
@@ -0,0 +1,198 @@
+use serde::{Deserialize, Serialize};
+
+use crate::board::{Board, Legality, Outcome};
+use crate::core::{Move, Player, STARTING_POS_FEN};
+use crate::lookup_tables::LookupTables;
+use crate::pgn::Game;
+
+/// A whole game in the shape it's exchanged with other programs as JSON:
+///
+/// ```json
+/// {
+///   "start_fen": "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+///   "chess960": false,
+///   "moves": ["f2f3", "e7e5", "g2g4", "d8h4"],
+///   "san": ["f3", "e5", "g4", "Qh4#"],
+///   "tags": [["White", "A"], ["Black", "B"]],
+///   "outcome": { "Checkmate": "Black" },
+///   "result": "0-1"
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GameRecord {
+    pub start_fen: String,
+    pub chess960: bool,
+    /// The moves in UCI.
+    pub moves: Vec<Move>,
+    /// The same moves in SAN.
+    pub san: Vec<String>,
+    /// PGN tag pairs, in order.
+    pub tags: Vec<(String, String)>,
+    /// How the game ended, if its last position is terminal.
+    pub outcome: Option<Outcome>,
+    /// `1-0`, `0-1`, `1/2-1/2` or `*`, which can be set by the tags when the game was resigned
+    /// or adjudicated rather than finished on the board.
+    pub result: String,
+}
+
+impl GameRecord {
+    /// The game played on `b`, made of the moves it recorded with `apply_move`.
+    pub fn new(b: &Board, tags: Vec<(String, String)>) -> Self {
+        let outcome = b.outcome();
+        let result = match tags.iter().find(|(name, _)| name == "Result") {
+            Some((_, result)) => result.clone(),
+            None => result(outcome).to_string(),
+        };
+        GameRecord {
+            start_fen: b.start_fen(),
+            chess960: b.is_chess960(),
+            moves: b.moves.clone(),
+            san: b.san_moves.clone(),
+            tags,
+            outcome,
+            result,
+        }
+    }
+
+    /// Replays the game, checking the moves are legal and agree with their SAN.
+    pub fn board<'a>(&self, l: &'a LookupTables) -> Result<Board<'a>, String> {
+        if self.san.len() != self.moves.len() {
+            return Err(format!(
+                "{} moves but {} in SAN",
+                self.moves.len(),
+                self.san.len()
+            ));
+        }
+
+        let mut b =
+            Board::from_fen(&self.start_fen, l).map_err(|e| format!("invalid fen: {}", e))?;
+        b.set_chess960(self.chess960);
+        for (m, san) in self.moves.iter().zip(&self.san) {
+            let legal_moves = b.generate_moves(Legality::Legal);
+            if !legal_moves.contains(m) {
+                return Err(format!("illegal move: {}", m));
+            }
+            b.apply_move(*m, &legal_moves);
+            if b.san_moves.last() != Some(san) {
+                return Err(format!(
+                    "{} is {} rather than {}",
+                    m,
+                    b.san_moves.last().unwrap(),
+                    san
+                ));
+            }
+        }
+        Ok(b)
+    }
+
+    pub fn from_pgn(game: &Game, l: &LookupTables) -> Result<Self, String> {
+        let mut b = game.start_position(l)?;
+        for m in game.parse_moves(l)? {
+            let legal_moves = b.generate_moves(Legality::Legal);
+            b.apply_move(m, &legal_moves);
+        }
+
+        let mut record = GameRecord::new(&b, game.tags.clone());
+        if !game.result.is_empty() {
+            record.result = game.result.clone();
+        }
+        Ok(record)
+    }
+
+    pub fn to_pgn(&self) -> Game {
+        let has_tag = |name: &str| self.tags.iter().any(|(n, _)| n == name);
+        let mut tags = self.tags.clone();
+        if !has_tag("FEN") && self.start_fen != STARTING_POS_FEN {
+            tags.push(("SetUp".to_string(), "1".to_string()));
+            tags.push(("FEN".to_string(), self.start_fen.clone()));
+        }
+        if self.chess960 && !has_tag("Variant") {
+            tags.push(("Variant".to_string(), "Chess960".to_string()));
+        }
+        Game {
+            tags,
+            moves: self.san.clone(),
+            result: self.result.clone(),
+        }
+    }
+}
+
+fn result(outcome: Option<Outcome>) -> &'static str {
+    match outcome {
+        Some(Outcome::Checkmate(Player::White)) => "1-0",
+        Some(Outcome::Checkmate(Player::Black)) => "0-1",
+        Some(_) => "1/2-1/2",
+        None => "*",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::BoardSeed;
+    use crate::pgn;
+    use serde::de::DeserializeSeed;
+
+    #[test]
+    fn serde_test() {
+        let l = LookupTables::generate();
+        let mut b = Board::start_pos(&l);
+        for m in ["f2f3", "e7e5", "g2g4", "d8h4"] {
+            let legal_moves = b.generate_moves(Legality::Legal);
+            b.apply_move(Move::try_from(m).unwrap(), &legal_moves);
+        }
+
+        // Moves are UCI strings.
+        let m = Move::try_from("e7e8q").unwrap();
+        assert_eq!(serde_json::to_string(&m).unwrap(), "\"e7e8q\"");
+        assert_eq!(serde_json::from_str::<Move>("\"e7e8q\"").unwrap(), m);
+        assert!(serde_json::from_str::<Move>("\"e7\"").is_err());
+
+        // Boards keep their history.
+        let json = serde_json::to_string(&b).unwrap();
+        assert_eq!(
+            json,
+            "{\"fen\":\"rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3\",\
+            \"start_fen\":\"rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1\",\
+            \"chess960\":false,\"moves\":[\"f2f3\",\"e7e5\",\"g2g4\",\"d8h4\"]}"
+        );
+        let mut de = serde_json::Deserializer::from_str(&json);
+        let read = BoardSeed(&l).deserialize(&mut de).unwrap();
+        assert_eq!(read.fen(), b.fen());
+        assert_eq!(read.san_moves, b.san_moves);
+        let wrong = json.replace("\"fen\":\"rnb1", "\"fen\":\"rnbq");
+        let mut de = serde_json::Deserializer::from_str(&wrong);
+        assert!(BoardSeed(&l).deserialize(&mut de).is_err());
+
+        let record = GameRecord::new(&b, vec![("White".to_string(), "A".to_string())]);
+        assert_eq!(record.outcome, Some(Outcome::Checkmate(Player::Black)));
+        assert_eq!(record.result, "0-1");
+        let json = serde_json::to_string(&record).unwrap();
+        assert!(json.contains(
+            "\"san\":[\"f3\",\"e5\",\"g4\",\"Qh4#\"],\"tags\":[[\"White\",\"A\"]],\
+            \"outcome\":{\"Checkmate\":\"Black\"},\"result\":\"0-1\""
+        ));
+        let read: GameRecord = serde_json::from_str(&json).unwrap();
+        assert_eq!(read, record);
+        assert_eq!(read.board(&l).unwrap().fen(), b.fen());
+
+        let mut wrong_san = record.clone();
+        wrong_san.san[3] = "Qh4+".to_string();
+        assert!(wrong_san.board(&l).is_err());
+    }
+
+    #[test]
+    fn pgn_test() {
+        let l = LookupTables::generate();
+        let games = pgn::parse(
+            "[Event \"Test\"]\n[FEN \"8/8/8/4k3/8/8/8/4K2R w K - 0 1\"]\n\n1. O-O Kd4 1/2-1/2",
+        )
+        .unwrap();
+        let record = GameRecord::from_pgn(&games[0], &l).unwrap();
+        assert_eq!(record.start_fen, "8/8/8/4k3/8/8/8/4K2R w K - 0 1");
+        assert_eq!(record.moves[0], Move::try_from("e1g1").unwrap());
+        assert_eq!(record.outcome, None);
+        assert_eq!(record.result, "1/2-1/2");
+        assert_eq!(record.to_pgn(), games[0]);
+    }
+}
@@ -0,0 +1,1307 @@
+use std::cmp;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::board::{Board, Legality};
+use crate::core::{Move, Piece, Player};
+use crate::dtm::{Dtm, DtmTables};
+use crate::eval;
+use crate::movepick::{ButterflyHistory, CounterMoves, Killers, MovePicker};
+use crate::pawns::PawnTable;
+use crate::strength::StrengthLimit;
+use crate::syzygy::{Tablebases, Wdl, TB_WIN};
+use crate::time::{Clock, TimeManager};
+use crate::tt::{Bound, TranspositionTable, TtEntry};
+
+pub const MAX_PLY: usize = 128;
+pub const MAX_DEPTH: u8 = 100;
+pub const INFINITY: i32 = 32_000;
+pub const MATE: i32 = 31_000;
+/// Scores at least this far from zero are mates.
+pub const MATE_BOUND: i32 = MATE - MAX_PLY as i32;
+pub const MAX_THREADS: usize = 256;
+pub const MAX_MULTI_PV: usize = 256;
+
+// How often the clock is checked.
+const NODES_BETWEEN_TIME_CHECKS: u64 = 1024;
+
+// Margins per ply of remaining depth, in centipawns.
+const RAZORING_MARGIN: i32 = 250;
+const REVERSE_FUTILITY_MARGIN: i32 = 90;
+const FUTILITY_MARGIN: i32 = 150;
+
+/// How the search runs. The switches for the techniques that make the search selective are so
+/// each can be measured on its own and are all on by default.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchOptions {
+    pub threads: usize,
+    /// How many of the best lines to search and report.
+    pub multi_pv: usize,
+    /// Plays weaker than full strength.
+    pub strength: Option<StrengthLimit>,
+    /// Endgame tablebases to look up positions with few pieces in.
+    pub tablebases: Option<Arc<Tablebases>>,
+    /// Distance to mate tables, which give the exact score of the positions they have.
+    pub dtm_tables: Option<Arc<DtmTables>>,
+    pub null_move: bool,
+    pub late_move_reductions: bool,
+    pub reverse_futility_pruning: bool,
+    pub futility_pruning: bool,
+    pub razoring: bool,
+    pub check_extensions: bool,
+    pub mate_distance_pruning: bool,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        SearchOptions {
+            threads: 1,
+            multi_pv: 1,
+            strength: None,
+            tablebases: None,
+            dtm_tables: None,
+            null_move: true,
+            late_move_reductions: true,
+            reverse_futility_pruning: true,
+            futility_pruning: true,
+            razoring: true,
+            check_extensions: true,
+            mate_distance_pruning: true,
+        }
+    }
+}
+
+impl SearchOptions {
+    /// The switches for the selective techniques with the names of their UCI options.
+    pub fn toggles(&mut self) -> [(&'static str, &mut bool); 7] {
+        [
+            ("NullMove", &mut self.null_move),
+            ("LateMoveReductions", &mut self.late_move_reductions),
+            ("ReverseFutilityPruning", &mut self.reverse_futility_pruning),
+            ("FutilityPruning", &mut self.futility_pruning),
+            ("Razoring", &mut self.razoring),
+            ("CheckExtensions", &mut self.check_extensions),
+            ("MateDistancePruning", &mut self.mate_distance_pruning),
+        ]
+    }
+
+    /// A plain alpha-beta search with none of the selectivity.
+    pub fn none() -> Self {
+        SearchOptions {
+            threads: 1,
+            multi_pv: 1,
+            strength: None,
+            tablebases: None,
+            dtm_tables: None,
+            null_move: false,
+            late_move_reductions: false,
+            reverse_futility_pruning: false,
+            futility_pruning: false,
+            razoring: false,
+            check_extensions: false,
+            mate_distance_pruning: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SearchLimits {
+    pub depth: Option<u8>,
+    pub nodes: Option<u64>,
+    pub movetime: Option<Duration>,
+    /// Lets the time manager decide how long to search for.
+    pub clock: Option<Clock>,
+    /// Kept back from the time for each move to cover the delay talking to the GUI.
+    pub move_overhead: Duration,
+    /// Search until stopped, even when there's nothing left to search.
+    pub infinite: bool,
+    /// Set while pondering, when the search ignores the clock and doesn't finish until this is
+    /// cleared by a ponderhit, when the clock starts, or it's stopped.
+    pub ponder: Option<Arc<AtomicBool>>,
+}
+
+impl SearchLimits {
+    pub fn is_pondering(&self) -> bool {
+        self.ponder
+            .as_ref()
+            .is_some_and(|p| p.load(Ordering::Relaxed))
+    }
+
+    /// UCI doesn't allow the best move to be sent during an infinite search or while pondering,
+    /// so the search waits to be stopped if it finishes early.
+    fn wait_until_finished(&self, stop: &AtomicBool) {
+        while (self.infinite || self.is_pondering()) && !stop.load(Ordering::Relaxed) {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn time_manager(&self) -> Option<TimeManager> {
+        match (&self.clock, self.movetime) {
+            (_, Some(movetime)) => Some(TimeManager::fixed(movetime, self.move_overhead)),
+            (Some(clock), None) => Some(TimeManager::new(clock, self.move_overhead)),
+            (None, None) => None,
+        }
+    }
+}
+
+/// A search score from the point of view of the player to move.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+pub enum Score {
+    Centipawns(i32),
+    /// Moves until mate, negative when the player to move is being mated.
+    Mate(i32),
+}
+
+impl From<i32> for Score {
+    fn from(score: i32) -> Self {
+        if score >= MATE_BOUND {
+            Score::Mate((MATE - score + 1) / 2)
+        } else if score <= -MATE_BOUND {
+            Score::Mate(-(MATE + score) / 2)
+        } else {
+            Score::Centipawns(score)
+        }
+    }
+}
+
+/// Formatted as in UCI `info` lines.
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Score::Centipawns(cp) => write!(f, "cp {}", cp),
+            Score::Mate(moves) => write!(f, "mate {}", moves),
+        }
+    }
+}
+
+/// Progress reported for each line after every completed iteration.
+#[derive(Clone, Debug, Serialize)]
+pub struct SearchInfo {
+    pub depth: u8,
+    pub seldepth: u8,
+    /// Which of the best lines this is, starting from 1.
+    pub multipv: usize,
+    pub score: Score,
+    pub nodes: u64,
+    pub nps: u64,
+    pub time: Duration,
+    pub hashfull: u32,
+    pub pv: Vec<Move>,
+    /// The moves of `pv` in SAN.
+    pub san: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SearchResult {
+    /// `None` only when there are no legal moves.
+    pub best_move: Option<Move>,
+    pub score: i32,
+    pub depth: u8,
+    pub nodes: u64,
+    pub pv: Vec<Move>,
+    /// The reply expected to `best_move`, to think about on the opponent's time.
+    pub ponder_move: Option<Move>,
+}
+
+/// Searches `b` by iterative deepening until `limits` are reached or `stop` is set, calling
+/// `on_info` after each iteration.
+///
+/// With more than one thread, helper threads search the same position with their own boards and
+/// share what they find through `tt` (Lazy SMP). Only the main thread reports progress and its
+/// result is the one returned. One thread searches deterministically.
+pub fn search(
+    b: &Board,
+    tt: &TranspositionTable,
+    limits: &SearchLimits,
+    options: &SearchOptions,
+    stop: &AtomicBool,
+    on_info: &mut dyn FnMut(&SearchInfo),
+) -> SearchResult {
+    tt.new_search();
+
+    let strength_limits;
+    let strength_options;
+    let (limits, options) = match &options.strength {
+        Some(strength) => {
+            strength_limits = strength.limit(limits);
+            strength_options = strength.limit_options(options);
+            (&strength_limits, &strength_options)
+        }
+        None => (limits, options),
+    };
+
+    let legal_moves = b.generate_moves(Legality::Legal);
+    if legal_moves.is_empty() {
+        limits.wait_until_finished(stop);
+        return SearchResult {
+            score: if b.is_in_check() { -MATE } else { 0 },
+            ..SearchResult::default()
+        };
+    }
+
+    // Only the moves that keep the tablebase result are searched.
+    let root_moves = options
+        .tablebases
+        .as_ref()
+        .and_then(|tablebases| tablebases.root_moves(&mut b.shallow_clone()))
+        .unwrap_or(legal_moves);
+
+    // Set when the main thread finishes so the helpers stop too.
+    let main_done = AtomicBool::new(false);
+    let helper_nodes = AtomicU64::new(0);
+    let helper_limits = SearchLimits {
+        depth: limits.depth,
+        ..SearchLimits::default()
+    };
+
+    let mut result = thread::scope(|scope| {
+        for thread_id in 1..options.threads.max(1) {
+            let shared = SharedState {
+                tt,
+                stop,
+                main_done: &main_done,
+                helper_nodes: &helper_nodes,
+                root_moves: &root_moves,
+            };
+            let helper_limits = &helper_limits;
+            scope.spawn(move || {
+                let mut searcher = Searcher::new(b, shared, helper_limits, options, thread_id);
+                searcher.iterative_deepening(&mut |_| {});
+                // Whole batches of nodes were added while searching.
+                shared.helper_nodes.fetch_add(
+                    searcher.nodes % NODES_BETWEEN_TIME_CHECKS,
+                    Ordering::Relaxed,
+                );
+            });
+        }
+
+        let shared = SharedState {
+            tt,
+            stop,
+            main_done: &main_done,
+            helper_nodes: &helper_nodes,
+            root_moves: &root_moves,
+        };
+        let mut searcher = Searcher::new(b, shared, limits, options, 0);
+        let mut result = searcher.iterative_deepening(on_info);
+        main_done.store(true, Ordering::Relaxed);
+
+        let chosen_line = options
+            .strength
+            .as_ref()
+            .and_then(|strength| strength.choose_line(&searcher.last_lines));
+        if let Some((score, pv)) = chosen_line {
+            result.best_move = pv.first().copied();
+            result.score = *score;
+            result.pv = pv.clone();
+        }
+
+        if result.best_move.is_none() {
+            result.best_move = root_moves.first().copied();
+        }
+        result.ponder_move = result
+            .pv
+            .get(1)
+            .copied()
+            .or_else(|| result.best_move.and_then(|m| ponder_move_from_tt(b, tt, m)));
+
+        limits.wait_until_finished(stop);
+        result
+    });
+
+    // The helpers have all been joined so their count is final.
+    result.nodes += helper_nodes.load(Ordering::Relaxed);
+    result
+}
+
+/// The best move in the TT for the position after `m`, for when the PV was cut short.
+fn ponder_move_from_tt(b: &Board, tt: &TranspositionTable, m: Move) -> Option<Move> {
+    let mut b = b.shallow_clone();
+    b.make_move(m);
+    let ponder_move = tt.probe(b.hash(), 0)?.best_move?;
+    b.generate_moves(Legality::Legal)
+        .contains(&ponder_move)
+        .then_some(ponder_move)
+}
+
+/// What the threads of a search share.
+#[derive(Copy, Clone)]
+struct SharedState<'b> {
+    tt: &'b TranspositionTable,
+    stop: &'b AtomicBool,
+    main_done: &'b AtomicBool,
+    /// The nodes searched by the helper threads, which they add in batches.
+    helper_nodes: &'b AtomicU64,
+    /// The moves searched at the root.
+    root_moves: &'b [Move],
+}
+
+struct Searcher<'a, 'b> {
+    board: Board<'a>,
+    shared: SharedState<'b>,
+    limits: &'b SearchLimits,
+    options: &'b SearchOptions,
+    /// 0 for the main thread.
+    thread_id: usize,
+    /// Only the main thread manages the time. The helpers stop when it does.
+    time_manager: Option<TimeManager>,
+    start: Instant,
+    /// When the clock started, which is after the start when pondering.
+    clock_start: Option<Instant>,
+    nodes: u64,
+    seldepth: usize,
+    stopped: bool,
+    history: ButterflyHistory,
+    killers: Killers,
+    countermoves: CounterMoves,
+    pawn_table: PawnTable,
+    /// The moves leading from the root to the current node and the pieces that made them, with
+    /// `None` for null moves.
+    move_stack: Vec<Option<(Piece, Move)>>,
+    /// Root moves already in a better line of a MultiPV search.
+    excluded_root_moves: Vec<Move>,
+    /// The scores and PVs of the lines of the last completed iteration.
+    last_lines: Vec<(i32, Vec<Move>)>,
+}
+
+impl<'a, 'b> Searcher<'a, 'b> {
+    fn new(
+        b: &Board<'a>,
+        shared: SharedState<'b>,
+        limits: &'b SearchLimits,
+        options: &'b SearchOptions,
+        thread_id: usize,
+    ) -> Self {
+        Searcher {
+            board: b.clone(),
+            shared,
+            limits,
+            options,
+            thread_id,
+            time_manager: if thread_id == 0 {
+                limits.time_manager()
+            } else {
+                None
+            },
+            start: Instant::now(),
+            clock_start: (!limits.is_pondering()).then(Instant::now),
+            nodes: 0,
+            seldepth: 0,
+            stopped: false,
+            history: ButterflyHistory::default(),
+            killers: Killers::default(),
+            countermoves: CounterMoves::default(),
+            pawn_table: PawnTable::default(),
+            move_stack: Vec::new(),
+            excluded_root_moves: Vec::new(),
+            last_lines: Vec::new(),
+        }
+    }
+
+    fn iterative_deepening(&mut self, on_info: &mut dyn FnMut(&SearchInfo)) -> SearchResult {
+        let mut result = SearchResult::default();
+        let root_move_count = self.shared.root_moves.len();
+        let single_legal_move = root_move_count == 1;
+
+        // Helpers start at different depths so they don't all search the same tree in step.
+        let first_depth = 1 + (self.thread_id % 2) as u8;
+        let max_depth = self.limits.depth.unwrap_or(MAX_DEPTH).min(MAX_DEPTH);
+        // Each line is searched with the moves of the better lines left out. Helpers only help
+        // with the best line.
+        let multi_pv = match self.thread_id {
+            0 => self.options.multi_pv.clamp(1, root_move_count.max(1)),
+            _ => 1,
+        };
+
+        for depth in first_depth..=max_depth {
+            let mut lines = Vec::new();
+            self.excluded_root_moves.clear();
+            for _ in 0..multi_pv {
+                let mut pv = Vec::new();
+                let score = self.negamax(depth as i32, -INFINITY, INFINITY, 0, &mut pv);
+                if self.stopped || pv.is_empty() {
+                    break;
+                }
+                self.excluded_root_moves.push(pv[0]);
+                lines.push((score, pv));
+            }
+            // The result of an interrupted iteration can't be trusted.
+            if self.stopped {
+                break;
+            }
+
+            // A later line can score higher than an earlier one when the TT has moved on.
+            lines.sort_by_key(|(score, _)| -score);
+            let (score, pv) = lines[0].clone();
+            result = SearchResult {
+                best_move: pv.first().copied().or(result.best_move),
+                score,
+                depth,
+                nodes: self.nodes,
+                pv,
+                ponder_move: None,
+            };
+
+            let time = self.start.elapsed();
+            let nodes = self.nodes + self.shared.helper_nodes.load(Ordering::Relaxed);
+            for (i, (score, pv)) in lines.iter().cloned().enumerate() {
+                on_info(&SearchInfo {
+                    depth,
+                    seldepth: self.seldepth as u8,
+                    multipv: i + 1,
+                    score: Score::from(score),
+                    nodes,
+                    nps: nodes * 1000 / (time.as_millis() as u64).max(1),
+                    time,
+                    hashfull: self.shared.tt.hashfull(),
+                    san: self.board.san_line(&pv),
+                    pv,
+                });
+            }
+            self.last_lines = lines;
+
+            // Searching deeper won't find a faster mate.
+            if score.abs() >= MATE_BOUND && MATE - score.abs() <= depth as i32 {
+                break;
+            }
+
+            if let (Some(elapsed), Some(time_manager)) =
+                (self.clock_elapsed(), self.time_manager.as_mut())
+            {
+                // There's nothing to think about when there's only one move.
+                if single_legal_move
+                    || !time_manager.should_continue(elapsed, result.best_move, score)
+                {
+                    break;
+                }
+            }
+        }
+
+        result.nodes = self.nodes;
+        result
+    }
+}
+
+impl<'a, 'b> Searcher<'a, 'b> {
+    fn negamax(
+        &mut self,
+        mut depth: i32,
+        mut alpha: i32,
+        mut beta: i32,
+        ply: usize,
+        pv: &mut Vec<Move>,
+    ) -> i32 {
+        pv.clear();
+        let in_check = self.board.is_in_check();
+        if in_check && self.options.check_extensions {
+            depth += 1;
+        }
+        if depth <= 0 {
+            return self.quiescence(alpha, beta, ply);
+        }
+
+        self.nodes += 1;
+        self.seldepth = cmp::max(self.seldepth, ply);
+        if self.should_stop() {
+            return 0;
+        }
+
+        let pv_node = beta - alpha > 1;
+        if ply > 0 {
+            if self.board.half_moves() >= 100 || self.board.repetitions() > 0 {
+                return 0;
+            }
+            if ply >= MAX_PLY - 1 {
+                return self.evaluate();
+            }
+
+            // No mate found from here can be faster than one already found nearer the root.
+            if self.options.mate_distance_pruning {
+                alpha = cmp::max(alpha, -MATE + ply as i32);
+                beta = cmp::min(beta, MATE - ply as i32 - 1);
+                if alpha >= beta {
+                    return alpha;
+                }
+            }
+        }
+
+        let hash = self.board.hash();
+        let tt_entry = self.shared.tt.probe(hash, ply);
+        if let Some(entry) = tt_entry {
+            let usable = match entry.bound {
+                Bound::Exact => true,
+                Bound::Lower => entry.score >= beta,
+                Bound::Upper => entry.score <= alpha,
+            };
+            // Cutoffs in PV nodes would cut the PV short.
+            if !pv_node && entry.depth as i32 >= depth && usable {
+                return entry.score;
+            }
+        }
+
+        let static_eval = match (in_check, tt_entry) {
+            (true, _) => -INFINITY,
+            (false, Some(entry)) => entry.eval,
+            (false, None) => self.evaluate(),
+        };
+
+        // Positions are looked up in the tablebases after captures and pawn moves, which are the
+        // moves that lead into them.
+        let dtm_tables = self.options.dtm_tables.as_deref();
+        if let Some(dtm_tables) = dtm_tables.filter(|_| ply > 0 && self.board.half_moves() == 0) {
+            if let Some(dtm) = dtm_tables.probe(&self.board) {
+                let score = match dtm {
+                    Dtm::Win(plies) => MATE - ply as i32 - plies as i32,
+                    Dtm::Loss(plies) => -MATE + ply as i32 + plies as i32,
+                    Dtm::Draw => 0,
+                };
+                self.shared.tt.store(
+                    hash,
+                    ply,
+                    TtEntry {
+                        best_move: None,
+                        score,
+                        eval: static_eval,
+                        depth: MAX_DEPTH,
+                        bound: Bound::Exact,
+                    },
+                );
+                return score;
+            }
+        }
+
+        let tablebases = self.options.tablebases.as_deref();
+        if let Some(tablebases) = tablebases
+            .filter(|tb| ply > 0 && self.board.half_moves() == 0 && tb.can_probe(&self.board))
+        {
+            if let Some(wdl) = tablebases.probe_wdl(&mut self.board) {
+                // Cursed wins and blessed losses are draws but are scored just off a draw in case
+                // the opponent goes wrong.
+                let (score, bound) = match wdl {
+                    Wdl::Win => (TB_WIN - ply as i32, Bound::Lower),
+                    Wdl::Loss => (-TB_WIN + ply as i32, Bound::Upper),
+                    wdl => (2 * wdl as i32, Bound::Exact),
+                };
+                let cutoff = match bound {
+                    Bound::Exact => true,
+                    Bound::Lower => score >= beta,
+                    Bound::Upper => score <= alpha,
+                };
+                if cutoff {
+                    self.shared.tt.store(
+                        hash,
+                        ply,
+                        TtEntry {
+                            best_move: None,
+                            score,
+                            eval: static_eval,
+                            depth: (depth + 6).min(MAX_DEPTH as i32) as u8,
+                            bound,
+                        },
+                    );
+                    return score;
+                }
+            }
+        }
+
+        if !pv_node && !in_check {
+            // The position is so bad that only a capture could save it, so check the captures.
+            if self.options.razoring && depth <= 2 && static_eval + RAZORING_MARGIN * depth < alpha
+            {
+                let score = self.quiescence(alpha - 1, alpha, ply);
+                if score < alpha {
+                    return score;
+                }
+            }
+
+            // The position is so good that it's unlikely any move will make it bad enough to
+            // fall below beta.
+            if self.options.reverse_futility_pruning
+                && depth <= 7
+                && beta.abs() < MATE_BOUND
+                && static_eval - REVERSE_FUTILITY_MARGIN * depth >= beta
+            {
+                return static_eval;
+            }
+
+            // If passing still fails high then making a move almost certainly would. Passing can
+            // be better than any move in zugzwang, which is common when only pawns are left.
+            if self.options.null_move
+                && depth >= 3
+                && static_eval >= beta
+                && self.move_stack.last().is_some_and(|m| m.is_some())
+                && self.has_non_pawn_material()
+            {
+                let reduction = 3 + depth / 4;
+                self.move_stack.push(None);
+                self.board.make_null_move();
+                let score = -self.negamax(
+                    depth - 1 - reduction,
+                    -beta,
+                    -beta + 1,
+                    ply + 1,
+                    &mut Vec::new(),
+                );
+                self.board.unmake_move();
+                self.move_stack.pop();
+
+                if self.stopped {
+                    return 0;
+                }
+                if score >= beta {
+                    // Mates found after passing aren't proven.
+                    return if score >= MATE_BOUND { beta } else { score };
+                }
+            }
+        }
+
+        // Quiet moves are unlikely to raise a position this far below alpha.
+        let futile = self.options.futility_pruning
+            && !pv_node
+            && !in_check
+            && depth <= 3
+            && alpha.abs() < MATE_BOUND
+            && static_eval + FUTILITY_MARGIN * depth <= alpha;
+
+        let previous_move = self.move_stack.last().copied().flatten();
+        let mut picker = MovePicker::new(
+            &self.board,
+            tt_entry.and_then(|e| e.best_move),
+            self.killers.get(ply),
+            self.countermoves.get(previous_move),
+        );
+
+        let original_alpha = alpha;
+        let mut best_score = -INFINITY;
+        let mut best_move = None;
+        let mut child_pv = Vec::new();
+        let mut quiets_searched = Vec::new();
+        let mut moves_searched = 0;
+        while let Some(m) = picker.next(&self.board, &self.history) {
+            if ply == 0
+                && (self.excluded_root_moves.contains(&m) || !self.shared.root_moves.contains(&m))
+            {
+                continue;
+            }
+            let is_quiet = !self.board.is_capture(&m) && m.2.is_none();
+            let history = self.history.get(self.board.turn(), &m);
+            self.make_move(m);
+            let gives_check = self.board.is_in_check();
+
+            if futile && is_quiet && !gives_check && moves_searched > 0 {
+                self.unmake_move();
+                continue;
+            }
+
+            // The first move is searched with the full window. The rest are expected to be worse
+            // so they're searched with a null window, and at reduced depth if they're quiet moves
+            // late in the ordering, then searched again if they turn out to be better.
+            let mut score;
+            if moves_searched == 0 {
+                score = -self.negamax(depth - 1, -beta, -alpha, ply + 1, &mut child_pv);
+            } else {
+                let mut reduction = 0;
+                if self.options.late_move_reductions
+                    && depth >= 3
+                    && moves_searched >= 3
+                    && is_quiet
+                    && !in_check
+                    && !gives_check
+                {
+                    reduction = late_move_reduction(depth, moves_searched) - history / 8192;
+                    if pv_node {
+                        reduction -= 1;
+                    }
+                    reduction = reduction.clamp(0, depth - 2);
+                }
+
+                score = -self.negamax(
+                    depth - 1 - reduction,
+                    -alpha - 1,
+                    -alpha,
+                    ply + 1,
+                    &mut child_pv,
+                );
+                if score > alpha && reduction > 0 {
+                    score = -self.negamax(depth - 1, -alpha - 1, -alpha, ply + 1, &mut child_pv);
+                }
+                if score > alpha && score < beta {
+                    score = -self.negamax(depth - 1, -beta, -alpha, ply + 1, &mut child_pv);
+                }
+            }
+            self.unmake_move();
+            moves_searched += 1;
+
+            if self.stopped {
+                return 0;
+            }
+
+            if score > best_score {
+                best_score = score;
+                best_move = Some(m);
+
+                if score > alpha {
+                    alpha = score;
+                    pv.clear();
+                    pv.push(m);
+                    pv.extend_from_slice(&child_pv);
+                }
+
+                if score >= beta {
+                    if is_quiet {
+                        self.update_quiet_stats(m, &quiets_searched, depth, ply);
+                    }
+                    break;
+                }
+            }
+
+            if is_quiet {
+                quiets_searched.push(m);
+            }
+        }
+
+        if moves_searched == 0 {
+            return if in_check { -MATE + ply as i32 } else { 0 };
+        }
+
+        let bound = if best_score >= beta {
+            Bound::Lower
+        } else if best_score > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        self.shared.tt.store(
+            hash,
+            ply,
+            TtEntry {
+                best_move,
+                score: best_score,
+                eval: static_eval,
+                depth: depth as u8,
+                bound,
+            },
+        );
+
+        best_score
+    }
+
+    /// Searches captures and promotions until the position is quiet so the evaluation isn't
+    /// taken in the middle of an exchange.
+    fn quiescence(&mut self, mut alpha: i32, beta: i32, ply: usize) -> i32 {
+        self.nodes += 1;
+        self.seldepth = cmp::max(self.seldepth, ply);
+        if self.should_stop() {
+            return 0;
+        }
+
+        if ply >= MAX_PLY - 1 {
+            return self.evaluate();
+        }
+
+        let in_check = self.board.is_in_check();
+        let mut best_score = -INFINITY;
+        if !in_check {
+            best_score = self.evaluate();
+            if best_score >= beta {
+                return best_score;
+            }
+            alpha = cmp::max(alpha, best_score);
+        }
+
+        let mut picker = MovePicker::new_quiescence(&self.board, None);
+        let mut searched_any = false;
+        while let Some(m) = picker.next(&self.board, &self.history) {
+            searched_any = true;
+            self.make_move(m);
+            let score = -self.quiescence(-beta, -alpha, ply + 1);
+            self.unmake_move();
+
+            if self.stopped {
+                return 0;
+            }
+
+            if score > best_score {
+                best_score = score;
+                if score > alpha {
+                    alpha = score;
+                }
+                if score >= beta {
+                    break;
+                }
+            }
+        }
+
+        // All evasions are searched when in check so a mate is found when there are none.
+        if in_check && !searched_any {
+            return -MATE + ply as i32;
+        }
+
+        best_score
+    }
+
+    fn make_move(&mut self, m: Move) {
+        self.move_stack
+            .push(Some((self.board.get_piece(m.0).unwrap(), m)));
+        self.board.make_move(m);
+    }
+
+    fn unmake_move(&mut self) {
+        self.move_stack.pop();
+        self.board.unmake_move();
+    }
+
+    /// Rewards a quiet move that caused a beta cutoff and penalises the quiet moves searched
+    /// before it, which should have been ordered after it.
+    fn update_quiet_stats(&mut self, m: Move, quiets_searched: &[Move], depth: i32, ply: usize) {
+        let bonus = depth * depth;
+        let player = self.board.turn();
+        self.history.update(player, &m, bonus);
+        for q in quiets_searched {
+            self.history.update(player, q, -bonus);
+        }
+        self.killers.update(ply, m);
+        self.countermoves
+            .update(self.move_stack.last().copied().flatten(), m);
+    }
+
+    fn has_non_pawn_material(&self) -> bool {
+        let pieces = match self.board.turn() {
+            Player::White => [
+                Piece::WhiteRook,
+                Piece::WhiteKnight,
+                Piece::WhiteBishop,
+                Piece::WhiteQueen,
+            ],
+            Player::Black => [
+                Piece::BlackRook,
+                Piece::BlackKnight,
+                Piece::BlackBishop,
+                Piece::BlackQueen,
+            ],
+        };
+        pieces.iter().any(|p| !self.board.piece_bb(*p).is_empty())
+    }
+
+    fn evaluate(&mut self) -> i32 {
+        let noise = self
+            .options
+            .strength
+            .as_ref()
+            .map_or(0, |strength| strength.noise_for(self.board.hash()));
+        eval::evaluate_cached(&self.board, &mut self.pawn_table) + noise
+    }
+
+    /// How long the search has been on the clock, which is `None` while pondering.
+    fn clock_elapsed(&mut self) -> Option<Duration> {
+        if self.limits.is_pondering() {
+            return None;
+        }
+        Some(self.clock_start.get_or_insert_with(Instant::now).elapsed())
+    }
+
+    fn is_out_of_time(&mut self) -> bool {
+        let Some(hard_limit) = self.time_manager.as_ref().map(|tm| tm.hard_limit()) else {
+            return false;
+        };
+        self.clock_elapsed()
+            .is_some_and(|elapsed| elapsed >= hard_limit)
+    }
+
+    fn should_stop(&mut self) -> bool {
+        if self.stopped {
+            return true;
+        }
+
+        let check_now = self.nodes.is_multiple_of(NODES_BETWEEN_TIME_CHECKS);
+        if check_now && self.thread_id > 0 {
+            self.shared
+                .helper_nodes
+                .fetch_add(NODES_BETWEEN_TIME_CHECKS, Ordering::Relaxed);
+        }
+
+        if self.shared.stop.load(Ordering::Relaxed)
+            || self.shared.main_done.load(Ordering::Relaxed)
+            // The node limit waits for the first iteration so there's always a searched move.
+            || (self.limits.nodes.is_some_and(|n| self.nodes >= n) && !self.last_lines.is_empty())
+            || (check_now && self.is_out_of_time())
+        {
+            self.stopped = true;
+        }
+
+        self.stopped
+    }
+}
+
+/// How many plies to reduce the search of a quiet move by, which grows with the depth and how
+/// late the move was ordered.
+fn late_move_reduction(depth: i32, moves_searched: i32) -> i32 {
+    (0.75 + (depth as f64).ln() * (moves_searched as f64).ln() / 2.25) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{Square, POS_2_KIWIPETE_FEN};
+    use crate::lookup_tables::LookupTables;
+
+    fn search_fen(fen: &str, depth: u8) -> SearchResult {
+        search_fen_with_options(fen, depth, &SearchOptions::default())
+    }
+
+    fn search_fen_with_options(fen: &str, depth: u8, options: &SearchOptions) -> SearchResult {
+        let l = LookupTables::generate();
+        let b = Board::from_fen(fen, &l).unwrap();
+        let tt = TranspositionTable::new(1);
+        let limits = SearchLimits {
+            depth: Some(depth),
+            ..SearchLimits::default()
+        };
+
+        search(
+            &b,
+            &tt,
+            &limits,
+            options,
+            &AtomicBool::new(false),
+            &mut |_| {},
+        )
+    }
+
+    #[test]
+    fn search_test() {
+        struct TestCase {
+            name: &'static str,
+            fen: &'static str,
+            depth: u8,
+            expected_move: Option<Move>,
+            expected_score: Option<i32>,
+        }
+
+        let test_cases = vec![
+            TestCase {
+                name: "mate in one",
+                fen: "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1",
+                depth: 2,
+                expected_move: Some(Move(Square::A1, Square::A8, None)),
+                expected_score: Some(MATE - 1),
+            },
+            TestCase {
+                name: "mate in two",
+                fen: "k7/8/2K5/8/8/8/8/7R w - - 0 1",
+                depth: 4,
+                expected_move: None,
+                expected_score: Some(MATE - 3),
+            },
+            TestCase {
+                name: "win a hanging queen",
+                fen: "4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1",
+                depth: 3,
+                expected_move: Some(Move(Square::D2, Square::D5, None)),
+                expected_score: None,
+            },
+        ];
+
+        for test_case in test_cases {
+            let result = search_fen(test_case.fen, test_case.depth);
+            if let Some(m) = test_case.expected_move {
+                assert_eq!(result.best_move, Some(m), "{} failed", test_case.name);
+            }
+            if let Some(score) = test_case.expected_score {
+                assert_eq!(result.score, score, "{} failed", test_case.name);
+            }
+            assert_eq!(result.pv.first(), result.best_move.as_ref());
+        }
+    }
+
+    #[test]
+    fn search_terminal_position_test() {
+        let result = search_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", 3);
+        assert_eq!(result.best_move, None);
+        assert_eq!(result.score, 0);
+
+        let result = search_fen("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1", 3);
+        assert_eq!(result.best_move, None);
+        assert_eq!(result.score, -MATE);
+    }
+
+    #[test]
+    fn search_options_test() {
+        let all_but = |f: fn(&mut SearchOptions)| {
+            let mut options = SearchOptions::default();
+            f(&mut options);
+            options
+        };
+        let option_sets = [
+            SearchOptions::default(),
+            SearchOptions::none(),
+            all_but(|o| o.null_move = false),
+            all_but(|o| o.late_move_reductions = false),
+            all_but(|o| o.reverse_futility_pruning = false),
+            all_but(|o| o.futility_pruning = false),
+            all_but(|o| o.razoring = false),
+            all_but(|o| o.check_extensions = false),
+            all_but(|o| o.mate_distance_pruning = false),
+        ];
+
+        for options in &option_sets {
+            let result = search_fen_with_options("k7/8/2K5/8/8/8/8/7R w - - 0 1", 5, options);
+            assert_eq!(result.score, MATE - 3, "{:?}", options);
+
+            let result = search_fen_with_options("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1", 4, options);
+            assert_eq!(
+                result.best_move,
+                Some(Move(Square::D2, Square::D5, None)),
+                "{:?}",
+                options
+            );
+        }
+
+        // The selectivity should pay for itself at a modest depth.
+        let selective = search_fen_with_options(POS_2_KIWIPETE_FEN, 5, &SearchOptions::default());
+        let full_width = search_fen_with_options(POS_2_KIWIPETE_FEN, 5, &SearchOptions::none());
+        assert!(selective.nodes < full_width.nodes);
+    }
+
+    #[test]
+    fn search_threads_test() {
+        // One thread searches the same tree every time.
+        let first = search_fen(POS_2_KIWIPETE_FEN, 6);
+        let second = search_fen(POS_2_KIWIPETE_FEN, 6);
+        assert_eq!(first, second);
+
+        let options = SearchOptions {
+            threads: 4,
+            ..SearchOptions::default()
+        };
+        let result = search_fen_with_options("k7/8/2K5/8/8/8/8/7R w - - 0 1", 6, &options);
+        assert_eq!(result.score, MATE - 3);
+
+        let result = search_fen_with_options(POS_2_KIWIPETE_FEN, 6, &options);
+        assert_eq!(result.pv.first(), result.best_move.as_ref());
+        assert!(result.nodes > 0);
+    }
+
+    #[test]
+    fn search_stop_test() {
+        let l = LookupTables::generate();
+        let b = Board::from_fen(POS_2_KIWIPETE_FEN, &l).unwrap();
+        let tt = TranspositionTable::new(1);
+        let options = SearchOptions {
+            threads: 4,
+            ..SearchOptions::default()
+        };
+
+        // Stopping before the search starts still returns a legal move once every thread has
+        // finished.
+        let stop = AtomicBool::new(true);
+        let result = search(
+            &b,
+            &tt,
+            &SearchLimits::default(),
+            &options,
+            &stop,
+            &mut |_| {},
+        );
+        let best_move = result.best_move.unwrap();
+        assert!(b.generate_moves(Legality::Legal).contains(&best_move));
+    }
+
+    #[test]
+    fn search_time_test() {
+        let l = LookupTables::generate();
+        let tt = TranspositionTable::new(1);
+        let limits = SearchLimits {
+            clock: Some(Clock {
+                time: Duration::from_secs(1000),
+                ..Clock::default()
+            }),
+            ..SearchLimits::default()
+        };
+
+        // With only one legal move the search stops after the first iteration.
+        let b = Board::from_fen("k7/8/8/8/8/8/1R6/7K b - - 0 1", &l).unwrap();
+        let options = SearchOptions::default();
+        let result = search(
+            &b,
+            &tt,
+            &limits,
+            &options,
+            &AtomicBool::new(false),
+            &mut |_| {},
+        );
+        assert_eq!(result.best_move, Some(Move(Square::A8, Square::A7, None)));
+        assert_eq!(result.depth, 1);
+
+        // The hard limit interrupts a search that would go on much longer.
+        let limits = SearchLimits {
+            movetime: Some(Duration::from_millis(200)),
+            move_overhead: Duration::from_millis(100),
+            ..SearchLimits::default()
+        };
+        let b = Board::from_fen(POS_2_KIWIPETE_FEN, &l).unwrap();
+        let start = Instant::now();
+        search(
+            &b,
+            &tt,
+            &limits,
+            &options,
+            &AtomicBool::new(false),
+            &mut |_| {},
+        );
+        assert!(start.elapsed() < Duration::from_millis(190));
+    }
+
+    #[test]
+    fn multi_pv_test() {
+        let l = LookupTables::generate();
+        let tt = TranspositionTable::new(1);
+        let limits = SearchLimits {
+            depth: Some(4),
+            ..SearchLimits::default()
+        };
+        let options = SearchOptions {
+            multi_pv: 3,
+            ..SearchOptions::default()
+        };
+
+        let b = Board::from_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1", &l).unwrap();
+        let mut infos = Vec::new();
+        let result = search(
+            &b,
+            &tt,
+            &limits,
+            &options,
+            &AtomicBool::new(false),
+            &mut |info| infos.push(info.clone()),
+        );
+
+        let last_iteration: Vec<&SearchInfo> = infos.iter().filter(|i| i.depth == 4).collect();
+        assert_eq!(
+            last_iteration.iter().map(|i| i.multipv).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(last_iteration[0].pv.first(), result.best_move.as_ref());
+        assert_eq!(last_iteration[0].san[0], "Rxd5");
+        assert_eq!(last_iteration[0].san.len(), last_iteration[0].pv.len());
+        for w in last_iteration.windows(2) {
+            assert_ne!(w[0].pv[0], w[1].pv[0]);
+        }
+
+        // There can't be more lines than legal moves.
+        let b = Board::from_fen("k7/8/8/8/8/8/1R6/7K b - - 0 1", &l).unwrap();
+        let mut lines = 0;
+        search(
+            &b,
+            &tt,
+            &limits,
+            &options,
+            &AtomicBool::new(false),
+            &mut |info| lines = lines.max(info.multipv),
+        );
+        assert_eq!(lines, 1);
+    }
+
+    #[test]
+    fn score_test() {
+        assert_eq!(Score::from(35), Score::Centipawns(35));
+        assert_eq!(Score::from(MATE - 1), Score::Mate(1));
+        assert_eq!(Score::from(MATE - 3), Score::Mate(2));
+        assert_eq!(Score::from(-MATE + 2), Score::Mate(-1));
+        assert_eq!(Score::from(-MATE + 2).to_string(), "mate -1");
+        assert_eq!(Score::from(-20).to_string(), "cp -20");
+    }
+
+    #[test]
+    fn search_infinite_and_ponder_test() {
+        let l = LookupTables::generate();
+        let b = Board::from_fen(POS_2_KIWIPETE_FEN, &l).unwrap();
+        let tt = TranspositionTable::new(1);
+        let options = SearchOptions::default();
+        let stop = AtomicBool::new(false);
+
+        // An infinite search waits to be stopped even when it has nothing left to search.
+        let limits = SearchLimits {
+            depth: Some(2),
+            infinite: true,
+            ..SearchLimits::default()
+        };
+        let start = Instant::now();
+        let result = thread::scope(|scope| {
+            let search = scope.spawn(|| search(&b, &tt, &limits, &options, &stop, &mut |_| {}));
+            thread::sleep(Duration::from_millis(100));
+            stop.store(true, Ordering::Relaxed);
+            search.join().unwrap()
+        });
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert_eq!(result.depth, 2);
+        assert!(result.ponder_move.is_some());
+
+        // Pondering ignores the clock until the ponderhit.
+        let pondering = Arc::new(AtomicBool::new(true));
+        let limits = SearchLimits {
+            movetime: Some(Duration::from_millis(50)),
+            ponder: Some(Arc::clone(&pondering)),
+            ..SearchLimits::default()
+        };
+        let stop = AtomicBool::new(false);
+        let start = Instant::now();
+        let (result, ponderhit) = thread::scope(|scope| {
+            let search = scope.spawn(|| search(&b, &tt, &limits, &options, &stop, &mut |_| {}));
+            thread::sleep(Duration::from_millis(150));
+            assert!(!search.is_finished());
+            pondering.store(false, Ordering::Relaxed);
+            (search.join().unwrap(), start.elapsed())
+        });
+        assert!(ponderhit >= Duration::from_millis(150));
+        assert!(result.best_move.is_some());
+        assert!(result.ponder_move.is_some());
+    }
+
+    #[test]
+    fn search_strength_test() {
+        let weakest = SearchOptions {
+            strength: Some(StrengthLimit::new(0, 1)),
+            ..SearchOptions::default()
+        };
+        let result = search_fen_with_options(POS_2_KIWIPETE_FEN, 10, &weakest);
+        assert_eq!(result.depth, 1);
+        assert!(result.pv.first() == result.best_move.as_ref());
+
+        // Even the weakest strength takes a mate in one.
+        for seed in 0..5 {
+            let options = SearchOptions {
+                strength: Some(StrengthLimit::new(0, seed)),
+                ..SearchOptions::default()
+            };
+            let result = search_fen_with_options("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 3, &options);
+            assert_eq!(result.best_move, Some(Move(Square::A1, Square::A8, None)));
+        }
+    }
+
+    #[test]
+    fn dtm_tables_test() {
+        let l = LookupTables::generate();
+        let mut tables = DtmTables::default();
+        let signature = crate::dtm::Signature::try_from("KRvK").unwrap();
+        tables.generate(&signature, &l, &mut |_| {}).unwrap();
+        let b = Board::from_fen("8/8/8/3k4/8/2R5/8/7K b - - 0 1", &l).unwrap();
+        let Some(Dtm::Loss(plies)) = tables.probe(&b) else {
+            panic!("expected a loss");
+        };
+
+        // Taking the knight leads to a position the tables know the mate in.
+        let options = SearchOptions {
+            dtm_tables: Some(Arc::new(tables)),
+            ..SearchOptions::default()
+        };
+        let result = search_fen_with_options("8/8/8/3k4/8/2n5/8/2R4K w - - 0 1", 2, &options);
+        assert_eq!(result.best_move, Some(Move(Square::C1, Square::C3, None)));
+        assert_eq!(result.score, MATE - 1 - plies as i32);
+    }
+}
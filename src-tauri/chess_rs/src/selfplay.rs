@@ -0,0 +1,740 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::board::{Board, Legality, Outcome};
+use crate::core::{Move, Player, STARTING_POS_FEN};
+use crate::epd::{self, Epd};
+use crate::lookup_tables::LookupTables;
+use crate::nnue::Network;
+use crate::pgn::{self, Game};
+use crate::search::{self, SearchLimits, SearchOptions, MAX_THREADS};
+use crate::sprt::{MatchScore, Sprt, SprtVerdict};
+use crate::tt::{TranspositionTable, DEFAULT_HASH_MB};
+
+/// How long a UCI engine gets to answer, on top of the time it's given to think.
+const UCI_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// One side of a match.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EngineConfig {
+    /// This crate's search with its UCI options (`Hash`, `Threads`, `EvalFile` and the search
+    /// switches like `NullMove`) set to the given values.
+    Internal {
+        name: String,
+        options: Vec<(String, String)>,
+    },
+    /// A UCI engine run as a subprocess, with the options to send it before the first game.
+    Uci {
+        name: String,
+        path: String,
+        options: Vec<(String, String)>,
+    },
+}
+
+impl EngineConfig {
+    pub fn name(&self) -> &str {
+        match self {
+            EngineConfig::Internal { name, .. } | EngineConfig::Uci { name, .. } => name,
+        }
+    }
+
+    fn start<'a>(&self) -> Result<Box<dyn MatchEngine<'a> + 'a>, String> {
+        Ok(match self {
+            EngineConfig::Internal { options, .. } => Box::new(InternalEngine::new(options)?),
+            EngineConfig::Uci { path, options, .. } => Box::new(UciEngine::start(path, options)?),
+        })
+    }
+}
+
+impl TryFrom<&str> for EngineConfig {
+    type Error = String;
+
+    /// Parses `internal[:Name=Value,...]` or `uci:<path>[,Name=Value,...]`, where the pseudo
+    /// option `name` sets the name the engine is given in the PGN.
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        let (kind, rest) = s.split_once(':').unwrap_or((s, ""));
+        let mut fields = rest.split(',').filter(|f| !f.is_empty());
+        let path = match kind {
+            "internal" => None,
+            "uci" => Some(
+                fields
+                    .next()
+                    .ok_or_else(|| format!("expected the path of the engine: {}", s))?,
+            ),
+            _ => return Err(format!("expected internal or uci: {}", s)),
+        };
+
+        let mut name = None;
+        let mut options = Vec::new();
+        for field in fields {
+            let (option, value) = field
+                .split_once('=')
+                .ok_or_else(|| format!("expected Name=Value: {}", field))?;
+            if option == "name" {
+                name = Some(value.to_string());
+            } else {
+                options.push((option.to_string(), value.to_string()));
+            }
+        }
+
+        Ok(match path {
+            None => EngineConfig::Internal {
+                name: name.unwrap_or_else(|| "chess_rs".to_string()),
+                options,
+            },
+            Some(path) => EngineConfig::Uci {
+                name: name.unwrap_or_else(|| {
+                    Path::new(path)
+                        .file_stem()
+                        .map_or(path.to_string(), |n| n.to_string_lossy().into_owned())
+                }),
+                path: path.to_string(),
+                options,
+            },
+        })
+    }
+}
+
+/// How long the engines think about each move. Whichever limit is reached first ends the search.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct MoveLimits {
+    pub depth: Option<u8>,
+    pub nodes: Option<u64>,
+    pub movetime: Option<Duration>,
+}
+
+/// A position to start games from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Opening {
+    pub fen: String,
+    /// Moves played from `fen` before the engines take over.
+    pub moves: Vec<Move>,
+    pub chess960: bool,
+}
+
+impl Default for Opening {
+    fn default() -> Self {
+        Opening {
+            fen: STARTING_POS_FEN.to_string(),
+            moves: Vec::new(),
+            chess960: false,
+        }
+    }
+}
+
+/// Reads the openings of a PGN file, which are the games' moves, or of an EPD file, which has a
+/// position on each line.
+pub fn load_openings(path: impl AsRef<Path>, l: &LookupTables) -> Result<Vec<Opening>, String> {
+    let path = path.as_ref();
+    let contents =
+        fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    let is_pgn = path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("pgn"));
+    let openings = if is_pgn {
+        parse_pgn_openings(&contents, l)
+    } else {
+        parse_epd_openings(&contents, l)
+    };
+    openings.map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn parse_pgn_openings(pgn: &str, l: &LookupTables) -> Result<Vec<Opening>, String> {
+    pgn::parse(pgn)?
+        .iter()
+        .enumerate()
+        .map(|(i, game)| {
+            let b = game
+                .start_position(l)
+                .map_err(|e| format!("game {}: {}", i + 1, e))?;
+            Ok(Opening {
+                fen: b.fen(),
+                moves: game
+                    .parse_moves(l)
+                    .map_err(|e| format!("game {}: {}", i + 1, e))?,
+                chess960: b.is_chess960(),
+            })
+        })
+        .collect()
+}
+
+fn parse_epd_openings(text: &str, l: &LookupTables) -> Result<Vec<Opening>, String> {
+    epd::lines(text)
+        .map(|(n, line)| {
+            // FENs are accepted too, as they're EPD records with the move counters on the end.
+            let b = match Epd::try_from(line) {
+                Ok(epd) => Board::from_epd(&epd, l).map_err(|e| e.to_string()),
+                Err(e) => Board::from_fen(line, l).map_err(|_| e),
+            };
+            let b = b.map_err(|e| format!("line {}: {}", n, e))?;
+            Ok(Opening {
+                fen: b.fen(),
+                moves: Vec::new(),
+                chess960: false,
+            })
+        })
+        .collect()
+}
+
+/// An engine taking part in a match.
+trait MatchEngine<'a> {
+    fn new_game(&mut self, chess960: bool) -> Result<(), String>;
+
+    /// The move to play in `b`, whose `moves` were played from `start_fen`.
+    fn best_move(
+        &mut self,
+        start_fen: &str,
+        b: &Board<'a>,
+        limits: &MoveLimits,
+    ) -> Result<Move, String>;
+}
+
+struct InternalEngine {
+    tt: TranspositionTable,
+    options: SearchOptions,
+    network: Option<Arc<Network>>,
+}
+
+impl InternalEngine {
+    fn new(options: &[(String, String)]) -> Result<Self, String> {
+        let mut engine = InternalEngine {
+            tt: TranspositionTable::new(DEFAULT_HASH_MB),
+            options: SearchOptions::default(),
+            network: None,
+        };
+
+        for (name, value) in options {
+            let invalid = || format!("invalid {} value: {}", name, value);
+            match name.as_str() {
+                "Hash" => {
+                    engine.tt = TranspositionTable::new(value.parse().map_err(|_| invalid())?)
+                }
+                "Threads" => {
+                    let threads: usize = value.parse().map_err(|_| invalid())?;
+                    engine.options.threads = threads.clamp(1, MAX_THREADS);
+                }
+                "EvalFile" => engine.network = Some(Arc::new(Network::load(value)?)),
+                name => {
+                    let (_, enabled) = engine
+                        .options
+                        .toggles()
+                        .into_iter()
+                        .find(|(toggle_name, _)| *toggle_name == name)
+                        .ok_or(format!("unknown option: {}", name))?;
+                    *enabled = value.parse().map_err(|_| invalid())?;
+                }
+            }
+        }
+        Ok(engine)
+    }
+}
+
+impl<'a> MatchEngine<'a> for InternalEngine {
+    fn new_game(&mut self, _chess960: bool) -> Result<(), String> {
+        self.tt.clear();
+        Ok(())
+    }
+
+    fn best_move(&mut self, _: &str, b: &Board<'a>, limits: &MoveLimits) -> Result<Move, String> {
+        let mut b = b.clone();
+        b.set_network(self.network.clone());
+        let limits = SearchLimits {
+            depth: limits.depth,
+            nodes: limits.nodes,
+            movetime: limits.movetime,
+            ..SearchLimits::default()
+        };
+        let stop = AtomicBool::new(false);
+        search::search(&b, &self.tt, &limits, &self.options, &stop, &mut |_| {})
+            .best_move
+            .ok_or_else(|| "no legal moves".to_string())
+    }
+}
+
+/// A UCI engine running as a subprocess. Its output is read on a thread of its own so waiting
+/// for an answer can time out.
+struct UciEngine {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+    /// The options the engine said it has.
+    options: Vec<String>,
+}
+
+impl UciEngine {
+    fn start(path: &str, options: &[(String, String)]) -> Result<Self, String> {
+        let mut child = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("cannot start {}: {}", path, e))?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut engine = UciEngine {
+            child,
+            stdin,
+            lines,
+            options: Vec::new(),
+        };
+        engine.send("uci")?;
+        let mut advertised = Vec::new();
+        engine.wait_for("uciok", UCI_TIMEOUT, |line| {
+            // option name <name> type ..., where the name can contain spaces.
+            if let Some(option) = line.strip_prefix("option name ") {
+                let name = option.split(" type ").next().unwrap_or(option);
+                advertised.push(name.trim().to_string());
+            }
+        })?;
+        engine.options = advertised;
+        for (name, value) in options {
+            engine.send(&format!("setoption name {} value {}", name, value))?;
+        }
+        Ok(engine)
+    }
+
+    fn send(&mut self, command: &str) -> Result<(), String> {
+        writeln!(self.stdin, "{}", command)
+            .and_then(|_| self.stdin.flush())
+            .map_err(|e| format!("cannot write to the engine: {}", e))
+    }
+
+    /// Reads lines until one starts with `prefix`, which is returned, passing the lines before it
+    /// to `on_line`.
+    fn wait_for(
+        &mut self,
+        prefix: &str,
+        timeout: Duration,
+        mut on_line: impl FnMut(&str),
+    ) -> Result<String, String> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.lines.recv_timeout(remaining) {
+                Ok(line) if line.split_whitespace().next() == Some(prefix) => return Ok(line),
+                Ok(line) => on_line(&line),
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(format!("the engine didn't send {} in time", prefix))
+                }
+                Err(RecvTimeoutError::Disconnected) => return Err("the engine stopped".to_string()),
+            }
+        }
+    }
+}
+
+impl<'a> MatchEngine<'a> for UciEngine {
+    fn new_game(&mut self, chess960: bool) -> Result<(), String> {
+        if self.options.iter().any(|o| o == "UCI_Chess960") {
+            self.send(&format!("setoption name UCI_Chess960 value {}", chess960))?;
+        }
+        self.send("ucinewgame")?;
+        self.send("isready")?;
+        self.wait_for("readyok", UCI_TIMEOUT, |_| {})?;
+        Ok(())
+    }
+
+    fn best_move(
+        &mut self,
+        start_fen: &str,
+        b: &Board<'a>,
+        limits: &MoveLimits,
+    ) -> Result<Move, String> {
+        let mut position = format!("position fen {}", start_fen);
+        if !b.moves.is_empty() {
+            position.push_str(" moves");
+            for m in &b.moves {
+                position.push_str(&format!(" {}", m));
+            }
+        }
+        self.send(&position)?;
+
+        let mut go = "go".to_string();
+        if let Some(depth) = limits.depth {
+            go.push_str(&format!(" depth {}", depth));
+        }
+        if let Some(nodes) = limits.nodes {
+            go.push_str(&format!(" nodes {}", nodes));
+        }
+        if let Some(movetime) = limits.movetime {
+            go.push_str(&format!(" movetime {}", movetime.as_millis()));
+        }
+        self.send(&go)?;
+
+        let line = self.wait_for(
+            "bestmove",
+            limits.movetime.unwrap_or_default() + UCI_TIMEOUT,
+            |_| {},
+        )?;
+        let m = line.split_whitespace().nth(1).unwrap_or_default();
+        Move::try_from(m).map_err(|_| format!("invalid bestmove: {}", line))
+    }
+}
+
+impl Drop for UciEngine {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        // Give the engine a moment to quit on its own before it's killed.
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Plays a game between `engines`, the first with white, and returns it with `tags` followed by
+/// the result and how the game ended, along with the index of the engine that lost by failing to
+/// answer or playing an illegal move, if one did.
+fn play_game<'a>(
+    engines: &mut [Box<dyn MatchEngine<'a> + 'a>; 2],
+    opening: &Opening,
+    mut tags: Vec<(String, String)>,
+    options: &MatchOptions,
+    l: &'a LookupTables,
+) -> Result<(Game, Option<usize>), String> {
+    let mut b = Board::from_fen(&opening.fen, l).map_err(|e| format!("invalid fen: {}", e))?;
+    b.set_chess960(opening.chess960);
+    for m in &opening.moves {
+        let legal_moves = b.generate_moves(Legality::Legal);
+        if !legal_moves.contains(m) {
+            return Err(format!("illegal opening move: {}", m));
+        }
+        b.apply_move(*m, &legal_moves);
+    }
+
+    let mut failed = None;
+    for (i, engine) in engines.iter_mut().enumerate() {
+        if let Err(e) = engine.new_game(opening.chess960) {
+            failed = Some((i, e));
+            break;
+        }
+    }
+
+    let white_engine = |b: &Board| match b.turn() {
+        Player::White => 0,
+        Player::Black => 1,
+    };
+    let (result, termination) = loop {
+        if let Some((i, e)) = &failed {
+            let result = if *i == 0 { "0-1" } else { "1-0" };
+            break (result, format!("rules infraction: {}", e));
+        }
+        if let Some(outcome) = b.outcome() {
+            let result = match outcome {
+                Outcome::Checkmate(Player::White) => "1-0",
+                Outcome::Checkmate(Player::Black) => "0-1",
+                _ => "1/2-1/2",
+            };
+            break (result, outcome.to_string());
+        }
+        if b.moves.len() >= options.max_plies {
+            break ("1/2-1/2", "adjudication: too many moves".to_string());
+        }
+
+        let i = white_engine(&b);
+        let legal_moves = b.generate_moves(Legality::Legal);
+        match engines[i].best_move(&opening.fen, &b, &options.limits) {
+            Ok(m) if legal_moves.contains(&m) => b.apply_move(m, &legal_moves),
+            Ok(m) => failed = Some((i, format!("illegal move {}", m))),
+            Err(e) => failed = Some((i, e)),
+        }
+    };
+
+    tags.push(("Result".to_string(), result.to_string()));
+    if opening.fen != STARTING_POS_FEN {
+        tags.push(("SetUp".to_string(), "1".to_string()));
+        tags.push(("FEN".to_string(), opening.fen.clone()));
+    }
+    if opening.chess960 {
+        tags.push(("Variant".to_string(), "Chess960".to_string()));
+    }
+    tags.push(("Termination".to_string(), termination));
+    tags.push(("PlyCount".to_string(), b.moves.len().to_string()));
+
+    let game = Game {
+        tags,
+        moves: b.san_moves.clone(),
+        result: result.to_string(),
+    };
+    Ok((game, failed.map(|(i, _)| i)))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MatchOptions {
+    /// The number of games, each opening being played twice with the colours swapped.
+    pub games: u32,
+    pub limits: MoveLimits,
+    /// Games still going after this many plies are adjudicated as draws.
+    pub max_plies: usize,
+    /// How many games are played at once.
+    pub concurrency: usize,
+    /// Stops the match as soon as the test accepts either hypothesis.
+    pub sprt: Option<Sprt>,
+}
+
+impl Default for MatchOptions {
+    fn default() -> Self {
+        MatchOptions {
+            games: 100,
+            limits: MoveLimits::default(),
+            max_plies: 400,
+            concurrency: 1,
+            sprt: None,
+        }
+    }
+}
+
+/// Plays a match between `engines` from `openings`, or the starting position if there are none,
+/// calling `on_game` with each game as it finishes and the score of the first engine so far.
+/// Game `i` is played from opening `i / 2` with the first engine playing white in the even games.
+/// An engine that fails to answer or plays an illegal move loses the game and is restarted.
+pub fn run_match(
+    engines: &[EngineConfig; 2],
+    openings: &[Opening],
+    options: &MatchOptions,
+    l: &LookupTables,
+    on_game: &mut (dyn FnMut(&Game, &MatchScore) + Send),
+) -> Result<MatchScore, String> {
+    let default_openings = [Opening::default()];
+    let openings = if openings.is_empty() {
+        &default_openings[..]
+    } else {
+        openings
+    };
+
+    let next_game = AtomicU32::new(0);
+    let stop = AtomicBool::new(false);
+    let state = Mutex::new((MatchScore::default(), on_game));
+    let date = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(since_epoch) => pgn_date(since_epoch.as_secs() / (24 * 60 * 60)),
+        Err(_) => "????.??.??".to_string(),
+    };
+    let play = || -> Result<(), String> {
+        let mut players = [engines[0].start()?, engines[1].start()?];
+        while !stop.load(Ordering::Relaxed) {
+            let i = next_game.fetch_add(1, Ordering::Relaxed);
+            if i >= options.games {
+                break;
+            }
+
+            // The engines are swapped round so the one playing white comes first.
+            let first_is_white = i.is_multiple_of(2);
+            if !first_is_white {
+                players.swap(0, 1);
+            }
+            let (white, black) = if first_is_white {
+                (engines[0].name(), engines[1].name())
+            } else {
+                (engines[1].name(), engines[0].name())
+            };
+            let tags = vec![
+                ("Event".to_string(), "chess_rs match".to_string()),
+                ("Site".to_string(), "?".to_string()),
+                ("Date".to_string(), date.clone()),
+                ("Round".to_string(), (i + 1).to_string()),
+                ("White".to_string(), white.to_string()),
+                ("Black".to_string(), black.to_string()),
+            ];
+            let opening = &openings[(i as usize / 2) % openings.len()];
+            let game = play_game(&mut players, opening, tags, options, l);
+            if !first_is_white {
+                players.swap(0, 1);
+            }
+            let (game, failed) = game?;
+            if let Some(failed) = failed {
+                let engine = if first_is_white { failed } else { 1 - failed };
+                players[engine] = engines[engine].start()?;
+            }
+
+            let mut state = state.lock().unwrap();
+            let (score, on_game) = &mut *state;
+            match (game.result.as_str(), first_is_white) {
+                ("1-0", true) | ("0-1", false) => score.wins += 1,
+                ("0-1", true) | ("1-0", false) => score.losses += 1,
+                _ => score.draws += 1,
+            }
+            on_game(&game, score);
+            if options
+                .sprt
+                .is_some_and(|sprt| sprt.verdict(score) != SprtVerdict::Continue)
+            {
+                stop.store(true, Ordering::Relaxed);
+            }
+        }
+        Ok(())
+    };
+
+    let results: Vec<Result<(), String>> = thread::scope(|s| {
+        let workers: Vec<_> = (0..options.concurrency.max(1))
+            .map(|_| s.spawn(play))
+            .collect();
+        workers.into_iter().map(|w| w.join().unwrap()).collect()
+    });
+    results.into_iter().collect::<Result<(), String>>()?;
+
+    let (score, _) = state.into_inner().unwrap();
+    Ok(score)
+}
+
+/// The PGN date, like `2024.01.31`, of a number of days since 1970-01-01.
+fn pgn_date(days: u64) -> String {
+    // Counts from 0000-03-01 so leap days come at the end of the year.
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as u64;
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn engine_config_test() {
+        assert_eq!(
+            EngineConfig::try_from("internal").unwrap(),
+            EngineConfig::Internal {
+                name: "chess_rs".to_string(),
+                options: Vec::new()
+            }
+        );
+        assert_eq!(
+            EngineConfig::try_from("internal:name=no null,NullMove=false").unwrap(),
+            EngineConfig::Internal {
+                name: "no null".to_string(),
+                options: vec![("NullMove".to_string(), "false".to_string())]
+            }
+        );
+        assert_eq!(
+            EngineConfig::try_from("uci:/usr/bin/stockfish,Hash=64").unwrap(),
+            EngineConfig::Uci {
+                name: "stockfish".to_string(),
+                path: "/usr/bin/stockfish".to_string(),
+                options: vec![("Hash".to_string(), "64".to_string())]
+            }
+        );
+        assert!(EngineConfig::try_from("uci").is_err());
+        assert!(EngineConfig::try_from("internal:Hash").is_err());
+        assert!(EngineConfig::try_from("xboard:crafty").is_err());
+        assert!(InternalEngine::new(&[("Fast".to_string(), "true".to_string())]).is_err());
+        assert!(InternalEngine::new(&[("NullMove".to_string(), "no".to_string())]).is_err());
+    }
+
+    #[test]
+    fn openings_test() {
+        let l = LookupTables::generate();
+        let openings = parse_epd_openings(
+            "# a comment\n\
+            rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - bm e5; id \"e4\";\n\
+            \n\
+            8/8/8/4k3/8/8/8/4K2R w K - 3 40\n",
+            &l,
+        )
+        .unwrap();
+        assert_eq!(openings.len(), 2);
+        assert_eq!(
+            openings[0].fen,
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1"
+        );
+        assert_eq!(openings[1].fen, "8/8/8/4k3/8/8/8/4K2R w K - 3 40");
+        assert!(parse_epd_openings("8/8/8 w", &l).is_err());
+
+        let openings = parse_pgn_openings("1. e4 e5 2. Nf3 *\n1. d4 *", &l).unwrap();
+        assert_eq!(openings.len(), 2);
+        assert_eq!(openings[0].fen, STARTING_POS_FEN);
+        assert_eq!(openings[0].moves.len(), 3);
+    }
+
+    #[test]
+    fn pgn_date_test() {
+        assert_eq!(pgn_date(0), "1970.01.01");
+        assert_eq!(pgn_date(11_016), "2000.02.29");
+        assert_eq!(pgn_date(19_782), "2024.02.29");
+        assert_eq!(pgn_date(20_088), "2024.12.31");
+    }
+
+    #[test]
+    fn run_match_test() {
+        let l = LookupTables::generate();
+        let engines = [
+            EngineConfig::try_from("internal:name=A").unwrap(),
+            EngineConfig::try_from("internal:name=B,NullMove=false").unwrap(),
+        ];
+        let openings = parse_pgn_openings("1. e4 e5 *\n1. d4 d5 *", &l).unwrap();
+        let options = MatchOptions {
+            games: 4,
+            limits: MoveLimits {
+                depth: Some(1),
+                ..MoveLimits::default()
+            },
+            max_plies: 40,
+            concurrency: 2,
+            sprt: None,
+        };
+
+        let mut games = Vec::new();
+        let score = run_match(&engines, &openings, &options, &l, &mut |game, _| {
+            games.push(game.clone())
+        })
+        .unwrap();
+        assert_eq!(score.games(), 4);
+        assert_eq!(games.len(), 4);
+
+        games.sort_by_key(|g| g.tag("Round").unwrap().parse::<u32>().unwrap());
+        assert_eq!(games[0].tag("White"), Some("A"));
+        assert_eq!(games[1].tag("White"), Some("B"));
+        assert_eq!(games[0].moves[..2], ["e4", "e5"]);
+        assert_eq!(games[1].moves[..2], ["e4", "e5"]);
+        assert_eq!(games[2].moves[..2], ["d4", "d5"]);
+        let roster: Vec<&str> = games[0]
+            .tags
+            .iter()
+            .take(7)
+            .map(|(n, _)| n.as_str())
+            .collect();
+        assert_eq!(
+            roster,
+            ["Event", "Site", "Date", "Round", "White", "Black", "Result"]
+        );
+        for game in &games {
+            // Every game is written in a way that reads back and replays.
+            let read = pgn::parse(&game.to_string()).unwrap();
+            assert_eq!(read[0].parse_moves(&l).unwrap().len(), game.moves.len());
+            assert!(game.moves.len() <= 40);
+        }
+    }
+}
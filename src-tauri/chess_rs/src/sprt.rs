@@ -0,0 +1,245 @@
+use std::fmt;
+
+/// The z score of a 95% confidence interval.
+const Z_95: f64 = 1.959964;
+
+/// The expected score against an opponent `elo` points weaker.
+pub fn elo_to_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+/// The Elo difference that gives an expected score of `score`.
+pub fn score_to_elo(score: f64) -> f64 {
+    400.0 * (score / (1.0 - score)).log10()
+}
+
+/// The results of a match from the point of view of the first engine.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct MatchScore {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl MatchScore {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// The points scored per game, from 0 to 1.
+    pub fn score(&self) -> f64 {
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.games() as f64
+    }
+
+    /// The variance of the score of a single game.
+    fn variance(&self) -> f64 {
+        let score = self.score();
+        let n = self.games() as f64;
+        (self.wins as f64 * (1.0 - score).powi(2)
+            + self.draws as f64 * (0.5 - score).powi(2)
+            + self.losses as f64 * score.powi(2))
+            / n
+    }
+
+    /// The Elo difference between the engines and the half width of its 95% confidence interval,
+    /// which are infinite when one engine has all the points.
+    pub fn elo(&self) -> Option<(f64, f64)> {
+        if self.games() == 0 {
+            return None;
+        }
+
+        let score = self.score();
+        let elo = score_to_elo(score);
+        if elo.is_infinite() {
+            return Some((elo, f64::INFINITY));
+        }
+        let margin = Z_95 * (self.variance() / self.games() as f64).sqrt();
+        let low = score_to_elo((score - margin).max(0.0));
+        let high = score_to_elo((score + margin).min(1.0));
+        Some((elo, (high - low) / 2.0))
+    }
+}
+
+impl fmt::Display for MatchScore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} - {} - {} [{:.3}] {}",
+            self.wins,
+            self.losses,
+            self.draws,
+            self.score(),
+            self.games()
+        )
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SprtVerdict {
+    /// The first engine is no more than `elo0` stronger.
+    AcceptH0,
+    /// The first engine is at least `elo1` stronger.
+    AcceptH1,
+    Continue,
+}
+
+/// A sequential probability ratio test of whether the first engine of a match is `elo0` or
+/// `elo1` Elo stronger than the second, with false positive rate `alpha` and false negative rate
+/// `beta`. The log likelihood ratio is the usual approximation for results of wins, draws and
+/// losses (the generalised SPRT), so the test can stop as soon as it's sure either way.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl Sprt {
+    pub fn new(elo0: f64, elo1: f64) -> Self {
+        Sprt {
+            elo0,
+            elo1,
+            alpha: 0.05,
+            beta: 0.05,
+        }
+    }
+
+    /// The log likelihood ratios at which H0 and H1 are accepted.
+    pub fn bounds(&self) -> (f64, f64) {
+        (
+            (self.beta / (1.0 - self.alpha)).ln(),
+            ((1.0 - self.beta) / self.alpha).ln(),
+        )
+    }
+
+    pub fn llr(&self, score: &MatchScore) -> f64 {
+        if score.games() == 0 {
+            return 0.0;
+        }
+        let variance = score.variance();
+        if variance == 0.0 {
+            return 0.0;
+        }
+
+        let s0 = elo_to_score(self.elo0);
+        let s1 = elo_to_score(self.elo1);
+        score.games() as f64 * (s1 - s0) * (2.0 * score.score() - s0 - s1) / (2.0 * variance)
+    }
+
+    pub fn verdict(&self, score: &MatchScore) -> SprtVerdict {
+        let llr = self.llr(score);
+        let (lower, upper) = self.bounds();
+        if llr >= upper {
+            SprtVerdict::AcceptH1
+        } else if llr <= lower {
+            SprtVerdict::AcceptH0
+        } else {
+            SprtVerdict::Continue
+        }
+    }
+}
+
+impl TryFrom<&str> for Sprt {
+    type Error = String;
+
+    /// Parses `elo0,elo1` with optional `,alpha,beta`.
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        let values = s
+            .split(',')
+            .map(|v| v.trim().parse::<f64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| format!("invalid sprt: {}", s))?;
+        let sprt = match values[..] {
+            [elo0, elo1] => Sprt::new(elo0, elo1),
+            [elo0, elo1, alpha, beta] => Sprt {
+                elo0,
+                elo1,
+                alpha,
+                beta,
+            },
+            _ => return Err(format!("expected elo0,elo1[,alpha,beta]: {}", s)),
+        };
+
+        let is_rate = |r: f64| r > 0.0 && r < 1.0;
+        if sprt.elo0 >= sprt.elo1 || !is_rate(sprt.alpha) || !is_rate(sprt.beta) {
+            return Err(format!("invalid sprt: {}", s));
+        }
+        Ok(sprt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 0.01, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn elo_test() {
+        assert_close(elo_to_score(0.0), 0.5);
+        assert_close(score_to_elo(elo_to_score(100.0)), 100.0);
+        assert_close(score_to_elo(0.64), 99.95);
+
+        let score = MatchScore {
+            wins: 60,
+            draws: 20,
+            losses: 20,
+        };
+        assert_eq!(score.to_string(), "60 - 20 - 20 [0.700] 100");
+        let (elo, margin) = score.elo().unwrap();
+        assert_close(elo, 147.19);
+        assert!(margin > 50.0 && margin < 100.0);
+        assert_eq!(MatchScore::default().elo(), None);
+        let (elo, margin) = MatchScore {
+            losses: 3,
+            ..MatchScore::default()
+        }
+        .elo()
+        .unwrap();
+        assert_eq!((elo, margin), (f64::NEG_INFINITY, f64::INFINITY));
+    }
+
+    #[test]
+    fn sprt_test() {
+        let sprt = Sprt::try_from("0,10").unwrap();
+        let (lower, upper) = sprt.bounds();
+        assert_close(lower, -2.94);
+        assert_close(upper, 2.94);
+
+        let even = MatchScore {
+            wins: 300,
+            draws: 400,
+            losses: 300,
+        };
+        assert!(sprt.llr(&even) < 0.0);
+        assert_eq!(sprt.verdict(&even), SprtVerdict::Continue);
+        let even = MatchScore {
+            wins: 3000,
+            draws: 4000,
+            losses: 3000,
+        };
+        assert_eq!(sprt.verdict(&even), SprtVerdict::AcceptH0);
+        let better = MatchScore {
+            wins: 400,
+            draws: 400,
+            losses: 200,
+        };
+        assert_eq!(sprt.verdict(&better), SprtVerdict::AcceptH1);
+
+        assert_eq!(
+            Sprt::try_from("-5,5,0.1,0.2").unwrap(),
+            Sprt {
+                elo0: -5.0,
+                elo1: 5.0,
+                alpha: 0.1,
+                beta: 0.2
+            }
+        );
+        assert!(Sprt::try_from("10,0").is_err());
+        assert!(Sprt::try_from("0,10,0").is_err());
+        assert!(Sprt::try_from("0,10,1,0.05").is_err());
+    }
+}
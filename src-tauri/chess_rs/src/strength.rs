@@ -0,0 +1,280 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::core::Move;
+use crate::search::{SearchLimits, SearchOptions, MATE_BOUND};
+
+/// The strongest skill level, which in the engine means playing at full strength.
+pub const MAX_SKILL_LEVEL: u32 = 20;
+
+/// How many lines a limited search looks at to pick its move from.
+const CANDIDATE_LINES: usize = 4;
+
+/// Skills, from 0 to 1, and their strength in Elo above the weakest, fitted by playing neighbouring
+/// skills against each other with `selfplay`, 30 games a pair. The skills are 0.1 apart below 0.3
+/// and 0.05 apart above it, and each gap is only known to within about 150 Elo. Skills up to 0.3
+/// couldn't be told apart so they share a segment. Refit them when the caps below change.
+const ANCHORS: [(u32, f64); 16] = [
+    (0, 0.0),
+    (236, 0.3),
+    (331, 0.35),
+    (414, 0.4),
+    (620, 0.45),
+    (754, 0.5),
+    (930, 0.55),
+    (1105, 0.6),
+    (1329, 0.65),
+    (1424, 0.7),
+    (1683, 0.75),
+    (2065, 0.8),
+    (2212, 0.85),
+    (2492, 0.9),
+    (2715, 0.95),
+    (2876, 1.0),
+];
+
+/// Weakens the search to a skill level by capping its depth and nodes, adding noise to the
+/// evaluation and sometimes playing a move that isn't the best.
+///
+/// The levels are spaced evenly by strength in self-play, so each is about the same number of Elo
+/// stronger than the one below. They aren't tied to ratings, as no engine of known rating was
+/// played against.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StrengthLimit {
+    level: u32,
+    /// Seeds the evaluation noise and the choice of move so games can be replayed.
+    seed: u64,
+}
+
+impl StrengthLimit {
+    /// `level` is clamped to at most `MAX_SKILL_LEVEL`.
+    pub fn new(level: u32, seed: u64) -> Self {
+        StrengthLimit {
+            level: level.min(MAX_SKILL_LEVEL),
+            seed,
+        }
+    }
+
+    pub fn level(&self) -> u32 {
+        self.level
+    }
+
+    /// How strong the search is, from 0 at level 0 to 1 at `MAX_SKILL_LEVEL`, interpolated
+    /// between the `ANCHORS` by the self-play strength the level is at.
+    fn skill(&self) -> f64 {
+        let top = ANCHORS[ANCHORS.len() - 1].0;
+        let strength = (self.level * top) as f64 / MAX_SKILL_LEVEL as f64;
+        let (lower, upper) = ANCHORS
+            .windows(2)
+            .map(|pair| (pair[0], pair[1]))
+            .find(|(_, (elo, _))| strength <= *elo as f64)
+            .unwrap();
+        lower.1 + (upper.1 - lower.1) * (strength - lower.0 as f64) / (upper.0 - lower.0) as f64
+    }
+
+    pub fn max_depth(&self) -> u8 {
+        1 + (self.skill() * 11.0).round() as u8
+    }
+
+    /// Grows exponentially from 100 to a million nodes.
+    pub fn max_nodes(&self) -> u64 {
+        (100.0 * 10f64.powf(self.skill() * 4.0)) as u64
+    }
+
+    /// The most the evaluation can be off by, in centipawns.
+    pub fn eval_noise(&self) -> i32 {
+        ((1.0 - self.skill()) * 200.0) as i32
+    }
+
+    /// Noise for the position with `hash`. It's the same every time the position is evaluated so
+    /// the search stays consistent with the TT.
+    pub fn noise_for(&self, hash: u64) -> i32 {
+        let amplitude = self.eval_noise();
+        if amplitude == 0 {
+            return 0;
+        }
+        let mut rng = StdRng::seed_from_u64(hash ^ self.seed);
+        rng.gen_range(-amplitude..=amplitude)
+    }
+
+    /// Tightens `limits` to the caps for this strength.
+    pub fn limit(&self, limits: &SearchLimits) -> SearchLimits {
+        SearchLimits {
+            depth: Some(
+                limits
+                    .depth
+                    .map_or(self.max_depth(), |d| d.min(self.max_depth())),
+            ),
+            nodes: Some(
+                limits
+                    .nodes
+                    .map_or(self.max_nodes(), |n| n.min(self.max_nodes())),
+            ),
+            ..limits.clone()
+        }
+    }
+
+    /// A limited search runs on one thread and looks at several lines to choose from. Lines past
+    /// the `multi_pv` that was asked for are reported too, so callers talking to a GUI should
+    /// leave them out.
+    pub fn limit_options(&self, options: &SearchOptions) -> SearchOptions {
+        SearchOptions {
+            threads: 1,
+            multi_pv: options.multi_pv.max(CANDIDATE_LINES),
+            ..options.clone()
+        }
+    }
+
+    /// Picks one of the searched `lines`, given as scores and PVs, favouring the better ones.
+    /// Weaker strengths consider worse moves and pick among them more evenly. Forced mates are
+    /// never passed up.
+    pub fn choose_line<'l>(&self, lines: &'l [(i32, Vec<Move>)]) -> Option<&'l (i32, Vec<Move>)> {
+        let best_score = lines.iter().map(|(score, _)| *score).max()?;
+        if best_score >= MATE_BOUND {
+            return lines.iter().find(|(score, _)| *score == best_score);
+        }
+
+        let max_loss = ((1.0 - self.skill()) * 300.0) as i32;
+        let temperature = 10.0 + (1.0 - self.skill()) * 150.0;
+        let candidates: Vec<(&(i32, Vec<Move>), f64)> = lines
+            .iter()
+            .filter(|(score, pv)| !pv.is_empty() && best_score - score <= max_loss)
+            .map(|line| (line, (-(best_score - line.0) as f64 / temperature).exp()))
+            .collect();
+
+        let total: f64 = candidates.iter().map(|(_, weight)| weight).sum();
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut pick = rng.gen_range(0.0..total);
+        for (line, weight) in &candidates {
+            if pick < *weight {
+                return Some(line);
+            }
+            pick -= weight;
+        }
+        candidates.last().map(|(line, _)| *line)
+    }
+}
+
+/// The difficulty levels offered when starting a game against the engine.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Difficulty {
+    Beginner,
+    Casual,
+    Intermediate,
+    Advanced,
+    Expert,
+    Maximum,
+}
+
+impl Difficulty {
+    /// `None` for `Maximum`, which plays at full strength.
+    pub fn skill_level(self) -> Option<u32> {
+        match self {
+            Difficulty::Beginner => Some(2),
+            Difficulty::Casual => Some(5),
+            Difficulty::Intermediate => Some(9),
+            Difficulty::Advanced => Some(13),
+            Difficulty::Expert => Some(16),
+            Difficulty::Maximum => None,
+        }
+    }
+
+    pub fn strength(self, seed: u64) -> Option<StrengthLimit> {
+        self.skill_level()
+            .map(|level| StrengthLimit::new(level, seed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Square;
+
+    #[test]
+    fn caps_test() {
+        let weakest = StrengthLimit::new(0, 0);
+        let strongest = StrengthLimit::new(u32::MAX, 0);
+        assert_eq!(strongest.level(), MAX_SKILL_LEVEL);
+
+        assert_eq!(weakest.max_depth(), 1);
+        assert_eq!(strongest.max_depth(), 12);
+        assert_eq!(weakest.max_nodes(), 100);
+        assert_eq!(strongest.max_nodes(), 1_000_000);
+        assert_eq!(weakest.eval_noise(), 200);
+        assert_eq!(strongest.eval_noise(), 0);
+
+        // Caps grow with the level.
+        let mut previous = StrengthLimit::new(0, 0);
+        for level in 0..=MAX_SKILL_LEVEL {
+            let strength = StrengthLimit::new(level, 0);
+            assert!(strength.max_depth() >= previous.max_depth());
+            assert!(strength.max_nodes() >= previous.max_nodes());
+            assert!(strength.eval_noise() <= previous.eval_noise());
+            previous = strength;
+        }
+
+        let limits = StrengthLimit::new(10, 0).limit(&SearchLimits {
+            depth: Some(3),
+            ..SearchLimits::default()
+        });
+        assert_eq!(limits.depth, Some(3));
+        assert_eq!(limits.nodes, Some(StrengthLimit::new(10, 0).max_nodes()));
+    }
+
+    #[test]
+    fn anchors_test() {
+        assert_eq!(ANCHORS[0], (0, 0.0));
+        assert_eq!(ANCHORS[ANCHORS.len() - 1].1, 1.0);
+        assert!(ANCHORS
+            .windows(2)
+            .all(|pair| pair[0].0 < pair[1].0 && pair[0].1 < pair[1].1));
+
+        assert_eq!(StrengthLimit::new(0, 0).skill(), 0.0);
+        assert_eq!(StrengthLimit::new(MAX_SKILL_LEVEL, 0).skill(), 1.0);
+        // Half way up in strength is past half way in skill, as the weak end is flat.
+        let middle = StrengthLimit::new(MAX_SKILL_LEVEL / 2, 0).skill();
+        assert!(0.7 < middle && middle < 0.75);
+    }
+
+    #[test]
+    fn noise_test() {
+        let strength = StrengthLimit::new(0, 7);
+        assert_eq!(strength.noise_for(1234), strength.noise_for(1234));
+        assert!((0..100).all(|h| strength.noise_for(h).abs() <= 200));
+        assert!((0..100).any(|h| strength.noise_for(h) != 0));
+        assert_eq!(StrengthLimit::new(MAX_SKILL_LEVEL, 7).noise_for(1234), 0);
+    }
+
+    #[test]
+    fn choose_line_test() {
+        let line = |score, to| (score, vec![Move(Square::E2, to, None)]);
+        let lines = vec![
+            line(50, Square::E4),
+            line(40, Square::E3),
+            line(-400, Square::D3),
+        ];
+
+        // Full strength always plays the best move.
+        for seed in 0..20 {
+            let strength = StrengthLimit::new(MAX_SKILL_LEVEL, seed);
+            assert_eq!(strength.choose_line(&lines), Some(&lines[0]));
+        }
+
+        // The weakest strength sometimes plays the second best but never the blunder.
+        let picks: Vec<_> = (0..50)
+            .map(|seed| StrengthLimit::new(0, seed).choose_line(&lines).unwrap())
+            .collect();
+        assert!(picks.contains(&&lines[0]));
+        assert!(picks.contains(&&lines[1]));
+        assert!(!picks.contains(&&lines[2]));
+
+        // A mate is never passed up.
+        let lines = vec![line(100, Square::E4), line(MATE_BOUND + 5, Square::E3)];
+        assert_eq!(
+            StrengthLimit::new(0, 3).choose_line(&lines),
+            Some(&lines[1])
+        );
+        assert_eq!(StrengthLimit::new(0, 3).choose_line(&[]), None);
+    }
+}
@@ -0,0 +1,1462 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::ops::Neg;
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+
+use crate::board::{Board, Legality};
+use crate::core::{Move, Piece, PieceKind, Player};
+use crate::endgame::{self, MaterialKey};
+use crate::search::{MATE_BOUND, MAX_PLY};
+
+/// The score of a position the tablebases say is won. It's below the mate scores, which are
+/// only given to mates the search has actually found, and well above the evaluation of any
+/// position that isn't known to be won.
+pub const TB_WIN: i32 = MATE_BOUND - MAX_PLY as i32;
+
+/// The most pieces in a table, including the kings.
+const MAX_PIECES: usize = 7;
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xE8, 0x23, 0x5D];
+const DTZ_MAGIC: [u8; 4] = [0xD7, 0x66, 0x0C, 0xA5];
+
+// The flags of the compressed data of a table.
+const STM: u8 = 1;
+const MAPPED: u8 = 2;
+const WIN_PLIES: u8 = 4;
+const LOSS_PLIES: u8 = 8;
+const WIDE: u8 = 16;
+const SINGLE_VALUE: u8 = 128;
+
+/// The result of a position with perfect play, from the point of view of the player to move.
+/// Cursed wins and blessed losses are wins and losses that the 50 move rule turns into draws.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(i32)]
+pub enum Wdl {
+    Loss = -2,
+    BlessedLoss = -1,
+    Draw = 0,
+    CursedWin = 1,
+    Win = 2,
+}
+
+impl Neg for Wdl {
+    type Output = Wdl;
+
+    fn neg(self) -> Wdl {
+        match self {
+            Wdl::Loss => Wdl::Win,
+            Wdl::BlessedLoss => Wdl::CursedWin,
+            Wdl::Draw => Wdl::Draw,
+            Wdl::CursedWin => Wdl::BlessedLoss,
+            Wdl::Win => Wdl::Loss,
+        }
+    }
+}
+
+impl Wdl {
+    fn from_value(value: i32) -> Wdl {
+        match value {
+            -2 => Wdl::Loss,
+            -1 => Wdl::BlessedLoss,
+            1 => Wdl::CursedWin,
+            2 => Wdl::Win,
+            _ => Wdl::Draw,
+        }
+    }
+}
+
+/// Syzygy endgame tablebases: win/draw/loss (`.rtbw`) and distance to zeroing (`.rtbz`) tables
+/// for positions with few pieces, where zeroing is a capture or pawn move, which resets the 50
+/// move counter.
+///
+/// The tables are found by name, like `KRvK.rtbw`, when the tablebases are opened and each is
+/// read into memory the first time it's probed.
+pub struct Tablebases {
+    paths: Vec<PathBuf>,
+    /// The tables keyed by the material of both their orientations.
+    wdl: HashMap<MaterialKey, Arc<Table>>,
+    dtz: HashMap<MaterialKey, Arc<Table>>,
+    max_pieces: usize,
+}
+
+impl fmt::Debug for Tablebases {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Tablebases({:?})", self.paths)
+    }
+}
+
+impl PartialEq for Tablebases {
+    fn eq(&self, other: &Self) -> bool {
+        self.paths == other.paths
+    }
+}
+
+impl Tablebases {
+    /// Finds the tables in `paths`, a list of directories separated like the `PATH` environment
+    /// variable.
+    pub fn open(paths: &str) -> Result<Self, String> {
+        let mut tablebases = Tablebases {
+            paths: env::split_paths(paths).collect(),
+            wdl: HashMap::new(),
+            dtz: HashMap::new(),
+            max_pieces: 0,
+        };
+
+        for dir in tablebases.paths.clone() {
+            let entries =
+                fs::read_dir(&dir).map_err(|e| format!("cannot read {}: {}", dir.display(), e))?;
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let (Some(stem), Some(extension)) = (path.file_stem(), path.extension()) else {
+                    continue;
+                };
+                let (kind, tables) = match extension.to_str() {
+                    Some("rtbw") => (TableKind::Wdl, &mut tablebases.wdl),
+                    Some("rtbz") => (TableKind::Dtz, &mut tablebases.dtz),
+                    _ => continue,
+                };
+                let Some((key, key2)) = stem.to_str().and_then(parse_table_name) else {
+                    continue;
+                };
+                // The first table found for an endgame is used.
+                if tables.contains_key(&key) {
+                    continue;
+                }
+
+                let table = Arc::new(Table::new(path, kind, key, key2));
+                if kind == TableKind::Wdl {
+                    tablebases.max_pieces = tablebases.max_pieces.max(table.piece_count);
+                }
+                tables.insert(key, table.clone());
+                tables.insert(key2, table);
+            }
+        }
+
+        Ok(tablebases)
+    }
+
+    /// The number of WDL and DTZ tables found.
+    pub fn table_count(&self) -> usize {
+        let count = |tables: &HashMap<MaterialKey, Arc<Table>>| {
+            tables
+                .iter()
+                .filter(|(key, table)| **key == table.key)
+                .count()
+        };
+        count(&self.wdl) + count(&self.dtz)
+    }
+
+    /// The most pieces in any of the WDL tables, or 0 if there aren't any.
+    pub fn max_pieces(&self) -> usize {
+        self.max_pieces
+    }
+
+    /// Whether `b` has few enough pieces to be in the tablebases. The tables don't have
+    /// positions where castling is possible.
+    pub fn can_probe(&self, b: &Board) -> bool {
+        let pieces = (b.occupancy(Player::White) | b.occupancy(Player::Black)).count();
+        pieces <= self.max_pieces && !b.can_castle()
+    }
+
+    /// The result of `b` with perfect play, or `None` if it isn't in the tablebases. The 50 move
+    /// counter of `b` isn't taken into account. `b` is left as it was.
+    pub fn probe_wdl(&self, b: &mut Board) -> Option<Wdl> {
+        if !self.can_probe(b) {
+            return None;
+        }
+        self.search(b, false).map(|(wdl, _)| wdl)
+    }
+
+    /// The plies until the next capture or pawn move that keeps the result of `b`, positive
+    /// when it's won, negative when it's lost and 0 when it's drawn, or `None` if it isn't in
+    /// the tablebases. Cursed wins and blessed losses are 100 plies further away than they are
+    /// so they aren't mistaken for wins and losses. `b` is left as it was.
+    ///
+    /// The distance may be a ply more than it really is, which can only matter when the 50 move
+    /// counter is close to running out.
+    pub fn probe_dtz(&self, b: &mut Board) -> Option<i32> {
+        if !self.can_probe(b) {
+            return None;
+        }
+        self.dtz(b)
+    }
+
+    /// The legal moves of `b` that keep the best result with the fastest progress: the winning
+    /// moves nearest to zeroing, or if there aren't any, the drawing moves, or the losing moves
+    /// that hold out the longest. The 50 move counter is taken into account, so a win that would
+    /// be drawn by it is only preferred to a draw. `None` if `b` isn't in the tablebases.
+    pub fn root_moves(&self, b: &mut Board) -> Option<Vec<Move>> {
+        if !self.can_probe(b) {
+            return None;
+        }
+
+        let half_moves = b.half_moves() as i32;
+        let mut ranked = Vec::new();
+        for m in b.generate_moves(Legality::Legal) {
+            b.make_move(m);
+            let dtz = if b.half_moves() == 0 {
+                self.search(b, false)
+                    .map(|(wdl, _)| dtz_before_zeroing(-wdl))
+            } else {
+                // The distance from after the move, so a ply further away.
+                self.dtz(b).map(|dtz| -dtz - dtz.signum())
+            };
+            // A mating move isn't a zeroing move but it ends the game straight away.
+            let is_mate = b.is_in_check() && b.generate_moves(Legality::Legal).is_empty();
+            b.unmake_move();
+            let dtz = dtz?;
+            let dtz = if dtz == 2 && is_mate { 1 } else { dtz };
+
+            let rank = match dtz {
+                d if d > 0 && d + half_moves <= 99 => 1000 - d,
+                d if d > 0 => 1,
+                d if d < 0 && -d + half_moves <= 99 => -1000 - d,
+                d if d < 0 => -1,
+                _ => 0,
+            };
+            ranked.push((rank, m));
+        }
+
+        let best_rank = ranked.iter().map(|(rank, _)| *rank).max()?;
+        Some(
+            ranked
+                .into_iter()
+                .filter(|(rank, _)| *rank == best_rank)
+                .map(|(_, m)| m)
+                .collect(),
+        )
+    }
+
+    /// Resolves the captures, and the pawn moves when `zeroing_moves` is set, of `b` before
+    /// probing its WDL table. The tables can't be trusted about positions where capturing is
+    /// best, and they don't have en passant captures at all. Also returns whether the best move
+    /// was one of the resolved moves.
+    fn search(&self, b: &mut Board, zeroing_moves: bool) -> Option<(Wdl, bool)> {
+        let moves = b.generate_moves(Legality::Legal);
+        let mut best = Wdl::Loss;
+        let mut searched = 0;
+        for m in &moves {
+            let is_pawn_move = b
+                .get_piece(m.0)
+                .is_some_and(|p| PieceKind::from(p) == PieceKind::Pawn);
+            if !(b.is_capture(m) || zeroing_moves && is_pawn_move) {
+                continue;
+            }
+
+            searched += 1;
+            b.make_move(*m);
+            let value = self.search(b, false).map(|(wdl, _)| -wdl);
+            b.unmake_move();
+            let value = value?;
+
+            if value > best {
+                best = value;
+                if value >= Wdl::Win {
+                    return Some((value, true));
+                }
+            }
+        }
+
+        // The table isn't needed when every move has been searched.
+        let all_searched = searched > 0 && searched == moves.len();
+        let value = if all_searched {
+            best
+        } else {
+            match self.probe_table(b, TableKind::Wdl, Wdl::Draw)? {
+                Probe::Value(value) => Wdl::from_value(value),
+                Probe::ChangeStm => return None,
+            }
+        };
+
+        // The table can have any value when capturing wins.
+        if best >= value {
+            Some((best, best > Wdl::Draw || all_searched))
+        } else {
+            Some((value, false))
+        }
+    }
+
+    fn dtz(&self, b: &mut Board) -> Option<i32> {
+        let (wdl, zeroing_is_best) = self.search(b, true)?;
+        if wdl == Wdl::Draw {
+            return Some(0);
+        }
+        // The table can have any value when a zeroing move is best.
+        if zeroing_is_best {
+            return Some(dtz_before_zeroing(wdl));
+        }
+
+        if let Probe::Value(dtz) = self.probe_table(b, TableKind::Dtz, wdl)? {
+            let cursed = matches!(wdl, Wdl::CursedWin | Wdl::BlessedLoss);
+            return Some((dtz + if cursed { 100 } else { 0 }) * (wdl as i32).signum());
+        }
+
+        // The table only has the other player to move, so search a ply for the move to the
+        // nearest zeroing.
+        let mut min_dtz = i32::MAX;
+        for m in b.generate_moves(Legality::Legal) {
+            let is_zeroing = b.is_capture(&m)
+                || b.get_piece(m.0)
+                    .is_some_and(|p| PieceKind::from(p) == PieceKind::Pawn);
+            b.make_move(m);
+            // The distance of a zeroing move is worked out from before it's made but the result
+            // after it's made is needed to tell whether it keeps the win.
+            let dtz = if is_zeroing {
+                self.search(b, false)
+                    .map(|(wdl, _)| -dtz_before_zeroing(wdl))
+            } else {
+                self.dtz(b).map(|dtz| -dtz)
+            };
+            let is_mate = b.is_in_check() && b.generate_moves(Legality::Legal).is_empty();
+            b.unmake_move();
+            let mut dtz = dtz?;
+
+            if is_mate && dtz == 1 {
+                min_dtz = 1;
+            }
+            // The distance from after a move that doesn't zero is a ply further away.
+            if !is_zeroing {
+                dtz += dtz.signum();
+            }
+            if dtz < min_dtz && dtz.signum() == (wdl as i32).signum() {
+                min_dtz = dtz;
+            }
+        }
+
+        // Without any legal moves it's mate.
+        Some(if min_dtz == i32::MAX { -1 } else { min_dtz })
+    }
+
+    fn probe_table(&self, b: &Board, kind: TableKind, wdl: Wdl) -> Option<Probe> {
+        let key = endgame::material_key(b);
+        // Two kings are always drawn and don't have a table.
+        if key.iter().map(|&n| n as usize).sum::<usize>() == 2 {
+            return Some(Probe::Value(0));
+        }
+
+        let tables = match kind {
+            TableKind::Wdl => &self.wdl,
+            TableKind::Dtz => &self.dtz,
+        };
+        tables.get(&key)?.probe(b, key, wdl)
+    }
+}
+
+/// The DTZ of a position where the best move zeroes.
+fn dtz_before_zeroing(wdl: Wdl) -> i32 {
+    match wdl {
+        Wdl::Win => 1,
+        Wdl::CursedWin => 101,
+        Wdl::Draw => 0,
+        Wdl::BlessedLoss => -101,
+        Wdl::Loss => -1,
+    }
+}
+
+/// The material of a table's endgame from its name, like `KRvKP`, with white having the pieces
+/// before the `v` and then with black having them.
+fn parse_table_name(name: &str) -> Option<(MaterialKey, MaterialKey)> {
+    let (first, second) = name.split_once('v')?;
+    if !first.starts_with('K') || !second.starts_with('K') || first.len() + second.len() > 7 {
+        return None;
+    }
+
+    let mut key = [0; 12];
+    let mut key2 = [0; 12];
+    for (pieces, white) in [(first, true), (second, false)] {
+        for c in pieces.chars() {
+            if !"KQRBNP".contains(c) {
+                return None;
+            }
+            let white_piece = Piece::try_from(c).ok()?;
+            let black_piece = Piece::try_from(c.to_ascii_lowercase()).ok()?;
+            let (p, p2) = if white {
+                (white_piece, black_piece)
+            } else {
+                (black_piece, white_piece)
+            };
+            key[p as usize] += 1;
+            key2[p2 as usize] += 1;
+        }
+    }
+
+    Some((key, key2))
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum TableKind {
+    Wdl,
+    Dtz,
+}
+
+enum Probe {
+    Value(i32),
+    /// The DTZ table only has the other player to move.
+    ChangeStm,
+}
+
+/// A table file, which is read the first time it's probed.
+struct Table {
+    path: PathBuf,
+    kind: TableKind,
+    /// The material with white as the stronger player, which is how the table is stored.
+    key: MaterialKey,
+    /// The material with the colours swapped.
+    key2: MaterialKey,
+    piece_count: usize,
+    has_pawns: bool,
+    /// Whether either player has only one of a kind of piece other than the king.
+    has_unique_pieces: bool,
+    /// The pawns of the leading player, which is the one with fewer pawns if both have them,
+    /// then the other player's.
+    pawn_count: [usize; 2],
+    data: OnceLock<Option<TableData>>,
+}
+
+struct TableData {
+    file: TableFile,
+    /// Indexed by player to move, then by the file of the leading pawn if there are pawns.
+    items: Vec<Vec<PairsData>>,
+}
+
+impl Table {
+    fn new(path: PathBuf, kind: TableKind, key: MaterialKey, key2: MaterialKey) -> Self {
+        let count = |p: Piece| key[p as usize] as usize;
+        let has_unique_pieces = [
+            Piece::WhitePawn,
+            Piece::WhiteKnight,
+            Piece::WhiteBishop,
+            Piece::WhiteRook,
+            Piece::WhiteQueen,
+            Piece::BlackPawn,
+            Piece::BlackKnight,
+            Piece::BlackBishop,
+            Piece::BlackRook,
+            Piece::BlackQueen,
+        ]
+        .into_iter()
+        .any(|p| count(p) == 1);
+
+        let white_pawns = count(Piece::WhitePawn);
+        let black_pawns = count(Piece::BlackPawn);
+        let white_leads = black_pawns == 0 || (white_pawns > 0 && black_pawns >= white_pawns);
+
+        Table {
+            path,
+            kind,
+            key,
+            key2,
+            piece_count: key.iter().map(|&n| n as usize).sum(),
+            has_pawns: white_pawns + black_pawns > 0,
+            has_unique_pieces,
+            pawn_count: if white_leads {
+                [white_pawns, black_pawns]
+            } else {
+                [black_pawns, white_pawns]
+            },
+            data: OnceLock::new(),
+        }
+    }
+
+    fn data(&self) -> Option<&TableData> {
+        self.data
+            .get_or_init(|| {
+                let bytes = fs::read(&self.path).ok()?;
+                self.parse(TableFile(bytes))
+            })
+            .as_ref()
+    }
+
+    /// Finds where the parts of the table are in `file`.
+    fn parse(&self, file: TableFile) -> Option<TableData> {
+        let magic = match self.kind {
+            TableKind::Wdl => WDL_MAGIC,
+            TableKind::Dtz => DTZ_MAGIC,
+        };
+        if file.0.len() % 64 != 16 || file.0[..4] != magic {
+            return None;
+        }
+
+        let flags = file.byte(4);
+        let split = self.kind == TableKind::Wdl && self.key != self.key2;
+        if (flags & 2 != 0) != self.has_pawns || (flags & 1 != 0) != (self.key != self.key2) {
+            return None;
+        }
+
+        let sides = if split { 2 } else { 1 };
+        let files = if self.has_pawns { 4 } else { 1 };
+        let pawns_on_both_sides = self.has_pawns && self.pawn_count[1] > 0;
+        let mut items = vec![vec![PairsData::default(); files]; 2];
+
+        let mut offset = 5;
+        for f in 0..files {
+            let order_byte = file.byte(offset);
+            let order_byte2 = file.byte(offset + 1);
+            let order = [
+                [
+                    order_byte & 0xF,
+                    if pawns_on_both_sides {
+                        order_byte2 & 0xF
+                    } else {
+                        0xF
+                    },
+                ],
+                [
+                    order_byte >> 4,
+                    if pawns_on_both_sides {
+                        order_byte2 >> 4
+                    } else {
+                        0xF
+                    },
+                ],
+            ];
+            offset += 1 + pawns_on_both_sides as usize;
+
+            for k in 0..self.piece_count {
+                let b = file.byte(offset);
+                for (side, side_items) in items.iter_mut().enumerate().take(sides) {
+                    side_items[f].pieces[k] = if side == 1 { b >> 4 } else { b & 0xF };
+                }
+                offset += 1;
+            }
+
+            for side in 0..sides {
+                self.set_groups(&mut items[side][f], order[side], f);
+            }
+        }
+        offset += offset & 1;
+
+        for f in 0..files {
+            for side_items in items.iter_mut().take(sides) {
+                offset = side_items[f].set_sizes(&file, offset);
+            }
+        }
+
+        if self.kind == TableKind::Dtz {
+            for d in items[0].iter_mut() {
+                if d.flags & MAPPED == 0 {
+                    continue;
+                }
+                if d.flags & WIDE != 0 {
+                    offset += offset & 1;
+                    for i in 0..4 {
+                        d.map_idx[i] = offset + 2;
+                        offset += 2 * file.u16_le(offset) as usize + 2;
+                    }
+                } else {
+                    for i in 0..4 {
+                        d.map_idx[i] = offset + 1;
+                        offset += file.byte(offset) as usize + 1;
+                    }
+                }
+            }
+            offset += offset & 1;
+        }
+
+        for f in 0..files {
+            for side_items in items.iter_mut().take(sides) {
+                side_items[f].sparse_index = offset;
+                offset += side_items[f].sparse_index_size * 6;
+            }
+        }
+        for f in 0..files {
+            for side_items in items.iter_mut().take(sides) {
+                side_items[f].block_lengths = offset;
+                offset += side_items[f].block_lengths_size * 2;
+            }
+        }
+        for f in 0..files {
+            for side_items in items.iter_mut().take(sides) {
+                offset = (offset + 0x3F) & !0x3F;
+                side_items[f].data = offset;
+                offset += side_items[f].num_blocks * side_items[f].block_size;
+            }
+        }
+
+        Some(TableData { file, items })
+    }
+
+    /// Works out how the groups of pieces are encoded in the index of a position. The pieces
+    /// are grouped into the leading pieces or pawns, the rest of the pawns, then the pieces of
+    /// each kind, and `order` gives the order the groups are encoded in.
+    fn set_groups(&self, d: &mut PairsData, order: [u8; 2], f: usize) {
+        let tables = index_tables();
+        let mut first_len = if self.has_pawns {
+            0
+        } else if self.has_unique_pieces {
+            3
+        } else {
+            2
+        };
+
+        let mut n = 0;
+        d.group_len[0] = 1;
+        for i in 1..self.piece_count {
+            first_len -= 1;
+            if first_len > 0 || d.pieces[i] == d.pieces[i - 1] {
+                d.group_len[n] += 1;
+            } else {
+                n += 1;
+                d.group_len[n] = 1;
+            }
+        }
+        n += 1;
+        d.group_len[n] = 0;
+
+        let pawns_on_both_sides = self.has_pawns && self.pawn_count[1] > 0;
+        let mut next = if pawns_on_both_sides { 2 } else { 1 };
+        let mut free_squares = 64
+            - d.group_len[0]
+            - if pawns_on_both_sides {
+                d.group_len[1]
+            } else {
+                0
+            };
+        let mut idx = 1;
+
+        let mut k = 0;
+        while next < n || k == order[0] as usize || k == order[1] as usize {
+            if k == order[0] as usize {
+                d.group_idx[0] = idx;
+                idx *= if self.has_pawns {
+                    tables.lead_pawns_size[d.group_len[0]][f]
+                } else if self.has_unique_pieces {
+                    31332
+                } else {
+                    462
+                };
+            } else if k == order[1] as usize {
+                d.group_idx[1] = idx;
+                idx *= tables.binomial[d.group_len[1]][48 - d.group_len[0]];
+            } else {
+                d.group_idx[next] = idx;
+                idx *= tables.binomial[d.group_len[next]][free_squares];
+                free_squares -= d.group_len[next];
+                next += 1;
+            }
+            k += 1;
+        }
+        d.group_idx[n] = idx;
+    }
+
+    fn get<'d>(&self, data: &'d TableData, stm: usize, f: usize) -> &'d PairsData {
+        let sides = match self.kind {
+            TableKind::Wdl => 2,
+            TableKind::Dtz => 1,
+        };
+        &data.items[stm % sides][if self.has_pawns { f } else { 0 }]
+    }
+
+    /// Looks up `b`, which has the material `key`, in the table. `wdl` is the result of `b`
+    /// when probing a DTZ table.
+    fn probe(&self, b: &Board, key: MaterialKey, wdl: Wdl) -> Option<Probe> {
+        let data = self.data()?;
+        let mut placement = [(0, 0); MAX_PIECES];
+        let occupied = b.occupancy(Player::White) | b.occupancy(Player::Black);
+        let mut size = 0;
+        for s in occupied {
+            placement[size] = (piece_code(b.get_piece(s)?), s as usize);
+            size += 1;
+        }
+        let black_to_move = b.turn() == Player::Black;
+        let (stm, tb_file, idx) = self.index(data, &placement[..size], black_to_move, key);
+
+        if self.kind == TableKind::Dtz {
+            let flags = self.get(data, stm, tb_file).flags;
+            if (flags & STM) as usize != stm && (self.key != self.key2 || self.has_pawns) {
+                return Some(Probe::ChangeStm);
+            }
+        }
+
+        let value = self.get(data, stm, tb_file).decompress(&data.file, idx);
+        Some(Probe::Value(match self.kind {
+            TableKind::Wdl => value - 2,
+            TableKind::Dtz => self.map_dtz(data, tb_file, value, wdl),
+        }))
+    }
+
+    /// The player to move and the file of the leading pawn as they're stored in the table, and
+    /// the index of the position with the pieces of `placement`, given by their piece code and
+    /// square in the order of their squares, which has the material `key`.
+    fn index(
+        &self,
+        data: &TableData,
+        placement: &[(u8, usize)],
+        black_to_move: bool,
+        key: MaterialKey,
+    ) -> (usize, usize, u64) {
+        let tables = index_tables();
+
+        // The tables are stored with white as the stronger player, and if both players have the
+        // same pieces only with white to move, so the colours are swapped and the board flipped
+        // to look up the other positions.
+        let flip = key != self.key || (self.key == self.key2 && black_to_move);
+        let flip_colour = if flip { 8 } else { 0 };
+        let flip_squares = if flip { 56 } else { 0 };
+        let stm = (flip ^ black_to_move) as usize;
+
+        let mut squares = [0; MAX_PIECES];
+        let mut pieces = [0; MAX_PIECES];
+        let mut size = 0;
+        let mut lead_pawn = None;
+        let mut tb_file = 0;
+
+        // Tables with pawns are split by the file of the leading pawn, which is the one nearest
+        // an edge and then nearest its own side of the board.
+        if self.has_pawns {
+            let code = self.get(data, 0, 0).pieces[0] ^ flip_colour;
+            lead_pawn = Some(code);
+            for &(_, s) in placement.iter().filter(|&&(c, _)| c == code) {
+                squares[size] = s ^ flip_squares;
+                size += 1;
+            }
+
+            let mut lead = 0;
+            for i in 1..size {
+                if tables.map_pawns[squares[i]] > tables.map_pawns[squares[lead]] {
+                    lead = i;
+                }
+            }
+            squares.swap(0, lead);
+
+            tb_file = squares[0] % 8;
+            if tb_file > 3 {
+                tb_file = 7 - tb_file;
+            }
+        }
+        let lead_pawn_count = size;
+
+        for &(code, s) in placement.iter().filter(|&&(c, _)| Some(c) != lead_pawn) {
+            squares[size] = s ^ flip_squares;
+            pieces[size] = code ^ flip_colour;
+            size += 1;
+        }
+
+        let d = self.get(data, stm, tb_file);
+
+        // The pieces are put in the order of the table's pieces.
+        for i in lead_pawn_count..size.saturating_sub(1) {
+            for j in i + 1..size {
+                if d.pieces[i] == pieces[j] {
+                    pieces.swap(i, j);
+                    squares.swap(i, j);
+                    break;
+                }
+            }
+        }
+
+        // The board is flipped so the leading piece is on the a to d files.
+        if squares[0] % 8 > 3 {
+            for s in squares.iter_mut().take(size) {
+                *s ^= 7;
+            }
+        }
+
+        let mut idx;
+        if self.has_pawns {
+            idx = tables.lead_pawn_idx[lead_pawn_count][squares[0]];
+            squares[1..lead_pawn_count].sort_by_key(|&s| tables.map_pawns[s]);
+            for (i, &s) in squares.iter().enumerate().take(lead_pawn_count).skip(1) {
+                idx += tables.binomial[i][tables.map_pawns[s]];
+            }
+        } else {
+            // Without pawns the board is also flipped so the leading piece is on the first four
+            // ranks and then so the first of the leading group off the a1-h8 diagonal is below
+            // it.
+            if squares[0] / 8 > 3 {
+                for s in squares.iter_mut().take(size) {
+                    *s ^= 56;
+                }
+            }
+            for i in 0..d.group_len[0] {
+                let off = off_diagonal(squares[i]);
+                if off == 0 {
+                    continue;
+                }
+                if off > 0 {
+                    for s in squares.iter_mut().take(size).skip(i) {
+                        *s = ((*s >> 3) | (*s << 3)) & 63;
+                    }
+                }
+                break;
+            }
+
+            idx = if self.has_unique_pieces {
+                encode_unique_pieces(&squares, tables)
+            } else {
+                tables.map_kk[tables.map_a1d1d4[squares[0]]][squares[1]]
+            };
+        }
+
+        idx *= d.group_idx[0];
+        let mut group_start = d.group_len[0];
+        let mut remaining_pawns = self.has_pawns && self.pawn_count[1] > 0;
+        let mut next = 1;
+        while d.group_len[next] != 0 {
+            let len = d.group_len[next];
+            squares[group_start..group_start + len].sort_unstable();
+            let mut n = 0;
+            for i in 0..len {
+                let s = squares[group_start + i];
+                // The squares of the earlier groups can't be used.
+                let adjust = squares[..group_start].iter().filter(|&&t| s > t).count();
+                let pawn_adjust = if remaining_pawns { 8 } else { 0 };
+                n += tables.binomial[i + 1][s - adjust - pawn_adjust];
+            }
+            remaining_pawns = false;
+            idx += n * d.group_idx[next];
+            group_start += len;
+            next += 1;
+        }
+
+        (stm, tb_file, idx)
+    }
+
+    /// Converts a value from a DTZ table to plies.
+    fn map_dtz(&self, data: &TableData, f: usize, value: i32, wdl: Wdl) -> i32 {
+        let d = self.get(data, 0, f);
+        let mut value = value;
+        if d.flags & MAPPED != 0 {
+            // The maps are stored for wins, losses, cursed wins then blessed losses.
+            let map = match wdl {
+                Wdl::Win | Wdl::Draw => 0,
+                Wdl::Loss => 1,
+                Wdl::CursedWin => 2,
+                Wdl::BlessedLoss => 3,
+            };
+            let start = d.map_idx[map];
+            value = if d.flags & WIDE != 0 {
+                data.file.u16_le(start + 2 * value as usize) as i32
+            } else {
+                data.file.byte(start + value as usize) as i32
+            };
+        }
+
+        let in_plies = match wdl {
+            Wdl::Win => d.flags & WIN_PLIES != 0,
+            Wdl::Loss => d.flags & LOSS_PLIES != 0,
+            _ => false,
+        };
+        if !in_plies {
+            value *= 2;
+        }
+        value + 1
+    }
+}
+
+/// Encodes the first three pieces when they're all different, with the first on the a1-d1-d4
+/// triangle and the others below the a1-h8 diagonal if the ones before them are on it.
+fn encode_unique_pieces(squares: &[usize], tables: &IndexTables) -> u64 {
+    let adjust1 = (squares[1] > squares[0]) as usize;
+    let adjust2 = (squares[2] > squares[0]) as usize + (squares[2] > squares[1]) as usize;
+    let rank = |s: usize| s / 8;
+
+    let idx = if off_diagonal(squares[0]) != 0 {
+        (tables.map_a1d1d4[squares[0]] * 63 + (squares[1] - adjust1)) * 62 + squares[2] - adjust2
+    } else if off_diagonal(squares[1]) != 0 {
+        (6 * 63 + rank(squares[0]) * 28 + tables.map_b1h1h7[squares[1]]) * 62 + squares[2] - adjust2
+    } else if off_diagonal(squares[2]) != 0 {
+        6 * 63 * 62
+            + 4 * 28 * 62
+            + rank(squares[0]) * 7 * 28
+            + (rank(squares[1]) - adjust1) * 28
+            + tables.map_b1h1h7[squares[2]]
+    } else {
+        6 * 63 * 62
+            + 4 * 28 * 62
+            + 4 * 7 * 28
+            + rank(squares[0]) * 7 * 6
+            + (rank(squares[1]) - adjust1) * 6
+            + (rank(squares[2]) - adjust2)
+    };
+    idx as u64
+}
+
+/// Positive above the a1-h8 diagonal, negative below it and 0 on it.
+fn off_diagonal(s: usize) -> i32 {
+    (s / 8) as i32 - (s % 8) as i32
+}
+
+/// The piece codes used in the tables.
+fn piece_code(p: Piece) -> u8 {
+    let code = match PieceKind::from(p) {
+        PieceKind::Pawn => 1,
+        PieceKind::Knight => 2,
+        PieceKind::Bishop => 3,
+        PieceKind::Rook => 4,
+        PieceKind::Queen => 5,
+        PieceKind::King => 6,
+    };
+    match Player::from(p) {
+        Player::White => code,
+        Player::Black => code + 8,
+    }
+}
+
+/// The bytes of a table file. Reads past the end are zeros, like the padding of a file that's
+/// mapped into memory.
+struct TableFile(Vec<u8>);
+
+impl TableFile {
+    fn byte(&self, i: usize) -> u8 {
+        self.0.get(i).copied().unwrap_or(0)
+    }
+
+    fn bytes<const N: usize>(&self, i: usize) -> [u8; N] {
+        std::array::from_fn(|j| self.byte(i + j))
+    }
+
+    fn u16_le(&self, i: usize) -> u16 {
+        u16::from_le_bytes(self.bytes(i))
+    }
+
+    fn u32_le(&self, i: usize) -> u32 {
+        u32::from_le_bytes(self.bytes(i))
+    }
+
+    fn u32_be(&self, i: usize) -> u32 {
+        u32::from_be_bytes(self.bytes(i))
+    }
+
+    fn u64_be(&self, i: usize) -> u64 {
+        u64::from_be_bytes(self.bytes(i))
+    }
+}
+
+/// A table of values compressed with recursive pairing into symbols, which are Huffman coded in
+/// blocks. The positions are all in the table in the order of their indexes, so the value of a
+/// position is found by finding its block and then decoding the symbols in it up to the
+/// position. The fields that say where things are in the file are offsets from its start.
+#[derive(Clone, Debug, Default)]
+struct PairsData {
+    flags: u8,
+    block_size: usize,
+    /// There's an entry in the sparse index for about every `span` positions.
+    span: u64,
+    num_blocks: usize,
+    max_sym_len: usize,
+    /// The value of every position when the table only has one.
+    min_sym_len: usize,
+    /// The lowest symbol with each code length.
+    lowest_sym: usize,
+    /// The pair of symbols each symbol stands for.
+    btree: usize,
+    /// The number of positions less one in each block.
+    block_lengths: usize,
+    block_lengths_size: usize,
+    sparse_index: usize,
+    sparse_index_size: usize,
+    data: usize,
+    /// The lowest code of each length, left aligned.
+    base64: Vec<u64>,
+    /// The number of values less one each symbol stands for.
+    symlen: Vec<u8>,
+    pieces: [u8; MAX_PIECES],
+    group_idx: [u64; MAX_PIECES + 1],
+    group_len: [usize; MAX_PIECES + 1],
+    /// The start of the DTZ value maps for wins, losses, cursed wins and blessed losses.
+    map_idx: [usize; 4],
+}
+
+impl PairsData {
+    /// Reads the sizes of the parts of the table from `offset` and returns the offset after them.
+    fn set_sizes(&mut self, file: &TableFile, mut offset: usize) -> usize {
+        self.flags = file.byte(offset);
+        offset += 1;
+        if self.flags & SINGLE_VALUE != 0 {
+            self.min_sym_len = file.byte(offset) as usize;
+            return offset + 1;
+        }
+
+        self.block_size = 1 << file.byte(offset);
+        self.span = 1 << file.byte(offset + 1);
+        self.sparse_index_size = self.table_size().div_ceil(self.span) as usize;
+        let padding = file.byte(offset + 2) as usize;
+        self.num_blocks = file.u32_le(offset + 3) as usize;
+        // The padding keeps the sparse index from pointing past the end.
+        self.block_lengths_size = self.num_blocks + padding;
+        self.max_sym_len = file.byte(offset + 7) as usize;
+        self.min_sym_len = file.byte(offset + 8) as usize;
+        offset += 9;
+        self.lowest_sym = offset;
+
+        // Longer codes have lower values, so the lowest code of each length is worked out from
+        // the lowest code of the next length.
+        let lengths = (self.max_sym_len + 1).saturating_sub(self.min_sym_len);
+        self.base64 = vec![0; lengths];
+        for i in (0..lengths.saturating_sub(1)).rev() {
+            let lowest = file.u16_le(self.lowest_sym + 2 * i) as u64;
+            let next_lowest = file.u16_le(self.lowest_sym + 2 * (i + 1)) as u64;
+            self.base64[i] = self.base64[i + 1]
+                .wrapping_add(lowest)
+                .wrapping_sub(next_lowest)
+                / 2;
+        }
+        for (i, base) in self.base64.iter_mut().enumerate() {
+            *base = base
+                .checked_shl((64 - i - self.min_sym_len) as u32)
+                .unwrap_or(0);
+        }
+        offset += lengths * 2;
+
+        let symbols = file.u16_le(offset) as usize;
+        offset += 2;
+        self.btree = offset;
+        self.symlen = vec![0; symbols];
+        let mut visited = vec![false; symbols];
+        for sym in 0..symbols {
+            if !visited[sym] {
+                self.symlen[sym] = self.set_symlen(file, sym, &mut visited);
+            }
+        }
+
+        offset + symbols * 3 + (symbols & 1)
+    }
+
+    /// The number of indexes, which is the size of the groups of pieces multiplied together.
+    fn table_size(&self) -> u64 {
+        let last_group = self
+            .group_len
+            .iter()
+            .position(|&l| l == 0)
+            .unwrap_or(MAX_PIECES);
+        self.group_idx[last_group]
+    }
+
+    fn set_symlen(&mut self, file: &TableFile, sym: usize, visited: &mut [bool]) -> u8 {
+        visited[sym] = true;
+        let right = self.right(file, sym);
+        if right == 0xFFF {
+            return 0;
+        }
+        let left = self.left(file, sym);
+        for s in [left, right] {
+            if s < visited.len() && !visited[s] {
+                self.symlen[s] = self.set_symlen(file, s, visited);
+            }
+        }
+        let len = |s: usize| self.symlen.get(s).copied().unwrap_or(0);
+        len(left).wrapping_add(len(right)).wrapping_add(1)
+    }
+
+    fn left(&self, file: &TableFile, sym: usize) -> usize {
+        let [b0, b1, _] = file.bytes::<3>(self.btree + 3 * sym);
+        ((b1 as usize & 0xF) << 8) | b0 as usize
+    }
+
+    fn right(&self, file: &TableFile, sym: usize) -> usize {
+        let [_, b1, b2] = file.bytes::<3>(self.btree + 3 * sym);
+        ((b2 as usize) << 4) | (b1 as usize >> 4)
+    }
+
+    /// The value of the position with index `idx`.
+    fn decompress(&self, file: &TableFile, idx: u64) -> i32 {
+        if self.flags & SINGLE_VALUE != 0 {
+            return self.min_sym_len as i32;
+        }
+
+        let entry = self.sparse_index + 6 * (idx / self.span) as usize;
+        let mut block = file.u32_le(entry) as usize;
+        let mut offset = file.u16_le(entry + 4) as i64;
+        offset += (idx % self.span) as i64 - (self.span / 2) as i64;
+
+        let block_length = |block: usize| file.u16_le(self.block_lengths + 2 * block) as i64;
+        while offset < 0 {
+            block = block.wrapping_sub(1);
+            offset += block_length(block) + 1;
+        }
+        while offset > block_length(block) {
+            offset -= block_length(block) + 1;
+            block += 1;
+        }
+
+        let mut ptr = self.data + block * self.block_size;
+        let mut buf64 = file.u64_be(ptr);
+        ptr += 8;
+        let mut buf64_size = 64;
+        let symlen = |sym: usize| self.symlen.get(sym).copied().unwrap_or(0) as i64;
+
+        let mut sym;
+        loop {
+            let mut len = 0;
+            while len + 1 < self.base64.len() && buf64 < self.base64[len] {
+                len += 1;
+            }
+            sym = ((buf64 - self.base64[len])
+                .checked_shr((64 - len - self.min_sym_len) as u32)
+                .unwrap_or(0)) as usize;
+            sym += file.u16_le(self.lowest_sym + 2 * len) as usize;
+            if offset < symlen(sym) + 1 {
+                break;
+            }
+            offset -= symlen(sym) + 1;
+            len += self.min_sym_len;
+            buf64 = buf64.checked_shl(len as u32).unwrap_or(0);
+            buf64_size -= len as i32;
+            if buf64_size <= 32 {
+                buf64_size += 32;
+                buf64 |= (file.u32_be(ptr) as u64) << (64 - buf64_size);
+                ptr += 4;
+            }
+        }
+
+        // Expand the symbol down to the value.
+        while symlen(sym) != 0 {
+            let left = self.left(file, sym);
+            if offset < symlen(left) + 1 {
+                sym = left;
+            } else {
+                offset -= symlen(left) + 1;
+                sym = self.right(file, sym);
+            }
+        }
+        self.left(file, sym) as i32
+    }
+}
+
+/// The tables used to turn positions into indexes.
+struct IndexTables {
+    /// Squares below the a1-h8 diagonal to 0 to 27.
+    map_b1h1h7: [usize; 64],
+    /// Squares in the a1-d1-d4 triangle to 0 to 9, with the diagonal last.
+    map_a1d1d4: [usize; 64],
+    /// The 462 legal placements of two kings with the first in the a1-d1-d4 triangle, indexed
+    /// by `map_a1d1d4` of the first and the square of the second.
+    map_kk: [[u64; 64]; 10],
+    /// `binomial[k][n]` is the number of ways to choose k things from n.
+    binomial: [[u64; 64]; MAX_PIECES],
+    /// Squares a2 to h7 to 0 to 47, with higher numbers nearer the edges and the second rank.
+    map_pawns: [usize; 64],
+    lead_pawn_idx: [[u64; 64]; MAX_PIECES],
+    /// The number of placements of the leading pawns with the leading one on each file.
+    lead_pawns_size: [[u64; 4]; MAX_PIECES],
+}
+
+fn index_tables() -> &'static IndexTables {
+    static TABLES: OnceLock<IndexTables> = OnceLock::new();
+    TABLES.get_or_init(IndexTables::generate)
+}
+
+impl IndexTables {
+    fn generate() -> Self {
+        let mut t = IndexTables {
+            map_b1h1h7: [0; 64],
+            map_a1d1d4: [0; 64],
+            map_kk: [[0; 64]; 10],
+            binomial: [[0; 64]; MAX_PIECES],
+            map_pawns: [0; 64],
+            lead_pawn_idx: [[0; 64]; MAX_PIECES],
+            lead_pawns_size: [[0; 4]; MAX_PIECES],
+        };
+
+        let mut code = 0;
+        for s in 0..64 {
+            if off_diagonal(s) < 0 {
+                t.map_b1h1h7[s] = code;
+                code += 1;
+            }
+        }
+
+        let mut code = 0;
+        let mut diagonal = Vec::new();
+        for s in 0..=27 {
+            if s % 8 > 3 {
+                continue;
+            }
+            if off_diagonal(s) < 0 {
+                t.map_a1d1d4[s] = code;
+                code += 1;
+            } else if off_diagonal(s) == 0 {
+                diagonal.push(s);
+            }
+        }
+        for s in diagonal {
+            t.map_a1d1d4[s] = code;
+            code += 1;
+        }
+
+        // Placements with both kings on the diagonal come last.
+        let mut code = 0;
+        let mut both_on_diagonal = Vec::new();
+        for idx in 0..10 {
+            for s1 in 0..=27 {
+                let in_triangle = s1 % 8 <= 3 && off_diagonal(s1) <= 0;
+                if !in_triangle || t.map_a1d1d4[s1] != idx {
+                    continue;
+                }
+                for s2 in 0..64 {
+                    let adjacent = (s1 % 8).abs_diff(s2 % 8) <= 1 && (s1 / 8).abs_diff(s2 / 8) <= 1;
+                    if adjacent || (off_diagonal(s1) == 0 && off_diagonal(s2) > 0) {
+                        continue;
+                    }
+                    if off_diagonal(s1) == 0 && off_diagonal(s2) == 0 {
+                        both_on_diagonal.push((idx, s2));
+                    } else {
+                        t.map_kk[idx][s2] = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+        for (idx, s2) in both_on_diagonal {
+            t.map_kk[idx][s2] = code;
+            code += 1;
+        }
+
+        t.binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..MAX_PIECES.min(n + 1) {
+                t.binomial[k][n] = if k > 0 { t.binomial[k - 1][n - 1] } else { 0 }
+                    + if k < n { t.binomial[k][n - 1] } else { 0 };
+            }
+        }
+
+        // The leading pawn is the one with the highest number, and any other pawn can only be on
+        // the squares with lower numbers, which there are 47 of when it's on a2.
+        let mut available_squares = 48;
+        for lead_pawn_count in 1..=5 {
+            for f in 0..4 {
+                let mut idx = 0;
+                for r in 1..7 {
+                    let s = r * 8 + f;
+                    if lead_pawn_count == 1 {
+                        available_squares -= 2;
+                        t.map_pawns[s] = available_squares + 1;
+                        t.map_pawns[s ^ 7] = available_squares;
+                    }
+                    t.lead_pawn_idx[lead_pawn_count][s] = idx;
+                    idx += t.binomial[lead_pawn_count - 1][t.map_pawns[s]];
+                }
+                t.lead_pawns_size[lead_pawn_count][f] = idx;
+            }
+        }
+
+        t
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::SQUARES;
+    use crate::dtm::{Dtm, DtmTables, Signature};
+    use crate::lookup_tables::LookupTables;
+
+    #[test]
+    fn index_tables_test() {
+        let t = index_tables();
+        let kk_codes: Vec<u64> = (0..10)
+            .flat_map(|idx| (0..64).map(move |s| (idx, s)))
+            .map(|(idx, s)| t.map_kk[idx][s])
+            .collect();
+        assert_eq!(kk_codes.iter().max(), Some(&461));
+
+        assert_eq!(t.map_a1d1d4[1], 0);
+        assert_eq!(t.map_a1d1d4[0], 6);
+        assert_eq!(t.map_a1d1d4[27], 9);
+        assert_eq!(t.map_b1h1h7[63 - 8], 27);
+        assert_eq!(t.binomial[2][62], 62 * 61 / 2);
+        assert_eq!(t.binomial[5][10], 252);
+        assert_eq!(t.map_pawns[8], 47);
+        assert_eq!(t.map_pawns[15], 46);
+        // The placements of a single pawn, on the second to seventh ranks of a file and its
+        // mirror.
+        assert_eq!(t.lead_pawns_size[1].iter().sum::<u64>(), 24);
+    }
+
+    #[test]
+    fn parse_table_name_test() {
+        let (key, key2) = parse_table_name("KRvKP").unwrap();
+        assert_eq!(key[Piece::WhiteKing as usize], 1);
+        assert_eq!(key[Piece::WhiteRook as usize], 1);
+        assert_eq!(key[Piece::BlackPawn as usize], 1);
+        assert_eq!(key2[Piece::BlackRook as usize], 1);
+        assert_eq!(key2[Piece::WhitePawn as usize], 1);
+
+        let (key, key2) = parse_table_name("KNvKN").unwrap();
+        assert_eq!(key, key2);
+
+        assert_eq!(parse_table_name("KRK"), None);
+        assert_eq!(parse_table_name("RvK"), None);
+        assert_eq!(parse_table_name("KXvK"), None);
+        assert_eq!(parse_table_name("KQQQQvKQQ"), None);
+    }
+
+    #[test]
+    fn open_test() {
+        let l = LookupTables::generate();
+        assert!(Tablebases::open("does/not/exist").is_err());
+
+        let dir = env::temp_dir().join(format!("chess_rs_syzygy_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // A table that can't be read is treated as missing.
+        fs::write(dir.join("KRvK.rtbw"), b"not a table").unwrap();
+        fs::write(dir.join("notes.txt"), b"").unwrap();
+        let tablebases = Tablebases::open(dir.to_str().unwrap()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(tablebases.table_count(), 1);
+        assert_eq!(tablebases.max_pieces(), 3);
+
+        let mut b = Board::from_fen("8/8/8/4k3/8/8/8/R3K3 w - - 0 1", &l).unwrap();
+        assert_eq!(tablebases.probe_wdl(&mut b), None);
+        assert_eq!(tablebases.root_moves(&mut b), None);
+
+        // Two kings don't need a table.
+        let mut b = Board::from_fen("8/8/8/4k3/8/8/8/4K3 w - - 0 1", &l).unwrap();
+        assert_eq!(tablebases.probe_wdl(&mut b), Some(Wdl::Draw));
+        assert_eq!(tablebases.probe_dtz(&mut b), Some(0));
+
+        let legal_moves = b.generate_moves(Legality::Legal);
+        assert_eq!(tablebases.root_moves(&mut b), Some(legal_moves));
+        assert_eq!(b.fen(), "8/8/8/4k3/8/8/8/4K3 w - - 0 1");
+    }
+
+    /// The tables in `SYZYGY_PATH`, which needs the 3 and 4 piece tables from the Syzygy
+    /// generator. The tests that use them are ignored, so run them with
+    /// `SYZYGY_PATH=<dir> cargo test --release syzygy -- --ignored`.
+    fn open_tablebases() -> Tablebases {
+        let path = env::var("SYZYGY_PATH").expect("SYZYGY_PATH isn't set");
+        let tablebases = Tablebases::open(&path).unwrap();
+        assert!(tablebases.max_pieces() >= 4);
+        tablebases
+    }
+
+    #[test]
+    #[ignore]
+    fn probe_test() {
+        let l = LookupTables::generate();
+        let tablebases = open_tablebases();
+
+        let probe = |fen: &str| {
+            let mut b = Board::from_fen(fen, &l).unwrap();
+            (tablebases.probe_wdl(&mut b), tablebases.probe_dtz(&mut b))
+        };
+        assert_eq!(probe("8/8/8/8/8/2k5/8/KR6 w - - 0 1").0, Some(Wdl::Win));
+        assert_eq!(probe("8/8/8/8/8/2k5/8/KR6 b - - 0 1").0, Some(Wdl::Loss));
+        // Black takes the rook.
+        assert_eq!(
+            probe("8/8/8/8/8/8/2k5/K2R4 b - - 0 1"),
+            (Some(Wdl::Draw), Some(0))
+        );
+        // Mate in one.
+        assert_eq!(
+            probe("k7/8/1K6/8/8/8/8/7R w - - 0 1"),
+            (Some(Wdl::Win), Some(1))
+        );
+        assert_eq!(probe("8/8/8/8/3k4/8/3P4/3K4 w - - 0 1").0, Some(Wdl::Draw));
+        assert_eq!(probe("8/8/8/8/8/3k4/3P4/3K4 b - - 0 1").0, Some(Wdl::Draw));
+        assert_eq!(probe("8/8/8/8/3K4/8/3P4/6k1 w - - 0 1").0, Some(Wdl::Win));
+        assert_eq!(probe("8/8/8/8/8/8/8/R3K2k w - - 0 1").0, Some(Wdl::Win));
+        // Black takes the queen and wins with the rook, which is in the KRvK tables with the
+        // colours swapped.
+        assert_eq!(
+            probe("K7/8/8/8/8/8/8/3Q2rk b - - 0 1"),
+            (Some(Wdl::Win), Some(1))
+        );
+        // A knight or bishop can't win.
+        assert_eq!(
+            probe("8/8/8/8/3k4/8/8/2BK4 w - - 0 1"),
+            (Some(Wdl::Draw), Some(0))
+        );
+
+        let mut b = Board::from_fen("k7/8/1K6/8/8/8/8/7R w - - 0 1", &l).unwrap();
+        assert_eq!(
+            tablebases.root_moves(&mut b),
+            Some(vec![Move::try_from("h1h8").unwrap()])
+        );
+        // Promoting to a rook or queen wins straight away.
+        let mut b = Board::from_fen("8/3P4/8/8/8/8/k7/3K4 w - - 0 1", &l).unwrap();
+        assert_eq!(
+            tablebases.root_moves(&mut b),
+            Some(vec![
+                Move::try_from("d7d8q").unwrap(),
+                Move::try_from("d7d8r").unwrap()
+            ])
+        );
+    }
+
+    /// Compares the tables with the DTM tables, which are generated separately. The distance to
+    /// zeroing of KQvK and KRvK is the distance to mate, but tables that store it in moves rather
+    /// than plies can be one ply over.
+    #[test]
+    #[ignore]
+    fn dtm_test() {
+        let l = LookupTables::generate();
+        let tablebases = open_tablebases();
+        let mut tables = DtmTables::default();
+        for signature in ["KPvK", "KQvKR"] {
+            tables
+                .generate(&Signature::try_from(signature).unwrap(), &l, &mut |_| {})
+                .unwrap();
+        }
+
+        let placements = SQUARES
+            .iter()
+            .flat_map(|&wk| SQUARES.iter().map(move |&bk| (wk, bk)))
+            .flat_map(|(wk, bk)| SQUARES.iter().map(move |&s| (wk, bk, s)));
+        for (i, (wk, bk, s)) in placements.enumerate() {
+            for p in [Piece::WhiteQueen, Piece::WhiteRook, Piece::WhitePawn] {
+                for turn in [Player::White, Player::Black] {
+                    let pieces = [(Piece::WhiteKing, wk), (Piece::BlackKing, bk), (p, s)];
+                    let Ok(mut b) = Board::from_pieces(&pieces, turn, &l) else {
+                        continue;
+                    };
+                    let dtm = tables.probe(&b).unwrap();
+                    assert_eq!(tablebases.probe_wdl(&mut b), Some(wdl(dtm)), "{}", b.fen());
+
+                    let dtz = tablebases.probe_dtz(&mut b).unwrap();
+                    let expected = match dtm {
+                        Dtm::Win(plies) => plies as i32,
+                        Dtm::Loss(plies) => -(plies.max(1) as i32),
+                        Dtm::Draw => 0,
+                    };
+                    if p == Piece::WhitePawn {
+                        assert_eq!(dtz.signum(), expected.signum(), "{}", b.fen());
+                    } else {
+                        assert!(
+                            dtz == expected || dtz == expected + expected.signum(),
+                            "{}: {} {}",
+                            b.fen(),
+                            dtz,
+                            expected
+                        );
+                    }
+                }
+            }
+
+            // A sample of KQvKR, with the rook on each square.
+            if i % 7 != 0 {
+                continue;
+            }
+            for r in SQUARES {
+                for turn in [Player::White, Player::Black] {
+                    let pieces = [
+                        (Piece::WhiteKing, wk),
+                        (Piece::BlackKing, bk),
+                        (Piece::WhiteQueen, s),
+                        (Piece::BlackRook, r),
+                    ];
+                    let Ok(mut b) = Board::from_pieces(&pieces, turn, &l) else {
+                        continue;
+                    };
+                    let dtm = tables.probe(&b).unwrap();
+                    assert_eq!(tablebases.probe_wdl(&mut b), Some(wdl(dtm)), "{}", b.fen());
+                    let dtz = tablebases.probe_dtz(&mut b).unwrap();
+                    assert_eq!(dtz.signum(), (wdl(dtm) as i32).signum(), "{}", b.fen());
+                }
+            }
+        }
+    }
+
+    fn wdl(dtm: Dtm) -> Wdl {
+        match dtm {
+            Dtm::Win(_) => Wdl::Win,
+            Dtm::Loss(_) => Wdl::Loss,
+            Dtm::Draw => Wdl::Draw,
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use std::time::{Duration, Instant};
+
+use crate::board::Board;
+use crate::core::Move;
+use crate::epd::{self, Epd};
+use crate::lookup_tables::LookupTables;
+use crate::search::{self, SearchLimits, SearchOptions};
+use crate::tt::TranspositionTable;
+
+/// A position of a test suite like WAC or STS, read from a line of an EPD file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TestPosition {
+    pub fen: String,
+    /// The `id` of the position, or its line number if it doesn't have one.
+    pub id: String,
+    /// The moves of the `bm` opcode, any of which solves the position.
+    pub best_moves: Vec<Move>,
+    /// The moves of the `am` opcode, which mustn't be played.
+    pub avoid_moves: Vec<Move>,
+    /// The `c0` comment.
+    pub comment: Option<String>,
+}
+
+impl TestPosition {
+    /// Reads a line like `<position> bm Qg6; id "WAC.001";`, where the moves of `bm` and `am`
+    /// are in SAN.
+    pub fn parse(line: &str, l: &LookupTables) -> Result<Self, String> {
+        let epd = Epd::try_from(line)?;
+        let b = Board::from_epd(&epd, l).map_err(|e| format!("invalid position: {}", e))?;
+        let best_moves = epd.moves("bm", &b)?;
+        let avoid_moves = epd.moves("am", &b)?;
+        if best_moves.is_empty() && avoid_moves.is_empty() {
+            return Err("expected a bm or am opcode".to_string());
+        }
+
+        Ok(TestPosition {
+            fen: b.fen(),
+            id: epd.operand("id").unwrap_or_default().to_string(),
+            best_moves,
+            avoid_moves,
+            comment: epd.operand("c0").map(|c| c.to_string()),
+        })
+    }
+
+    pub fn is_solved_by(&self, m: Move) -> bool {
+        (self.best_moves.is_empty() || self.best_moves.contains(&m))
+            && !self.avoid_moves.contains(&m)
+    }
+}
+
+/// Reads the positions of an EPD file, skipping blank lines and lines starting with `#`.
+pub fn load(path: impl AsRef<Path>, l: &LookupTables) -> Result<Vec<TestPosition>, String> {
+    let path = path.as_ref();
+    let contents =
+        fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+
+    let mut positions = Vec::new();
+    for (line_number, line) in epd::lines(&contents) {
+        let mut position = TestPosition::parse(line, l)
+            .map_err(|e| format!("{} line {}: {}", path.display(), line_number, e))?;
+        if position.id.is_empty() {
+            position.id = line_number.to_string();
+        }
+        positions.push(position);
+    }
+    Ok(positions)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TestResult {
+    /// The move the search settled on.
+    pub best_move: Option<Move>,
+    pub solved: bool,
+    /// When the search found the answer it kept until the end, if it did.
+    pub found_after: Option<Duration>,
+    pub time: Duration,
+    pub depth: u8,
+    pub nodes: u64,
+}
+
+/// Searches each of `positions` with `limits`, starting with an empty transposition table, and
+/// calls `on_result` with each result as it's found.
+pub fn run(
+    positions: &[TestPosition],
+    limits: &SearchLimits,
+    options: &SearchOptions,
+    tt: &TranspositionTable,
+    l: &LookupTables,
+    on_result: &mut dyn FnMut(&TestPosition, &TestResult),
+) -> Result<Vec<TestResult>, String> {
+    let mut results = Vec::with_capacity(positions.len());
+    for position in positions {
+        let b = Board::from_fen(&position.fen, l).map_err(|e| format!("invalid fen: {}", e))?;
+        tt.clear();
+
+        let start = Instant::now();
+        let mut found_after = None;
+        let stop = AtomicBool::new(false);
+        let result = search::search(&b, tt, limits, options, &stop, &mut |info| match info
+            .pv
+            .first()
+        {
+            Some(m) if position.is_solved_by(*m) => {
+                found_after.get_or_insert(info.time);
+            }
+            _ => found_after = None,
+        });
+
+        let solved = result.best_move.is_some_and(|m| position.is_solved_by(m));
+        let result = TestResult {
+            best_move: result.best_move,
+            solved,
+            found_after: if solved { found_after } else { None },
+            time: start.elapsed(),
+            depth: result.depth,
+            nodes: result.nodes,
+        };
+        on_result(position, &result);
+        results.push(result);
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_test() {
+        let l = LookupTables::generate();
+        let position = TestPosition::parse(
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id \"WAC.001\"; \
+            c0 \"mate; in 3\";",
+            &l,
+        )
+        .unwrap();
+        assert_eq!(position.id, "WAC.001");
+        assert_eq!(position.best_moves, [Move::try_from("g3g6").unwrap()]);
+        assert_eq!(position.comment.as_deref(), Some("mate; in 3"));
+        assert_eq!(
+            position.fen,
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1"
+        );
+        assert!(position.is_solved_by(Move::try_from("g3g6").unwrap()));
+        assert!(!position.is_solved_by(Move::try_from("g3g4").unwrap()));
+
+        let position = TestPosition::parse(
+            "8/8/8/4k3/8/8/8/4K2R w K - am Kd1 Rh5+; hmvc 7; fmvn 30;",
+            &l,
+        )
+        .unwrap();
+        assert_eq!(position.fen, "8/8/8/4k3/8/8/8/4K2R w K - 7 30");
+        assert_eq!(position.avoid_moves.len(), 2);
+        assert!(position.is_solved_by(Move::try_from("e1g1").unwrap()));
+        assert!(!position.is_solved_by(Move::try_from("h1h5").unwrap()));
+
+        assert!(TestPosition::parse("8/8/8/4k3/8/8/8/4K2R w K - id \"x\";", &l).is_err());
+        assert!(TestPosition::parse("8/8/8/4k3/8/8/8/4K2R w K - bm Ra1;", &l).is_err());
+        assert!(TestPosition::parse("8/8/8/4k3/8/8/8/4K2R w K - bm Rh5", &l).is_err());
+    }
+
+    #[test]
+    fn run_test() {
+        let l = LookupTables::generate();
+        let positions = [
+            // Mate in one.
+            TestPosition::parse("6k1/5ppp/8/8/8/8/8/R5K1 w - - bm Ra8#; id \"mate\";", &l).unwrap(),
+            // Stepping away from the queen instead of taking it.
+            TestPosition::parse("4k3/8/8/8/8/8/3q4/4K3 w - - am Kf1; id \"avoid\";", &l).unwrap(),
+        ];
+        let limits = SearchLimits {
+            depth: Some(3),
+            ..SearchLimits::default()
+        };
+        let tt = TranspositionTable::new(1);
+        let mut ids = Vec::new();
+        let results = run(
+            &positions,
+            &limits,
+            &SearchOptions::default(),
+            &tt,
+            &l,
+            &mut |position, _| ids.push(position.id.clone()),
+        )
+        .unwrap();
+        assert_eq!(ids, ["mate", "avoid"]);
+        assert!(results[0].solved);
+        assert!(results[0].found_after.is_some());
+        assert_eq!(results[0].best_move, Some(Move::try_from("a1a8").unwrap()));
+        assert!(results[1].solved);
+    }
+}
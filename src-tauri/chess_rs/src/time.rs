@@ -0,0 +1,226 @@
+use std::time::Duration;
+
+use crate::core::Move;
+
+/// How many moves the remaining time is assumed to cover when the time control doesn't say.
+const DEFAULT_MOVES_TO_GO: u32 = 40;
+
+/// The most of the remaining time a single move may use.
+const MAX_TIME_FRACTION: f64 = 0.8;
+
+/// How far past the soft limit a move may go before the search is interrupted.
+const HARD_LIMIT_FACTOR: u32 = 5;
+
+/// The time left on the player to move's clock.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Clock {
+    pub time: Duration,
+    pub increment: Duration,
+    /// The number of moves until the next time control, if it has one.
+    pub moves_to_go: Option<u32>,
+}
+
+/// Decides how long to search a move for. The soft limit is checked between iterations and
+/// stretched when the search is unsure of its move, the hard limit interrupts the search.
+#[derive(Clone, Debug)]
+pub struct TimeManager {
+    soft_limit: Duration,
+    hard_limit: Duration,
+    /// A fixed time per move is used up rather than stopping between iterations.
+    fixed: bool,
+    previous_best_move: Option<Move>,
+    previous_score: Option<i32>,
+    /// Decays each iteration so recent changes count for more.
+    best_move_changes: f64,
+}
+
+impl TimeManager {
+    /// `move_overhead` is kept back from every move for the time lost communicating with the GUI.
+    pub fn new(clock: &Clock, move_overhead: Duration) -> Self {
+        let available = clock.time.saturating_sub(move_overhead);
+        let moves_to_go = clock
+            .moves_to_go
+            .unwrap_or(DEFAULT_MOVES_TO_GO)
+            .clamp(1, DEFAULT_MOVES_TO_GO);
+
+        let max_time = available.mul_f64(MAX_TIME_FRACTION);
+        let base = available / moves_to_go + clock.increment * 3 / 4;
+        let soft_limit = base.min(max_time);
+        let hard_limit = (soft_limit * HARD_LIMIT_FACTOR).min(max_time);
+
+        TimeManager {
+            soft_limit,
+            hard_limit,
+            fixed: false,
+            previous_best_move: None,
+            previous_score: None,
+            best_move_changes: 0.0,
+        }
+    }
+
+    /// A fixed time per move, which is never extended.
+    pub fn fixed(movetime: Duration, move_overhead: Duration) -> Self {
+        let limit = movetime.saturating_sub(move_overhead);
+        TimeManager {
+            soft_limit: limit,
+            hard_limit: limit,
+            fixed: true,
+            previous_best_move: None,
+            previous_score: None,
+            best_move_changes: 0.0,
+        }
+    }
+
+    pub fn soft_limit(&self) -> Duration {
+        self.soft_limit
+    }
+
+    pub fn hard_limit(&self) -> Duration {
+        self.hard_limit
+    }
+
+    /// Called after each completed iteration. Returns whether there's time for another.
+    pub fn should_continue(
+        &mut self,
+        elapsed: Duration,
+        best_move: Option<Move>,
+        score: i32,
+    ) -> bool {
+        if self.fixed {
+            return elapsed < self.hard_limit;
+        }
+
+        self.best_move_changes /= 2.0;
+        if self.previous_best_move.is_some() && self.previous_best_move != best_move {
+            self.best_move_changes += 1.0;
+        }
+        self.previous_best_move = best_move;
+
+        // A falling score means the search found a problem it should spend time solving.
+        let score_drop = self
+            .previous_score
+            .map_or(0, |previous| (previous - score).clamp(0, 100));
+        self.previous_score = Some(score);
+
+        let instability = 1.0 + self.best_move_changes;
+        let scale = (instability * (1.0 + score_drop as f64 / 100.0)).min(HARD_LIMIT_FACTOR as f64);
+
+        // The next iteration would take longer than all of the previous ones, so it isn't started
+        // if it would be unlikely to finish.
+        elapsed < self.soft_limit.mul_f64(scale / 2.0).min(self.hard_limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Square;
+
+    #[test]
+    fn limits_test() {
+        struct TestCase {
+            name: &'static str,
+            clock: Clock,
+            expected_soft_limit: Duration,
+            expected_hard_limit: Duration,
+        }
+
+        let test_cases = vec![
+            TestCase {
+                name: "sudden death",
+                clock: Clock {
+                    time: Duration::from_millis(60_010),
+                    increment: Duration::ZERO,
+                    moves_to_go: None,
+                },
+                expected_soft_limit: Duration::from_millis(1500),
+                expected_hard_limit: Duration::from_millis(7500),
+            },
+            TestCase {
+                name: "increment",
+                clock: Clock {
+                    time: Duration::from_millis(60_010),
+                    increment: Duration::from_millis(1000),
+                    moves_to_go: None,
+                },
+                expected_soft_limit: Duration::from_millis(2250),
+                expected_hard_limit: Duration::from_millis(11_250),
+            },
+            TestCase {
+                name: "last move before the time control",
+                clock: Clock {
+                    time: Duration::from_millis(10_010),
+                    increment: Duration::ZERO,
+                    moves_to_go: Some(1),
+                },
+                expected_soft_limit: Duration::from_millis(8000),
+                expected_hard_limit: Duration::from_millis(8000),
+            },
+            TestCase {
+                name: "less time than the overhead",
+                clock: Clock {
+                    time: Duration::from_millis(5),
+                    increment: Duration::ZERO,
+                    moves_to_go: None,
+                },
+                expected_soft_limit: Duration::ZERO,
+                expected_hard_limit: Duration::ZERO,
+            },
+        ];
+
+        for test_case in test_cases {
+            let tm = TimeManager::new(&test_case.clock, Duration::from_millis(10));
+            assert_eq!(
+                tm.soft_limit(),
+                test_case.expected_soft_limit,
+                "{} failed",
+                test_case.name
+            );
+            assert_eq!(
+                tm.hard_limit(),
+                test_case.expected_hard_limit,
+                "{} failed",
+                test_case.name
+            );
+        }
+
+        let tm = TimeManager::fixed(Duration::from_millis(1000), Duration::from_millis(10));
+        assert_eq!(tm.soft_limit(), Duration::from_millis(990));
+        assert_eq!(tm.hard_limit(), Duration::from_millis(990));
+    }
+
+    #[test]
+    fn should_continue_test() {
+        let clock = Clock {
+            time: Duration::from_secs(100),
+            increment: Duration::ZERO,
+            moves_to_go: None,
+        };
+        let e4 = Some(Move(Square::E2, Square::E4, None));
+        let d4 = Some(Move(Square::D2, Square::D4, None));
+        // Stable iterations stop at half the soft limit.
+        let elapsed = Duration::from_millis(1300);
+
+        let mut tm = TimeManager::new(&clock, Duration::ZERO);
+        assert!(tm.should_continue(Duration::from_millis(100), e4, 20));
+        assert!(!tm.should_continue(elapsed, e4, 20));
+
+        // The best move changing buys more time.
+        let mut tm = TimeManager::new(&clock, Duration::ZERO);
+        assert!(tm.should_continue(Duration::from_millis(100), e4, 20));
+        assert!(tm.should_continue(elapsed, d4, 20));
+
+        // So does the score dropping.
+        let mut tm = TimeManager::new(&clock, Duration::ZERO);
+        assert!(tm.should_continue(Duration::from_millis(100), e4, 20));
+        assert!(tm.should_continue(elapsed, e4, -80));
+
+        // But never past the hard limit.
+        let mut tm = TimeManager::new(&clock, Duration::ZERO);
+        for i in 0..10 {
+            let best_move = if i % 2 == 0 { e4 } else { d4 };
+            tm.should_continue(Duration::from_millis(100), best_move, 20 - i * 100);
+        }
+        assert!(!tm.should_continue(tm.hard_limit(), e4, -2000));
+    }
+}
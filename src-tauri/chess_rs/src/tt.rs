@@ -0,0 +1,384 @@
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+use crate::core::{Move, PieceKind, SQUARES};
+use crate::search::MATE_BOUND;
+
+pub const DEFAULT_HASH_MB: usize = 16;
+pub const MAX_HASH_MB: usize = 65536;
+
+const ENTRIES_PER_BUCKET: usize = 4;
+// Generations wrap around after this many searches.
+const GENERATION_CYCLE: u8 = 64;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(u8)]
+pub enum Bound {
+    /// The score is exact.
+    Exact = 1,
+    /// The search failed high so the score is a lower bound.
+    Lower = 2,
+    /// The search failed low so the score is an upper bound.
+    Upper = 3,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TtEntry {
+    pub best_move: Option<Move>,
+    pub score: i32,
+    pub eval: i32,
+    pub depth: u8,
+    pub bound: Bound,
+}
+
+/// An entry is stored as the key xored with the data next to the data. Threads can read and
+/// write entries without locking because an entry that was torn by a concurrent write no longer
+/// matches its key and is ignored.
+#[derive(Default)]
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+#[derive(Default)]
+#[repr(align(64))]
+struct Bucket {
+    slots: [Slot; ENTRIES_PER_BUCKET],
+}
+
+/// A transposition table shared between search threads.
+pub struct TranspositionTable {
+    buckets: Vec<Bucket>,
+    generation: AtomicU8,
+}
+
+impl TranspositionTable {
+    pub fn new(mb: usize) -> Self {
+        let mb = mb.clamp(1, MAX_HASH_MB);
+        let num_buckets = mb * 1024 * 1024 / std::mem::size_of::<Bucket>();
+
+        Self {
+            buckets: (0..num_buckets).map(|_| Bucket::default()).collect(),
+            generation: AtomicU8::new(0),
+        }
+    }
+
+    /// Resizes the table, which also clears it.
+    pub fn resize(&mut self, mb: usize) {
+        *self = TranspositionTable::new(mb);
+    }
+
+    pub fn size_mb(&self) -> usize {
+        self.buckets.len() * std::mem::size_of::<Bucket>() / (1024 * 1024)
+    }
+
+    pub fn clear(&self) {
+        for slot in self.buckets.iter().flat_map(|b| b.slots.iter()) {
+            slot.key.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+        self.generation.store(0, Ordering::Relaxed);
+    }
+
+    /// Ages the entries of previous searches so they are replaced first.
+    pub fn new_search(&self) {
+        let generation = self.generation.load(Ordering::Relaxed);
+        self.generation
+            .store((generation + 1) % GENERATION_CYCLE, Ordering::Relaxed);
+    }
+
+    /// Looks up the position with `hash`. Mate scores are converted to be relative to `ply`.
+    pub fn probe(&self, hash: u64, ply: usize) -> Option<TtEntry> {
+        self.bucket(hash).slots.iter().find_map(|slot| {
+            let data = slot.data.load(Ordering::Relaxed);
+            let key = slot.key.load(Ordering::Relaxed);
+            if data == 0 || key ^ data != hash {
+                return None;
+            }
+
+            let mut entry = unpack_entry(data);
+            entry.score = score_from_tt(entry.score, ply);
+            Some(entry)
+        })
+    }
+
+    /// Stores the result of searching the position with `hash` at `ply`. Entries for the same
+    /// position are replaced unless they were searched much deeper, otherwise the shallowest and
+    /// oldest entry in the bucket is replaced.
+    pub fn store(&self, hash: u64, ply: usize, entry: TtEntry) {
+        let generation = self.generation.load(Ordering::Relaxed);
+        let bucket = self.bucket(hash);
+
+        let mut replace = &bucket.slots[0];
+        let mut replace_value = i32::MAX;
+        let mut existing = None;
+        for slot in bucket.slots.iter() {
+            let data = slot.data.load(Ordering::Relaxed);
+            let key = slot.key.load(Ordering::Relaxed);
+            if data == 0 {
+                if replace_value > i32::MIN {
+                    replace = slot;
+                    replace_value = i32::MIN;
+                }
+                continue;
+            }
+
+            if key ^ data == hash {
+                replace = slot;
+                existing = Some(data);
+                break;
+            }
+
+            // Prefer replacing shallow entries and entries from earlier searches.
+            let age = (GENERATION_CYCLE + generation - unpack_generation(data)) % GENERATION_CYCLE;
+            let value = unpack_entry(data).depth as i32 - 8 * age as i32;
+            if value < replace_value {
+                replace = slot;
+                replace_value = value;
+            }
+        }
+
+        let mut entry = entry;
+        if let Some(data) = existing {
+            let old = unpack_entry(data);
+            if entry.bound != Bound::Exact
+                && unpack_generation(data) == generation
+                && (entry.depth as i32) + 3 < old.depth as i32
+            {
+                return;
+            }
+            // Keep the old best move rather than losing it to a search that didn't find one.
+            if entry.best_move.is_none() {
+                entry.best_move = old.best_move;
+            }
+        }
+
+        entry.score = score_to_tt(entry.score, ply);
+        let data = pack_entry(&entry, generation);
+        replace.data.store(data, Ordering::Relaxed);
+        replace.key.store(hash ^ data, Ordering::Relaxed);
+    }
+
+    /// An estimate of how full the table is in permille, counting only entries from the current
+    /// search.
+    pub fn hashfull(&self) -> u32 {
+        let generation = self.generation.load(Ordering::Relaxed);
+        let sample = self.buckets.iter().take(1000 / ENTRIES_PER_BUCKET);
+        let mut used: u32 = 0;
+        let mut total = 0;
+        for slot in sample.flat_map(|b| b.slots.iter()) {
+            let data = slot.data.load(Ordering::Relaxed);
+            if data != 0 && unpack_generation(data) == generation {
+                used += 1;
+            }
+            total += 1;
+        }
+
+        (used * 1000).checked_div(total).unwrap_or(0)
+    }
+
+    fn bucket(&self, hash: u64) -> &Bucket {
+        // Maps the hash onto the buckets without needing a power of two size.
+        let index = ((hash as u128 * self.buckets.len() as u128) >> 64) as usize;
+        &self.buckets[index]
+    }
+}
+
+/// Mate scores are stored relative to the position instead of the root so they are still right
+/// when the position is reached at a different ply.
+fn score_to_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE_BOUND {
+        score + ply as i32
+    } else if score <= -MATE_BOUND {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE_BOUND {
+        score - ply as i32
+    } else if score <= -MATE_BOUND {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+// The data is packed as
+// bits  0-15: best move
+// bits 16-31: score
+// bits 32-47: static evaluation
+// bits 48-55: depth
+// bits 56-57: bound
+// bits 58-63: generation
+fn pack_entry(entry: &TtEntry, generation: u8) -> u64 {
+    pack_move(entry.best_move) as u64
+        | (entry.score as i16 as u16 as u64) << 16
+        | (entry.eval as i16 as u16 as u64) << 32
+        | (entry.depth as u64) << 48
+        | (entry.bound as u64) << 56
+        | (generation as u64) << 58
+}
+
+fn unpack_entry(data: u64) -> TtEntry {
+    TtEntry {
+        best_move: unpack_move(data as u16),
+        score: (data >> 16) as u16 as i16 as i32,
+        eval: (data >> 32) as u16 as i16 as i32,
+        depth: (data >> 48) as u8,
+        bound: match (data >> 56) & 0b11 {
+            1 => Bound::Exact,
+            2 => Bound::Lower,
+            _ => Bound::Upper,
+        },
+    }
+}
+
+fn unpack_generation(data: u64) -> u8 {
+    (data >> 58) as u8
+}
+
+const PROMOTIONS: [PieceKind; 4] = [
+    PieceKind::Knight,
+    PieceKind::Bishop,
+    PieceKind::Rook,
+    PieceKind::Queen,
+];
+
+// A move is packed as 6 bits for each square and 3 bits for the promotion. No move is 0 because
+// a move from A1 to A1 can't happen.
+fn pack_move(m: Option<Move>) -> u16 {
+    match m {
+        None => 0,
+        Some(Move(from, to, promotion)) => {
+            let promotion = promotion
+                .and_then(|pk| PROMOTIONS.iter().position(|p| *p == pk))
+                .map_or(0, |i| i as u16 + 1);
+            from as u16 | (to as u16) << 6 | promotion << 12
+        }
+    }
+}
+
+fn unpack_move(m: u16) -> Option<Move> {
+    if m == 0 {
+        return None;
+    }
+
+    let promotion = match (m >> 12) & 0b111 {
+        0 => None,
+        i => Some(PROMOTIONS[i as usize - 1]),
+    };
+    Some(Move(
+        SQUARES[(m & 0b11_1111) as usize],
+        SQUARES[((m >> 6) & 0b11_1111) as usize],
+        promotion,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Square;
+    use crate::search::MATE;
+
+    fn entry(best_move: Option<Move>, score: i32, depth: u8, bound: Bound) -> TtEntry {
+        TtEntry {
+            best_move,
+            score,
+            eval: -25,
+            depth,
+            bound,
+        }
+    }
+
+    #[test]
+    fn store_and_probe_test() {
+        let tt = TranspositionTable::new(1);
+        assert_eq!(tt.size_mb(), 1);
+
+        let m = Some(Move(Square::E7, Square::E8, Some(PieceKind::Knight)));
+        tt.store(0xDEAD_BEEF, 0, entry(m, -150, 7, Bound::Lower));
+        assert_eq!(
+            tt.probe(0xDEAD_BEEF, 0),
+            Some(entry(m, -150, 7, Bound::Lower))
+        );
+        assert_eq!(tt.probe(0xDEAD_BEEE, 0), None);
+
+        // Mate scores are stored relative to the position.
+        tt.store(42, 3, entry(None, MATE - 5, 2, Bound::Exact));
+        assert_eq!(tt.probe(42, 3).unwrap().score, MATE - 5);
+        assert_eq!(tt.probe(42, 1).unwrap().score, MATE - 3);
+        tt.store(43, 3, entry(None, -MATE + 5, 2, Bound::Exact));
+        assert_eq!(tt.probe(43, 1).unwrap().score, -MATE + 3);
+
+        tt.clear();
+        assert_eq!(tt.probe(0xDEAD_BEEF, 0), None);
+    }
+
+    #[test]
+    fn replacement_test() {
+        let tt = TranspositionTable::new(1);
+        let m = Some(Move(Square::G1, Square::F3, None));
+
+        // A shallower search of the same position doesn't replace a much deeper one but keeps the
+        // best move when it has none.
+        tt.store(1, 0, entry(m, 10, 12, Bound::Lower));
+        tt.store(1, 0, entry(None, 20, 2, Bound::Upper));
+        assert_eq!(tt.probe(1, 0), Some(entry(m, 10, 12, Bound::Lower)));
+        tt.store(1, 0, entry(None, 30, 11, Bound::Upper));
+        assert_eq!(tt.probe(1, 0), Some(entry(m, 30, 11, Bound::Upper)));
+
+        // Hashes that differ only in their low bits share a bucket. Once the bucket is full the
+        // shallowest entry is replaced, preferring entries from older searches.
+        let hashes: Vec<u64> = (1..=7).map(|i| 7 << 60 | i).collect();
+        tt.store(hashes[0], 0, entry(None, 0, 3, Bound::Exact));
+        tt.new_search();
+        for (h, depth) in hashes[1..4].iter().zip([5, 1, 4]) {
+            tt.store(*h, 0, entry(None, 0, depth, Bound::Exact));
+        }
+        tt.store(hashes[4], 0, entry(None, 0, 6, Bound::Exact));
+        assert_eq!(tt.probe(hashes[0], 0), None);
+
+        for h in &hashes[5..] {
+            tt.store(*h, 0, entry(None, 0, 9, Bound::Exact));
+        }
+        assert_eq!(tt.probe(hashes[1], 0).map(|e| e.depth), Some(5));
+        assert_eq!(tt.probe(hashes[2], 0), None);
+        assert_eq!(tt.probe(hashes[3], 0), None);
+    }
+
+    #[test]
+    fn hashfull_test() {
+        let mut tt = TranspositionTable::new(1);
+        assert_eq!(tt.hashfull(), 0);
+
+        for i in 0..(1u64 << 16) {
+            tt.store(
+                i.wrapping_mul(0x9E37_79B9_7F4A_7C15),
+                0,
+                entry(None, 0, 1, Bound::Exact),
+            );
+        }
+        assert!(tt.hashfull() > 500);
+
+        tt.new_search();
+        assert_eq!(tt.hashfull(), 0);
+
+        tt.resize(2);
+        assert_eq!(tt.size_mb(), 2);
+        assert_eq!(tt.hashfull(), 0);
+    }
+
+    #[test]
+    fn pack_move_test() {
+        for m in [
+            None,
+            Some(Move(Square::A1, Square::H8, None)),
+            Some(Move(Square::B7, Square::A8, Some(PieceKind::Queen))),
+            Some(Move(Square::H2, Square::H1, Some(PieceKind::Rook))),
+        ] {
+            assert_eq!(unpack_move(pack_move(m)), m);
+        }
+    }
+}
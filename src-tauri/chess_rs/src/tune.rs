@@ -0,0 +1,387 @@
+use std::fmt::Write;
+use std::thread;
+
+use crate::board::Board;
+use crate::core::{Move, Piece, PieceKind, Square};
+use crate::eval::{self, EvalParams};
+use crate::movepick::{ButterflyHistory, MovePicker};
+
+/// How deep the quiescence search goes before it takes the position as it is.
+const MAX_QUIESCENCE_PLY: usize = 32;
+
+/// A position from a game and how the game ended, with 1 for a white win, 0.5 for a draw and 0
+/// for a black win.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TuningPosition {
+    pub pieces: Vec<(Piece, Square)>,
+    pub result: f64,
+}
+
+/// Parses a line of a training file, which is a FEN followed by the result of the game as
+/// `1-0`, `0-1`, `1/2-1/2` or a number from white's point of view. The result may be wrapped in
+/// brackets or quotes and come after an EPD `c9` opcode, so `<fen> [1-0]` and `<fen> c9 "1-0";`
+/// both work.
+pub fn parse_line(line: &str) -> Result<(String, f64), String> {
+    let mut tokens: Vec<&str> = line.split_whitespace().collect();
+    let result_str = tokens
+        .pop()
+        .ok_or("expected a fen and a result")?
+        .trim_matches(|c| matches!(c, '[' | ']' | '"' | ';'));
+    if tokens.last() == Some(&"c9") {
+        tokens.pop();
+    }
+    if tokens.is_empty() {
+        return Err("expected a fen before the result".to_string());
+    }
+
+    let result = match result_str {
+        "1-0" => 1.0,
+        "0-1" => 0.0,
+        "1/2-1/2" => 0.5,
+        r => r
+            .parse::<f64>()
+            .ok()
+            .filter(|r| (0.0..=1.0).contains(r))
+            .ok_or(format!("invalid result: {}", r))?,
+    };
+
+    Ok((tokens.join(" "), result))
+}
+
+/// Resolves captures in `b` with a quiescence search using `params` and returns the pieces of the
+/// position at the end of the principal variation, so the tuned evaluation sees quiet positions.
+pub fn quiet_pieces(b: &Board, params: &EvalParams) -> Vec<(Piece, Square)> {
+    let mut b = b.shallow_clone();
+    let history = ButterflyHistory::default();
+    let mut pv = Vec::new();
+    quiescence(&mut b, -i32::MAX, i32::MAX, 0, params, &history, &mut pv);
+
+    for m in pv {
+        b.make_move(m);
+    }
+    b.pieces()
+}
+
+fn quiescence(
+    b: &mut Board,
+    mut alpha: i32,
+    beta: i32,
+    ply: usize,
+    params: &EvalParams,
+    history: &ButterflyHistory,
+    pv: &mut Vec<Move>,
+) -> i32 {
+    pv.clear();
+    let stand_pat = eval::evaluate_with(b, params);
+    // Evasions aren't searched, so positions in check are taken as they are.
+    if ply >= MAX_QUIESCENCE_PLY || b.is_in_check() || stand_pat >= beta {
+        return stand_pat;
+    }
+    alpha = alpha.max(stand_pat);
+
+    let mut picker = MovePicker::new_quiescence(b, None);
+    let mut child_pv = Vec::new();
+    while let Some(m) = picker.next(b, history) {
+        b.make_move(m);
+        let score = -quiescence(b, -beta, -alpha, ply + 1, params, history, &mut child_pv);
+        b.unmake_move();
+
+        if score > alpha {
+            alpha = score;
+            pv.clear();
+            pv.push(m);
+            pv.append(&mut child_pv);
+            if score >= beta {
+                break;
+            }
+        }
+    }
+
+    alpha
+}
+
+/// Maps a score in centipawns to the expected result of the game.
+pub fn sigmoid(score: i32, k: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * score as f64 / 400.0))
+}
+
+/// The mean squared error between the results of the games and what the evaluation with
+/// `params` predicts for them. The positions are split between `threads` threads.
+pub fn error(positions: &[TuningPosition], params: &EvalParams, k: f64, threads: usize) -> f64 {
+    if positions.is_empty() {
+        return 0.0;
+    }
+
+    let chunk_size = positions.len().div_ceil(threads.max(1));
+    let total: f64 = thread::scope(|scope| {
+        let handles: Vec<_> = positions
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|p| {
+                            let score = eval::evaluate_pieces(p.pieces.iter().copied(), params);
+                            (p.result - sigmoid(score, k)).powi(2)
+                        })
+                        .sum::<f64>()
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).sum()
+    });
+
+    total / positions.len() as f64
+}
+
+/// Finds the scaling constant of the sigmoid that best fits the results to the evaluation with
+/// `params`, by narrowing in on it a decimal place at a time.
+pub fn find_k(positions: &[TuningPosition], params: &EvalParams, threads: usize) -> f64 {
+    let mut best_k = 1.0;
+    let mut best_error = error(positions, params, best_k, threads);
+    let mut step = 1.0;
+    for _ in 0..4 {
+        let centre = best_k;
+        for i in -10..=10 {
+            let k = centre + i as f64 * step / 10.0;
+            if k <= 0.0 {
+                continue;
+            }
+            let e = error(positions, params, k, threads);
+            if e < best_error {
+                best_k = k;
+                best_error = e;
+            }
+        }
+        step /= 10.0;
+    }
+
+    best_k
+}
+
+/// The parameters that change the evaluation. The kings' material cancels out and pawns are
+/// never on the first or last rank so those are left alone.
+fn tunable_params(params: &mut EvalParams) -> Vec<&mut i32> {
+    let king = PieceKind::King as usize;
+    let pawn = PieceKind::Pawn as usize;
+    let mut tunable = Vec::new();
+
+    for material in [&mut params.material_mg, &mut params.material_eg] {
+        for (kind, value) in material.iter_mut().enumerate() {
+            if kind != king {
+                tunable.push(value);
+            }
+        }
+    }
+    for pst in [&mut params.pst_mg, &mut params.pst_eg] {
+        for (kind, table) in pst.iter_mut().enumerate() {
+            for (i, value) in table.iter_mut().enumerate() {
+                if kind != pawn || (8..56).contains(&i) {
+                    tunable.push(value);
+                }
+            }
+        }
+    }
+    for passed in [&mut params.passed_pawn_mg, &mut params.passed_pawn_eg] {
+        tunable.extend(&mut passed[1..7]);
+    }
+    tunable.extend([
+        &mut params.doubled_pawn_mg,
+        &mut params.doubled_pawn_eg,
+        &mut params.isolated_pawn_mg,
+        &mut params.isolated_pawn_eg,
+        &mut params.rook_open_file_mg,
+        &mut params.rook_open_file_eg,
+        &mut params.rook_semi_open_file_mg,
+        &mut params.rook_semi_open_file_eg,
+        &mut params.pawn_shield_mg,
+        &mut params.pawn_shield_eg,
+    ]);
+
+    tunable
+}
+
+/// Tunes `params` by local search: each parameter is moved by `step` in whichever direction
+/// lowers the error until a pass over all of them doesn't improve it, or after `max_passes`
+/// passes. `on_pass` is called with the pass number, the error and the parameters after each pass.
+pub fn tune(
+    positions: &[TuningPosition],
+    params: &EvalParams,
+    k: f64,
+    step: i32,
+    max_passes: usize,
+    threads: usize,
+    on_pass: &mut dyn FnMut(usize, f64, &EvalParams),
+) -> EvalParams {
+    let mut best = params.clone();
+    let mut best_error = error(positions, &best, k, threads);
+    let param_count = tunable_params(&mut best.clone()).len();
+
+    for pass in 1..=max_passes {
+        let mut improved = false;
+        for i in 0..param_count {
+            for delta in [step, -step] {
+                let mut candidate = best.clone();
+                *tunable_params(&mut candidate)[i] += delta;
+                let e = error(positions, &candidate, k, threads);
+                if e < best_error {
+                    best = candidate;
+                    best_error = e;
+                    improved = true;
+                    break;
+                }
+            }
+        }
+
+        on_pass(pass, best_error, &best);
+        if !improved {
+            break;
+        }
+    }
+
+    best
+}
+
+/// The source of `eval_params.rs` with `params` as the default parameters.
+pub fn to_rust_source(params: &EvalParams) -> String {
+    let kinds = [
+        PieceKind::Rook,
+        PieceKind::Knight,
+        PieceKind::Bishop,
+        PieceKind::Queen,
+        PieceKind::King,
+        PieceKind::Pawn,
+    ];
+
+    let mut s = String::new();
+    s.push_str("use crate::eval::EvalParams;\n\n");
+    s.push_str("// The piece square tables are laid out as the board is seen from white's side, so the first row\n");
+    s.push_str(
+        "// is the eighth rank. They are indexed by `PieceKind` and from white's point of view.\n",
+    );
+    s.push_str("#[rustfmt::skip]\n");
+    s.push_str("pub const DEFAULT_PARAMS: EvalParams = EvalParams {\n");
+    writeln!(s, "    material_mg: {:?},", params.material_mg).unwrap();
+    writeln!(s, "    material_eg: {:?},", params.material_eg).unwrap();
+    for (name, pst) in [("pst_mg", &params.pst_mg), ("pst_eg", &params.pst_eg)] {
+        writeln!(s, "    {}: [", name).unwrap();
+        for (kind, table) in kinds.iter().zip(pst) {
+            writeln!(s, "        // {:?}", kind).unwrap();
+            s.push_str("        [\n");
+            for row in table.chunks(8) {
+                s.push_str("           ");
+                for value in row {
+                    write!(s, " {:>3},", value).unwrap();
+                }
+                s.push('\n');
+            }
+            s.push_str("        ],\n");
+        }
+        s.push_str("    ],\n");
+    }
+    writeln!(s, "    passed_pawn_mg: {:?},", params.passed_pawn_mg).unwrap();
+    writeln!(s, "    passed_pawn_eg: {:?},", params.passed_pawn_eg).unwrap();
+    for (name, value) in [
+        ("doubled_pawn_mg", params.doubled_pawn_mg),
+        ("doubled_pawn_eg", params.doubled_pawn_eg),
+        ("isolated_pawn_mg", params.isolated_pawn_mg),
+        ("isolated_pawn_eg", params.isolated_pawn_eg),
+        ("rook_open_file_mg", params.rook_open_file_mg),
+        ("rook_open_file_eg", params.rook_open_file_eg),
+        ("rook_semi_open_file_mg", params.rook_semi_open_file_mg),
+        ("rook_semi_open_file_eg", params.rook_semi_open_file_eg),
+        ("pawn_shield_mg", params.pawn_shield_mg),
+        ("pawn_shield_eg", params.pawn_shield_eg),
+    ] {
+        writeln!(s, "    {}: {},", name, value).unwrap();
+    }
+    s.push_str("};\n");
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::STARTING_POS_FEN;
+    use crate::eval_params::DEFAULT_PARAMS;
+    use crate::lookup_tables::LookupTables;
+
+    #[test]
+    fn parse_line_test() {
+        assert_eq!(
+            parse_line(&format!("{} [1-0]", STARTING_POS_FEN)),
+            Ok((STARTING_POS_FEN.to_string(), 1.0))
+        );
+        assert_eq!(
+            parse_line("8/8/8/8/8/8/8/K1k5 w - - c9 \"1/2-1/2\";"),
+            Ok(("8/8/8/8/8/8/8/K1k5 w - -".to_string(), 0.5))
+        );
+        assert_eq!(
+            parse_line(&format!("{} 0.0", STARTING_POS_FEN)),
+            Ok((STARTING_POS_FEN.to_string(), 0.0))
+        );
+        assert!(parse_line("").is_err());
+        assert!(parse_line("1-0").is_err());
+        assert!(parse_line(&format!("{} 2-0", STARTING_POS_FEN)).is_err());
+    }
+
+    #[test]
+    fn to_rust_source_test() {
+        assert_eq!(
+            to_rust_source(&DEFAULT_PARAMS),
+            include_str!("eval_params.rs")
+        );
+    }
+
+    #[test]
+    fn quiet_pieces_test() {
+        let l = LookupTables::generate();
+
+        // The hanging queen is taken.
+        let b = Board::from_fen("4k3/8/8/3q4/4P3/8/8/4K3 w - - 0 1", &l).unwrap();
+        let pieces = quiet_pieces(&b, &DEFAULT_PARAMS);
+        assert_eq!(pieces.len(), 3);
+        assert!(pieces.contains(&(Piece::WhitePawn, Square::D5)));
+
+        // A quiet position is left alone.
+        let b = Board::from_fen(STARTING_POS_FEN, &l).unwrap();
+        assert_eq!(quiet_pieces(&b, &DEFAULT_PARAMS), b.pieces());
+    }
+
+    #[test]
+    fn tune_test() {
+        let l = LookupTables::generate();
+        // An extra knight wins and the same material is a draw, so tuning should lower the error.
+        let positions: Vec<TuningPosition> = [
+            ("4k3/8/8/8/8/8/8/1N2K3 w - - 0 1", 1.0),
+            ("1n2k3/8/8/8/8/8/8/4K3 w - - 0 1", 0.0),
+            ("1n2k3/8/8/8/8/8/8/1N2K3 w - - 0 1", 0.5),
+            ("4k3/pp6/8/8/8/8/PP6/4K3 w - - 0 1", 0.5),
+        ]
+        .iter()
+        .map(|(fen, result)| TuningPosition {
+            pieces: Board::from_fen(fen, &l).unwrap().pieces(),
+            result: *result,
+        })
+        .collect();
+
+        let k = find_k(&positions, &DEFAULT_PARAMS, 2);
+        assert!(k > 0.0);
+        let before = error(&positions, &DEFAULT_PARAMS, k, 2);
+        let mut passes = 0;
+        let tuned = tune(
+            &positions,
+            &DEFAULT_PARAMS,
+            k,
+            5,
+            2,
+            2,
+            &mut |pass, e, _| {
+                passes = pass;
+                assert!(e <= before);
+            },
+        );
+        assert!(passes > 0);
+        assert!(error(&positions, &tuned, k, 2) < before);
+    }
+}
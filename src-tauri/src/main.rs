@@ -1,18 +1,41 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 use std::{collections::HashMap, sync::Mutex};
 
 use serde::Serialize;
 use uuid::Uuid;
 
 use chess_rs::board;
-use chess_rs::core::{Move, Piece, PieceKind, Square};
+use chess_rs::book::Book;
+use chess_rs::core::{Move, Piece, Square};
 use chess_rs::lookup_tables;
+use chess_rs::search::{self, SearchLimits, SearchOptions, MAX_MULTI_PV};
+use chess_rs::strength::Difficulty;
+use chess_rs::tt::{TranspositionTable, DEFAULT_HASH_MB};
+
+/// How long the engine thinks about its moves in games.
+const ENGINE_MOVE_TIME: Duration = Duration::from_secs(1);
 
 struct MyState<'a> {
     l: &'a lookup_tables::LookupTables,
     games: Mutex<HashMap<String, board::Board<'a>>>,
+    /// How strongly the engine plays in each game.
+    difficulties: Mutex<HashMap<String, Difficulty>>,
+    tt: Arc<TranspositionTable>,
+    analysis: Mutex<Option<Analysis>>,
+    /// The opening book the engine plays from in games, if one has been loaded.
+    book: Mutex<Option<Book>>,
+}
+
+/// A search running in the background for the analysis board.
+struct Analysis {
+    stop: Arc<AtomicBool>,
+    handle: thread::JoinHandle<()>,
 }
 
 #[derive(Serialize)]
@@ -21,6 +44,8 @@ struct GameState {
     pieces: Vec<(Piece, Square)>,
     valid_moves: Vec<Move>,
     moves: Vec<String>,
+    /// The move that led to the position, so the GUI can show the engine's moves.
+    last_move: Option<Move>,
 }
 
 // Learn more about Tauri commands at https://tauri.app/v1/guides/features/command
@@ -30,40 +55,38 @@ fn greet(name: &str) -> String {
 }
 
 #[tauri::command]
-fn new_game(state: tauri::State<MyState>) -> GameState {
+fn new_game(state: tauri::State<MyState>, difficulty: Option<Difficulty>) -> GameState {
     let id = Uuid::new_v4().to_string();
     let b = board::Board::start_pos(state.l);
     let pieces = b.pieces();
     let valid_moves = b.generate_moves(board::Legality::Legal);
     let moves = b.san_moves.clone();
     state.games.lock().unwrap().insert(id.clone(), b);
+    state
+        .difficulties
+        .lock()
+        .unwrap()
+        .insert(id.clone(), difficulty.unwrap_or(Difficulty::Maximum));
 
     GameState {
         game_id: id,
         pieces,
         valid_moves,
         moves,
+        last_move: None,
     }
 }
 
 #[tauri::command]
 fn make_move(state: tauri::State<MyState>, id: String, m: String) -> Result<GameState, String> {
-    let m = if m.len() == 4 {
-        let source = Square::try_from(&m[0..2]).unwrap();
-        let dest = Square::try_from(&m[2..]).unwrap();
-        Move(source, dest, None)
-    } else if m.len() == 5 {
-        let source = Square::try_from(&m[0..2]).unwrap();
-        let dest = Square::try_from(&m[2..4]).unwrap();
-        let promotion = Some(PieceKind::try_from(&m[4..]).expect("cannot parse move"));
-        Move(source, dest, promotion)
-    } else {
-        panic!("invalid move");
-    };
+    let m = Move::try_from(m.as_str())?;
 
     let mut games = state.games.lock().unwrap();
     let b: &mut board::Board = games.get_mut(&id).ok_or("cannot find game")?;
     let valid_moves_before = b.generate_moves(board::Legality::Legal);
+    if !valid_moves_before.contains(&m) {
+        return Err(format!("illegal move: {}", m));
+    }
     b.apply_move(m, &valid_moves_before);
 
     let valid_moves = b.generate_moves(board::Legality::Legal);
@@ -74,9 +97,133 @@ fn make_move(state: tauri::State<MyState>, id: String, m: String) -> Result<Game
         pieces: b.pieces(),
         valid_moves,
         moves,
+        last_move: Some(m),
     })
 }
 
+/// Has the engine play a move in a game at the game's difficulty. It's async so the search
+/// doesn't block the main thread.
+#[tauri::command]
+async fn engine_move(
+    state: tauri::State<'_, MyState<'static>>,
+    id: String,
+) -> Result<GameState, String> {
+    let b = state
+        .games
+        .lock()
+        .unwrap()
+        .get(&id)
+        .ok_or("cannot find game")?
+        .clone();
+    let difficulty = state
+        .difficulties
+        .lock()
+        .unwrap()
+        .get(&id)
+        .copied()
+        .ok_or("cannot find game")?;
+
+    let book_move = state
+        .book
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|book| book.choose(&b, Uuid::new_v4().as_u64_pair().0));
+    if let Some(m) = book_move {
+        return make_move(state, id, m.to_string());
+    }
+
+    let limits = SearchLimits {
+        movetime: Some(ENGINE_MOVE_TIME),
+        ..SearchLimits::default()
+    };
+    let options = SearchOptions {
+        strength: difficulty.strength(Uuid::new_v4().as_u64_pair().0),
+        ..SearchOptions::default()
+    };
+    let result = search::search(
+        &b,
+        &state.tt,
+        &limits,
+        &options,
+        &AtomicBool::new(false),
+        &mut |_| {},
+    );
+    let m = result.best_move.ok_or("the game is over")?;
+
+    make_move(state, id, m.to_string())
+}
+
+/// Loads the Polyglot opening book at `path` for the engine to play from in games, or stops using
+/// a book without a path. Returns the number of moves in the book.
+#[tauri::command]
+fn set_book(state: tauri::State<MyState>, path: Option<String>) -> Result<usize, String> {
+    let book = path.map(Book::load).transpose()?;
+    let moves = book.as_ref().map_or(0, |book| book.entries().len());
+    *state.book.lock().unwrap() = book;
+    Ok(moves)
+}
+
+/// Searches a game's position in the background, emitting an `analysis-info` event with a
+/// `SearchInfo` for each of the `multi_pv` best lines after every iteration and an
+/// `analysis-done` event with the best move when it finishes. Without a depth it runs until
+/// `stop_analysis`. Any analysis already running is stopped first.
+#[tauri::command]
+fn start_analysis(
+    window: tauri::Window,
+    state: tauri::State<MyState<'static>>,
+    id: String,
+    multi_pv: usize,
+    depth: Option<u8>,
+) -> Result<(), String> {
+    stop_running_analysis(&state);
+
+    let b = state
+        .games
+        .lock()
+        .unwrap()
+        .get(&id)
+        .ok_or("cannot find game")?
+        .clone();
+    let tt = Arc::clone(&state.tt);
+    let stop = Arc::new(AtomicBool::new(false));
+    let search_stop = Arc::clone(&stop);
+
+    let handle = thread::spawn(move || {
+        let limits = SearchLimits {
+            depth,
+            infinite: depth.is_none(),
+            ..SearchLimits::default()
+        };
+        let options = SearchOptions {
+            multi_pv: multi_pv.clamp(1, MAX_MULTI_PV),
+            ..SearchOptions::default()
+        };
+        let result = search::search(&b, &tt, &limits, &options, &search_stop, &mut |info| {
+            // The window may have been closed, which just ends the analysis early.
+            if window.emit("analysis-info", info).is_err() {
+                search_stop.store(true, Ordering::Relaxed);
+            }
+        });
+        let _ = window.emit("analysis-done", result.best_move);
+    });
+
+    *state.analysis.lock().unwrap() = Some(Analysis { stop, handle });
+    Ok(())
+}
+
+#[tauri::command]
+fn stop_analysis(state: tauri::State<MyState<'static>>) {
+    stop_running_analysis(&state);
+}
+
+fn stop_running_analysis(state: &MyState) {
+    if let Some(analysis) = state.analysis.lock().unwrap().take() {
+        analysis.stop.store(true, Ordering::Relaxed);
+        analysis.handle.join().unwrap();
+    }
+}
+
 fn main() {
     let l = Box::new(lookup_tables::LookupTables::generate());
     let games = Mutex::new(HashMap::new());
@@ -85,8 +232,20 @@ fn main() {
         .manage(MyState {
             l: Box::leak(l),
             games,
+            difficulties: Mutex::new(HashMap::new()),
+            tt: Arc::new(TranspositionTable::new(DEFAULT_HASH_MB)),
+            analysis: Mutex::new(None),
+            book: Mutex::new(None),
         })
-        .invoke_handler(tauri::generate_handler![greet, new_game, make_move])
+        .invoke_handler(tauri::generate_handler![
+            greet,
+            new_game,
+            make_move,
+            engine_move,
+            set_book,
+            start_analysis,
+            stop_analysis
+        ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
@@ -1,11 +1,12 @@
 import { Map as IMap, List as IList } from 'immutable';
-import React, { useState, useEffect } from "react";
+import React, { useState, useEffect, useRef } from "react";
 import { invoke } from "@tauri-apps/api/tauri";
 
 import "./Game.css"
 
 import Board from "./Board";
 import SidePanel from "./SidePanel";
+import { GameSettings } from "./NewGame";
 
 function mapGameState(gameState: any) {
   let pieceMapping = new IMap<string, string>(
@@ -28,9 +29,10 @@ function mapGameState(gameState: any) {
     "gameId": gameState["game_id"],
     "pieces": gameState["pieces"].map(([p, s]) => [pieceMapping.get(p), s.toLowerCase()]),
     "validMoves": gameState["valid_moves"].reduce(
-      (acc, [from, to]) => {
-        from = from.toLowerCase();
-        to = to.toLowerCase();
+      (acc, m: string) => {
+        // Moves are UCI strings like "e2e4" or "e7e8q".
+        const from = m.slice(0, 2);
+        const to = m.slice(2, 4);
         if (!acc.has(from)) {
           return acc.set(from, IList<string>([to]));
         } else {
@@ -44,33 +46,85 @@ function mapGameState(gameState: any) {
       new IMap<string, string[]>(),
     ),
     "moves": gameState["moves"],
+    "lastMove": gameState["last_move"] === null ? [] : [gameState["last_move"].slice(0, 2), gameState["last_move"].slice(2, 4)],
   };
   return newState;
 }
 
+// Games start from the standard position, so White is to move after an even number of moves.
+function sideToMove(gameState: any): string {
+  return gameState["moves"].length % 2 === 0 ? "white" : "black";
+}
+
 function Game() {
   const [gameStates, setGameStates] = useState(new IList([{ "pieces": new IList<IList<string>>(), "moves": [], }]));
   const [moves, setMoves] = useState(new IList([[]]));
   const [currentGameState, setCurrentGameState] = useState(0);
   const [flipped, setFlipped] = useState(false);
+  // The side the engine plays, or null in a game between two players.
+  const [engineColour, setEngineColour] = useState<string | null>(null);
+  const [engineThinking, setEngineThinking] = useState(false);
+  // The engine's moves can arrive after a new game has been started.
+  const gameId = useRef("");
 
   useEffect(() => {
-    invoke('new_game').then((res) => {
-      let newState = mapGameState(res);
-      setGameStates(gameStates.push(newState));
-      setMoves(moves.push([]));
-      setCurrentGameState(currentGameState + 1);
-    });
+    startGame({ difficulty: null, playerColour: "white", bookPath: "" })
+      .catch((error) => console.error(error));
   }, []);
 
+  // Adds the state after a move and shows it.
+  let pushGameState = function(newState: any) {
+    setGameStates((gameStates) => gameStates.push(newState));
+    setMoves((moves) => moves.push(newState["lastMove"]));
+    // The first state is a placeholder and the next is the start of the game.
+    setCurrentGameState(newState["moves"].length + 1);
+  }
+
+  let engineMove = function(id: string) {
+    setEngineThinking(true);
+    invoke('engine_move', { id: id })
+      .then((res) => {
+        if (id === gameId.current) {
+          pushGameState(mapGameState(res));
+        }
+      })
+      .catch((error) => console.error(error))
+      .finally(() => {
+        if (id === gameId.current) {
+          setEngineThinking(false);
+        }
+      });
+  }
+
+  let startGame = async function(settings: GameSettings) {
+    // The book is only used by the engine, and loading it first means a bad path doesn't start a
+    // game.
+    if (settings.difficulty !== null) {
+      await invoke('set_book', { path: settings.bookPath === "" ? null : settings.bookPath });
+    }
+    let newState = mapGameState(await invoke('new_game', { difficulty: settings.difficulty }));
+    let engine = settings.difficulty === null ? null : (settings.playerColour === "white" ? "black" : "white");
+    gameId.current = newState["gameId"];
+    setEngineColour(engine);
+    setEngineThinking(false);
+    setGameStates(new IList([gameStates.first(), newState]));
+    setMoves(new IList([[], []]));
+    setCurrentGameState(1);
+    setFlipped(engine === "white");
+    if (engine === "white") {
+      engineMove(newState["gameId"]);
+    }
+  }
+
   // FIXME: need to handle promotions
   let makeMove = function(m: string) {
     invoke('make_move', { id: gameStates.last()["gameId"], m: m })
       .then((res) => {
         let newState = mapGameState(res);
-        setGameStates(gameStates.push(newState));
-        setMoves(moves.push([m.substring(0, 2), m.substring(2, 4)]));
-        setCurrentGameState(currentGameState + 1);
+        pushGameState(newState);
+        if (engineColour === sideToMove(newState) && newState["validMoves"].size > 0) {
+          engineMove(newState["gameId"]);
+        }
       })
       .catch((error) => console.error(error));
   }
@@ -81,14 +135,15 @@ function Game() {
         gameState={gameStates.get(currentGameState)}
         lastMove={moves.get(currentGameState)}
         makeMove={makeMove}
-        canMove={currentGameState == gameStates.size - 1}
+        canMove={currentGameState == gameStates.size - 1 && !engineThinking && engineColour !== sideToMove(gameStates.last())}
         flipped={flipped} />
       <SidePanel
         moves={gameStates.last().moves}
         currentGameState={currentGameState}
         setCurrentGameState={setCurrentGameState}
         numGameStates={gameStates.size}
-        toggleFlipped={() => setFlipped(!flipped)} />
+        toggleFlipped={() => setFlipped(!flipped)}
+        startGame={startGame} />
     </div>
   );
 }
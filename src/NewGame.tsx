@@ -0,0 +1,59 @@
+import { useState } from "react";
+
+import "./NewGame.css"
+
+// The difficulties `new_game` takes, weakest first.
+const difficulties = ["Beginner", "Casual", "Intermediate", "Advanced", "Expert", "Maximum"];
+
+export type GameSettings = {
+  // `null` for a game between two players.
+  difficulty: string | null,
+  playerColour: string,
+  // The path of a Polyglot `.bin` book for the engine to play from, or "" for none.
+  bookPath: string,
+}
+
+type NewGameProps = {
+  startGame: (settings: GameSettings) => Promise<void>,
+}
+
+function NewGame(props: NewGameProps) {
+  const [difficulty, setDifficulty] = useState("");
+  const [playerColour, setPlayerColour] = useState("white");
+  const [bookPath, setBookPath] = useState("");
+  const [error, setError] = useState("");
+
+  let startGame = function() {
+    setError("");
+    props.startGame({ difficulty: difficulty === "" ? null : difficulty, playerColour: playerColour, bookPath: bookPath })
+      .catch((e) => setError(String(e)));
+  }
+
+  let againstEngine = difficulty !== "";
+  return (
+    <div className="new-game">
+      <label>
+        Opponent
+        <select value={difficulty} onChange={(e) => setDifficulty(e.target.value)}>
+          <option value="">Two players</option>
+          {difficulties.map((d) => <option key={d} value={d}>{`Engine: ${d}`}</option>)}
+        </select>
+      </label>
+      <label>
+        Play as
+        <select value={playerColour} disabled={!againstEngine} onChange={(e) => setPlayerColour(e.target.value)}>
+          <option value="white">White</option>
+          <option value="black">Black</option>
+        </select>
+      </label>
+      <label>
+        Opening book
+        <input type="text" placeholder="book.bin" value={bookPath} disabled={!againstEngine} onChange={(e) => setBookPath(e.target.value)} />
+      </label>
+      <button onClick={startGame}>New game</button>
+      {error !== "" && <div className="error">{error}</div>}
+    </div>
+  );
+}
+
+export default NewGame;
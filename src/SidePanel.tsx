@@ -2,12 +2,15 @@ import { LuChevronFirst, LuChevronLeft, LuChevronRight, LuChevronLast, LuRepeat2
 
 import './SidePanel.css'
 
+import NewGame, { GameSettings } from "./NewGame";
+
 type SidePanelProps = {
   moves: string[];
   currentGameState: number,
   setCurrentGameState: Function,
   numGameStates: number,
   toggleFlipped: Function,
+  startGame: (settings: GameSettings) => Promise<void>,
 }
 
 function halfMove(currentGameState: number, numGameStates: number, numMoves: number): number {
@@ -61,7 +64,7 @@ function SidePanel(props: SidePanelProps) {
   // FIXME: Make the buttons look nice
   return (
     <div className="side-panel">
-
+      <NewGame startGame={props.startGame} />
       <div className="moves">
         {x.map((i) => Move(i[0], i[1], i[2], currentHalfMove, numGameStates, numMoves, props.setCurrentGameState))}
       </div>